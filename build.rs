@@ -0,0 +1,24 @@
+//! Embeds the short git hash of the checkout being built as `GIT_HASH`, for
+//! the `X-Server-Version` header. Builds from outside a git checkout, such as
+//! a source tarball, get `unknown`.
+
+// Build scripts talk to cargo over stdout
+#![allow(clippy::print_stdout)]
+
+use std::process::Command;
+
+fn main() {
+    let hash = Command::new("git")
+        .args(["rev-parse", "--short", "HEAD"])
+        .output()
+        .ok()
+        .filter(|output| output.status.success())
+        .and_then(|output| String::from_utf8(output.stdout).ok())
+        .map(|hash| hash.trim().to_string())
+        .filter(|hash| !hash.is_empty())
+        .unwrap_or_else(|| "unknown".to_string());
+
+    println!("cargo:rustc-env=GIT_HASH={hash}");
+    println!("cargo:rerun-if-changed=.git/HEAD");
+    println!("cargo:rerun-if-changed=.git/refs");
+}
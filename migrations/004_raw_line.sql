@@ -0,0 +1,5 @@
+-- Optional provenance: the exact JSONL line each row was parsed from, only
+-- populated when STORE_RAW_LINE is enabled
+ALTER TABLE gottcha2_results ADD COLUMN IF NOT EXISTS raw_line TEXT;
+
+ALTER TABLE stast_results ADD COLUMN IF NOT EXISTS raw_line TEXT;
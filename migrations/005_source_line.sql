@@ -0,0 +1,5 @@
+-- Optional input order: the 1-based line of the upload each row was parsed
+-- from, only populated when PRESERVE_INPUT_ORDER is enabled
+ALTER TABLE gottcha2_results ADD COLUMN IF NOT EXISTS source_line BIGINT;
+
+ALTER TABLE stast_results ADD COLUMN IF NOT EXISTS source_line BIGINT;
@@ -0,0 +1,59 @@
+-- Hash-partition gottcha2_results by sample_id so each partition's table and
+-- indexes stay a manageable size. Inserts still target gottcha2_results and
+-- Postgres routes every row to its partition. Existing rows are moved into
+-- the partitions; a table that's already partitioned is left alone.
+DO $$
+BEGIN
+  IF (SELECT relkind FROM pg_class WHERE oid = 'gottcha2_results'::regclass) = 'p' THEN
+    RETURN;
+  END IF;
+
+  ALTER TABLE gottcha2_results RENAME TO gottcha2_results_unpartitioned;
+  ALTER SEQUENCE gottcha2_results_id_seq OWNED BY NONE;
+  DROP INDEX IF EXISTS idx_gottcha2_sample_id;
+  DROP INDEX IF EXISTS idx_gottcha2_level;
+  DROP INDEX IF EXISTS idx_gottcha2_taxid;
+
+  -- A partitioned table's primary key must include the partition key
+  CREATE TABLE gottcha2_results (
+    id bigint NOT NULL DEFAULT nextval('gottcha2_results_id_seq'),
+    sample_id text NOT NULL,
+    LEVEL text NOT NULL,
+    name text NOT NULL,
+    taxid text NOT NULL,
+    read_count bigint NOT NULL,
+    total_bp_mapped bigint NOT NULL,
+    ani_ci95 double precision NOT NULL,
+    covered_sig_len bigint NOT NULL,
+    best_sig_cov double precision NOT NULL,
+    depth double precision NOT NULL,
+    rel_abundance double precision NOT NULL,
+    created_at timestamptz NOT NULL DEFAULT NOW(),
+    raw_line TEXT,
+    source_line BIGINT,
+    PRIMARY KEY (id, sample_id)
+  ) PARTITION BY HASH (sample_id);
+  ALTER SEQUENCE gottcha2_results_id_seq OWNED BY gottcha2_results.id;
+
+  CREATE TABLE gottcha2_results_p0 PARTITION OF gottcha2_results
+    FOR VALUES WITH (MODULUS 4, REMAINDER 0);
+  CREATE TABLE gottcha2_results_p1 PARTITION OF gottcha2_results
+    FOR VALUES WITH (MODULUS 4, REMAINDER 1);
+  CREATE TABLE gottcha2_results_p2 PARTITION OF gottcha2_results
+    FOR VALUES WITH (MODULUS 4, REMAINDER 2);
+  CREATE TABLE gottcha2_results_p3 PARTITION OF gottcha2_results
+    FOR VALUES WITH (MODULUS 4, REMAINDER 3);
+
+  CREATE INDEX idx_gottcha2_sample_id ON gottcha2_results(sample_id);
+  CREATE INDEX idx_gottcha2_level ON gottcha2_results(LEVEL);
+  CREATE INDEX idx_gottcha2_taxid ON gottcha2_results(taxid);
+
+  INSERT INTO gottcha2_results
+  SELECT id, sample_id, LEVEL, name, taxid, read_count, total_bp_mapped,
+    ani_ci95, covered_sig_len, best_sig_cov, depth, rel_abundance, created_at,
+    raw_line, source_line
+  FROM gottcha2_results_unpartitioned;
+
+  DROP TABLE gottcha2_results_unpartitioned;
+END
+$$;
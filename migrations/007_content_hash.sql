@@ -0,0 +1,5 @@
+-- Optional change detection: a hash of each row's fields, only populated when
+-- STORE_CONTENT_HASH is enabled
+ALTER TABLE gottcha2_results ADD COLUMN IF NOT EXISTS content_hash TEXT;
+
+ALTER TABLE stast_results ADD COLUMN IF NOT EXISTS content_hash TEXT;
@@ -0,0 +1,5 @@
+-- Optional analysis time: when the upstream tool produced each row, as
+-- reported by the record itself; NULL when the record doesn't say
+ALTER TABLE gottcha2_results ADD COLUMN IF NOT EXISTS observed_at TIMESTAMPTZ;
+
+ALTER TABLE stast_results ADD COLUMN IF NOT EXISTS observed_at TIMESTAMPTZ;
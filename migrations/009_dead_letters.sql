@@ -0,0 +1,12 @@
+-- Lines skipped under ON_INVALID_ROW=skip, kept when STORE_DEAD_LETTERS is
+-- enabled so they can be inspected with GET /dead-letters
+CREATE TABLE IF NOT EXISTS dead_letters (
+  id BIGSERIAL PRIMARY KEY,
+  endpoint TEXT NOT NULL,
+  line_number BIGINT NOT NULL,
+  raw_line TEXT NOT NULL,
+  error TEXT NOT NULL,
+  created_at TIMESTAMPTZ NOT NULL DEFAULT NOW()
+);
+
+CREATE INDEX IF NOT EXISTS idx_dead_letters_endpoint ON dead_letters(endpoint);
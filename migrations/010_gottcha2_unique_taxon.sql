@@ -0,0 +1,28 @@
+-- A sample holds one row per taxon and level, so a retried upload can be
+-- inserted with ON CONFLICT DO NOTHING instead of duplicating rows. Rows that
+-- already break that rule are never removed here: the migration fails and
+-- names how many exist, so an operator can decide which to keep. The index
+-- includes sample_id, the partition key, as a partitioned table's unique
+-- indexes must.
+DO $$
+DECLARE
+  duplicates BIGINT;
+BEGIN
+  SELECT count(*) INTO duplicates
+  FROM (
+    SELECT 1
+    FROM gottcha2_results
+    GROUP BY sample_id, taxid, level
+    HAVING count(*) > 1
+  ) AS groups;
+
+  IF duplicates > 0 THEN
+    RAISE EXCEPTION
+      'gottcha2_results has % (sample_id, taxid, level) groups with more than one row; remove the extra rows and rerun migrations',
+      duplicates;
+  END IF;
+END
+$$;
+
+CREATE UNIQUE INDEX IF NOT EXISTS idx_gottcha2_sample_taxid_level
+  ON gottcha2_results (sample_id, taxid, level);
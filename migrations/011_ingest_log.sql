@@ -0,0 +1,8 @@
+-- One row per upload sent with an Idempotency-Key header, recorded once it
+-- succeeds, so a retried upload is answered from here without re-parsing
+CREATE TABLE IF NOT EXISTS ingest_log (
+  idempotency_key TEXT PRIMARY KEY,
+  endpoint TEXT NOT NULL,
+  inserted BIGINT NOT NULL,
+  created_at TIMESTAMPTZ NOT NULL DEFAULT NOW()
+);
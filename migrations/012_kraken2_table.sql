@@ -0,0 +1,24 @@
+-- Kraken2 reports: one row per taxon a sample's reads were classified to,
+-- with the same optional provenance columns as the other result tables
+CREATE TABLE IF NOT EXISTS kraken2_results (
+  id BIGSERIAL PRIMARY KEY,
+  sample_id TEXT NOT NULL,
+  percentage DOUBLE PRECISION NOT NULL,
+  clade_reads BIGINT NOT NULL,
+  taxon_reads BIGINT NOT NULL,
+  rank_code TEXT NOT NULL,
+  taxid TEXT NOT NULL,
+  name TEXT NOT NULL,
+  raw_line TEXT,
+  source_line BIGINT,
+  content_hash TEXT,
+  observed_at TIMESTAMPTZ,
+  created_at TIMESTAMPTZ NOT NULL DEFAULT NOW()
+);
+
+-- A report lists each taxon once, so a retried upload can be inserted with
+-- ON CONFLICT DO NOTHING, as for GOTTCHA2
+CREATE UNIQUE INDEX IF NOT EXISTS idx_kraken2_sample_taxid
+  ON kraken2_results (sample_id, taxid);
+
+CREATE INDEX IF NOT EXISTS idx_kraken2_taxid ON kraken2_results(taxid);
@@ -0,0 +1,6 @@
+-- An upload now claims its Idempotency-Key before it is processed, leaving
+-- inserted NULL until it succeeds, so two uploads racing under one key can't
+-- both run. Keys are scoped to the endpoint they were sent to.
+ALTER TABLE ingest_log ALTER COLUMN inserted DROP NOT NULL;
+ALTER TABLE ingest_log DROP CONSTRAINT ingest_log_pkey;
+ALTER TABLE ingest_log ADD PRIMARY KEY (endpoint, idempotency_key);
@@ -1,50 +1,947 @@
-use std::path::PathBuf;
+use std::{
+    net::{IpAddr, Ipv4Addr},
+    path::PathBuf,
+    sync::Arc,
+};
 
-use color_eyre::eyre::Result;
-use rustls::ServerConfig;
-use rustls_pemfile::{certs, pkcs8_private_keys};
-use serde::Deserialize;
+use color_eyre::eyre::{Result, eyre};
+use rustls::{
+    ServerConfig,
+    crypto::ring::Ticketer,
+    server::{NoServerSessionStorage, ServerSessionMemoryCache},
+};
+use rustls_pemfile::{certs, private_key};
+use serde::{Deserialize, Deserializer};
 
+use crate::{
+    db::{
+        health,
+        operations::{DEFAULT_INSERT_MAX_RETRIES, effective_batch_size},
+    },
+    models::record::{BulkInsertable, DummyRecord, Gottcha2FullRecord, Kraken2Record, StastRecord},
+    tls::SniAllowList,
+};
+
+/// What to do with a record that deserializes but fails validation.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum InvalidRowPolicy {
+    /// Fail the whole upload on the first invalid record
+    #[default]
+    Reject,
+    /// Drop invalid or malformed records, log them, and insert the rest
+    Skip,
+}
+
+/// When an upload's `X-Expected-Records` is compared with what it held.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum RecordCountCheck {
+    /// Once the upload is inserted; a mismatch is reported but its rows stay
+    #[default]
+    After,
+    /// Before anything commits: the upload is inserted in one transaction,
+    /// rolled back on a mismatch
+    Before,
+}
+
+/// How startup treats the embedded schema migrations.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum MigrationMode {
+    /// Apply any pending migrations
+    #[default]
+    Auto,
+    /// Refuse to start unless every migration has already been applied
+    Verify,
+    /// Don't touch or check the schema
+    Skip,
+}
+
+/// Transaction isolation each batch insert runs under.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum IsolationLevel {
+    /// Postgres's default; each batch is a single autocommitted statement
+    #[default]
+    ReadCommitted,
+    RepeatableRead,
+    Serializable,
+}
+
+impl IsolationLevel {
+    /// The level as spelled in `SET TRANSACTION ISOLATION LEVEL`.
+    #[must_use]
+    pub fn as_sql(self) -> &'static str {
+        match self {
+            IsolationLevel::ReadCommitted => "READ COMMITTED",
+            IsolationLevel::RepeatableRead => "REPEATABLE READ",
+            IsolationLevel::Serializable => "SERIALIZABLE",
+        }
+    }
+}
+
+/// How batches are written to tables that allow a choice.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum InsertMethod {
+    /// Multi-row `INSERT ... VALUES` with bound parameters
+    #[default]
+    Values,
+    /// `COPY ... FROM STDIN`, for record types without an ON CONFLICT
+    /// clause; the rest still use VALUES
+    Copy,
+}
+
+/// Where the ingest bearer token is read from.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum TokenSource {
+    /// The `INGEST_TOKEN` environment variable
+    #[default]
+    Env,
+    /// A Vault secret, fetched at startup
+    Vault,
+}
+
+// Feature flags from the environment are naturally independent booleans
+#[allow(clippy::struct_excessive_bools)]
 #[derive(Deserialize, Clone)]
 pub struct AppConfig {
     pub database_url: String,
+    /// How long startup keeps retrying, with backoff, to reach the database
+    /// before giving up; `0` tries once
+    #[serde(default = "default_db_connect_timeout_secs")]
+    pub db_connect_timeout_secs: u64,
+    /// Most connections the pool opens to the database
+    #[serde(
+        default = "default_db_max_connections",
+        alias = "database_max_connections"
+    )]
+    pub db_max_connections: u32,
+    /// Connections the pool keeps open even when idle, so bursts don't wait
+    /// on fresh connections; at most `db_max_connections`
+    #[serde(
+        default = "default_db_min_connections",
+        alias = "database_min_connections"
+    )]
+    pub db_min_connections: u32,
+    /// How long a request waits for a free pooled connection before failing
+    /// with `503`
+    #[serde(default = "default_db_acquire_timeout_secs")]
+    pub db_acquire_timeout_secs: u64,
+    /// `auto` runs pending migrations at startup; `verify` only checks
+    /// they've been applied (for deployments that migrate in a separate job);
+    /// `skip` does neither
+    #[serde(default)]
+    pub migration_mode: MigrationMode,
+    /// Statement `/readyz` runs to check the database, for Postgres-compatible
+    /// backends where a different probe is wanted. Must be read-only.
+    #[serde(
+        default = "default_health_check_query",
+        deserialize_with = "read_only_query"
+    )]
+    pub health_check_query: String,
+    /// Required unless `INGEST_TOKENS` is set or `INGEST_TOKEN_SOURCE`
+    /// fetches it from elsewhere
+    #[serde(default)]
     pub ingest_token: String,
+    /// Every accepted ingest token, comma-separated, so a new token can be
+    /// deployed alongside the old one while clients roll over. Takes the
+    /// place of `INGEST_TOKEN` when set.
+    #[serde(default)]
+    pub ingest_tokens: Vec<String>,
+    /// `env` reads `INGEST_TOKEN`; `vault` fetches the token from
+    /// `VAULT_ADDR` at startup
+    #[serde(default)]
+    pub ingest_token_source: TokenSource,
+    /// Base URL of the Vault server, e.g. `https://vault.internal:8200`
+    #[serde(default)]
+    pub vault_addr: Option<String>,
+    /// Token the server authenticates to Vault with
+    #[serde(default)]
+    pub vault_token: Option<String>,
+    /// API path of the secret under `/v1/`, e.g. `secret/data/nvd-support-car`
+    #[serde(default)]
+    pub vault_secret_path: Option<String>,
+    /// Key within the secret that holds the ingest token
+    #[serde(default = "default_vault_secret_field")]
+    pub vault_secret_field: String,
+    /// Re-fetch the ingest token from its secrets backend this often, so a
+    /// rotated token takes effect without a restart. Unset fetches only at
+    /// startup.
+    #[serde(default)]
+    pub ingest_token_refresh_secs: Option<u64>,
+    /// Token for the `/admin` endpoints, which answer `404` when it is unset
+    #[serde(default)]
+    pub admin_token: Option<String>,
+    /// Explanation sent to clients turned away while ingestion is paused,
+    /// unless the pause request gives its own
+    #[serde(default)]
+    pub maintenance_message: Option<String>,
+    /// Interface both listeners bind to, e.g. `127.0.0.1` or `::`. Defaults
+    /// to every IPv4 interface.
+    #[serde(default = "default_bind_address", deserialize_with = "ip_address")]
+    pub bind_address: IpAddr,
     pub server_port: u16,
-    pub cert_path: PathBuf,
-    pub key_path: PathBuf,
-    #[allow(dead_code)]
-    pub rate_limit_rps: usize,
+    /// Optional second, plaintext listener sharing the same router, for
+    /// internal clients during a phased TLS rollout. Insecure.
+    #[serde(default)]
+    pub http_port: Option<u16>,
+    /// Serve `server_port` over TLS. Turning this off serves it as plaintext
+    /// HTTP instead, for local development only, and makes the certificate
+    /// paths optional.
+    #[serde(default = "default_true")]
+    pub tls_enabled: bool,
+    /// PEM certificate chain and private key; required while `tls_enabled`
+    #[serde(default)]
+    pub cert_path: Option<PathBuf>,
+    #[serde(default)]
+    pub key_path: Option<PathBuf>,
+    /// Sustained requests per second each client may make to governed
+    /// routes. Must not be zero.
+    #[serde(default = "default_rate_limit_rps")]
+    pub rate_limit_rps: u32,
+    /// How many requests a client may make at once before the sustained
+    /// rate applies. Defaults to twice `rate_limit_rps`; must not be zero.
+    #[serde(default)]
+    pub rate_limit_burst: Option<u32>,
+    // Per-table batch size overrides, falling back to `batch_size` (500 by
+    // default). Each is capped at the widest batch that fits under
+    // Postgres's bind parameter limit.
+    #[serde(default = "default_batch_size")]
+    pub batch_size: usize,
+    #[serde(default)]
+    pub dummy_batch_size: Option<usize>,
+    #[serde(default)]
+    pub gottcha2_batch_size: Option<usize>,
+    #[serde(default)]
+    pub stast_batch_size: Option<usize>,
+    #[serde(default)]
+    pub kraken2_batch_size: Option<usize>,
+    /// `read-committed` (the default), `repeatable-read`, or `serializable`.
+    /// Stricter levels run each batch in its own transaction and retry it
+    /// when Postgres reports a serialization failure or deadlock.
+    #[serde(default)]
+    pub ingest_isolation_level: IsolationLevel,
+    /// `values` (the default) or `copy`, which loads STAST batches, and any
+    /// other type without an ON CONFLICT clause, with COPY
+    #[serde(default)]
+    pub insert_method: InsertMethod,
+    /// Insert each upload in one transaction, committed only once it has
+    /// been parsed in full, so a failed upload leaves none of its rows
+    /// behind. Off by default, so very large uploads commit batch by batch.
+    #[serde(default)]
+    pub atomic_ingest: bool,
+    /// Times a batch is retried, with backoff, after a transient failure
+    /// such as a lost connection or a serialization failure
+    #[serde(default = "default_insert_max_retries")]
+    pub insert_max_retries: u32,
+    /// Batches of one upload inserted concurrently, each on its own pooled
+    /// connection; 1 inserts them one at a time
+    #[serde(default = "default_insert_parallelism")]
+    pub insert_parallelism: usize,
+    /// Largest single JSONL line, in bytes, the parser will buffer
+    #[serde(default = "default_max_line_bytes")]
+    pub max_line_bytes: usize,
+    /// Most bytes an upload may expand to once decompressed, so a small
+    /// compression bomb can't keep the server parsing indefinitely
+    #[serde(default = "default_max_decompressed_bytes")]
+    pub max_decompressed_bytes: u64,
+    /// Reject GOTTCHA2 levels, STAST ranks, and Kraken2 rank codes outside
+    /// the known set
+    #[serde(default)]
+    pub strict_taxonomic_levels: bool,
+    /// Tokio worker threads; defaults to the detected core count
+    #[serde(default)]
+    pub worker_threads: Option<usize>,
+    /// Skip the startup storage check, e.g. in sandboxes that block it
+    #[serde(default)]
+    pub assume_ssd: bool,
+    /// Let a request send `X-Debug-SQL: true` to have its INSERTs logged
+    #[serde(default)]
+    pub debug_sql: bool,
+    /// Unsafe: include bound values, i.e. the uploaded data, when logging a
+    /// request's SQL
+    #[serde(default)]
+    pub debug_sql_params: bool,
+    /// Log a snapshot of the metrics every this many seconds, for
+    /// deployments without a Prometheus scraper; unset disables it
+    #[serde(default)]
+    pub metrics_log_secs: Option<u64>,
+    /// Add `X-Server-Version` (crate version and git hash) to every response
+    #[serde(default)]
+    pub server_version_header: bool,
+    /// Overall time limit for a request, including streaming its body
+    #[serde(default = "default_request_timeout_secs")]
+    pub request_timeout_secs: u64,
+    /// How long in-flight requests may keep running after a shutdown signal
+    #[serde(default = "default_shutdown_grace_secs")]
+    pub shutdown_grace_secs: u64,
+    /// Abort an upload once no body bytes have arrived for this long
+    #[serde(default = "default_read_idle_secs")]
+    pub read_idle_secs: u64,
+    /// Debugging only: tee every accepted record to this JSONL file as well
+    /// as inserting it. Leave unset in production.
+    #[serde(default)]
+    pub debug_sink_path: Option<PathBuf>,
+    /// Test/staging only: let inserts fail or stall at random. Off unless set.
+    #[serde(default)]
+    pub fault_injection: bool,
+    /// With `fault_injection`, the probability an insert fails transiently
+    #[serde(default)]
+    pub fault_failure_rate: f64,
+    /// With `fault_injection`, the probability an insert is delayed
+    #[serde(default)]
+    pub fault_delay_rate: f64,
+    /// With `fault_injection`, how long a delayed insert waits
+    #[serde(default)]
+    pub fault_delay_ms: u64,
+    /// Treat `/ingest` idempotency keys older than this as new, and prune
+    /// them in the background. Unset means keys never expire.
+    #[serde(default)]
+    pub idempotency_ttl_secs: Option<u64>,
+    /// Store each GOTTCHA2/STAST/Kraken2 row's original JSONL line in its
+    /// `raw_line` column. Costs storage, so it's off by default.
+    #[serde(default)]
+    pub store_raw_line: bool,
+    /// Store a blake3 hash of each GOTTCHA2/STAST/Kraken2 record's fields in
+    /// its `content_hash` column, so re-ingests can be compared cheaply
+    #[serde(default)]
+    pub store_content_hash: bool,
+    /// `reject` fails an upload on its first invalid row; `skip` drops
+    /// invalid rows and inserts the rest
+    #[serde(default)]
+    pub on_invalid_row: InvalidRowPolicy,
+    /// Keep the lines `ON_INVALID_ROW=skip` drops, with their errors, for
+    /// `GET /dead-letters`
+    #[serde(default)]
+    pub store_dead_letters: bool,
+    /// Most dead letters one `GET /dead-letters` may return, whatever its
+    /// `limit`
+    #[serde(default = "default_dead_letters_max_limit")]
+    pub dead_letters_max_limit: u32,
+    /// Whether an upload whose `X-Expected-Records` doesn't match is
+    /// rejected `before` or `after` its rows are committed
+    #[serde(default)]
+    pub expected_records_check: RecordCountCheck,
+    /// Most taxids a `?distinct_taxids=true` STAST response lists
+    #[serde(default = "default_max_distinct_taxids")]
+    pub max_distinct_taxids: usize,
+    /// Zero-fill numeric GOTTCHA2 fields that older tool versions omit
+    /// instead of rejecting the line
+    #[serde(default)]
+    pub fill_missing_fields: bool,
+    /// Store each GOTTCHA2/STAST/Kraken2 row's line number in its
+    /// `source_line` column so exports can return rows in their original
+    /// file order
+    #[serde(default)]
+    pub preserve_input_order: bool,
+    /// Lowercase sample IDs on ingest and in queries, for pipelines whose
+    /// sample names differ only in case
+    #[serde(default)]
+    pub lowercase_sample_ids: bool,
+    /// Fail an upload with a line-numbered `400` as soon as a
+    /// GOTTCHA2/STAST/Kraken2 record's `sample_id` is missing or blank
+    #[serde(default)]
+    pub require_sample_id: bool,
+    /// Fail an upload whose last line doesn't end in a newline, treating it
+    /// as a possibly truncated transfer rather than a complete record
+    #[serde(default)]
+    pub strict_final_newline: bool,
+    /// Drop lines that exactly repeat an earlier line of the same upload
+    #[serde(default)]
+    pub dedup_identical_lines: bool,
+    /// Fail an upload with a `400` citing both lines when two of its records
+    /// share a key that should be unique, such as STAST's
+    /// `(sample_id, qseqid, sseqid)`
+    #[serde(default)]
+    pub strict_duplicate_keys: bool,
+    /// Respond to `/ingest` with received/inserted/deduplicated counts rather
+    /// than a bare `ingested`
+    #[serde(default = "default_true")]
+    pub echo_idempotency_stats: bool,
+    /// Ingests a single bearer token may have in flight at once; further
+    /// uploads with that token get `429` until one finishes. Unset means no
+    /// per-token limit.
+    #[serde(default)]
+    pub max_concurrent_ingests_per_token: Option<usize>,
+    /// Count and export requests allowed to run at once, independent of the
+    /// ingest limits; further reads get `503` until one finishes. Unset means
+    /// no limit.
+    #[serde(default)]
+    pub max_concurrent_reads: Option<usize>,
+    /// TLS sessions remembered for resumption by session ID; `0` disables
+    /// the cache
+    #[serde(default = "default_tls_session_cache_size")]
+    pub tls_session_cache_size: usize,
+    /// Issue TLS session tickets so clients can resume without the server
+    /// keeping per-session state
+    #[serde(default = "default_true")]
+    pub tls_session_tickets: bool,
+    /// Host names clients must offer via SNI, comma-separated; handshakes
+    /// naming any other host, or none, are refused. Empty accepts any SNI.
+    #[serde(default)]
+    pub tls_allowed_server_names: Vec<String>,
+    /// TLS handshakes allowed to run at once
+    #[serde(default = "default_max_concurrent_handshakes")]
+    pub max_concurrent_handshakes: usize,
+    /// How long a connection may wait for a handshake slot before it's dropped
+    #[serde(default = "default_handshake_queue_timeout_ms")]
+    pub handshake_queue_timeout_ms: u64,
+    /// Bytes a request's line and headers may take up; larger requests get
+    /// `431`. Values under 8 KiB are raised to 8 KiB.
+    #[serde(default = "default_max_header_bytes")]
+    pub max_header_bytes: usize,
+    /// Headers a request may carry; more get `431`. HTTP/1 only, as HTTP/2
+    /// is bounded by `max_header_bytes` alone.
+    #[serde(default = "default_max_header_count")]
+    pub max_header_count: usize,
+}
+
+fn default_batch_size() -> usize {
+    500
+}
+
+fn default_rate_limit_rps() -> u32 {
+    200
+}
+
+fn default_max_line_bytes() -> usize {
+    16 * 1024 * 1024
+}
+
+fn default_max_decompressed_bytes() -> u64 {
+    2 * 1024 * 1024 * 1024
+}
+
+fn default_max_distinct_taxids() -> usize {
+    1000
+}
+
+fn default_insert_max_retries() -> u32 {
+    DEFAULT_INSERT_MAX_RETRIES
+}
+
+fn default_insert_parallelism() -> usize {
+    1
+}
+
+fn default_dead_letters_max_limit() -> u32 {
+    1000
+}
+
+fn default_tls_session_cache_size() -> usize {
+    1024
+}
+
+fn default_vault_secret_field() -> String {
+    "token".to_string()
+}
+
+fn default_health_check_query() -> String {
+    "SELECT 1".to_string()
+}
+
+/// Rejects a `HEALTH_CHECK_QUERY` that could modify data, at startup rather
+/// than on the first probe.
+fn read_only_query<'de, D: Deserializer<'de>>(deserializer: D) -> Result<String, D::Error> {
+    let query = String::deserialize(deserializer)?;
+    health::validate_read_only(&query).map_err(serde::de::Error::custom)?;
+    Ok(query)
+}
+
+fn default_bind_address() -> IpAddr {
+    IpAddr::V4(Ipv4Addr::UNSPECIFIED)
+}
+
+/// Parses `BIND_ADDRESS`, naming the variable in the error so a typo isn't
+/// reported as a bare "invalid IP address syntax".
+fn parse_bind_address(raw: &str) -> Result<IpAddr, String> {
+    raw.trim()
+        .parse()
+        .map_err(|e| format!("BIND_ADDRESS {raw:?} is not an IP address: {e}"))
+}
+
+fn ip_address<'de, D: Deserializer<'de>>(deserializer: D) -> Result<IpAddr, D::Error> {
+    let raw = String::deserialize(deserializer)?;
+    parse_bind_address(&raw).map_err(serde::de::Error::custom)
+}
+
+fn default_db_connect_timeout_secs() -> u64 {
+    30
+}
+
+fn default_db_max_connections() -> u32 {
+    30
+}
+
+fn default_db_min_connections() -> u32 {
+    2
+}
+
+fn default_db_acquire_timeout_secs() -> u64 {
+    3
+}
+
+fn default_request_timeout_secs() -> u64 {
+    5
+}
+
+fn default_shutdown_grace_secs() -> u64 {
+    30
+}
+
+fn default_read_idle_secs() -> u64 {
+    5
+}
+
+fn default_true() -> bool {
+    true
+}
+
+fn default_max_concurrent_handshakes() -> usize {
+    64
+}
+
+fn default_handshake_queue_timeout_ms() -> u64 {
+    1000
+}
+
+fn default_max_header_bytes() -> usize {
+    64 * 1024
+}
+
+fn default_max_header_count() -> usize {
+    100
+}
+
+impl Default for AppConfig {
+    fn default() -> Self {
+        AppConfig {
+            database_url: String::new(),
+            db_connect_timeout_secs: default_db_connect_timeout_secs(),
+            db_max_connections: default_db_max_connections(),
+            db_min_connections: default_db_min_connections(),
+            db_acquire_timeout_secs: default_db_acquire_timeout_secs(),
+            migration_mode: MigrationMode::Auto,
+            health_check_query: default_health_check_query(),
+            ingest_token: String::new(),
+            ingest_tokens: Vec::new(),
+            ingest_token_source: TokenSource::Env,
+            vault_addr: None,
+            vault_token: None,
+            vault_secret_path: None,
+            vault_secret_field: default_vault_secret_field(),
+            ingest_token_refresh_secs: None,
+            admin_token: None,
+            maintenance_message: None,
+            bind_address: default_bind_address(),
+            server_port: 0,
+            http_port: None,
+            tls_enabled: true,
+            cert_path: None,
+            key_path: None,
+            rate_limit_rps: default_rate_limit_rps(),
+            rate_limit_burst: None,
+            batch_size: default_batch_size(),
+            dummy_batch_size: None,
+            gottcha2_batch_size: None,
+            stast_batch_size: None,
+            kraken2_batch_size: None,
+            ingest_isolation_level: IsolationLevel::ReadCommitted,
+            insert_method: InsertMethod::Values,
+            atomic_ingest: false,
+            insert_max_retries: default_insert_max_retries(),
+            insert_parallelism: default_insert_parallelism(),
+            max_line_bytes: default_max_line_bytes(),
+            max_decompressed_bytes: default_max_decompressed_bytes(),
+            strict_taxonomic_levels: false,
+            worker_threads: None,
+            assume_ssd: false,
+            debug_sql: false,
+            debug_sql_params: false,
+            metrics_log_secs: None,
+            server_version_header: false,
+            request_timeout_secs: default_request_timeout_secs(),
+            shutdown_grace_secs: default_shutdown_grace_secs(),
+            read_idle_secs: default_read_idle_secs(),
+            debug_sink_path: None,
+            fault_injection: false,
+            fault_failure_rate: 0.0,
+            fault_delay_rate: 0.0,
+            fault_delay_ms: 0,
+            idempotency_ttl_secs: None,
+            store_raw_line: false,
+            store_content_hash: false,
+            on_invalid_row: InvalidRowPolicy::Reject,
+            store_dead_letters: false,
+            dead_letters_max_limit: default_dead_letters_max_limit(),
+            expected_records_check: RecordCountCheck::After,
+            max_distinct_taxids: default_max_distinct_taxids(),
+            fill_missing_fields: false,
+            preserve_input_order: false,
+            lowercase_sample_ids: false,
+            require_sample_id: false,
+            strict_final_newline: false,
+            dedup_identical_lines: false,
+            strict_duplicate_keys: false,
+            echo_idempotency_stats: default_true(),
+            max_concurrent_ingests_per_token: None,
+            max_concurrent_reads: None,
+            tls_session_cache_size: default_tls_session_cache_size(),
+            tls_session_tickets: true,
+            tls_allowed_server_names: Vec::new(),
+            max_concurrent_handshakes: default_max_concurrent_handshakes(),
+            handshake_queue_timeout_ms: default_handshake_queue_timeout_ms(),
+            max_header_bytes: default_max_header_bytes(),
+            max_header_count: default_max_header_count(),
+        }
+    }
+}
+
+/// Rejects a `BATCH_SIZE` of zero or one too large for every table's rows
+/// to fit under Postgres's bind parameter limit. Unlike the per-table
+/// overrides, which are capped with a warning, the shared size must suit
+/// every table as given.
+fn check_batch_size(batch_size: usize) -> Result<(), envy::Error> {
+    let max = DummyRecord::max_batch_size()
+        .min(Gottcha2FullRecord::max_batch_size())
+        .min(StastRecord::max_batch_size())
+        .min(Kraken2Record::max_batch_size());
+    if !(1..=max).contains(&batch_size) {
+        return Err(envy::Error::Custom(format!(
+            "BATCH_SIZE must be between 1 and {max} to stay under Postgres's bind parameter limit; got {batch_size}"
+        )));
+    }
+    Ok(())
+}
+
+/// Rejects a zero `RATE_LIMIT_RPS` or `RATE_LIMIT_BURST`, which would
+/// otherwise only surface as an opaque governor error at startup.
+fn check_rate_limit(rps: u32, burst: Option<u32>) -> Result<(), envy::Error> {
+    if rps == 0 {
+        return Err(envy::Error::Custom(
+            "RATE_LIMIT_RPS must be at least 1".to_string(),
+        ));
+    }
+    if burst == Some(0) {
+        return Err(envy::Error::Custom(
+            "RATE_LIMIT_BURST must be at least 1".to_string(),
+        ));
+    }
+    Ok(())
+}
+
+fn check_pool_size(max: u32, min: u32) -> Result<(), envy::Error> {
+    if max == 0 {
+        return Err(envy::Error::Custom(
+            "DB_MAX_CONNECTIONS must be at least 1".to_string(),
+        ));
+    }
+    if min > max {
+        return Err(envy::Error::Custom(format!(
+            "DB_MIN_CONNECTIONS ({min}) must not exceed DB_MAX_CONNECTIONS ({max})"
+        )));
+    }
+    Ok(())
+}
+
+/// Rejects an `INSERT_PARALLELISM` of zero, or one that no single upload
+/// could reach without outgrowing the pool.
+fn check_insert_parallelism(parallelism: usize, max_connections: u32) -> Result<(), envy::Error> {
+    if parallelism == 0 || parallelism > max_connections as usize {
+        return Err(envy::Error::Custom(format!(
+            "INSERT_PARALLELISM must be between 1 and DB_MAX_CONNECTIONS ({max_connections}); got {parallelism}"
+        )));
+    }
+    Ok(())
 }
 
 impl AppConfig {
+    /// The batch size `T` is inserted in: its table's override if set, and
+    /// `BATCH_SIZE` otherwise, capped to fit under the bind parameter limit.
+    #[must_use]
+    pub fn batch_size_for<T: BulkInsertable>(&self, table_override: Option<usize>) -> usize {
+        effective_batch_size::<T>(Some(table_override.unwrap_or(self.batch_size)))
+    }
+
+    /// The bearer tokens ingest requests may present: `INGEST_TOKENS` if
+    /// set, otherwise `INGEST_TOKEN` alone. Blank entries are dropped.
+    #[must_use]
+    pub fn accepted_ingest_tokens(&self) -> Vec<String> {
+        let tokens =
+            if self.ingest_token_source == TokenSource::Env && !self.ingest_tokens.is_empty() {
+                self.ingest_tokens.as_slice()
+            } else {
+                std::slice::from_ref(&self.ingest_token)
+            };
+        tokens
+            .iter()
+            .map(|token| token.trim())
+            .filter(|token| !token.is_empty())
+            .map(str::to_string)
+            .collect()
+    }
+
+    /// The governor's burst size: `RATE_LIMIT_BURST`, or else twice the
+    /// sustained rate.
+    #[must_use]
+    pub fn rate_limit_burst(&self) -> u32 {
+        self.rate_limit_burst
+            .unwrap_or_else(|| self.rate_limit_rps.saturating_mul(2))
+    }
+
     /// Creates a new `AppConfig` by reading from environment variables.
     ///
     /// # Errors
     ///
     /// Returns an error if required environment variables are missing or invalid.
     pub fn new_from_env() -> Result<Self, envy::Error> {
-        envy::from_env()
+        let config: Self = envy::from_env()?;
+        if config.ingest_token_source == TokenSource::Env
+            && config.accepted_ingest_tokens().is_empty()
+        {
+            return Err(envy::Error::MissingValue("ingest_token"));
+        }
+        check_batch_size(config.batch_size)?;
+        check_rate_limit(config.rate_limit_rps, config.rate_limit_burst)?;
+        check_pool_size(config.db_max_connections, config.db_min_connections)?;
+        check_insert_parallelism(config.insert_parallelism, config.db_max_connections)?;
+        if config.tls_enabled {
+            if config.cert_path.is_none() {
+                return Err(envy::Error::MissingValue("cert_path"));
+            }
+            if config.key_path.is_none() {
+                return Err(envy::Error::MissingValue("key_path"));
+            }
+        }
+        Ok(config)
     }
 
-    /// Loads TLS configuration from certificate and key files.
+    /// Loads TLS configuration from certificate and key files, with session
+    /// resumption set up per `TLS_SESSION_CACHE_SIZE` and
+    /// `TLS_SESSION_TICKETS`, and SNI restricted to
+    /// `TLS_ALLOWED_SERVER_NAMES` if set.
     ///
     /// # Errors
     ///
-    /// Returns an error if certificate or key files cannot be read or parsed.
-    #[allow(dead_code)]
+    /// Returns an error if either path is unset, the certificate or key files
+    /// cannot be read or parsed, or the ticket keys cannot be generated.
     pub fn load_tls_config(&self) -> Result<ServerConfig> {
-        let cert_file = std::fs::File::open(&self.cert_path)?;
+        let (Some(cert_path), Some(key_path)) = (&self.cert_path, &self.key_path) else {
+            return Err(eyre!("TLS needs both CERT_PATH and KEY_PATH"));
+        };
+        let cert_file = std::fs::File::open(cert_path)?;
         let mut cert_reader = std::io::BufReader::new(cert_file);
         let certs = certs(&mut cert_reader).collect::<Result<Vec<_>, _>>()?;
 
-        let key_file = std::fs::File::open(&self.key_path)?;
+        let key_file = std::fs::File::open(key_path)?;
         let mut key_reader = std::io::BufReader::new(key_file);
-        let keys = pkcs8_private_keys(&mut key_reader).collect::<Result<Vec<_>, _>>()?;
+        let key = private_key(&mut key_reader)?
+            .ok_or_else(|| eyre!("no private key found in {}", key_path.display()))?;
 
-        let config = ServerConfig::builder()
+        let mut config = ServerConfig::builder()
             .with_no_client_auth()
-            .with_single_cert(certs, keys[0].clone_key().into())?;
+            .with_single_cert(certs, key)?;
+        config.alpn_protocols = vec![b"h2".to_vec(), b"http/1.1".to_vec()];
+        if !self.tls_allowed_server_names.is_empty() {
+            config.cert_resolver = Arc::new(SniAllowList::new(
+                Arc::clone(&config.cert_resolver),
+                self.tls_allowed_server_names.clone(),
+            ));
+        }
+
+        // Stateful resumption: a bounded in-memory cache of session IDs
+        config.session_storage = if self.tls_session_cache_size == 0 {
+            Arc::new(NoServerSessionStorage {})
+        } else {
+            ServerSessionMemoryCache::new(self.tls_session_cache_size)
+        };
+        // Stateless resumption: tickets encrypted under keys that rotate
+        // every 6 hours, so a leaked key exposes at most a window of sessions
+        if self.tls_session_tickets {
+            config.ticketer = Ticketer::new()?;
+        }
 
         Ok(config)
     }
+
+    /// Applies `MAX_HEADER_BYTES` and `MAX_HEADER_COUNT` to `server`'s
+    /// connections, so hyper answers oversized requests with `431` before
+    /// they reach the router.
+    pub fn limit_headers<A>(&self, server: &mut axum_server::Server<A>) {
+        // hyper panics on an HTTP/1 buffer smaller than this
+        const MIN_HEADER_BYTES: usize = 8 * 1024;
+
+        let max_bytes = self.max_header_bytes.max(MIN_HEADER_BYTES);
+        let builder = server.http_builder();
+        builder
+            .http1()
+            .max_buf_size(max_bytes)
+            .max_headers(self.max_header_count);
+        builder
+            .http2()
+            .max_header_list_size(u32::try_from(max_bytes).unwrap_or(u32::MAX));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn batch_size_must_fit_every_table() {
+        let max = DummyRecord::max_batch_size()
+            .min(Gottcha2FullRecord::max_batch_size())
+            .min(StastRecord::max_batch_size())
+            .min(Kraken2Record::max_batch_size());
+        assert!(check_batch_size(default_batch_size()).is_ok());
+        assert!(check_batch_size(1).is_ok());
+        assert!(check_batch_size(max).is_ok());
+        assert!(check_batch_size(0).is_err());
+        assert!(check_batch_size(max + 1).is_err());
+    }
+
+    #[test]
+    fn tables_fall_back_to_batch_size() {
+        let config = AppConfig::default();
+        assert_eq!(config.batch_size_for::<StastRecord>(None), 500);
+        assert_eq!(config.batch_size_for::<StastRecord>(Some(250)), 250);
+        assert_eq!(
+            config.batch_size_for::<StastRecord>(Some(1_000_000)),
+            StastRecord::max_batch_size()
+        );
+    }
+
+    #[test]
+    fn bind_address_accepts_ipv4_and_ipv6() {
+        assert_eq!(
+            parse_bind_address("127.0.0.1"),
+            Ok(IpAddr::V4(Ipv4Addr::LOCALHOST))
+        );
+        assert_eq!(
+            parse_bind_address("::1"),
+            Ok(IpAddr::V6(std::net::Ipv6Addr::LOCALHOST))
+        );
+        assert_eq!(parse_bind_address("::"), Ok("::".parse().expect("valid")));
+        let error = parse_bind_address("localhost").expect_err("not an address");
+        assert!(error.contains("BIND_ADDRESS"), "{error}");
+    }
+
+    #[test]
+    fn ingest_tokens_take_the_place_of_ingest_token() {
+        let config = AppConfig {
+            ingest_token: "single".to_string(),
+            ..AppConfig::default()
+        };
+        assert_eq!(config.accepted_ingest_tokens(), ["single"]);
+
+        let config = AppConfig {
+            ingest_tokens: vec!["old".to_string(), " new ".to_string(), String::new()],
+            ..config
+        };
+        assert_eq!(config.accepted_ingest_tokens(), ["old", "new"]);
+    }
+
+    #[test]
+    fn zero_rate_limits_are_rejected() {
+        assert!(check_rate_limit(1, None).is_ok());
+        assert!(check_rate_limit(1, Some(1)).is_ok());
+        assert!(check_rate_limit(0, None).is_err());
+        assert!(check_rate_limit(200, Some(0)).is_err());
+    }
+
+    #[test]
+    fn pool_minimum_may_not_exceed_its_maximum() {
+        assert!(check_pool_size(30, 2).is_ok());
+        assert!(check_pool_size(4, 4).is_ok());
+        assert!(check_pool_size(0, 0).is_err());
+        assert!(matches!(
+            check_pool_size(4, 8),
+            Err(envy::Error::Custom(ref msg)) if msg.contains("DB_MIN_CONNECTIONS (8)")
+        ));
+    }
+
+    #[test]
+    fn insert_parallelism_must_fit_the_pool() {
+        assert!(check_insert_parallelism(1, 30).is_ok());
+        assert!(check_insert_parallelism(30, 30).is_ok());
+        assert!(check_insert_parallelism(0, 30).is_err());
+        assert!(check_insert_parallelism(31, 30).is_err());
+    }
+
+    #[test]
+    fn pool_size_falls_back_to_older_variable_names() {
+        let env = |vars: &[(&str, &str)]| {
+            let base = [
+                ("DATABASE_URL", "postgres://localhost/db"),
+                ("INGEST_TOKEN", "t"),
+                ("SERVER_PORT", "8443"),
+                ("CERT_PATH", "cert.pem"),
+                ("KEY_PATH", "key.pem"),
+            ];
+            envy::from_iter::<_, AppConfig>(
+                base.iter()
+                    .chain(vars)
+                    .map(|(k, v)| ((*k).to_string(), (*v).to_string())),
+            )
+            .expect("Failed to parse config")
+        };
+
+        let defaults = env(&[]);
+        assert_eq!(
+            (defaults.db_max_connections, defaults.db_min_connections),
+            (30, 2)
+        );
+        let renamed = env(&[
+            ("DATABASE_MAX_CONNECTIONS", "10"),
+            ("DB_MIN_CONNECTIONS", "4"),
+        ]);
+        assert_eq!(
+            (renamed.db_max_connections, renamed.db_min_connections),
+            (10, 4)
+        );
+    }
+
+    #[test]
+    fn certificate_paths_are_required_only_with_tls() {
+        let load = |tls_enabled: &str| {
+            let vars = [
+                ("DATABASE_URL", "postgres://localhost/db"),
+                ("INGEST_TOKEN", "t"),
+                ("SERVER_PORT", "8080"),
+                ("TLS_ENABLED", tls_enabled),
+            ];
+            let config: AppConfig = envy::from_iter(
+                vars.iter()
+                    .map(|(k, v)| ((*k).to_string(), (*v).to_string())),
+            )
+            .expect("Failed to parse config");
+            config
+        };
+
+        let plaintext = load("false");
+        assert!(!plaintext.tls_enabled);
+        assert!(plaintext.cert_path.is_none());
+        assert!(plaintext.load_tls_config().is_err());
+
+        // `new_from_env` refuses a TLS listener without its certificate
+        let tls = load("true");
+        assert!(tls.tls_enabled && tls.cert_path.is_none());
+    }
+
+    #[test]
+    fn rate_limit_burst_defaults_to_twice_the_rate() {
+        let config = AppConfig {
+            rate_limit_rps: 50,
+            ..AppConfig::default()
+        };
+        assert_eq!(config.rate_limit_burst(), 100);
+        let config = AppConfig {
+            rate_limit_burst: Some(7),
+            ..config
+        };
+        assert_eq!(config.rate_limit_burst(), 7);
+    }
 }
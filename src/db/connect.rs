@@ -0,0 +1,48 @@
+use std::time::Duration;
+
+use sqlx::{PgPool, postgres::PgPoolOptions};
+use tokio::time::Instant;
+
+/// Delay before the first retry; doubled after each failure.
+const INITIAL_BACKOFF: Duration = Duration::from_millis(250);
+
+/// Longest delay between attempts, however many have failed.
+const MAX_BACKOFF: Duration = Duration::from_secs(5);
+
+/// Opens a pool on `url`, retrying with exponential backoff while the
+/// database is unreachable, e.g. because it's still starting alongside this
+/// service. Each failed attempt is logged. A `timeout` of zero makes a
+/// single attempt.
+///
+/// # Errors
+///
+/// Returns the last connection error once `timeout` has passed since the
+/// first attempt.
+pub async fn connect_with_backoff(
+    options: PgPoolOptions,
+    url: &str,
+    timeout: Duration,
+) -> Result<PgPool, sqlx::Error> {
+    let deadline = Instant::now() + timeout;
+    let mut backoff = INITIAL_BACKOFF;
+    let mut attempt = 1_u32;
+    loop {
+        match options.clone().connect(url).await {
+            Ok(pool) => return Ok(pool),
+            Err(e) => {
+                let remaining = deadline.saturating_duration_since(Instant::now());
+                if remaining.is_zero() {
+                    return Err(e);
+                }
+                let delay = backoff.min(remaining);
+                tracing::warn!(
+                    "Database connection attempt {attempt} failed: {e}; retrying in {}ms",
+                    delay.as_millis()
+                );
+                tokio::time::sleep(delay).await;
+                backoff = (backoff * 2).min(MAX_BACKOFF);
+                attempt += 1;
+            }
+        }
+    }
+}
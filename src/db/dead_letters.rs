@@ -0,0 +1,93 @@
+use futures_util::StreamExt;
+use sqlx::PgPool;
+use tokio::sync::mpsc;
+
+use super::queries::EXPORT_BUFFER;
+use crate::{
+    error::AppError,
+    models::dead_letter::{DeadLetter, DeadLetterRow},
+};
+
+/// Stores the lines an upload to `endpoint` skipped, in one statement.
+///
+/// # Errors
+///
+/// Returns an internal error if the insert fails.
+pub async fn insert_dead_letters(
+    db: &PgPool,
+    endpoint: &str,
+    letters: &[DeadLetter],
+) -> Result<u64, AppError> {
+    if letters.is_empty() {
+        return Ok(0);
+    }
+
+    let line_numbers: Vec<i64> = letters
+        .iter()
+        .map(|letter| i64::try_from(letter.line_number).unwrap_or(i64::MAX))
+        .collect();
+    let raw_lines: Vec<&str> = letters
+        .iter()
+        .map(|letter| letter.raw_line.as_str())
+        .collect();
+    let errors: Vec<&str> = letters.iter().map(|letter| letter.error.as_str()).collect();
+
+    sqlx::query(
+        "INSERT INTO dead_letters (endpoint, line_number, raw_line, error) \
+         SELECT $1, * FROM UNNEST($2::BIGINT[], $3::TEXT[], $4::TEXT[])",
+    )
+    .bind(endpoint)
+    .bind(line_numbers)
+    .bind(raw_lines)
+    .bind(errors)
+    .execute(db)
+    .await
+    .map(|result| result.rows_affected())
+    .map_err(|e| AppError::InternalServerError(format!("dead letter insert failed: {e}")))
+}
+
+/// Streams up to `limit` dead letters, oldest first, through the returned
+/// channel, optionally only those uploaded to `endpoint`. Like
+/// `stream_sample_rows`, the query runs on its own task and ends after
+/// forwarding the first error.
+#[must_use]
+pub fn stream_dead_letters(
+    db: PgPool,
+    endpoint: Option<String>,
+    limit: i64,
+) -> mpsc::Receiver<Result<DeadLetterRow, sqlx::Error>> {
+    let (tx, rx) = mpsc::channel(EXPORT_BUFFER);
+
+    tokio::spawn(async move {
+        let mut rows = sqlx::query_as::<_, DeadLetterRow>(
+            "SELECT id, endpoint, line_number, raw_line, error, created_at FROM dead_letters \
+             WHERE $1::TEXT IS NULL OR endpoint = $1 ORDER BY id LIMIT $2",
+        )
+        .bind(endpoint)
+        .bind(limit)
+        .fetch(&db);
+        while let Some(row) = rows.next().await {
+            let failed = row.is_err();
+            if tx.send(row).await.is_err() || failed {
+                break;
+            }
+        }
+    });
+
+    rx
+}
+
+/// Deletes every dead letter, or only those uploaded to `endpoint`, and
+/// returns how many were removed.
+///
+/// # Errors
+///
+/// Returns an internal error if the delete fails.
+pub async fn delete_dead_letters(db: &PgPool, endpoint: Option<&str>) -> Result<u64, AppError> {
+    sqlx::query("DELETE FROM dead_letters WHERE $1::TEXT IS NULL OR endpoint = $1")
+        .bind(endpoint)
+        .execute(db)
+        .await
+        .map(|result| result.rows_affected())
+        .map_err(|e| AppError::InternalServerError(format!("dead letter delete failed: {e}")))
+}
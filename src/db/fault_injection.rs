@@ -0,0 +1,67 @@
+use std::time::Duration;
+
+use crate::config::AppConfig;
+
+/// Simulated failures for exercising retry and degraded-mode paths in test
+/// and staging environments. Only built when `FAULT_INJECTION` is enabled, so
+/// production inserts never consult it.
+#[derive(Debug, Clone, Copy)]
+pub struct FaultInjection {
+    /// Probability, from 0.0 to 1.0, that an insert fails with a transient error
+    pub failure_rate: f64,
+    /// Probability, from 0.0 to 1.0, that an insert is held up by `delay`
+    pub delay_rate: f64,
+    /// How long a delayed insert waits before running
+    pub delay: Duration,
+}
+
+impl FaultInjection {
+    /// Returns the configured faults, or `None` unless injection is enabled.
+    #[must_use]
+    pub fn from_config(config: &AppConfig) -> Option<Self> {
+        config.fault_injection.then(|| FaultInjection {
+            failure_rate: config.fault_failure_rate.clamp(0.0, 1.0),
+            delay_rate: config.fault_delay_rate.clamp(0.0, 1.0),
+            delay: Duration::from_millis(config.fault_delay_ms),
+        })
+    }
+
+    /// Rolls the dice ahead of an insert, sleeping or failing as configured.
+    /// Failures look like a pool timeout so they take the same path a real
+    /// transient outage would.
+    pub(crate) async fn before_insert(&self) -> Result<(), sqlx::Error> {
+        if rand::random::<f64>() < self.delay_rate {
+            tokio::time::sleep(self.delay).await;
+        }
+        if rand::random::<f64>() < self.failure_rate {
+            tracing::warn!("Injecting a transient insert failure");
+            return Err(sqlx::Error::PoolTimedOut);
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn disabled_by_default() {
+        assert!(FaultInjection::from_config(&AppConfig::default()).is_none());
+    }
+
+    #[tokio::test]
+    async fn full_failure_rate_always_fails() {
+        let faults = FaultInjection {
+            failure_rate: 1.0,
+            delay_rate: 0.0,
+            delay: Duration::ZERO,
+        };
+        for _ in 0..10 {
+            assert!(matches!(
+                faults.before_insert().await,
+                Err(sqlx::Error::PoolTimedOut)
+            ));
+        }
+    }
+}
@@ -0,0 +1,91 @@
+use std::time::Duration;
+
+use sqlx::PgPool;
+
+use crate::error::AppError;
+
+/// How long the readiness query may take before the database counts as
+/// unavailable
+pub const READINESS_TIMEOUT: Duration = Duration::from_secs(2);
+
+/// Statement keywords a health check query may start with
+const READ_ONLY_KEYWORDS: &[&str] = &["SELECT", "SHOW", "VALUES"];
+
+/// Checks that `query` is a single statement that only reads, so a
+/// misconfigured probe can't modify data every few seconds. This is a
+/// conservative syntactic check: one statement (a trailing `;` is allowed)
+/// beginning with `SELECT`, `SHOW`, or `VALUES`.
+///
+/// # Errors
+///
+/// Returns a message explaining why the query was refused.
+pub fn validate_read_only(query: &str) -> Result<(), String> {
+    let statement = query.trim().trim_end_matches(';').trim_end();
+    if statement.contains(';') {
+        return Err(format!(
+            "health check query must be a single statement: {query:?}"
+        ));
+    }
+
+    let keyword = statement
+        .split(|c: char| !c.is_ascii_alphabetic())
+        .next()
+        .unwrap_or_default();
+    if READ_ONLY_KEYWORDS
+        .iter()
+        .any(|allowed| keyword.eq_ignore_ascii_case(allowed))
+    {
+        Ok(())
+    } else {
+        Err(format!(
+            "health check query must start with one of {READ_ONLY_KEYWORDS:?}: {query:?}"
+        ))
+    }
+}
+
+/// Runs the configured health check query, failing if it errors or takes
+/// longer than `READINESS_TIMEOUT`.
+///
+/// # Errors
+///
+/// Returns `AppError::ServiceUnavailable` describing the failure.
+pub async fn check(db: &PgPool, query: &str) -> Result<(), AppError> {
+    match tokio::time::timeout(READINESS_TIMEOUT, sqlx::query(query).execute(db)).await {
+        Ok(Ok(_)) => Ok(()),
+        Ok(Err(e)) => Err(AppError::ServiceUnavailable(format!(
+            "health check query failed: {e}"
+        ))),
+        Err(_) => Err(AppError::ServiceUnavailable(format!(
+            "health check query took longer than {READINESS_TIMEOUT:?}"
+        ))),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn read_only_queries_are_accepted() {
+        for query in [
+            "SELECT 1",
+            "select 1;",
+            "  SHOW server_version",
+            "VALUES (1)",
+        ] {
+            assert!(validate_read_only(query).is_ok(), "{query:?} should pass");
+        }
+    }
+
+    #[test]
+    fn writes_and_multiple_statements_are_refused() {
+        for query in [
+            "DELETE FROM results",
+            "SELECT 1; DROP TABLE results",
+            "WITH gone AS (DELETE FROM results RETURNING 1) SELECT * FROM gone",
+            "",
+        ] {
+            assert!(validate_read_only(query).is_err(), "{query:?} should fail");
+        }
+    }
+}
@@ -0,0 +1,133 @@
+use std::time::Duration;
+
+use sqlx::PgPool;
+
+use crate::error::AppError;
+
+/// Where an `Idempotency-Key` stands for one endpoint after trying to claim
+/// it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum IngestClaim {
+    /// This call holds the key, so its upload should be processed
+    Claimed,
+    /// An earlier upload under the key succeeded and inserted this many rows
+    Done(i64),
+    /// Another upload under the key is still being processed
+    InProgress,
+}
+
+/// The row count recorded for an earlier upload sent with `key` to
+/// `endpoint`, if one succeeded.
+///
+/// # Errors
+///
+/// Returns an internal error if the lookup fails.
+pub async fn find_ingest(db: &PgPool, key: &str, endpoint: &str) -> Result<Option<i64>, AppError> {
+    sqlx::query_scalar(
+        "SELECT inserted FROM ingest_log \
+         WHERE endpoint = $1 AND idempotency_key = $2 AND inserted IS NOT NULL",
+    )
+    .bind(endpoint)
+    .bind(key)
+    .fetch_optional(db)
+    .await
+    .map_err(|e| AppError::InternalServerError(format!("ingest log lookup failed: {e}")))
+}
+
+/// Claims `key` for an upload to `endpoint` by storing it with no row count
+/// yet, so a second upload under the key sees it taken instead of running
+/// alongside. A claim left unrecorded for longer than `stale_after`, by a
+/// server that died mid-upload, is taken over.
+///
+/// # Errors
+///
+/// Returns an internal error if the claim or lookup fails.
+pub async fn claim_ingest(
+    db: &PgPool,
+    key: &str,
+    endpoint: &str,
+    stale_after: Duration,
+) -> Result<IngestClaim, AppError> {
+    let claim_error =
+        |e: sqlx::Error| AppError::InternalServerError(format!("ingest log claim failed: {e}"));
+    loop {
+        let claimed = sqlx::query(
+            "INSERT INTO ingest_log (idempotency_key, endpoint) VALUES ($1, $2) \
+             ON CONFLICT (endpoint, idempotency_key) DO UPDATE SET created_at = NOW() \
+             WHERE ingest_log.inserted IS NULL \
+               AND ingest_log.created_at < NOW() - make_interval(secs => $3)",
+        )
+        .bind(key)
+        .bind(endpoint)
+        .bind(stale_after.as_secs_f64())
+        .execute(db)
+        .await
+        .map_err(claim_error)?
+        .rows_affected()
+            == 1;
+        if claimed {
+            return Ok(IngestClaim::Claimed);
+        }
+
+        let existing: Option<Option<i64>> = sqlx::query_scalar(
+            "SELECT inserted FROM ingest_log WHERE endpoint = $1 AND idempotency_key = $2",
+        )
+        .bind(endpoint)
+        .bind(key)
+        .fetch_optional(db)
+        .await
+        .map_err(claim_error)?;
+        match existing {
+            Some(Some(inserted)) => return Ok(IngestClaim::Done(inserted)),
+            Some(None) => return Ok(IngestClaim::InProgress),
+            // Released between the two statements, so it's free to claim
+            None => {}
+        }
+    }
+}
+
+/// Records that the upload holding the claim on `key` for `endpoint`
+/// inserted `inserted` rows. Returns whether the claim was still pending; it
+/// isn't if another upload under the key already recorded a count.
+///
+/// # Errors
+///
+/// Returns an internal error if the update fails.
+pub async fn record_ingest(
+    db: &PgPool,
+    key: &str,
+    endpoint: &str,
+    inserted: u64,
+) -> Result<bool, AppError> {
+    sqlx::query(
+        "UPDATE ingest_log SET inserted = $3 \
+         WHERE endpoint = $2 AND idempotency_key = $1 AND inserted IS NULL",
+    )
+    .bind(key)
+    .bind(endpoint)
+    .bind(i64::try_from(inserted).unwrap_or(i64::MAX))
+    .execute(db)
+    .await
+    .map(|result| result.rows_affected() == 1)
+    .map_err(|e| AppError::InternalServerError(format!("ingest log insert failed: {e}")))
+}
+
+/// Drops an unrecorded claim on `key` for `endpoint`, after its upload
+/// failed, so a retry under the same key is processed. A recorded count is
+/// never removed.
+///
+/// # Errors
+///
+/// Returns an internal error if the delete fails.
+pub async fn release_ingest(db: &PgPool, key: &str, endpoint: &str) -> Result<(), AppError> {
+    sqlx::query(
+        "DELETE FROM ingest_log \
+         WHERE endpoint = $2 AND idempotency_key = $1 AND inserted IS NULL",
+    )
+    .bind(key)
+    .bind(endpoint)
+    .execute(db)
+    .await
+    .map(drop)
+    .map_err(|e| AppError::InternalServerError(format!("ingest log release failed: {e}")))
+}
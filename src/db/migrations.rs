@@ -0,0 +1,58 @@
+use std::collections::HashSet;
+
+use color_eyre::eyre::{Result, WrapErr, eyre};
+use sqlx::{PgPool, migrate::Migrator};
+
+use crate::config::MigrationMode;
+
+/// The schema migrations compiled into this binary.
+pub static MIGRATOR: Migrator = sqlx::migrate!();
+
+/// Brings the schema up to date, or merely checks it, according to `mode`.
+///
+/// # Errors
+///
+/// Returns an error if a migration fails under `auto`, or if the database is
+/// missing any embedded migration under `verify`.
+pub async fn apply(db: &PgPool, mode: MigrationMode) -> Result<()> {
+    match mode {
+        MigrationMode::Auto => MIGRATOR
+            .run(db)
+            .await
+            .wrap_err("Failed to run database migrations"),
+        MigrationMode::Verify => verify(db).await,
+        MigrationMode::Skip => Ok(()),
+    }
+}
+
+/// Checks that every embedded migration has been applied successfully,
+/// without running any DDL.
+///
+/// # Errors
+///
+/// Returns an error naming the pending versions, or if the migrations table
+/// can't be read (e.g. the schema was never migrated).
+pub async fn verify(db: &PgPool) -> Result<()> {
+    let applied: HashSet<i64> =
+        sqlx::query_scalar("SELECT version FROM _sqlx_migrations WHERE success")
+            .fetch_all(db)
+            .await
+            .wrap_err("Failed to read applied migrations; has the schema been migrated?")?
+            .into_iter()
+            .collect();
+
+    let pending: Vec<String> = MIGRATOR
+        .iter()
+        .filter(|m| m.migration_type.is_up_migration() && !applied.contains(&m.version))
+        .map(|m| format!("{} ({})", m.version, m.description))
+        .collect();
+
+    if pending.is_empty() {
+        Ok(())
+    } else {
+        Err(eyre!(
+            "Database schema is behind this build; pending migrations: {}",
+            pending.join(", ")
+        ))
+    }
+}
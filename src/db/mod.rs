@@ -1 +1,8 @@
+pub mod connect;
+pub mod dead_letters;
+pub mod fault_injection;
+pub mod health;
+pub mod ingest_log;
+pub mod migrations;
 pub mod operations;
+pub mod queries;
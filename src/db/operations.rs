@@ -1,22 +1,200 @@
-use sqlx::PgPool;
-use std::fmt::Write;
-use tokio::sync::mpsc;
+use sqlx::{PgConnection, PgPool};
+use std::{
+    fmt::Write,
+    time::{Duration, Instant},
+};
+use tokio::{
+    sync::mpsc,
+    task::{JoinHandle, JoinSet},
+};
+use tracing::Instrument;
 
 use crate::{
+    config::{InsertMethod, IsolationLevel},
+    db::fault_injection::FaultInjection,
     error::AppError,
-    models::record::{BulkInsertable, DummyRecord, Gottcha2FullRecord, StastRecord},
+    models::{
+        record::{
+            BulkInsertable, CopyInsertable, DummyRecord, Gottcha2FullRecord, Kraken2Record,
+            MAX_BIND_PARAMS, StastRecord,
+        },
+        sample_id::SampleId,
+    },
 };
 
-const BATCH_SIZE: usize = 500;
+/// Whether an ingest's INSERTs are logged, as a request asks with
+/// `X-Debug-SQL` once `DEBUG_SQL` allows it.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum SqlLogging {
+    #[default]
+    Off,
+    /// Statements, row counts, and timings, never the bound values
+    Redacted,
+    /// As `Redacted`, plus the bound values; only under `DEBUG_SQL_PARAMS`
+    WithParams,
+}
 
-async fn bulk_insert_chunk<T: BulkInsertable>(
-    db: &PgPool,
-    records: Vec<T>, // Interior mutability alert: consumes records for binding
-) -> Result<(), AppError> {
-    if records.is_empty() {
-        return Ok(());
+/// Tunables for the `batch_insert_*` functions.
+#[derive(Debug, Clone, Copy)]
+pub struct InsertOptions {
+    /// Rows per `INSERT` statement; clamped to the record type's maximum
+    pub batch_size: usize,
+    /// Simulated failures for resilience testing; `None` outside test/staging
+    pub fault_injection: Option<FaultInjection>,
+    /// Idempotency keys stored longer than this are treated as new on replay;
+    /// `None` means keys never expire
+    pub idempotency_ttl: Option<Duration>,
+    /// Rough number of records the upload is expected to hold, used to size
+    /// batch buffers; `None` allocates full batches
+    pub expected_records: Option<usize>,
+    /// Isolation each batch's transaction runs under
+    pub isolation_level: IsolationLevel,
+    /// Insert the whole upload in one transaction, committed only once the
+    /// channel closes, instead of committing batch by batch
+    pub atomic: bool,
+    /// Log each INSERT for a request debugging its SQL
+    pub sql_logging: SqlLogging,
+    /// Whether copyable record types are loaded with COPY
+    pub method: InsertMethod,
+    /// Times a batch that fails transiently is retried before giving up;
+    /// batches inside an atomic upload's transaction are never retried
+    pub max_retries: u32,
+    /// Most batches inserted at once, each on its own pooled connection.
+    /// Atomic uploads, COPYs, and record types with `ordered_inserts`
+    /// always insert one batch at a time.
+    pub parallelism: usize,
+}
+
+impl InsertOptions {
+    #[must_use]
+    pub fn new(batch_size: usize) -> Self {
+        InsertOptions {
+            batch_size,
+            fault_injection: None,
+            idempotency_ttl: None,
+            expected_records: None,
+            isolation_level: IsolationLevel::ReadCommitted,
+            atomic: false,
+            sql_logging: SqlLogging::Off,
+            method: InsertMethod::Values,
+            max_retries: DEFAULT_INSERT_MAX_RETRIES,
+            parallelism: 1,
+        }
+    }
+
+    #[must_use]
+    pub fn with_fault_injection(mut self, faults: Option<FaultInjection>) -> Self {
+        self.fault_injection = faults;
+        self
+    }
+
+    #[must_use]
+    pub fn with_idempotency_ttl(mut self, ttl: Option<Duration>) -> Self {
+        self.idempotency_ttl = ttl;
+        self
+    }
+
+    #[must_use]
+    pub fn with_expected_records(mut self, expected: Option<usize>) -> Self {
+        self.expected_records = expected;
+        self
+    }
+
+    #[must_use]
+    pub fn with_isolation_level(mut self, level: IsolationLevel) -> Self {
+        self.isolation_level = level;
+        self
+    }
+
+    #[must_use]
+    pub fn with_atomic(mut self, atomic: bool) -> Self {
+        self.atomic = atomic;
+        self
+    }
+
+    #[must_use]
+    pub fn with_sql_logging(mut self, logging: SqlLogging) -> Self {
+        self.sql_logging = logging;
+        self
+    }
+
+    #[must_use]
+    pub fn with_insert_method(mut self, method: InsertMethod) -> Self {
+        self.method = method;
+        self
+    }
+
+    #[must_use]
+    pub fn with_max_retries(mut self, retries: u32) -> Self {
+        self.max_retries = retries;
+        self
+    }
+
+    #[must_use]
+    pub fn with_parallelism(mut self, parallelism: usize) -> Self {
+        self.parallelism = parallelism;
+        self
+    }
+}
+
+/// Times a batch that fails transiently is retried, unless configured
+/// otherwise, before the error is returned to the client.
+pub const DEFAULT_INSERT_MAX_RETRIES: u32 = 4;
+
+/// Longest wait between retries of a batch, before jitter.
+const MAX_RETRY_BACKOFF_MS: u64 = 2000;
+
+/// Resolves the batch size used for a record type, honoring a configured
+/// override but never exceeding what fits under the bind parameter limit.
+#[must_use]
+pub fn effective_batch_size<T: BulkInsertable>(configured: Option<usize>) -> usize {
+    let max = T::max_batch_size();
+    configured.map_or(max, |size| size.clamp(1, max))
+}
+
+/// Warns, naming `setting`, when a configured batch size is too large for
+/// `T`'s rows to fit under the bind parameter limit and will be capped.
+/// Returns whether it was capped. Meant to be called once at startup.
+pub fn warn_if_batch_size_capped<T: BulkInsertable>(
+    setting: &str,
+    configured: Option<usize>,
+) -> bool {
+    let max = T::max_batch_size();
+    match configured {
+        Some(size) if size > max => {
+            tracing::warn!(
+                "{setting}={size} would exceed Postgres's {MAX_BIND_PARAMS} bind parameter limit for {} ({} columns per row); using {max} instead.",
+                T::table_name(),
+                T::field_count()
+            );
+            true
+        }
+        _ => false,
     }
+}
 
+/// Guesses how many records a gzipped JSONL body of `compressed_len` bytes
+/// holds, assuming roughly 8x compression and 256-byte lines. Only used as a
+/// capacity hint, so it needs to be in the right ballpark, not exact.
+#[must_use]
+pub fn estimate_record_count(compressed_len: u64) -> usize {
+    const ASSUMED_COMPRESSION_RATIO: u64 = 8;
+    const ASSUMED_BYTES_PER_RECORD: u64 = 256;
+    let estimate =
+        compressed_len.saturating_mul(ASSUMED_COMPRESSION_RATIO) / ASSUMED_BYTES_PER_RECORD;
+    usize::try_from(estimate).unwrap_or(usize::MAX)
+}
+
+/// Capacity for the next batch buffer: a full batch, unless the upload is
+/// expected to end before filling one.
+fn batch_capacity(batch_size: usize, expected: Option<usize>, received: usize) -> usize {
+    expected.map_or(batch_size, |expected| {
+        expected.saturating_sub(received).clamp(1, batch_size)
+    })
+}
+
+/// Builds the multi-row `INSERT` for `rows` records of type `T`.
+fn insert_statement<T: BulkInsertable>(rows: usize) -> String {
     let field_count = T::field_count();
 
     // Build the SQL query with multiple VALUE rows
@@ -27,7 +205,7 @@ async fn bulk_insert_chunk<T: BulkInsertable>(
     );
 
     // Add placeholders for each record
-    for (i, _) in records.iter().enumerate() {
+    for i in 0..rows {
         if i > 0 {
             query.push_str(", ");
         }
@@ -43,63 +221,606 @@ async fn bulk_insert_chunk<T: BulkInsertable>(
         query.push_str(clause);
     }
 
-    // Bind all records
-    let mut q = sqlx::query(&query);
+    query
+}
+
+/// `query` cut after its first row of placeholders, as a multi-row INSERT
+/// can run to thousands of them.
+fn statement_preview(query: &str) -> String {
+    match query.find("), (") {
+        Some(end) => format!("{}), ...", &query[..end]),
+        None => query.to_string(),
+    }
+}
+
+/// Runs `query` bound to `records`, returning how many rows it inserted;
+/// rows skipped by ON CONFLICT aren't counted. Under `logging`, the statement
+/// and its outcome are logged in the caller's span.
+async fn execute_insert<'c, T, E>(
+    db: E,
+    query: &str,
+    records: &[T],
+    logging: SqlLogging,
+) -> Result<u64, sqlx::Error>
+where
+    T: BulkInsertable,
+    E: sqlx::PgExecutor<'c>,
+{
+    let rows = records.len();
+    let params = (logging == SqlLogging::WithParams).then(|| format!("{records:?}"));
+    let started = Instant::now();
+
+    let mut q = sqlx::query(query);
     for record in records {
         q = record.bind_to(q);
     }
+    let result = q.execute(db).await.map(|done| done.rows_affected());
+
+    if logging != SqlLogging::Off {
+        let sql = statement_preview(query);
+        let elapsed_ms = started.elapsed().as_secs_f64() * 1000.0;
+        let params = params.as_deref().unwrap_or("[redacted]");
+        match &result {
+            Ok(inserted) => {
+                tracing::info!(sql, rows, inserted, elapsed_ms, params, "Executed INSERT");
+            }
+            Err(e) => {
+                tracing::info!(sql, rows, elapsed_ms, params, error = %e, "INSERT failed");
+            }
+        }
+    }
+    result
+}
 
-    // Execute the bulk insert
-    q.execute(db)
+/// Expires replayed keys and inserts `records` in one transaction at
+/// `options.isolation_level`.
+async fn insert_in_transaction<T: BulkInsertable>(
+    db: &PgPool,
+    query: &str,
+    records: &[T],
+    options: &InsertOptions,
+) -> Result<u64, sqlx::Error> {
+    let mut tx = db.begin().await?;
+    let set_level = format!(
+        "SET TRANSACTION ISOLATION LEVEL {}",
+        options.isolation_level.as_sql()
+    );
+    sqlx::query(&set_level).execute(&mut *tx).await?;
+    if let Some(ttl) = options.idempotency_ttl {
+        expire_replayed_keys(&mut *tx, records, ttl).await?;
+    }
+    let inserted = execute_insert(&mut *tx, query, records, options.sql_logging).await?;
+    tx.commit().await?;
+    Ok(inserted)
+}
+
+/// Whether a failed statement may succeed if simply run again: Postgres
+/// aborted it for a serialization failure (`40001`) or deadlock (`40P01`),
+/// the connection failed (class `08`) or was cut by a server restart or
+/// failover (`57P01` to `57P03`), or no pooled connection came free in
+/// time. Constraint violations and other errors are permanent.
+fn is_retryable(e: &sqlx::Error) -> bool {
+    match e {
+        sqlx::Error::PoolTimedOut | sqlx::Error::Io(_) => true,
+        sqlx::Error::Database(db_err) => db_err.code().is_some_and(|code| {
+            matches!(
+                code.as_ref(),
+                "40001" | "40P01" | "57P01" | "57P02" | "57P03"
+            ) || code.starts_with("08")
+        }),
+        _ => false,
+    }
+}
+
+/// Runs `attempt` until it succeeds, fails permanently, or has been retried
+/// `max_retries` times, backing off exponentially, with jitter, in between.
+async fn with_retries<R, F, Fut>(max_retries: u32, mut attempt: F) -> Result<R, sqlx::Error>
+where
+    F: FnMut() -> Fut,
+    Fut: Future<Output = Result<R, sqlx::Error>>,
+{
+    let mut retries = 0;
+    loop {
+        match attempt().await {
+            Err(e) if is_retryable(&e) && retries < max_retries => {
+                retries += 1;
+                tracing::debug!("Retrying batch after attempt {retries} failed: {e}");
+                let backoff_ms = (10 * 2_u64.pow(retries.min(8))).min(MAX_RETRY_BACKOFF_MS);
+                let jitter_ms = rand::random_range(0..=backoff_ms / 2);
+                tokio::time::sleep(Duration::from_millis(backoff_ms + jitter_ms)).await;
+            }
+            result => return result,
+        }
+    }
+}
+
+/// Rolls `options`' injected faults, if any, ahead of one insert attempt.
+async fn inject_fault(options: &InsertOptions) -> Result<(), sqlx::Error> {
+    match &options.fault_injection {
+        Some(faults) => faults.before_insert().await,
+        None => Ok(()),
+    }
+}
+
+/// Inserts one batch, retrying transient failures up to
+/// `options.max_retries` times, each attempt rolling any injected faults
+/// afresh. Each attempt is a single statement, or a single transaction when
+/// replayed keys are expired first or under a stricter isolation level, so a
+/// failed attempt normally leaves nothing behind; only a connection lost after
+/// Postgres committed can make a retry store the batch twice.
+async fn bulk_insert_chunk<T: BulkInsertable>(
+    db: &PgPool,
+    records: &[T],
+    options: &InsertOptions,
+) -> Result<u64, AppError> {
+    if records.is_empty() {
+        return Ok(0);
+    }
+
+    let query = &insert_statement::<T>(records.len());
+
+    // A lone insert is already atomic; expiring replayed keys alongside it
+    // needs a transaction, so the delete never commits without the insert
+    if options.isolation_level == IsolationLevel::ReadCommitted && options.idempotency_ttl.is_none()
+    {
+        return with_retries(options.max_retries, || async move {
+            inject_fault(options).await?;
+            execute_insert(db, query, records, options.sql_logging).await
+        })
         .await
-        .map_err(|e| AppError::InternalServerError(format!("bulk insert failed: {e}")))?;
+        .map_err(|e| insert_error(&e));
+    }
+
+    // Stricter levels abort conflicting transactions instead of blocking,
+    // so those are retried like any other transient failure
+    with_retries(options.max_retries, || async move {
+        inject_fault(options).await?;
+        insert_in_transaction(db, query, records, options).await
+    })
+    .await
+    .map_err(|e| insert_error(&e))
+}
+
+/// Deletes stored rows whose idempotency key is being replayed but has
+/// outlived `ttl`, so the replay is inserted fresh rather than deduplicated
+/// against a stale result.
+async fn expire_replayed_keys<'c, T, E>(
+    db: E,
+    records: &[T],
+    ttl: Duration,
+) -> Result<(), sqlx::Error>
+where
+    T: BulkInsertable,
+    E: sqlx::PgExecutor<'c>,
+{
+    let keys: Vec<&str> = records.iter().filter_map(T::idempotency_key).collect();
+    if keys.is_empty() {
+        return Ok(());
+    }
+
+    let query = format!(
+        "DELETE FROM {} WHERE idempotency_key = ANY($1) AND created_at < NOW() - make_interval(secs => $2)",
+        T::table_name()
+    );
+    sqlx::query(&query)
+        .bind(&keys)
+        .bind(ttl.as_secs_f64())
+        .execute(db)
+        .await?;
 
     Ok(())
 }
 
+/// Maps a failed insert to an error the client can act on: constraint
+/// violations are reported as conflicts, pool exhaustion and I/O failures as
+/// transient, and everything else stays internal.
+fn insert_error(e: &sqlx::Error) -> AppError {
+    match e {
+        sqlx::Error::PoolTimedOut | sqlx::Error::Io(_) => {
+            AppError::ServiceUnavailable(format!("bulk insert failed: {e}"))
+        }
+        sqlx::Error::Database(db_err)
+            if db_err.is_unique_violation()
+                || db_err.is_foreign_key_violation()
+                || db_err.is_check_violation() =>
+        {
+            AppError::Conflict(format!("bulk insert failed: {}", db_err.message()))
+        }
+        _ => AppError::InternalServerError(format!("bulk insert failed: {e}")),
+    }
+}
+
 async fn insert_records<T: BulkInsertable>(
     db: &PgPool,
-    mut records: Vec<T>, // Consumes the vector since we need ownership for binding
-) -> Result<(), AppError> {
+    records: Vec<T>,
+    options: &InsertOptions,
+) -> Result<u64, AppError> {
+    let batch_size = options.batch_size;
     if records.is_empty() {
-        return Ok(());
+        return Ok(0);
     }
 
-    // PostgreSQL has a limit of ~65535 parameters
-    // We use BATCH_SIZE to control both channel batching and SQL insert size
-    // Process in chunks to avoid hitting parameter limits
-    while !records.is_empty() {
-        let chunk_size = std::cmp::min(BATCH_SIZE, records.len());
-        let chunk: Vec<T> = records.drain(..chunk_size).collect();
-        bulk_insert_chunk(db, chunk).await?;
+    // PostgreSQL has a limit of 65535 parameters per statement. batch_size
+    // controls both channel batching and SQL insert size, but is capped at
+    // what fits so an oversized setting splits into more statements instead
+    // of failing with an opaque protocol error
+    let rows_per_statement = batch_size.clamp(1, T::max_batch_size());
+    let mut inserted = 0;
+    for chunk in records.chunks(rows_per_statement) {
+        inserted += bulk_insert_chunk(db, chunk, options).await?;
     }
 
-    Ok(())
+    Ok(inserted)
 }
 
-async fn batch_insert_from_channel<T: BulkInsertable>(
+/// Drains `rx` into batched multi-row INSERTs for any `BulkInsertable` type,
+/// returning how many rows were actually inserted. The `batch_insert_*`
+/// functions are typed wrappers around this.
+///
+/// # Errors
+///
+/// Returns an error if database insertion fails.
+pub async fn batch_insert_from_channel<T: BulkInsertable>(
     mut rx: mpsc::Receiver<T>,
     db: &PgPool,
-) -> Result<(), AppError> {
-    let mut batch = Vec::with_capacity(BATCH_SIZE);
+    options: InsertOptions,
+) -> Result<u64, AppError> {
+    let batch_size = effective_batch_size::<T>(Some(options.batch_size));
+    let options = InsertOptions {
+        batch_size,
+        ..options
+    };
+    if options.atomic {
+        return insert_atomically(rx, db, &options).await;
+    }
+    if options.parallelism > 1 && !T::ordered_inserts() {
+        return insert_in_parallel(rx, db, options).await;
+    }
+
+    let mut batch = Vec::with_capacity(batch_capacity(batch_size, options.expected_records, 0));
+    let mut received = 0;
+    let mut inserted = 0;
 
     while let Some(record) = rx.recv().await {
         batch.push(record);
+        received += 1;
 
-        if batch.len() >= BATCH_SIZE {
-            let current_batch = std::mem::replace(&mut batch, Vec::with_capacity(BATCH_SIZE));
-            insert_records(db, current_batch).await?;
+        if batch.len() >= batch_size {
+            let next_capacity = batch_capacity(batch_size, options.expected_records, received);
+            let current_batch = std::mem::replace(&mut batch, Vec::with_capacity(next_capacity));
+            inserted += insert_records(db, current_batch, &options).await?;
         }
     }
 
     if !batch.is_empty() {
-        insert_records(db, batch).await?;
+        inserted += insert_records(db, batch, &options).await?;
     }
 
-    Ok(())
+    Ok(inserted)
+}
+
+/// Like `batch_insert_from_channel`, but with up to `options.parallelism`
+/// batches inserting at once on their own tasks, each acquiring its own
+/// connection, so a large upload isn't held to one connection's pace.
+/// Batches may commit out of order. On the first failure the remaining
+/// batches are aborted, though any already committed stay.
+async fn insert_in_parallel<T: BulkInsertable>(
+    mut rx: mpsc::Receiver<T>,
+    db: &PgPool,
+    options: InsertOptions,
+) -> Result<u64, AppError> {
+    let batch_size = options.batch_size;
+    let mut tasks = JoinSet::new();
+    let mut batch = Vec::with_capacity(batch_capacity(batch_size, options.expected_records, 0));
+    let mut received = 0;
+    let mut inserted = 0;
+
+    while let Some(record) = rx.recv().await {
+        batch.push(record);
+        received += 1;
+
+        if batch.len() >= batch_size {
+            let next_capacity = batch_capacity(batch_size, options.expected_records, received);
+            let current_batch = std::mem::replace(&mut batch, Vec::with_capacity(next_capacity));
+            inserted += spawn_batch(&mut tasks, db, current_batch, &options).await?;
+        }
+    }
+
+    if !batch.is_empty() {
+        inserted += spawn_batch(&mut tasks, db, batch, &options).await?;
+    }
+    while !tasks.is_empty() {
+        inserted += join_batch(&mut tasks).await?;
+    }
+    Ok(inserted)
+}
+
+/// Starts inserting `records` on a task of its own, first waiting for one
+/// of `tasks` to finish if `options.parallelism` are already running.
+/// Returns how many rows that finished batch inserted.
+async fn spawn_batch<T: BulkInsertable>(
+    tasks: &mut JoinSet<Result<u64, AppError>>,
+    db: &PgPool,
+    records: Vec<T>,
+    options: &InsertOptions,
+) -> Result<u64, AppError> {
+    let finished = if tasks.len() >= options.parallelism {
+        join_batch(tasks).await?
+    } else {
+        0
+    };
+    let db = db.clone();
+    let options = *options;
+    tasks.spawn(async move { insert_records(&db, records, &options).await }.in_current_span());
+    Ok(finished)
+}
+
+/// Waits for the next of `tasks` to finish, returning how many rows its
+/// batch inserted.
+async fn join_batch(tasks: &mut JoinSet<Result<u64, AppError>>) -> Result<u64, AppError> {
+    match tasks.join_next().await {
+        Some(Ok(result)) => result,
+        Some(Err(e)) => Err(AppError::InternalServerError(format!(
+            "insert task failed: {e}"
+        ))),
+        None => Ok(0),
+    }
+}
+
+/// Loads `records` with one `COPY ... FROM STDIN` on `conn`, returning how
+/// many rows were copied.
+async fn copy_batch<T: CopyInsertable>(
+    conn: &mut PgConnection,
+    records: &[T],
+    options: &InsertOptions,
+) -> Result<u64, AppError> {
+    inject_fault(options).await.map_err(|e| insert_error(&e))?;
+
+    let statement = format!(
+        "COPY {} ({}) FROM STDIN",
+        T::table_name(),
+        T::column_names()
+    );
+    let mut rows = String::new();
+    for record in records {
+        record.write_copy_row(&mut rows);
+    }
+    let started = Instant::now();
+
+    let result = async {
+        let mut copy = conn.copy_in_raw(&statement).await?;
+        copy.send(rows.as_bytes()).await?;
+        copy.finish().await
+    }
+    .await;
+
+    if options.sql_logging != SqlLogging::Off {
+        let elapsed_ms = started.elapsed().as_secs_f64() * 1000.0;
+        let params = match options.sql_logging {
+            SqlLogging::WithParams => rows.as_str(),
+            _ => "[redacted]",
+        };
+        let rows = records.len();
+        match &result {
+            Ok(inserted) => {
+                tracing::info!(
+                    sql = statement,
+                    rows,
+                    inserted,
+                    elapsed_ms,
+                    params,
+                    "Executed COPY"
+                );
+            }
+            Err(e) => {
+                tracing::info!(sql = statement, rows, elapsed_ms, params, error = %e, "COPY failed");
+            }
+        }
+    }
+    result.map_err(|e| insert_error(&e))
+}
+
+/// Drains `rx` into batched COPYs on `conn`, returning how many rows were
+/// copied. Batches commit one by one unless `conn` is a transaction.
+async fn copy_batches<T: CopyInsertable>(
+    conn: &mut PgConnection,
+    mut rx: mpsc::Receiver<T>,
+    options: &InsertOptions,
+) -> Result<u64, AppError> {
+    let batch_size = options.batch_size;
+    let mut batch = Vec::with_capacity(batch_capacity(batch_size, options.expected_records, 0));
+    let mut received = 0;
+    let mut inserted = 0;
+
+    while let Some(record) = rx.recv().await {
+        batch.push(record);
+        received += 1;
+
+        if batch.len() >= batch_size {
+            inserted += copy_batch(conn, &batch, options).await?;
+            batch.clear();
+            batch.reserve(batch_capacity(
+                batch_size,
+                options.expected_records,
+                received,
+            ));
+        }
+    }
+
+    if !batch.is_empty() {
+        inserted += copy_batch(conn, &batch, options).await?;
+    }
+
+    Ok(inserted)
 }
 
-/// Processes `DummyRecord` items from a channel and inserts them in batches.
+/// Like `batch_insert_from_channel`, but loads each batch with COPY when
+/// `options.method` asks for it and `T` has no conflict clause for COPY to
+/// lose. Batches have the same size either way, bounding memory, and with
+/// `options.atomic` share one transaction at `options.isolation_level`.
+/// Non-atomic COPYs are single autocommitted statements, whatever the
+/// isolation level.
+///
+/// # Errors
+///
+/// Returns an error if database insertion fails.
+pub async fn copy_insert_from_channel<T: CopyInsertable>(
+    rx: mpsc::Receiver<T>,
+    db: &PgPool,
+    options: InsertOptions,
+) -> Result<u64, AppError> {
+    if options.method != InsertMethod::Copy || T::conflict_clause().is_some() {
+        return batch_insert_from_channel(rx, db, options).await;
+    }
+    let options = InsertOptions {
+        batch_size: effective_batch_size::<T>(Some(options.batch_size)),
+        ..options
+    };
+
+    if !options.atomic {
+        let mut conn = db.acquire().await.map_err(|e| insert_error(&e))?;
+        return copy_batches(&mut conn, rx, &options).await;
+    }
+
+    let mut tx = db.begin().await.map_err(|e| insert_error(&e))?;
+    let set_level = format!(
+        "SET TRANSACTION ISOLATION LEVEL {}",
+        options.isolation_level.as_sql()
+    );
+    sqlx::query(&set_level)
+        .execute(&mut *tx)
+        .await
+        .map_err(|e| insert_error(&e))?;
+    let inserted = copy_batches(&mut tx, rx, &options).await?;
+    tx.commit().await.map_err(|e| insert_error(&e))?;
+    Ok(inserted)
+}
+
+/// Inserts one batch on the open transaction `tx`, applying the same fault
+/// injection and idempotency-key expiry as a standalone batch.
+async fn insert_batch_in<T: BulkInsertable>(
+    tx: &mut PgConnection,
+    records: Vec<T>,
+    options: &InsertOptions,
+) -> Result<u64, AppError> {
+    inject_fault(options).await.map_err(|e| insert_error(&e))?;
+    if let Some(ttl) = options.idempotency_ttl {
+        expire_replayed_keys(&mut *tx, &records, ttl)
+            .await
+            .map_err(|e| insert_error(&e))?;
+    }
+    let query = insert_statement::<T>(records.len());
+    execute_insert(&mut *tx, &query, &records, options.sql_logging)
+        .await
+        .map_err(|e| insert_error(&e))
+}
+
+/// Drains `rx` into batched INSERTs on the open transaction `tx`, returning
+/// how many rows were inserted. Nothing is committed here; the caller does
+/// that once it knows the upload is complete.
+async fn insert_batches_in<T: BulkInsertable>(
+    tx: &mut PgConnection,
+    mut rx: mpsc::Receiver<T>,
+    options: &InsertOptions,
+) -> Result<u64, AppError> {
+    let batch_size = effective_batch_size::<T>(Some(options.batch_size));
+    let mut batch = Vec::with_capacity(batch_capacity(batch_size, options.expected_records, 0));
+    let mut received = 0;
+    let mut inserted = 0;
+
+    while let Some(record) = rx.recv().await {
+        batch.push(record);
+        received += 1;
+
+        if batch.len() >= batch_size {
+            let next_capacity = batch_capacity(batch_size, options.expected_records, received);
+            let current_batch = std::mem::replace(&mut batch, Vec::with_capacity(next_capacity));
+            inserted += insert_batch_in(tx, current_batch, options).await?;
+        }
+    }
+
+    if !batch.is_empty() {
+        inserted += insert_batch_in(tx, batch, options).await?;
+    }
+
+    Ok(inserted)
+}
+
+/// Inserts everything drained from `rx` in one transaction at
+/// `options.isolation_level`, committed only once the channel closes. If the
+/// parser fails first, the transaction is dropped and rolled back, so none
+/// of the upload lands. Unlike per-batch inserts, a serialization failure
+/// can't be retried, as the records are gone by then.
+async fn insert_atomically<T: BulkInsertable>(
+    rx: mpsc::Receiver<T>,
+    db: &PgPool,
+    options: &InsertOptions,
+) -> Result<u64, AppError> {
+    let mut tx = db.begin().await.map_err(|e| insert_error(&e))?;
+    let set_level = format!(
+        "SET TRANSACTION ISOLATION LEVEL {}",
+        options.isolation_level.as_sql()
+    );
+    sqlx::query(&set_level)
+        .execute(&mut *tx)
+        .await
+        .map_err(|e| insert_error(&e))?;
+    let inserted = insert_batches_in(&mut tx, rx, options).await?;
+    tx.commit().await.map_err(|e| insert_error(&e))?;
+    Ok(inserted)
+}
+
+/// Replaces every `T` row for `sample_id` with the records drained from `rx`,
+/// returning how many rows were deleted and inserted. The delete and every
+/// insert share one transaction, so readers see either the old rows or the
+/// new ones, and a failed upload leaves the old rows in place, whatever
+/// `options.atomic` says. The transaction runs at `options.isolation_level`.
+///
+/// Concurrent replacements of the same sample are serialized on a
+/// transaction-scoped advisory lock; otherwise neither would see, and
+/// delete, the other's uncommitted rows, and both sets would survive.
+///
+/// # Errors
+///
+/// Returns an error if the delete or any insert fails.
+pub async fn replace_sample_from_channel<T: BulkInsertable>(
+    rx: mpsc::Receiver<T>,
+    db: &PgPool,
+    sample_id: &SampleId,
+    options: InsertOptions,
+) -> Result<(u64, u64), AppError> {
+    let table = T::table_name();
+    let mut tx = db.begin().await.map_err(|e| insert_error(&e))?;
+    // Must be the transaction's first statement, ahead of the lock
+    let set_level = format!(
+        "SET TRANSACTION ISOLATION LEVEL {}",
+        options.isolation_level.as_sql()
+    );
+    sqlx::query(&set_level)
+        .execute(&mut *tx)
+        .await
+        .map_err(|e| insert_error(&e))?;
+
+    sqlx::query("SELECT pg_advisory_xact_lock(hashtextextended($1, 0))")
+        .bind(format!("{table}:{sample_id}"))
+        .execute(&mut *tx)
+        .await
+        .map_err(|e| insert_error(&e))?;
+    let deleted = sqlx::query(&format!("DELETE FROM {table} WHERE sample_id = $1"))
+        .bind(sample_id.as_str())
+        .execute(&mut *tx)
+        .await
+        .map_err(|e| insert_error(&e))?
+        .rows_affected();
+
+    let inserted = insert_batches_in(&mut tx, rx, &options).await?;
+
+    tx.commit().await.map_err(|e| insert_error(&e))?;
+    Ok((deleted, inserted))
+}
+
+/// Processes `DummyRecord` items from a channel and inserts them in batches of up to
+/// `options.batch_size` rows, returning how many rows were actually inserted.
 ///
 /// # Errors
 ///
@@ -107,11 +828,13 @@ async fn batch_insert_from_channel<T: BulkInsertable>(
 pub async fn batch_insert_dummy(
     rx: mpsc::Receiver<DummyRecord>,
     db: &PgPool,
-) -> Result<(), AppError> {
-    batch_insert_from_channel(rx, db).await
+    options: InsertOptions,
+) -> Result<u64, AppError> {
+    batch_insert_from_channel(rx, db, options).await
 }
 
-/// Processes `Gottcha2FullRecord` items from a channel and inserts them in batches.
+/// Processes `Gottcha2FullRecord` items from a channel and inserts them in batches of up to
+/// `options.batch_size` rows, returning how many rows were actually inserted.
 ///
 /// # Errors
 ///
@@ -119,11 +842,27 @@ pub async fn batch_insert_dummy(
 pub async fn batch_insert_gottcha2(
     rx: mpsc::Receiver<Gottcha2FullRecord>,
     db: &PgPool,
-) -> Result<(), AppError> {
-    batch_insert_from_channel(rx, db).await
+    options: InsertOptions,
+) -> Result<u64, AppError> {
+    copy_insert_from_channel(rx, db, options).await
+}
+
+/// Processes `Kraken2Record` items from a channel and inserts them in batches of up to
+/// `options.batch_size` rows, returning how many rows were actually inserted.
+///
+/// # Errors
+///
+/// Returns an error if database insertion fails.
+pub async fn batch_insert_kraken2(
+    rx: mpsc::Receiver<Kraken2Record>,
+    db: &PgPool,
+    options: InsertOptions,
+) -> Result<u64, AppError> {
+    copy_insert_from_channel(rx, db, options).await
 }
 
-/// Processes `StastRecord` items from a channel and inserts them in batches.
+/// Processes `StastRecord` items from a channel and inserts them in batches of up to
+/// `options.batch_size` rows, returning how many rows were actually inserted.
 ///
 /// # Errors
 ///
@@ -131,6 +870,195 @@ pub async fn batch_insert_gottcha2(
 pub async fn batch_insert_stast(
     rx: mpsc::Receiver<StastRecord>,
     db: &PgPool,
-) -> Result<(), AppError> {
-    batch_insert_from_channel(rx, db).await
+    options: InsertOptions,
+) -> Result<u64, AppError> {
+    copy_insert_from_channel(rx, db, options).await
+}
+
+/// Deletes rows of an idempotency-keyed table that are older than `ttl`,
+/// returning how many were removed.
+///
+/// # Errors
+///
+/// Returns an error if the delete fails.
+pub async fn prune_expired_idempotency_keys<T: BulkInsertable>(
+    db: &PgPool,
+    ttl: Duration,
+) -> Result<u64, AppError> {
+    let query = format!(
+        "DELETE FROM {} WHERE created_at < NOW() - make_interval(secs => $1)",
+        T::table_name()
+    );
+    let result = sqlx::query(&query)
+        .bind(ttl.as_secs_f64())
+        .execute(db)
+        .await
+        .map_err(|e| AppError::InternalServerError(format!("idempotency prune failed: {e}")))?;
+
+    Ok(result.rows_affected())
+}
+
+/// Spawns a background task that prunes expired `/ingest` idempotency keys
+/// every `ttl` (at most hourly), keeping the table bounded.
+#[must_use]
+pub fn spawn_idempotency_pruner(db: PgPool, ttl: Duration) -> JoinHandle<()> {
+    let period = ttl.clamp(Duration::from_secs(1), Duration::from_secs(3600));
+    tokio::spawn(async move {
+        let mut ticker = tokio::time::interval(period);
+        loop {
+            ticker.tick().await;
+            match prune_expired_idempotency_keys::<DummyRecord>(&db, ttl).await {
+                Ok(0) => {}
+                Ok(pruned) => tracing::info!("Pruned {pruned} expired idempotency keys"),
+                Err(e) => tracing::warn!("Failed to prune idempotency keys: {e:?}"),
+            }
+        }
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::atomic::{AtomicU32, Ordering};
+
+    use super::*;
+
+    #[tokio::test]
+    async fn transient_failures_are_retried_until_success() {
+        let attempts = AtomicU32::new(0);
+        let result = with_retries(3, || async {
+            if attempts.fetch_add(1, Ordering::SeqCst) < 2 {
+                Err(sqlx::Error::Io(std::io::ErrorKind::ConnectionReset.into()))
+            } else {
+                Ok(7)
+            }
+        })
+        .await;
+
+        assert_eq!(result.ok(), Some(7));
+        assert_eq!(attempts.load(Ordering::SeqCst), 3);
+    }
+
+    #[tokio::test]
+    async fn retries_stop_at_the_limit_and_on_permanent_errors() {
+        let attempts = AtomicU32::new(0);
+        let result: Result<(), _> = with_retries(2, || async {
+            attempts.fetch_add(1, Ordering::SeqCst);
+            Err(sqlx::Error::PoolTimedOut)
+        })
+        .await;
+        assert!(matches!(result, Err(sqlx::Error::PoolTimedOut)));
+        assert_eq!(
+            attempts.load(Ordering::SeqCst),
+            3,
+            "one try plus two retries"
+        );
+
+        let attempts = AtomicU32::new(0);
+        let result: Result<(), _> = with_retries(2, || async {
+            attempts.fetch_add(1, Ordering::SeqCst);
+            Err(sqlx::Error::RowNotFound)
+        })
+        .await;
+        assert!(result.is_err());
+        assert_eq!(
+            attempts.load(Ordering::SeqCst),
+            1,
+            "permanent errors fail at once"
+        );
+    }
+
+    #[test]
+    fn effective_batch_size_defaults_to_max() {
+        assert_eq!(
+            effective_batch_size::<StastRecord>(None),
+            StastRecord::max_batch_size()
+        );
+    }
+
+    #[test]
+    fn effective_batch_size_honors_override() {
+        assert_eq!(effective_batch_size::<Gottcha2FullRecord>(Some(250)), 250);
+    }
+
+    #[test]
+    fn oversized_batch_size_is_reported() {
+        let max = StastRecord::max_batch_size();
+        assert!(warn_if_batch_size_capped::<StastRecord>(
+            "STAST_BATCH_SIZE",
+            Some(max + 1)
+        ));
+        assert!(!warn_if_batch_size_capped::<StastRecord>(
+            "STAST_BATCH_SIZE",
+            Some(max)
+        ));
+        assert!(!warn_if_batch_size_capped::<StastRecord>(
+            "STAST_BATCH_SIZE",
+            None
+        ));
+    }
+
+    #[test]
+    fn effective_batch_size_clamps_override() {
+        assert_eq!(
+            effective_batch_size::<StastRecord>(Some(1_000_000)),
+            StastRecord::max_batch_size()
+        );
+        assert_eq!(effective_batch_size::<DummyRecord>(Some(0)), 1);
+    }
+
+    /// Pushes `records` items into batch buffers the way
+    /// `batch_insert_from_channel` does, returning how many times a buffer
+    /// had to grow and the largest capacity allocated.
+    fn simulate_batching(
+        records: usize,
+        batch_size: usize,
+        expected: Option<usize>,
+    ) -> (usize, usize) {
+        let mut batch: Vec<u64> = Vec::with_capacity(batch_capacity(batch_size, expected, 0));
+        let mut reallocations = 0;
+        let mut peak_capacity = batch.capacity();
+        for received in 1..=records {
+            let before = batch.capacity();
+            batch.push(0);
+            if batch.capacity() != before {
+                reallocations += 1;
+            }
+            peak_capacity = peak_capacity.max(batch.capacity());
+            if batch.len() >= batch_size {
+                batch = Vec::with_capacity(batch_capacity(batch_size, expected, received));
+            }
+        }
+        (reallocations, peak_capacity)
+    }
+
+    #[test]
+    fn estimate_scales_with_compressed_length() {
+        assert_eq!(estimate_record_count(0), 0);
+        assert_eq!(estimate_record_count(32 * 1000), 1000);
+    }
+
+    #[test]
+    fn capacity_hint_avoids_overallocating_small_uploads() {
+        let batch_size = 5000;
+
+        let (realloc_without, peak_without) = simulate_batching(100, batch_size, None);
+        let (realloc_with, peak_with) = simulate_batching(100, batch_size, Some(100));
+
+        assert_eq!(realloc_without, 0);
+        assert_eq!(realloc_with, 0);
+        assert_eq!(peak_without, batch_size);
+        assert_eq!(peak_with, 100);
+    }
+
+    #[test]
+    fn capacity_hint_keeps_large_uploads_in_full_batches() {
+        let batch_size = 5000;
+        let (reallocations, peak) = simulate_batching(12_000, batch_size, Some(12_000));
+        assert_eq!(reallocations, 0);
+        assert_eq!(peak, batch_size);
+
+        // An underestimate costs a few doublings on the final batch, no more
+        let (reallocations, _) = simulate_batching(12_000, batch_size, Some(11_000));
+        assert!(reallocations <= 4, "got {reallocations} reallocations");
+    }
 }
@@ -0,0 +1,301 @@
+use futures_util::StreamExt;
+use serde_json::{Map, Value};
+use sqlx::{FromRow, PgPool, postgres::PgRow};
+use std::fmt::Write;
+use tokio::sync::mpsc;
+
+use crate::{
+    error::AppError,
+    models::record::{BulkInsertable, Gottcha2FullRecord},
+};
+
+/// Rows buffered between the export query and the response body
+pub(crate) const EXPORT_BUFFER: usize = 256;
+
+/// Columns of `gottcha2_results` that may be used as equality filters.
+pub const GOTTCHA2_FILTER_COLUMNS: &[&str] = &["sample_id", "level", "name", "taxid"];
+
+/// Counts `gottcha2_results` rows matching every `(column, value)` equality
+/// filter. Column names are checked against `GOTTCHA2_FILTER_COLUMNS` and
+/// values are always bound as parameters.
+///
+/// # Errors
+///
+/// Returns `AppError::BadRequest` for a column outside the allowlist, or an
+/// internal error if the query fails.
+pub async fn count_gottcha2_where(
+    db: &PgPool,
+    filters: &[(String, String)],
+) -> Result<i64, AppError> {
+    let mut query = String::from("SELECT COUNT(*) FROM gottcha2_results");
+
+    for (i, (column, _)) in filters.iter().enumerate() {
+        if !GOTTCHA2_FILTER_COLUMNS.contains(&column.as_str()) {
+            return Err(AppError::BadRequest(format!(
+                "unknown filter column: {column}"
+            )));
+        }
+        let keyword = if i == 0 { " WHERE" } else { " AND" };
+        write!(&mut query, "{keyword} {column} = ${}", i + 1).expect("Failed to write to string");
+    }
+
+    let mut q = sqlx::query_scalar::<_, i64>(&query);
+    for (_, value) in filters {
+        q = q.bind(value);
+    }
+
+    q.fetch_one(db)
+        .await
+        .map_err(|e| AppError::InternalServerError(format!("count query failed: {e}")))
+}
+
+/// SQL type of an updatable column, used to check and bind incoming JSON values.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum ColumnType {
+    Text,
+    BigInt,
+    Double,
+}
+
+/// Columns of `gottcha2_results` that `PATCH /gottcha2/{id}` may change. The
+/// `id` key, `created_at`, and the `raw_line` provenance are deliberately
+/// absent.
+const GOTTCHA2_MUTABLE_COLUMNS: &[(&str, ColumnType)] = &[
+    ("sample_id", ColumnType::Text),
+    ("level", ColumnType::Text),
+    ("name", ColumnType::Text),
+    ("taxid", ColumnType::Text),
+    ("read_count", ColumnType::BigInt),
+    ("total_bp_mapped", ColumnType::BigInt),
+    ("ani_ci95", ColumnType::Double),
+    ("covered_sig_len", ColumnType::BigInt),
+    ("best_sig_cov", ColumnType::Double),
+    ("depth", ColumnType::Double),
+    ("rel_abundance", ColumnType::Double),
+];
+
+/// The `SET` list of an update, its parameters numbered from `$1`, and the
+/// values to bind to them in order.
+struct SetClause<'a> {
+    sql: String,
+    values: Vec<(ColumnType, &'a Value)>,
+}
+
+/// Checks `fields` against `GOTTCHA2_MUTABLE_COLUMNS` and builds the `SET`
+/// list for them.
+fn gottcha2_set_clause(fields: &Map<String, Value>) -> Result<SetClause<'_>, AppError> {
+    if fields.is_empty() {
+        return Err(AppError::BadRequest("no fields to update".to_string()));
+    }
+
+    let mut sql = String::new();
+    let mut values = Vec::with_capacity(fields.len());
+    for (i, (column, value)) in fields.iter().enumerate() {
+        let Some(&(_, column_type)) = GOTTCHA2_MUTABLE_COLUMNS
+            .iter()
+            .find(|(name, _)| *name == column.as_str())
+        else {
+            return Err(AppError::BadRequest(format!(
+                "column cannot be updated: {column}"
+            )));
+        };
+        let type_ok = match column_type {
+            ColumnType::Text => value.is_string(),
+            ColumnType::BigInt => value.is_i64(),
+            ColumnType::Double => value.is_number(),
+        };
+        if !type_ok {
+            return Err(AppError::BadRequest(format!(
+                "wrong type for column {column}: {value}"
+            )));
+        }
+        let separator = if i == 0 { "" } else { ", " };
+        write!(&mut sql, "{separator}{column} = ${}", i + 1).expect("Failed to write to string");
+        values.push((column_type, value));
+    }
+    Ok(SetClause { sql, values })
+}
+
+/// Updates the given columns of one `gottcha2_results` row and returns the
+/// row as it now stands. Column names are checked against
+/// `GOTTCHA2_MUTABLE_COLUMNS` and values are always bound as parameters.
+///
+/// The row is locked and `fields` merged into it first, and `prepare` sees
+/// the merged record, so an update can't store a row an upload would have
+/// been refused for. The record's `content_hash` after `prepare` replaces the
+/// stored one, which is cleared if `prepare` sets none, as it no longer
+/// describes the row.
+///
+/// # Errors
+///
+/// Returns `AppError::BadRequest` if `fields` is empty, names a column outside
+/// the allowlist, or holds a value of the wrong type; whatever `prepare`
+/// returns for the merged record; `AppError::NotFound` if no row has this
+/// `id`; `AppError::Conflict` if the update would give the row the same
+/// `(sample_id, taxid, level)` as another; or an internal error if the query
+/// fails.
+pub async fn update_gottcha2_row<F>(
+    db: &PgPool,
+    id: i64,
+    fields: &Map<String, Value>,
+    prepare: F,
+) -> Result<Value, AppError>
+where
+    F: FnOnce(&mut Gottcha2FullRecord) -> Result<(), AppError>,
+{
+    let set = gottcha2_set_clause(fields)?;
+
+    let update_error = |e: sqlx::Error| match e {
+        sqlx::Error::Database(db_err) if db_err.is_unique_violation() => {
+            AppError::Conflict(format!(
+                "another row already has this sample_id, taxid and level: {}",
+                db_err.message()
+            ))
+        }
+        e => AppError::InternalServerError(format!("update query failed: {e}")),
+    };
+    let mut tx = db.begin().await.map_err(update_error)?;
+
+    let current: Value = sqlx::query_scalar(
+        "SELECT to_jsonb(gottcha2_results) FROM gottcha2_results WHERE id = $1 FOR UPDATE",
+    )
+    .bind(id)
+    .fetch_optional(&mut *tx)
+    .await
+    .map_err(update_error)?
+    .ok_or_else(|| AppError::NotFound(format!("no gottcha2 row with id {id}")))?;
+    let Value::Object(mut merged) = current else {
+        return Err(AppError::InternalServerError(format!(
+            "gottcha2 row {id} did not read back as an object"
+        )));
+    };
+    merged.extend(fields.iter().map(|(k, v)| (k.clone(), v.clone())));
+    let mut record: Gottcha2FullRecord = serde_json::from_value(Value::Object(merged))
+        .map_err(|e| AppError::BadRequest(format!("updated row is not a valid record: {e}")))?;
+    prepare(&mut record)?;
+
+    let query = format!(
+        "UPDATE gottcha2_results SET {}, content_hash = ${} WHERE id = ${} \
+         RETURNING to_jsonb(gottcha2_results)",
+        set.sql,
+        set.values.len() + 1,
+        set.values.len() + 2
+    );
+    let mut q = sqlx::query_scalar::<_, Value>(&query);
+    for (column_type, value) in set.values {
+        q = match column_type {
+            ColumnType::Text => q.bind(value.as_str()),
+            ColumnType::BigInt => q.bind(value.as_i64()),
+            ColumnType::Double => q.bind(value.as_f64()),
+        };
+    }
+    let row = q
+        .bind(record.content_hash)
+        .bind(id)
+        .fetch_one(&mut *tx)
+        .await
+        .map_err(update_error)?;
+    tx.commit().await.map_err(update_error)?;
+    Ok(row)
+}
+
+/// Deletes every row for `sample_id` from each of `tables` in one
+/// transaction, returning how many rows were deleted in all.
+///
+/// Takes the same per-table advisory locks as a sample replacement, so a
+/// purge never interleaves with a `PUT` of the same sample.
+///
+/// # Errors
+///
+/// Returns an internal error if any delete fails, in which case no rows are
+/// deleted.
+pub async fn delete_sample_rows(
+    db: &PgPool,
+    sample_id: &str,
+    tables: &[&str],
+) -> Result<u64, AppError> {
+    let delete_error =
+        |e: sqlx::Error| AppError::InternalServerError(format!("delete failed: {e}"));
+    let mut tx = db.begin().await.map_err(delete_error)?;
+
+    let mut deleted = 0;
+    for table in tables {
+        sqlx::query("SELECT pg_advisory_xact_lock(hashtextextended($1, 0))")
+            .bind(format!("{table}:{sample_id}"))
+            .execute(&mut *tx)
+            .await
+            .map_err(delete_error)?;
+        deleted += sqlx::query(&format!("DELETE FROM {table} WHERE sample_id = $1"))
+            .bind(sample_id)
+            .execute(&mut *tx)
+            .await
+            .map_err(delete_error)?
+            .rows_affected();
+    }
+
+    tx.commit().await.map_err(delete_error)?;
+    Ok(deleted)
+}
+
+/// Fetches up to `limit` `T::table_name()` rows for `sample_id`, in the
+/// same order `stream_sample_rows` streams them.
+///
+/// # Errors
+///
+/// Returns an internal error if the query fails.
+pub async fn fetch_sample_rows<T>(
+    db: &PgPool,
+    sample_id: &str,
+    limit: i64,
+) -> Result<Vec<T>, AppError>
+where
+    T: for<'r> FromRow<'r, PgRow> + BulkInsertable + Send + Unpin,
+{
+    let query = format!(
+        "SELECT {} FROM {} WHERE sample_id = $1 ORDER BY source_line NULLS LAST, id LIMIT $2",
+        T::column_names(),
+        T::table_name()
+    );
+    sqlx::query_as::<_, T>(&query)
+        .bind(sample_id)
+        .bind(limit)
+        .fetch_all(db)
+        .await
+        .map_err(|e| AppError::InternalServerError(format!("sample query failed: {e}")))
+}
+
+/// Streams every `T::table_name()` row for `sample_id` through the returned
+/// channel. Rows tagged with a `source_line` come back in their original
+/// file order; the rest follow in insertion order. Ordering is by line
+/// number alone, so a sample ingested from several files interleaves them.
+///
+/// The query runs on its own task so the response body can stream rows as
+/// they arrive; it stops early if the receiver is dropped, and ends after
+/// forwarding the first error.
+#[must_use]
+pub fn stream_sample_rows<T>(
+    db: PgPool,
+    sample_id: String,
+) -> mpsc::Receiver<Result<T, sqlx::Error>>
+where
+    T: for<'r> FromRow<'r, PgRow> + BulkInsertable + Send + Unpin + 'static,
+{
+    let (tx, rx) = mpsc::channel(EXPORT_BUFFER);
+
+    tokio::spawn(async move {
+        let query = format!(
+            "SELECT {} FROM {} WHERE sample_id = $1 ORDER BY source_line NULLS LAST, id",
+            T::column_names(),
+            T::table_name()
+        );
+        let mut rows = sqlx::query_as::<_, T>(&query).bind(sample_id).fetch(&db);
+        while let Some(row) = rows.next().await {
+            let failed = row.is_err();
+            if tx.send(row).await.is_err() || failed {
+                break;
+            }
+        }
+    });
+
+    rx
+}
@@ -1,12 +1,60 @@
 use axum::{
-    http::StatusCode,
+    Json,
+    http::{StatusCode, header},
     response::{IntoResponse, Response},
 };
+use serde_json::json;
+
+use crate::{middleware::RequestId, models::validation::FieldError};
+
+/// `msg`, followed by the id of the request it answers when there is one,
+/// so a client reporting the error can quote the id.
+fn with_request_id(msg: &str) -> String {
+    match RequestId::current() {
+        Some(RequestId(id)) => format!("{msg} (request id {id})"),
+        None => msg.to_string(),
+    }
+}
 
 #[derive(Debug)]
 pub enum AppError {
     Unauthorized,
     BadRequest(String),
+    IngestionPaused,
+    /// Ingestion is paused for maintenance the operator explained; carries
+    /// their message and, if they gave one, when to retry
+    Maintenance {
+        message: String,
+        retry_after_secs: Option<u64>,
+    },
+    /// The server is shutting down and only finishing in-flight ingests;
+    /// carries the seconds a client should wait before retrying
+    ShuttingDown {
+        retry_after_secs: u64,
+    },
+    /// The caller's token already has as many ingests in flight as it's
+    /// allowed
+    TooManyIngests,
+    /// Every `max_concurrent_reads` query slot is taken
+    TooManyReads,
+    /// The client stopped sending the request body mid-upload
+    ReadTimeout,
+    /// The body decompressed to more than `max_decompressed_bytes`
+    PayloadTooLarge(String),
+    /// The upload's `Content-Type` isn't one the ingest endpoints read
+    UnsupportedMediaType(String),
+    /// A dependency such as the database is temporarily unavailable; the
+    /// client should retry later
+    ServiceUnavailable(String),
+    /// A record field failed validation; reported to the client as JSON
+    InvalidField(FieldError),
+    /// The requested row does not exist
+    NotFound(String),
+    /// The insert violated a table constraint, e.g. a duplicate primary key
+    Conflict(String),
+    /// The parser could not hand records to the inserter because it stopped
+    /// listening; the inserter's own error is the real cause
+    ChannelClosed,
     InternalServerError(String),
 }
 
@@ -14,10 +62,84 @@ impl IntoResponse for AppError {
     fn into_response(self) -> Response {
         match self {
             AppError::Unauthorized => (StatusCode::UNAUTHORIZED, "").into_response(),
-            AppError::BadRequest(msg) => (StatusCode::BAD_REQUEST, msg).into_response(),
+            AppError::BadRequest(msg) => {
+                (StatusCode::BAD_REQUEST, with_request_id(&msg)).into_response()
+            }
+            AppError::IngestionPaused => (
+                StatusCode::SERVICE_UNAVAILABLE,
+                Json(json!({ "error": "ingestion_paused" })),
+            )
+                .into_response(),
+            AppError::Maintenance {
+                message,
+                retry_after_secs,
+            } => {
+                let body = Json(json!({
+                    "error": "maintenance",
+                    "message": message,
+                    "retry_after": retry_after_secs,
+                }));
+                match retry_after_secs {
+                    Some(secs) => (
+                        StatusCode::SERVICE_UNAVAILABLE,
+                        [(header::RETRY_AFTER, secs.to_string())],
+                        body,
+                    )
+                        .into_response(),
+                    None => (StatusCode::SERVICE_UNAVAILABLE, body).into_response(),
+                }
+            }
+            AppError::ShuttingDown { retry_after_secs } => (
+                StatusCode::SERVICE_UNAVAILABLE,
+                [(header::RETRY_AFTER, retry_after_secs.to_string())],
+                Json(json!({ "error": "shutting_down" })),
+            )
+                .into_response(),
+            AppError::TooManyIngests => (
+                StatusCode::TOO_MANY_REQUESTS,
+                Json(json!({ "error": "too_many_concurrent_ingests" })),
+            )
+                .into_response(),
+            AppError::TooManyReads => (
+                StatusCode::SERVICE_UNAVAILABLE,
+                Json(json!({ "error": "too_many_concurrent_reads" })),
+            )
+                .into_response(),
+            AppError::ReadTimeout => (
+                StatusCode::REQUEST_TIMEOUT,
+                "request body stalled; upload aborted",
+            )
+                .into_response(),
+            AppError::PayloadTooLarge(msg) => (StatusCode::PAYLOAD_TOO_LARGE, msg).into_response(),
+            AppError::UnsupportedMediaType(msg) => {
+                (StatusCode::UNSUPPORTED_MEDIA_TYPE, msg).into_response()
+            }
+            AppError::ServiceUnavailable(msg) => {
+                tracing::warn!("Service unavailable: {}", msg);
+                (
+                    StatusCode::SERVICE_UNAVAILABLE,
+                    "service temporarily unavailable",
+                )
+                    .into_response()
+            }
+            AppError::InvalidField(err) => (StatusCode::BAD_REQUEST, Json(err)).into_response(),
+            AppError::NotFound(msg) => (StatusCode::NOT_FOUND, msg).into_response(),
+            AppError::Conflict(msg) => (StatusCode::CONFLICT, msg).into_response(),
+            AppError::ChannelClosed => {
+                tracing::error!("Internal server error: record channel closed");
+                (
+                    StatusCode::INTERNAL_SERVER_ERROR,
+                    with_request_id("internal server error"),
+                )
+                    .into_response()
+            }
             AppError::InternalServerError(msg) => {
                 tracing::error!("Internal server error: {}", msg);
-                (StatusCode::INTERNAL_SERVER_ERROR, "internal server error").into_response()
+                (
+                    StatusCode::INTERNAL_SERVER_ERROR,
+                    with_request_id("internal server error"),
+                )
+                    .into_response()
             }
         }
     }
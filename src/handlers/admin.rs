@@ -0,0 +1,63 @@
+use axum::{
+    Json,
+    extract::State,
+    http::{HeaderMap, StatusCode},
+    response::IntoResponse,
+};
+use serde::Deserialize;
+use serde_json::json;
+
+use crate::{
+    middleware::validate_admin_token,
+    state::{AppState, MaintenanceNotice},
+};
+
+/// Optional body of `POST /admin/pause`, explaining the pause to clients.
+#[derive(Deserialize, Default)]
+pub struct PauseRequest {
+    /// Overrides `MAINTENANCE_MESSAGE` for this pause
+    pub message: Option<String>,
+    pub retry_after_secs: Option<u64>,
+}
+
+pub async fn pause_ingestion(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    request: Option<Json<PauseRequest>>,
+) -> impl IntoResponse {
+    if let Err(e) = validate_admin_token(&state, &headers) {
+        return e.into_response();
+    }
+
+    let request = request.map(|Json(request)| request).unwrap_or_default();
+    let message = request
+        .message
+        .or_else(|| state.config.maintenance_message.clone());
+    state.set_maintenance(message.clone().map(|message| MaintenanceNotice {
+        message,
+        retry_after_secs: request.retry_after_secs,
+    }));
+    state.set_paused(true);
+    tracing::warn!("Ingestion paused by admin request; ingest endpoints will return 503");
+
+    (
+        StatusCode::OK,
+        Json(json!({ "paused": true, "message": message })),
+    )
+        .into_response()
+}
+
+pub async fn resume_ingestion(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+) -> impl IntoResponse {
+    if let Err(e) = validate_admin_token(&state, &headers) {
+        return e.into_response();
+    }
+
+    state.set_paused(false);
+    state.set_maintenance(None);
+    tracing::info!("Ingestion resumed by admin request");
+
+    (StatusCode::OK, Json(json!({ "paused": false }))).into_response()
+}
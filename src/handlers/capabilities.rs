@@ -0,0 +1,37 @@
+use axum::{Json, extract::State, response::IntoResponse};
+use serde_json::json;
+
+use super::ingest::RECORD_TYPES;
+use crate::{
+    router::ingest_routes,
+    services::parsing::{CONTENT_ENCODINGS, CONTENT_TYPES},
+    state::AppState,
+};
+
+/// Describes what this server accepts, derived from the running
+/// configuration so clients can configure themselves without out-of-band
+/// docs. Unauthenticated, and deliberately free of secrets.
+pub async fn capabilities(State(state): State<AppState>) -> impl IntoResponse {
+    let config = &state.config;
+    let routes: Vec<&str> = ingest_routes().into_iter().map(|(path, _)| path).collect();
+
+    Json(json!({
+        "version": env!("CARGO_PKG_VERSION"),
+        "content_encodings": CONTENT_ENCODINGS,
+        "content_types": CONTENT_TYPES,
+        "ingest_routes": routes,
+        "record_types": RECORD_TYPES,
+        "limits": {
+            "max_line_bytes": config.max_line_bytes,
+            "max_decompressed_bytes": config.max_decompressed_bytes,
+            "max_concurrent_ingests_per_token": config.max_concurrent_ingests_per_token,
+            "max_concurrent_reads": config.max_concurrent_reads,
+            "request_timeout_secs": config.request_timeout_secs,
+            "read_idle_secs": config.read_idle_secs,
+        },
+        "auth": {
+            "mode": "bearer",
+            "separate_admin_token": config.admin_token.is_some(),
+        },
+    }))
+}
@@ -0,0 +1,67 @@
+use axum::{
+    extract::{Query, State},
+    http::HeaderMap,
+    response::IntoResponse,
+};
+use serde::Deserialize;
+use serde_json::json;
+
+use super::query::{jsonl_line, row_lines};
+use crate::{
+    db::dead_letters::{delete_dead_letters, stream_dead_letters},
+    middleware::validate_bearer_token,
+    services::encoding::ResponseEncoding,
+    state::AppState,
+};
+
+/// Dead letters returned when a request gives no `limit`.
+const DEFAULT_LIMIT: u32 = 100;
+
+#[derive(Deserialize)]
+pub struct DeadLetterQuery {
+    /// Only dead letters uploaded to this route, e.g. `ingest-gottcha2`
+    pub endpoint: Option<String>,
+    /// Capped at `dead_letters_max_limit`
+    pub limit: Option<u32>,
+    pub encoding: Option<ResponseEncoding>,
+}
+
+/// Streams stored dead letters, oldest first, as JSONL.
+pub async fn list_dead_letters(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    Query(query): Query<DeadLetterQuery>,
+) -> impl IntoResponse {
+    if let Err(e) = validate_bearer_token(&state, &headers) {
+        return e.into_response();
+    }
+
+    let slot = match state.acquire_read_slot() {
+        Ok(slot) => slot,
+        Err(e) => return e.into_response(),
+    };
+
+    let limit = query
+        .limit
+        .unwrap_or(DEFAULT_LIMIT)
+        .min(state.config.dead_letters_max_limit);
+    let encoding = ResponseEncoding::negotiate(query.encoding, &headers);
+    let rows = stream_dead_letters(state.db.clone(), query.endpoint, i64::from(limit));
+    encoding.respond("application/x-ndjson", row_lines(rows, slot, jsonl_line))
+}
+
+/// Deletes stored dead letters, all of them or only one `endpoint`'s.
+pub async fn clear_dead_letters(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    Query(query): Query<DeadLetterQuery>,
+) -> impl IntoResponse {
+    if let Err(e) = validate_bearer_token(&state, &headers) {
+        return e.into_response();
+    }
+
+    match delete_dead_letters(&state.db, query.endpoint.as_deref()).await {
+        Ok(deleted) => super::serialized(&headers, &json!({ "deleted": deleted })),
+        Err(e) => e.into_response(),
+    }
+}
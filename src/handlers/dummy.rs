@@ -1,14 +1,13 @@
-use axum::{
-    body::Body,
-    extract::State,
-    http::{HeaderMap, StatusCode},
-    response::IntoResponse,
-};
-use tokio::sync::mpsc;
+use axum::{body::Body, extract::State, http::HeaderMap, response::IntoResponse};
+use serde_json::json;
+use std::time::Duration;
 
+use super::IngestRoute;
 use crate::{
-    db::operations::batch_insert_dummy, middleware::validate_bearer_token,
-    services::parsing::parse_gzipped_jsonl, state::AppState,
+    db::operations::{InsertOptions, batch_insert_dummy},
+    models::record::DummyRecord,
+    services::parsing::Verdict,
+    state::AppState,
 };
 
 pub async fn ingest_dummy(
@@ -16,18 +15,33 @@ pub async fn ingest_dummy(
     headers: HeaderMap,
     body: Body,
 ) -> impl IntoResponse {
-    if let Err(e) = validate_bearer_token(&state, &headers) {
-        return e.into_response();
-    }
+    let route = IngestRoute {
+        endpoint: "ingest",
+        path: "/ingest",
+        batch_size: state.config.dummy_batch_size,
+    };
+    let ttl = state.config.idempotency_ttl_secs.map(Duration::from_secs);
+    let insert = |rx, options: InsertOptions| {
+        batch_insert_dummy(rx, &state.db, options.with_idempotency_ttl(ttl))
+    };
 
-    let (tx, rx) = mpsc::channel(1000);
+    let check = |_: &DummyRecord| Verdict::Keep;
 
-    let parser = parse_gzipped_jsonl(body, tx);
-    let inserter = batch_insert_dummy(rx, &state.db);
+    let (summary, inserted, mut response) =
+        match super::ingest_upload(&state, &headers, &route, None, body, check, insert).await {
+            Ok(ingested) => ingested,
+            Err(response) => return response,
+        };
 
-    if let Err(e) = tokio::try_join!(parser, inserter) {
-        return e.into_response();
+    if state.config.echo_idempotency_stats {
+        // Records are accepted unless they hit ON CONFLICT (idempotency_key),
+        // so the shortfall is exactly the number of replayed keys
+        let received = summary.accepted as u64;
+        response.insert("received".to_string(), json!(received));
+        response.insert(
+            "deduplicated".to_string(),
+            json!(received.saturating_sub(inserted)),
+        );
     }
-
-    (StatusCode::OK, "ingested").into_response()
+    super::ack_response(&headers, response, None)
 }
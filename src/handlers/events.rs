@@ -0,0 +1,49 @@
+use std::convert::Infallible;
+
+use axum::{
+    extract::State,
+    http::HeaderMap,
+    response::{
+        IntoResponse,
+        sse::{Event, KeepAlive, Sse},
+    },
+};
+use futures_util::stream::{self, Stream};
+use tokio::sync::broadcast::{self, error::RecvError};
+
+use crate::{middleware::validate_bearer_token, services::events::IngestEvent, state::AppState};
+
+/// Streams ingest start/complete/error events as Server-Sent Events.
+/// Subscribers that fall more than `EVENT_BUFFER` events behind silently
+/// miss the oldest ones rather than slowing ingestion down.
+pub async fn stream_events(State(state): State<AppState>, headers: HeaderMap) -> impl IntoResponse {
+    if let Err(e) = validate_bearer_token(&state, &headers) {
+        return e.into_response();
+    }
+
+    Sse::new(event_stream(state.subscribe()))
+        .keep_alive(KeepAlive::default())
+        .into_response()
+}
+
+fn event_stream(
+    rx: broadcast::Receiver<IngestEvent>,
+) -> impl Stream<Item = Result<Event, Infallible>> {
+    stream::unfold(rx, |mut rx| async move {
+        loop {
+            match rx.recv().await {
+                Ok(event) => {
+                    let sse = Event::default()
+                        .event(event.name())
+                        .json_data(&event)
+                        .unwrap_or_default();
+                    return Some((Ok(sse), rx));
+                }
+                Err(RecvError::Lagged(missed)) => {
+                    tracing::warn!("/events subscriber fell behind; dropped {missed} events");
+                }
+                Err(RecvError::Closed) => return None,
+            }
+        }
+    })
+}
@@ -1,33 +1,166 @@
 use axum::{
     body::Body,
-    extract::State,
-    http::{HeaderMap, StatusCode},
+    extract::{Path, Query, State},
+    http::HeaderMap,
     response::IntoResponse,
 };
+use serde_json::{Map, json};
 use tokio::sync::mpsc;
+use tracing::Instrument;
 
+use super::IngestRoute;
 use crate::{
-    db::operations::batch_insert_gottcha2, middleware::validate_bearer_token,
-    services::parsing::parse_gzipped_jsonl, state::AppState,
+    config::InvalidRowPolicy,
+    db::operations::{batch_insert_gottcha2, replace_sample_from_channel},
+    error::AppError,
+    middleware::{bearer_token, validate_bearer_token},
+    models::{record::Gottcha2FullRecord, sample_id::SampleId},
+    services::breakdown::{BreakdownQuery, SampleCounts},
+    services::events,
+    services::parsing::{FormatQuery, Verdict, parse_gzipped_jsonl_with},
+    services::pipeline::join_ingest,
+    state::AppState,
 };
 
+/// The taxonomic-level and range checks every uploaded GOTTCHA2 record
+/// must pass.
+pub(super) fn check_record(record: &Gottcha2FullRecord, strict_levels: bool) -> Verdict {
+    if strict_levels && let Err(reason) = record.validate_level() {
+        return Verdict::Reject(reason);
+    }
+    if let Err(violation) = record.validate_ranges() {
+        return Verdict::Invalid(violation);
+    }
+    Verdict::Keep
+}
+
 pub async fn ingest_gottcha2(
     State(state): State<AppState>,
     headers: HeaderMap,
+    Query(format): Query<FormatQuery>,
+    Query(breakdown): Query<BreakdownQuery>,
+    body: Body,
+) -> impl IntoResponse {
+    let route = IngestRoute {
+        endpoint: "ingest-gottcha2",
+        path: "/ingest-gottcha2",
+        batch_size: state.config.gottcha2_batch_size,
+    };
+    let strict = state.config.strict_taxonomic_levels;
+    let mut by_sample = breakdown.breakdown.then(SampleCounts::default);
+    let check = |record: &Gottcha2FullRecord| {
+        let verdict = check_record(record, strict);
+        if verdict == Verdict::Keep
+            && let Some(counts) = by_sample.as_mut()
+        {
+            counts.record(&record.sample_id);
+        }
+        verdict
+    };
+    let insert = |rx, options| batch_insert_gottcha2(rx, &state.db, options);
+
+    match super::ingest_upload(&state, &headers, &route, format.format, body, check, insert).await {
+        Ok((_, _, response)) => super::ack_response(&headers, response, by_sample),
+        Err(response) => response,
+    }
+}
+
+/// Replaces every row for the sample in the path with the uploaded records,
+/// in one transaction. The path's sample ID overrides any in the body.
+pub async fn replace_gottcha2(
+    State(state): State<AppState>,
+    Path(sample_id): Path<String>,
+    headers: HeaderMap,
+    Query(format): Query<FormatQuery>,
     body: Body,
 ) -> impl IntoResponse {
     if let Err(e) = validate_bearer_token(&state, &headers) {
         return e.into_response();
     }
 
+    if state.is_paused() {
+        return state.pause_error().into_response();
+    }
+
+    if let Some(retry_after_secs) = state.drain_retry_after_secs() {
+        return AppError::ShuttingDown { retry_after_secs }.into_response();
+    }
+
+    let sample_id = match SampleId::normalized(&sample_id, state.config.lowercase_sample_ids) {
+        Ok(id) => id,
+        Err(reason) => return AppError::BadRequest(reason).into_response(),
+    };
+
+    let idempotency_key = match super::idempotency_key(&state, &headers, "replace-gottcha2").await {
+        Ok(key) => key,
+        Err(response) => return response,
+    };
+
+    let _slot = match state.acquire_ingest_slot(bearer_token(&headers).unwrap_or_default()) {
+        Ok(slot) => slot,
+        Err(e) => return e.into_response(),
+    };
+
     let (tx, rx) = mpsc::channel(1000);
 
-    let parser = parse_gzipped_jsonl(body, tx);
-    let inserter = batch_insert_gottcha2(rx, &state.db);
+    let declared = match super::declared_record_count(&headers) {
+        Ok(declared) => declared,
+        Err(e) => return e.into_response(),
+    };
 
-    if let Err(e) = tokio::try_join!(parser, inserter) {
-        return e.into_response();
+    let mut options =
+        match super::parse_options::<Gottcha2FullRecord>(&state, &headers, format.format) {
+            Ok(options) => options,
+            Err(e) => return e.into_response(),
+        };
+    options.sample_id_override = Some(sample_id.clone());
+    // The replacement is one transaction anyway, so a count mismatch always
+    // rolls it back and keeps the old rows
+    options.expected_records = declared;
+    let strict = options.strict_taxonomic_levels;
+    let skipping = options.on_invalid_row == InvalidRowPolicy::Skip;
+    let parser = parse_gzipped_jsonl_with(body, tx, options, |record| check_record(record, strict));
+    let batch_size = state
+        .config
+        .batch_size_for::<Gottcha2FullRecord>(state.config.gottcha2_batch_size);
+    let sql_logging = super::sql_logging(&state, &headers);
+    let insert_options = super::insert_options(&state, batch_size, sql_logging)
+        .with_expected_records(declared.or_else(|| super::expected_records(&headers)));
+    let mut deleted = 0;
+    let inserter = async {
+        let (rows_deleted, rows_inserted) =
+            replace_sample_from_channel(rx, &state.db, &sample_id, insert_options).await?;
+        deleted = rows_deleted;
+        Ok(rows_inserted)
+    }
+    .instrument(super::sql_span(sql_logging, "/gottcha2/{sample_id}"));
+
+    let claim =
+        match super::claim_idempotency_key(&state, &headers, idempotency_key, "replace-gottcha2")
+            .await
+        {
+            Ok(claim) => claim,
+            Err(response) => return response,
+        };
+    let (summary, rows_inserted) = match events::track(
+        &state,
+        "/gottcha2/{sample_id}",
+        join_ingest(parser, inserter),
+    )
+    .await
+    {
+        Ok(counts) => counts,
+        Err(response) => return super::release_idempotency_key(claim, response).await,
+    };
+    super::store_dead_letters(&state, "replace-gottcha2", &summary.dead_letters).await;
+
+    if let Err(response) = super::settle_ingest(claim, declared, &summary, rows_inserted).await {
+        return response;
     }
 
-    (StatusCode::OK, "ingested").into_response()
+    let mut response = Map::new();
+    response.insert("deleted".to_string(), json!(deleted));
+    response.insert("inserted".to_string(), json!(rows_inserted));
+    super::insert_line_counts(&state, &mut response, &summary, skipping);
+    super::ack_response(&headers, response, None)
 }
@@ -1,3 +1,57 @@
-pub async fn healthz() -> &'static str {
-    "ok"
+use axum::{
+    Json,
+    extract::{Query, State},
+    response::{IntoResponse, Response},
+};
+use serde::{Deserialize, Serialize};
+
+use crate::{db::health, state::AppState};
+
+/// How `/healthz` answers: a JSON status (the default), or a bare `ok` for
+/// probes that only compare the body text.
+#[derive(Debug, Default, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum HealthFormat {
+    #[default]
+    Json,
+    Plain,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct HealthQuery {
+    #[serde(default)]
+    pub format: HealthFormat,
+}
+
+#[derive(Debug, Serialize)]
+pub struct HealthStatus {
+    pub status: &'static str,
+    pub version: &'static str,
+    /// The commit the binary was built from; `None` outside a git checkout
+    pub git_commit: Option<&'static str>,
+    pub uptime_secs: u64,
+}
+
+/// Liveness: answers as long as the process is serving, with the build it's
+/// running and how long it has been up.
+pub async fn healthz(State(state): State<AppState>, Query(query): Query<HealthQuery>) -> Response {
+    match query.format {
+        HealthFormat::Plain => "ok".into_response(),
+        HealthFormat::Json => Json(HealthStatus {
+            status: "ok",
+            version: env!("CARGO_PKG_VERSION"),
+            git_commit: Some(env!("GIT_HASH")).filter(|hash| *hash != "unknown"),
+            uptime_secs: state.uptime().as_secs(),
+        })
+        .into_response(),
+    }
+}
+
+/// Readiness: `ready` only if the database answers the configured health
+/// check query, otherwise `503`.
+pub async fn readyz(State(state): State<AppState>) -> impl IntoResponse {
+    match health::check(&state.db, &state.config.health_check_query).await {
+        Ok(()) => "ready".into_response(),
+        Err(e) => e.into_response(),
+    }
 }
@@ -0,0 +1,37 @@
+use axum::{
+    extract::{Path, Request, State},
+    handler::Handler,
+    response::{IntoResponse, Response},
+};
+
+use super::{ingest_dummy, ingest_gottcha2, ingest_kraken2, ingest_stast};
+use crate::{error::AppError, middleware::validate_bearer_token, state::AppState};
+
+/// Record types `POST /ingest/{record_type}` accepts.
+pub const RECORD_TYPES: &[&str] = &["dummy", "gottcha2", "stast", "kraken2"];
+
+/// Ingests an upload of the record type named in the path, exactly as that
+/// type's dedicated route would, query parameters included. Unknown types
+/// get `404` once the caller has authenticated.
+pub async fn ingest_record_type(
+    State(state): State<AppState>,
+    Path(record_type): Path<String>,
+    request: Request,
+) -> Response {
+    match record_type.as_str() {
+        "dummy" => Handler::<_, AppState>::call(ingest_dummy, request, state).await,
+        "gottcha2" => Handler::<_, AppState>::call(ingest_gottcha2, request, state).await,
+        "stast" => Handler::<_, AppState>::call(ingest_stast, request, state).await,
+        "kraken2" => Handler::<_, AppState>::call(ingest_kraken2, request, state).await,
+        unknown => {
+            if let Err(e) = validate_bearer_token(&state, request.headers()) {
+                return e.into_response();
+            }
+            AppError::NotFound(format!(
+                "unknown record type {unknown:?}; expected one of {}",
+                RECORD_TYPES.join(", ")
+            ))
+            .into_response()
+        }
+    }
+}
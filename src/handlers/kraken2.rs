@@ -0,0 +1,57 @@
+use axum::{
+    body::Body,
+    extract::{Query, State},
+    http::HeaderMap,
+    response::IntoResponse,
+};
+
+use super::IngestRoute;
+use crate::{
+    db::operations::batch_insert_kraken2,
+    models::record::Kraken2Record,
+    services::breakdown::{BreakdownQuery, SampleCounts},
+    services::parsing::{FormatQuery, Verdict},
+    state::AppState,
+};
+
+/// The rank code and range checks every uploaded Kraken2 record must pass.
+fn check_record(record: &Kraken2Record, strict_ranks: bool) -> Verdict {
+    if strict_ranks && let Err(reason) = record.validate_rank_code() {
+        return Verdict::Reject(reason);
+    }
+    if let Err(violation) = record.validate_ranges() {
+        return Verdict::Invalid(violation);
+    }
+    Verdict::Keep
+}
+
+pub async fn ingest_kraken2(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    Query(format): Query<FormatQuery>,
+    Query(breakdown): Query<BreakdownQuery>,
+    body: Body,
+) -> impl IntoResponse {
+    let route = IngestRoute {
+        endpoint: "ingest-kraken2",
+        path: "/ingest-kraken2",
+        batch_size: state.config.kraken2_batch_size,
+    };
+    let strict = state.config.strict_taxonomic_levels;
+    let mut by_sample = breakdown.breakdown.then(SampleCounts::default);
+    let check = |record: &Kraken2Record| {
+        let verdict = check_record(record, strict);
+        if verdict == Verdict::Keep
+            && let Some(counts) = by_sample.as_mut()
+        {
+            counts.record(&record.sample_id);
+        }
+        verdict
+    };
+    let insert = |rx, options| batch_insert_kraken2(rx, &state.db, options);
+
+    match super::ingest_upload(&state, &headers, &route, format.format, body, check, insert).await {
+        Ok((_, _, response)) => super::ack_response(&headers, response, by_sample),
+        Err(response) => response,
+    }
+}
@@ -0,0 +1,13 @@
+use axum::{extract::State, http::header, response::IntoResponse};
+
+use crate::state::AppState;
+
+pub async fn metrics(State(state): State<AppState>) -> impl IntoResponse {
+    state
+        .metrics
+        .observe_pool(state.db.size(), state.db.num_idle());
+    (
+        [(header::CONTENT_TYPE, "text/plain; version=0.0.4")],
+        state.metrics.render(),
+    )
+}
@@ -1,9 +1,519 @@
+pub mod admin;
+pub mod capabilities;
+pub mod dead_letters;
 pub mod dummy;
+pub mod events;
 pub mod gottcha2;
 pub mod health;
+pub mod ingest;
+pub mod kraken2;
+pub mod metrics;
+pub mod query;
 pub mod stast;
 
+pub use admin::{pause_ingestion, resume_ingestion};
+pub use capabilities::capabilities;
+pub use dead_letters::{clear_dead_letters, list_dead_letters};
 pub use dummy::ingest_dummy;
-pub use gottcha2::ingest_gottcha2;
-pub use health::healthz;
+pub use events::stream_events;
+pub use gottcha2::{ingest_gottcha2, replace_gottcha2};
+pub use health::{healthz, readyz};
+pub use ingest::ingest_record_type;
+pub use kraken2::ingest_kraken2;
+pub use metrics::metrics;
+pub use query::{
+    count_gottcha2, delete_sample, export_gottcha2, export_stast, patch_gottcha2, sample_gottcha2,
+};
 pub use stast::ingest_stast;
+
+use axum::{
+    Json,
+    body::Body,
+    http::{
+        HeaderMap, StatusCode,
+        header::{ACCEPT, CONTENT_LENGTH, CONTENT_TYPE},
+    },
+    response::{IntoResponse, Response},
+};
+use serde::{Serialize, de::DeserializeOwned};
+use serde_json::{Map, Value, json};
+use std::{future::Future, time::Duration};
+use tokio::sync::mpsc;
+use tracing::{Instrument, Span};
+
+use crate::{
+    config::{InvalidRowPolicy, RecordCountCheck},
+    db::{
+        dead_letters::insert_dead_letters,
+        fault_injection::FaultInjection,
+        ingest_log::{IngestClaim, claim_ingest, find_ingest, record_ingest, release_ingest},
+        operations::{InsertOptions, SqlLogging, estimate_record_count},
+    },
+    error::AppError,
+    middleware::{bearer_token, validate_bearer_token},
+    models::{dead_letter::DeadLetter, record::BulkInsertable},
+    services::{
+        breakdown::SampleCounts,
+        encoding::ResponseEncoding,
+        events::track,
+        parsing::{
+            Compression, InputFormat, ParseOptions, ParseSummary, Verdict, check_content_type,
+            check_record_count, parse_gzipped_jsonl_with,
+        },
+        pipeline::join_ingest,
+    },
+    state::AppState,
+};
+
+/// What sets one ingest route apart from the others, for `ingest_upload`.
+pub(crate) struct IngestRoute {
+    /// Scopes the route's `Idempotency-Key`s and files its dead letters
+    pub endpoint: &'static str,
+    /// The route's path, naming it in events, metrics, and SQL logs
+    pub path: &'static str,
+    /// The table's own batch size, overriding `BATCH_SIZE`
+    pub batch_size: Option<usize>,
+}
+
+/// Runs an upload of `T` records to `route` from authentication through
+/// settling its `Idempotency-Key`: each record must pass `check`, and
+/// `insert` stores the records it is sent. Returns the upload's summary, the
+/// rows it inserted, and the acknowledgment fields every route sends,
+/// `inserted` and the line counts, for the caller to add its own to; the
+/// error is the response to send instead.
+pub(crate) async fn ingest_upload<T, C, I, F>(
+    state: &AppState,
+    headers: &HeaderMap,
+    route: &IngestRoute,
+    format: Option<InputFormat>,
+    body: Body,
+    check: C,
+    insert: I,
+) -> Result<(ParseSummary, u64, Map<String, Value>), Response>
+where
+    T: DeserializeOwned + Serialize + BulkInsertable,
+    C: FnMut(&T) -> Verdict,
+    I: FnOnce(mpsc::Receiver<T>, InsertOptions) -> F,
+    F: Future<Output = Result<u64, AppError>>,
+{
+    validate_bearer_token(state, headers).map_err(IntoResponse::into_response)?;
+
+    if state.is_paused() {
+        return Err(state.pause_error().into_response());
+    }
+
+    if let Some(retry_after_secs) = state.drain_retry_after_secs() {
+        return Err(AppError::ShuttingDown { retry_after_secs }.into_response());
+    }
+
+    let idempotency_key = idempotency_key(state, headers, route.endpoint).await?;
+
+    // Held until the upload is settled, whichever way it ends
+    let _slot = state
+        .acquire_ingest_slot(bearer_token(headers).unwrap_or_default())
+        .map_err(IntoResponse::into_response)?;
+
+    let (tx, rx) = mpsc::channel(1000);
+
+    let declared = declared_record_count(headers).map_err(IntoResponse::into_response)?;
+    let check_before =
+        declared.is_some() && state.config.expected_records_check == RecordCountCheck::Before;
+
+    let mut options =
+        parse_options::<T>(state, headers, format).map_err(IntoResponse::into_response)?;
+    if check_before {
+        options.expected_records = declared;
+    }
+    let skipping = options.on_invalid_row == InvalidRowPolicy::Skip;
+    // Boxed so the parser's sizable state doesn't bloat the handler's future
+    let parser = Box::pin(parse_gzipped_jsonl_with(body, tx, options, check));
+    let batch_size = state.config.batch_size_for::<T>(route.batch_size);
+    let sql_logging = sql_logging(state, headers);
+    let insert_options = insert_options(state, batch_size, sql_logging)
+        .with_expected_records(declared.or_else(|| expected_records(headers)))
+        .with_atomic(check_before || state.config.atomic_ingest);
+    let inserter = insert(rx, insert_options).instrument(sql_span(sql_logging, route.path));
+
+    let claim = claim_idempotency_key(state, headers, idempotency_key, route.endpoint).await?;
+    let (summary, rows_inserted) =
+        match track(state, route.path, join_ingest(parser, inserter)).await {
+            Ok(counts) => counts,
+            Err(response) => return Err(release_idempotency_key(claim, response).await),
+        };
+    store_dead_letters(state, route.endpoint, &summary.dead_letters).await;
+
+    settle_ingest(claim, declared, &summary, rows_inserted).await?;
+
+    let mut response = Map::new();
+    response.insert("inserted".to_string(), json!(rows_inserted));
+    insert_line_counts(state, &mut response, &summary, skipping);
+    Ok((summary, rows_inserted, response))
+}
+
+/// Derives a batch sizing hint from the upload's (compressed) `Content-Length`.
+pub(crate) fn expected_records(headers: &HeaderMap) -> Option<usize> {
+    headers
+        .get(CONTENT_LENGTH)?
+        .to_str()
+        .ok()?
+        .parse()
+        .ok()
+        .map(estimate_record_count)
+}
+
+/// The record count a client declared with `X-Expected-Records`.
+pub(crate) fn declared_record_count(headers: &HeaderMap) -> Result<Option<usize>, AppError> {
+    let Some(value) = headers.get("x-expected-records") else {
+        return Ok(None);
+    };
+    value
+        .to_str()
+        .ok()
+        .and_then(|v| v.trim().parse().ok())
+        .map(Some)
+        .ok_or_else(|| {
+            AppError::BadRequest("X-Expected-Records must be a non-negative integer".to_string())
+        })
+}
+
+/// Longest `Idempotency-Key` accepted.
+const MAX_IDEMPOTENCY_KEY_LEN: usize = 255;
+
+/// The key a client sent with `Idempotency-Key`. If an upload under that key
+/// to `endpoint` already succeeded, the error holds the response to send
+/// instead: its recorded row count, marked as a duplicate, without reading
+/// this body.
+pub(crate) async fn idempotency_key(
+    state: &AppState,
+    headers: &HeaderMap,
+    endpoint: &str,
+) -> Result<Option<String>, Response> {
+    let Some(value) = headers.get("idempotency-key") else {
+        return Ok(None);
+    };
+    let key = value
+        .to_str()
+        .ok()
+        .map(str::trim)
+        .filter(|key| !key.is_empty() && key.len() <= MAX_IDEMPOTENCY_KEY_LEN)
+        .ok_or_else(|| {
+            AppError::BadRequest(format!(
+                "Idempotency-Key must be 1 to {MAX_IDEMPOTENCY_KEY_LEN} visible ASCII characters"
+            ))
+            .into_response()
+        })?;
+    match find_ingest(&state.db, key, endpoint).await {
+        Ok(None) => Ok(Some(key.to_string())),
+        Ok(Some(inserted)) => Err(duplicate_ingest(headers, key, inserted)),
+        Err(e) => Err(e.into_response()),
+    }
+}
+
+/// The response to an upload whose `Idempotency-Key` already succeeded.
+fn duplicate_ingest(headers: &HeaderMap, key: &str, inserted: i64) -> Response {
+    tracing::info!("Idempotency-Key {key} was already ingested; skipping the upload");
+    serialized(
+        headers,
+        &json!({ "inserted": inserted, "status": "duplicate" }),
+    )
+}
+
+/// An `Idempotency-Key` held by the upload being processed under it. It is
+/// settled by `record_idempotency_key` on success or
+/// `release_idempotency_key` on failure; one dropped unsettled, as when the
+/// client disconnects, is released in the background.
+pub(crate) struct IdempotencyClaim {
+    db: sqlx::PgPool,
+    key: String,
+    endpoint: &'static str,
+    settled: bool,
+}
+
+impl Drop for IdempotencyClaim {
+    fn drop(&mut self) {
+        if self.settled {
+            return;
+        }
+        let (db, key, endpoint) = (
+            self.db.clone(),
+            std::mem::take(&mut self.key),
+            self.endpoint,
+        );
+        tokio::spawn(async move {
+            if let Err(e) = release_ingest(&db, &key, endpoint).await {
+                tracing::error!("Failed to release Idempotency-Key {key}: {e:?}");
+            }
+        });
+    }
+}
+
+/// Claims `key` for this upload just before its body is processed, so a
+/// second upload under the key can't run alongside it. The error holds the
+/// response to send instead: the recorded count if an upload under the key
+/// finished in the meantime, or `409` while one is still running.
+pub(crate) async fn claim_idempotency_key(
+    state: &AppState,
+    headers: &HeaderMap,
+    key: Option<String>,
+    endpoint: &'static str,
+) -> Result<Option<IdempotencyClaim>, Response> {
+    let Some(key) = key else {
+        return Ok(None);
+    };
+    // No request outlives its timeout, so an older claim was abandoned
+    let stale_after = Duration::from_secs(state.config.request_timeout_secs);
+    match claim_ingest(&state.db, &key, endpoint, stale_after).await {
+        Ok(IngestClaim::Claimed) => Ok(Some(IdempotencyClaim {
+            db: state.db.clone(),
+            key,
+            endpoint,
+            settled: false,
+        })),
+        Ok(IngestClaim::Done(inserted)) => Err(duplicate_ingest(headers, &key, inserted)),
+        Ok(IngestClaim::InProgress) => Err(AppError::Conflict(format!(
+            "an upload with Idempotency-Key {key} is already in progress"
+        ))
+        .into_response()),
+        Err(e) => Err(e.into_response()),
+    }
+}
+
+/// Records a successful upload's row count under its `Idempotency-Key`, if
+/// it claimed one. Its rows are already committed by now, so a failure here
+/// is logged rather than reported to the client.
+pub(crate) async fn record_idempotency_key(claim: Option<IdempotencyClaim>, inserted: u64) {
+    let Some(mut claim) = claim else {
+        return;
+    };
+    claim.settled = true;
+    match record_ingest(&claim.db, &claim.key, claim.endpoint, inserted).await {
+        Ok(true) => {}
+        Ok(false) => tracing::warn!(
+            "Idempotency-Key {} already had a recorded count; keeping that one",
+            claim.key
+        ),
+        Err(e) => tracing::error!("Failed to record Idempotency-Key {}: {e:?}", claim.key),
+    }
+}
+
+/// Releases a failed upload's claim on its `Idempotency-Key`, if it holds
+/// one, before passing on `response` reporting the failure, so the client's
+/// retry is processed.
+pub(crate) async fn release_idempotency_key(
+    claim: Option<IdempotencyClaim>,
+    response: Response,
+) -> Response {
+    if let Some(mut claim) = claim {
+        claim.settled = true;
+        if let Err(e) = release_ingest(&claim.db, &claim.key, claim.endpoint).await {
+            tracing::error!("Failed to release Idempotency-Key {}: {e:?}", claim.key);
+        }
+    }
+    response
+}
+
+/// Finishes an upload whose rows are in: if it declared a record count that
+/// doesn't match, the claim on its key is released and the error holds the
+/// response to send; otherwise its row count is recorded under the key.
+pub(crate) async fn settle_ingest(
+    claim: Option<IdempotencyClaim>,
+    declared: Option<usize>,
+    summary: &ParseSummary,
+    inserted: u64,
+) -> Result<(), Response> {
+    // Under `RecordCountCheck::Before` the parser already enforced this
+    if let Some(expected) = declared
+        && let Err(e) = check_record_count(expected, summary)
+    {
+        return Err(release_idempotency_key(claim, e.into_response()).await);
+    }
+    record_idempotency_key(claim, inserted).await;
+    Ok(())
+}
+
+/// Whether this request's INSERTs are logged: only when it sends
+/// `X-Debug-SQL: true` and `DEBUG_SQL` is enabled.
+pub(crate) fn sql_logging(state: &AppState, headers: &HeaderMap) -> SqlLogging {
+    let requested = headers
+        .get("x-debug-sql")
+        .and_then(|value| value.to_str().ok())
+        .is_some_and(|value| value.trim().eq_ignore_ascii_case("true"));
+    match (
+        requested && state.config.debug_sql,
+        state.config.debug_sql_params,
+    ) {
+        (false, _) => SqlLogging::Off,
+        (true, false) => SqlLogging::Redacted,
+        (true, true) => SqlLogging::WithParams,
+    }
+}
+
+/// The span a request's inserts run in: a `debug_sql` span naming the
+/// route when its SQL is being logged, and none otherwise.
+pub(crate) fn sql_span(logging: SqlLogging, endpoint: &'static str) -> Span {
+    if logging == SqlLogging::Off {
+        Span::none()
+    } else {
+        tracing::info_span!("debug_sql", endpoint)
+    }
+}
+
+/// Insert options for an upload: the configured isolation level, retries,
+/// parallelism, insert method, and fault injection, with this request's SQL
+/// logging.
+pub(crate) fn insert_options(
+    state: &AppState,
+    batch_size: usize,
+    sql_logging: SqlLogging,
+) -> InsertOptions {
+    InsertOptions::new(batch_size)
+        .with_sql_logging(sql_logging)
+        .with_fault_injection(FaultInjection::from_config(&state.config))
+        .with_isolation_level(state.config.ingest_isolation_level)
+        .with_max_retries(state.config.insert_max_retries)
+        .with_parallelism(state.config.insert_parallelism)
+        .with_insert_method(state.config.insert_method)
+}
+
+/// Parse options for an upload of `T`: the configured defaults plus the
+/// line layout and compression its headers declare, and the input format
+/// they or `format` (from `?format=`) name.
+pub(crate) fn parse_options<T: BulkInsertable>(
+    state: &AppState,
+    headers: &HeaderMap,
+    format: Option<InputFormat>,
+) -> Result<ParseOptions, AppError> {
+    check_content_type(headers)?;
+    let mut options = ParseOptions::from_config(&state.config);
+    options.schema_version = schema_version::<T>(headers)?;
+    options.compression = Compression::from_headers(headers)?;
+    options.format = InputFormat::from_request(headers, format);
+    if let Some(policy) = ingest_mode(headers)? {
+        options.on_invalid_row = policy;
+    }
+    Ok(options)
+}
+
+/// The invalid row policy requested with `X-Ingest-Mode`: `strict` fails the
+/// upload on its first malformed or invalid line, `lenient` skips such lines
+/// and counts them. `None` leaves `ON_INVALID_ROW` in charge.
+pub(crate) fn ingest_mode(headers: &HeaderMap) -> Result<Option<InvalidRowPolicy>, AppError> {
+    let Some(value) = headers.get("x-ingest-mode") else {
+        return Ok(None);
+    };
+    match value.to_str().unwrap_or_default().trim() {
+        mode if mode.eq_ignore_ascii_case("strict") => Ok(Some(InvalidRowPolicy::Reject)),
+        mode if mode.eq_ignore_ascii_case("lenient") => Ok(Some(InvalidRowPolicy::Skip)),
+        _ => Err(AppError::BadRequest(
+            "X-Ingest-Mode must be strict or lenient".to_string(),
+        )),
+    }
+}
+
+/// The upload-wide line layout requested with `X-Schema-Version`, checked
+/// against the versions `T` supports.
+pub(crate) fn schema_version<T: BulkInsertable>(
+    headers: &HeaderMap,
+) -> Result<Option<u32>, AppError> {
+    let Some(value) = headers.get("x-schema-version") else {
+        return Ok(None);
+    };
+    let version: u32 = value
+        .to_str()
+        .ok()
+        .and_then(|v| v.trim().parse().ok())
+        .ok_or_else(|| {
+            AppError::BadRequest("X-Schema-Version must be a non-negative integer".to_string())
+        })?;
+    match T::schema_versions() {
+        Some(versions) if versions.contains(&version) => Ok(Some(version)),
+        Some(versions) => Err(AppError::BadRequest(format!(
+            "unsupported X-Schema-Version {version}; expected {}..={}",
+            versions.start(),
+            versions.end()
+        ))),
+        None => Err(AppError::BadRequest(format!(
+            "{} records are not versioned",
+            T::table_name()
+        ))),
+    }
+}
+
+/// Adds an ingest response's line counts: `skipped` when invalid lines were
+/// being skipped, and `duplicate_lines` when repeats were being dropped.
+pub(crate) fn insert_line_counts(
+    state: &AppState,
+    response: &mut Map<String, Value>,
+    summary: &ParseSummary,
+    skipping: bool,
+) {
+    if skipping {
+        response.insert("skipped".to_string(), json!(summary.skipped));
+    }
+    if state.config.dedup_identical_lines {
+        response.insert(
+            "duplicate_lines".to_string(),
+            json!(summary.duplicate_lines),
+        );
+    }
+}
+
+/// Stores the lines an upload to `endpoint` skipped. The upload's rows are
+/// already committed by now, so a failure here is logged rather than
+/// reported to the client.
+pub(crate) async fn store_dead_letters(state: &AppState, endpoint: &str, letters: &[DeadLetter]) {
+    if let Err(e) = insert_dead_letters(&state.db, endpoint, letters).await {
+        tracing::error!("Failed to store {} dead letters: {e:?}", letters.len());
+    }
+}
+
+/// Media type of msgpack response bodies.
+const MSGPACK: &str = "application/msgpack";
+
+/// Whether the client's `Accept` header asks for msgpack.
+fn accepts_msgpack(headers: &HeaderMap) -> bool {
+    headers
+        .get(ACCEPT)
+        .and_then(|value| value.to_str().ok())
+        .is_some_and(|accept| {
+            accept.split(',').any(|range| {
+                let media_type = range.split(';').next().unwrap_or_default().trim();
+                media_type.eq_ignore_ascii_case(MSGPACK)
+                    || media_type.eq_ignore_ascii_case("application/x-msgpack")
+            })
+        })
+}
+
+/// A `200 OK` carrying `body` as msgpack when the client sends
+/// `Accept: application/msgpack`, and as JSON otherwise.
+pub(crate) fn serialized<T: Serialize>(headers: &HeaderMap, body: &T) -> Response {
+    if !accepts_msgpack(headers) {
+        return (StatusCode::OK, Json(body)).into_response();
+    }
+    match rmp_serde::to_vec_named(body) {
+        Ok(bytes) => (StatusCode::OK, [(CONTENT_TYPE, MSGPACK)], bytes).into_response(),
+        Err(e) => AppError::InternalServerError(format!("failed to encode MessagePack: {e}"))
+            .into_response(),
+    }
+}
+
+/// Sends an ingest acknowledgment. A per-sample breakdown can run to one
+/// entry per sample in the upload, so as JSON it is streamed through
+/// whatever compression the client's `Accept-Encoding` asks for (none if it
+/// doesn't say).
+pub(crate) fn ack_response(
+    headers: &HeaderMap,
+    mut fields: Map<String, Value>,
+    by_sample: Option<SampleCounts>,
+) -> Response {
+    match by_sample {
+        Some(counts) if accepts_msgpack(headers) => {
+            fields.insert("by_sample".to_string(), serde_json::json!(counts));
+            serialized(headers, &fields)
+        }
+        Some(counts) => ResponseEncoding::accepted(headers)
+            .unwrap_or(ResponseEncoding::None)
+            .respond("application/json", counts.into_json_stream(fields)),
+        None => serialized(headers, &fields),
+    }
+}
@@ -0,0 +1,336 @@
+use std::{collections::BTreeMap, io};
+
+use axum::{
+    Json,
+    body::Bytes,
+    extract::{Path, Query, State},
+    http::HeaderMap,
+    response::{IntoResponse, Response},
+};
+use futures_util::{Stream, StreamExt, future, stream};
+use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value, json};
+use sqlx::{FromRow, postgres::PgRow};
+use tokio::sync::{OwnedSemaphorePermit, mpsc};
+
+use crate::{
+    db::queries::{
+        count_gottcha2_where, delete_sample_rows, fetch_sample_rows, stream_sample_rows,
+        update_gottcha2_row,
+    },
+    error::AppError,
+    middleware::validate_bearer_token,
+    models::{
+        record::{BulkInsertable, Gottcha2FullRecord, Kraken2Record, StastRecord},
+        sample_id::SampleId,
+    },
+    services::{
+        encoding::ResponseEncoding,
+        parsing::{Verdict, content_hash},
+    },
+    state::AppState,
+};
+
+use super::gottcha2::check_record;
+
+/// Row format of an export.
+#[derive(Deserialize, Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum ExportFormat {
+    #[default]
+    Jsonl,
+    Csv,
+}
+
+#[derive(Deserialize)]
+pub struct ExportQuery {
+    pub sample_id: String,
+    pub encoding: Option<ResponseEncoding>,
+    #[serde(default)]
+    pub format: ExportFormat,
+}
+
+/// Rows `GET /samples/{sample_id}/gottcha2` returns when given no `limit`.
+const DEFAULT_SAMPLE_LIMIT: u32 = 1000;
+
+/// Most rows `GET /samples/{sample_id}/gottcha2` returns; larger samples
+/// should be read with an export.
+const MAX_SAMPLE_LIMIT: u32 = 10_000;
+
+#[derive(Deserialize)]
+pub struct SampleQuery {
+    /// Capped at `MAX_SAMPLE_LIMIT`
+    pub limit: Option<u32>,
+}
+
+/// A per-sample results table, as `DELETE /samples/{sample_id}?table=` names it.
+#[derive(Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum SampleTable {
+    Gottcha2,
+    Stast,
+    Kraken2,
+}
+
+impl SampleTable {
+    const ALL: [SampleTable; 3] = [Self::Gottcha2, Self::Stast, Self::Kraken2];
+
+    fn table_name(self) -> &'static str {
+        match self {
+            Self::Gottcha2 => Gottcha2FullRecord::table_name(),
+            Self::Stast => StastRecord::table_name(),
+            Self::Kraken2 => Kraken2Record::table_name(),
+        }
+    }
+}
+
+#[derive(Deserialize)]
+pub struct PurgeQuery {
+    /// Only purge this table; every per-sample table when unset
+    pub table: Option<SampleTable>,
+}
+
+/// Normalizes a sample ID from a request the same way ingest does, so queries
+/// match the stored form.
+fn normalize_sample_id(state: &AppState, raw: &str) -> Result<String, AppError> {
+    SampleId::normalized(raw, state.config.lowercase_sample_ids)
+        .map(String::from)
+        .map_err(AppError::BadRequest)
+}
+
+pub async fn count_gottcha2(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    Query(mut filters): Query<BTreeMap<String, String>>,
+) -> impl IntoResponse {
+    if let Err(e) = validate_bearer_token(&state, &headers) {
+        return e.into_response();
+    }
+
+    let _slot = match state.acquire_read_slot() {
+        Ok(slot) => slot,
+        Err(e) => return e.into_response(),
+    };
+
+    if let Some(raw) = filters.get_mut("sample_id") {
+        match normalize_sample_id(&state, raw) {
+            Ok(id) => *raw = id,
+            Err(e) => return e.into_response(),
+        }
+    }
+
+    let filters: Vec<(String, String)> = filters.into_iter().collect();
+    match count_gottcha2_where(&state.db, &filters).await {
+        Ok(count) => super::serialized(&headers, &json!({ "count": count })),
+        Err(e) => e.into_response(),
+    }
+}
+
+/// Returns a sample's GOTTCHA2 rows as a JSON array, in export order, or
+/// `404` if it has none.
+pub async fn sample_gottcha2(
+    State(state): State<AppState>,
+    Path(sample_id): Path<String>,
+    headers: HeaderMap,
+    Query(query): Query<SampleQuery>,
+) -> impl IntoResponse {
+    if let Err(e) = validate_bearer_token(&state, &headers) {
+        return e.into_response();
+    }
+
+    let sample_id = match normalize_sample_id(&state, &sample_id) {
+        Ok(id) => id,
+        Err(e) => return e.into_response(),
+    };
+    let _slot = match state.acquire_read_slot() {
+        Ok(slot) => slot,
+        Err(e) => return e.into_response(),
+    };
+
+    let limit = query
+        .limit
+        .unwrap_or(DEFAULT_SAMPLE_LIMIT)
+        .min(MAX_SAMPLE_LIMIT);
+    match fetch_sample_rows::<Gottcha2FullRecord>(&state.db, &sample_id, i64::from(limit)).await {
+        Ok(rows) if rows.is_empty() => {
+            AppError::NotFound(format!("no gottcha2 rows for sample {sample_id}")).into_response()
+        }
+        Ok(rows) => super::serialized(&headers, &rows),
+        Err(e) => e.into_response(),
+    }
+}
+
+/// Deletes a sample's rows from every per-sample table, or only `?table=`'s,
+/// in one transaction, so it can be re-ingested from scratch.
+pub async fn delete_sample(
+    State(state): State<AppState>,
+    Path(sample_id): Path<String>,
+    headers: HeaderMap,
+    Query(query): Query<PurgeQuery>,
+) -> impl IntoResponse {
+    if let Err(e) = validate_bearer_token(&state, &headers) {
+        return e.into_response();
+    }
+
+    let sample_id = match normalize_sample_id(&state, &sample_id) {
+        Ok(id) => id,
+        Err(e) => return e.into_response(),
+    };
+
+    let tables: Vec<&str> = match query.table {
+        Some(table) => vec![table.table_name()],
+        None => SampleTable::ALL.map(SampleTable::table_name).to_vec(),
+    };
+    match delete_sample_rows(&state.db, &sample_id, &tables).await {
+        Ok(deleted) => {
+            tracing::info!("Purged {deleted} rows for sample {sample_id} from {tables:?}");
+            super::serialized(&headers, &json!({ "deleted": deleted }))
+        }
+        Err(e) => e.into_response(),
+    }
+}
+
+pub async fn patch_gottcha2(
+    State(state): State<AppState>,
+    Path(id): Path<i64>,
+    headers: HeaderMap,
+    Json(mut fields): Json<Map<String, Value>>,
+) -> impl IntoResponse {
+    if let Err(e) = validate_bearer_token(&state, &headers) {
+        return e.into_response();
+    }
+
+    // Non-string values are left for the update's type check to reject
+    if let Some(Value::String(raw)) = fields.get_mut("sample_id") {
+        match normalize_sample_id(&state, raw) {
+            Ok(id) => *raw = id,
+            Err(e) => return e.into_response(),
+        }
+    }
+
+    let config = &state.config;
+    let prepare = |record: &mut Gottcha2FullRecord| {
+        match check_record(record, config.strict_taxonomic_levels) {
+            Verdict::Keep | Verdict::Filter => {}
+            Verdict::Reject(reason) => return Err(AppError::BadRequest(reason)),
+            // The update is one JSON object, so it is all line 1
+            Verdict::Invalid(violation) => {
+                return Err(AppError::InvalidField(violation.at_line(1)));
+            }
+        }
+        if config.store_content_hash {
+            record.set_content_hash(content_hash(record)?);
+        }
+        Ok(())
+    };
+    match update_gottcha2_row(&state.db, id, &fields, prepare).await {
+        Ok(row) => super::serialized(&headers, &row),
+        Err(e) => e.into_response(),
+    }
+}
+
+/// Turns exported rows into a stream of lines rendered by `line`. A database
+/// error midway aborts the stream, so the client sees a failed transfer
+/// rather than a silently truncated file. `slot` is held until the stream is
+/// done or dropped, so a read counts against `max_concurrent_reads` for as
+/// long as it's streaming.
+pub(crate) fn row_lines<T, F>(
+    rows: mpsc::Receiver<Result<T, sqlx::Error>>,
+    slot: Option<OwnedSemaphorePermit>,
+    line: F,
+) -> impl Stream<Item = io::Result<Bytes>>
+where
+    T: Send + 'static,
+    F: Fn(&T) -> io::Result<Vec<u8>> + Send + 'static,
+{
+    stream::unfold((rows, slot, line), |(mut rows, slot, line)| async move {
+        let chunk = match rows.recv().await? {
+            Ok(row) => line(&row).map(Bytes::from),
+            Err(e) => {
+                tracing::error!("Export query failed: {e}");
+                Err(io::Error::other(e))
+            }
+        };
+        Some((chunk, (rows, slot, line)))
+    })
+}
+
+/// One row as a JSONL line.
+pub(crate) fn jsonl_line<T: Serialize>(row: &T) -> io::Result<Vec<u8>> {
+    let mut line = serde_json::to_vec(row).map_err(io::Error::other)?;
+    line.push(b'\n');
+    Ok(line)
+}
+
+/// One CSV record of `cells`, each quoted if it holds a comma, quote, or
+/// line break.
+fn csv_line<S: AsRef<str>>(cells: impl IntoIterator<Item = S>) -> io::Result<Vec<u8>> {
+    let mut writer = csv::Writer::from_writer(Vec::new());
+    writer
+        .write_record(cells.into_iter().map(|cell| cell.as_ref().to_owned()))
+        .map_err(io::Error::other)?;
+    writer
+        .into_inner()
+        .map_err(|e| io::Error::other(e.to_string()))
+}
+
+/// One row as a CSV record in `columns` order. Columns the row doesn't
+/// serialize, such as `raw_line`, and null values are left empty.
+fn csv_row<T: Serialize>(columns: &[&'static str], row: &T) -> io::Result<Vec<u8>> {
+    let Value::Object(fields) = serde_json::to_value(row).map_err(io::Error::other)? else {
+        return Err(io::Error::other("exported row is not an object"));
+    };
+    csv_line(columns.iter().map(|column| match fields.get(*column) {
+        None | Some(Value::Null) => String::new(),
+        Some(Value::String(text)) => text.clone(),
+        Some(other) => other.to_string(),
+    }))
+}
+
+fn export_sample<T>(state: &AppState, headers: &HeaderMap, query: &ExportQuery) -> Response
+where
+    T: for<'r> FromRow<'r, PgRow> + BulkInsertable + Serialize + Send + Unpin + 'static,
+{
+    if let Err(e) = validate_bearer_token(state, headers) {
+        return e.into_response();
+    }
+
+    let sample_id = match normalize_sample_id(state, &query.sample_id) {
+        Ok(id) => id,
+        Err(e) => return e.into_response(),
+    };
+    let slot = match state.acquire_read_slot() {
+        Ok(slot) => slot,
+        Err(e) => return e.into_response(),
+    };
+
+    let encoding = ResponseEncoding::negotiate(query.encoding, headers);
+    let rows = stream_sample_rows::<T>(state.db.clone(), sample_id);
+    match query.format {
+        ExportFormat::Jsonl => {
+            encoding.respond("application/x-ndjson", row_lines(rows, slot, jsonl_line))
+        }
+        ExportFormat::Csv => {
+            let columns: Vec<&'static str> = T::column_names().split(',').map(str::trim).collect();
+            let header = stream::once(future::ready(csv_line(&columns).map(Bytes::from)));
+            let body = row_lines(rows, slot, move |row| csv_row(&columns, row));
+            encoding.respond("text/csv; charset=utf-8", header.chain(body))
+        }
+    }
+}
+
+pub async fn export_gottcha2(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    Query(query): Query<ExportQuery>,
+) -> impl IntoResponse {
+    export_sample::<Gottcha2FullRecord>(&state, &headers, &query)
+}
+
+pub async fn export_stast(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    Query(query): Query<ExportQuery>,
+) -> impl IntoResponse {
+    export_sample::<StastRecord>(&state, &headers, &query)
+}
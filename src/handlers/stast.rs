@@ -1,33 +1,118 @@
 use axum::{
     body::Body,
-    extract::State,
-    http::{HeaderMap, StatusCode},
+    extract::{Query, State},
+    http::HeaderMap,
     response::IntoResponse,
 };
-use tokio::sync::mpsc;
+use serde::Deserialize;
+use serde_json::json;
 
+use super::IngestRoute;
 use crate::{
-    db::operations::batch_insert_stast, middleware::validate_bearer_token,
-    services::parsing::parse_gzipped_jsonl, state::AppState,
+    db::operations::batch_insert_stast,
+    error::AppError,
+    models::record::StastRecord,
+    services::breakdown::{BreakdownQuery, DistinctTaxids, DistinctTaxidsQuery, SampleCounts},
+    services::parsing::{FormatQuery, Verdict},
+    state::AppState,
 };
 
+/// Optional score thresholds for dropping low-quality hits at ingest time.
+/// Hits exactly at a threshold are kept.
+#[derive(Debug, Deserialize)]
+pub struct StastFilter {
+    pub min_bitscore: Option<f64>,
+    pub max_evalue: Option<f64>,
+}
+
+impl StastFilter {
+    fn validate(&self) -> Result<(), AppError> {
+        for (name, value) in [
+            ("min_bitscore", self.min_bitscore),
+            ("max_evalue", self.max_evalue),
+        ] {
+            if value.is_some_and(|v| !v.is_finite()) {
+                return Err(AppError::BadRequest(format!(
+                    "{name} must be a finite number"
+                )));
+            }
+        }
+        Ok(())
+    }
+
+    #[must_use]
+    pub fn keep(&self, record: &StastRecord) -> bool {
+        self.min_bitscore.is_none_or(|min| record.bitscore >= min)
+            && self.max_evalue.is_none_or(|max| record.evalue <= max)
+    }
+}
+
+/// The rank and range checks every uploaded STAST record must pass.
+fn check_record(record: &StastRecord, strict_ranks: bool) -> Verdict {
+    if strict_ranks && let Err(reason) = record.validate_rank() {
+        return Verdict::Reject(reason);
+    }
+    if let Err(violation) = record.validate_ranges() {
+        return Verdict::Invalid(violation);
+    }
+    Verdict::Keep
+}
+
 pub async fn ingest_stast(
     State(state): State<AppState>,
     headers: HeaderMap,
+    Query(format): Query<FormatQuery>,
+    Query(filter): Query<StastFilter>,
+    Query(breakdown): Query<BreakdownQuery>,
+    Query(taxids): Query<DistinctTaxidsQuery>,
     body: Body,
 ) -> impl IntoResponse {
-    if let Err(e) = validate_bearer_token(&state, &headers) {
+    // Refused before the upload is looked at, like a malformed threshold
+    if let Err(e) = filter.validate() {
         return e.into_response();
     }
 
-    let (tx, rx) = mpsc::channel(1000);
+    let route = IngestRoute {
+        endpoint: "ingest-stast",
+        path: "/ingest-stast",
+        batch_size: state.config.stast_batch_size,
+    };
+    let strict = state.config.strict_taxonomic_levels;
+    let mut by_sample = breakdown.breakdown.then(SampleCounts::default);
+    let mut distinct_taxids = taxids
+        .distinct_taxids
+        .then(|| DistinctTaxids::new(state.config.max_distinct_taxids));
+    let check = |record: &StastRecord| {
+        let verdict = check_record(record, strict);
+        if verdict != Verdict::Keep {
+            return verdict;
+        }
+        if !filter.keep(record) {
+            return Verdict::Filter;
+        }
+        if let Some(counts) = by_sample.as_mut() {
+            counts.record(&record.sample_id);
+        }
+        if let Some(taxids) = distinct_taxids.as_mut() {
+            taxids.record(&record.staxids);
+        }
+        Verdict::Keep
+    };
+    let insert = |rx, options| batch_insert_stast(rx, &state.db, options);
 
-    let parser = parse_gzipped_jsonl(body, tx);
-    let inserter = batch_insert_stast(rx, &state.db);
-
-    if let Err(e) = tokio::try_join!(parser, inserter) {
-        return e.into_response();
+    let (summary, _, mut response) =
+        match super::ingest_upload(&state, &headers, &route, format.format, body, check, insert)
+            .await
+        {
+            Ok(ingested) => ingested,
+            Err(response) => return response,
+        };
+    response.insert("filtered".to_string(), json!(summary.filtered));
+    if let Some(taxids) = distinct_taxids {
+        if taxids.truncated() {
+            response.insert("distinct_taxids_truncated".to_string(), json!(true));
+        }
+        response.insert("distinct_taxids".to_string(), json!(taxids.into_sorted()));
     }
-
-    (StatusCode::OK, "ingested").into_response()
+    super::ack_response(&headers, response, by_sample)
 }
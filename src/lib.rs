@@ -4,5 +4,9 @@ pub mod error;
 pub mod handlers;
 pub mod middleware;
 pub mod models;
+pub mod router;
+pub mod runtime;
 pub mod services;
+pub mod shutdown;
 pub mod state;
+pub mod tls;
@@ -1,14 +1,8 @@
 use std::{net::SocketAddr, sync::Arc, time::Duration};
 
-use axum::{
-    Router,
-    routing::{get, post},
-};
 use axum_server::tls_rustls::RustlsConfig;
-use color_eyre::eyre::{Result, eyre};
-use tower::ServiceBuilder;
-use tower_governor::{GovernorLayer, governor::GovernorConfigBuilder};
-use tower_http::timeout::TimeoutLayer;
+use color_eyre::eyre::Result;
+use futures_util::{FutureExt, future::BoxFuture};
 
 mod config;
 mod db;
@@ -17,12 +11,16 @@ mod handlers;
 mod middleware;
 mod models;
 mod preflight;
+mod router;
+mod runtime;
 mod services;
+mod shutdown;
 mod state;
+mod tls;
 
 use config::AppConfig;
-use handlers::{healthz, ingest_dummy, ingest_gottcha2, ingest_stast};
 use state::AppState;
+use tls::HandshakeLimitAcceptor;
 
 /// Main entry point for the NVD support car server.
 ///
@@ -30,80 +28,196 @@ use state::AppState;
 ///
 /// Returns an error if:
 /// - Environment variables cannot be loaded
+/// - The tokio runtime cannot be built
 /// - Database connection fails
 /// - TLS certificates cannot be loaded
 /// - Server fails to start
-///
-/// # Panics
-///
-/// Panics if the governor rate limiter configuration fails to build.
-#[tokio::main]
-pub async fn main() -> Result<()> {
+pub fn main() -> Result<()> {
     // let the user now we've started
     tracing::info!(
         "You've launched to the NVD support car, designed to support the NVD metagenomic pipeline as it races to identify human virus-family pathogens in big sequence datasets. Proceeding to preflight checks..."
     );
 
     // run preflight checks
-    preflight::setup_tracing();
+    preflight::setup_tracing(preflight::LogFormat::from_env()?);
     preflight::init_error_formatter()?;
-    preflight::checks();
-    tracing::info!("All preflight checks passed. Proceeding to server setup");
 
     // set up app configs
     tracing::info!("Setting up application configuration from environment variables.");
     let config = AppConfig::new_from_env()?;
 
+    preflight::checks(&config)?;
+    tracing::info!("All preflight checks passed. Proceeding to server setup");
+    if let Some(path) = &config.debug_sink_path {
+        tracing::warn!(
+            "DEBUG_SINK_PATH is set; every accepted record will also be written to {}. Do not run like this in production.",
+            path.display()
+        );
+    }
+
+    if config.fault_injection {
+        tracing::warn!(
+            "FAULT_INJECTION is enabled; inserts will fail {:.0}% and stall {:.0}% of the time. Never enable this in production.",
+            config.fault_failure_rate * 100.0,
+            config.fault_delay_rate * 100.0
+        );
+    }
+
+    // batch sizes too large for the bind parameter limit are capped; say so
+    db::operations::warn_if_batch_size_capped::<models::record::DummyRecord>(
+        "DUMMY_BATCH_SIZE",
+        config.dummy_batch_size,
+    );
+    db::operations::warn_if_batch_size_capped::<models::record::Gottcha2FullRecord>(
+        "GOTTCHA2_BATCH_SIZE",
+        config.gottcha2_batch_size,
+    );
+    db::operations::warn_if_batch_size_capped::<models::record::StastRecord>(
+        "STAST_BATCH_SIZE",
+        config.stast_batch_size,
+    );
+    db::operations::warn_if_batch_size_capped::<models::record::Kraken2Record>(
+        "KRAKEN2_BATCH_SIZE",
+        config.kraken2_batch_size,
+    );
+
+    // size the async runtime to the configured (or detected) core count
+    let worker_threads = config
+        .worker_threads
+        .unwrap_or_else(preflight::available_cores);
+    tracing::info!("Starting the async runtime with {worker_threads} worker threads.");
+    runtime::build_runtime(worker_threads)?.block_on(serve(config))
+}
+
+/// Connects the database, builds the router, and serves it until shutdown.
+///
+/// # Errors
+///
+/// Returns an error if database connection, migrations, TLS setup, or the
+/// server itself fail.
+async fn serve(mut config: AppConfig) -> Result<()> {
     // connect the database
     tracing::info!("Configuration Set. Proceeding to launching a database connecton pool...");
-    let db = sqlx::postgres::PgPoolOptions::new()
-        .max_connections(30)
-        .acquire_timeout(Duration::from_secs(3))
-        .connect(&config.database_url)
-        .await?;
+    let pool_options = sqlx::postgres::PgPoolOptions::new()
+        .max_connections(config.db_max_connections)
+        .min_connections(config.db_min_connections)
+        .acquire_timeout(Duration::from_secs(config.db_acquire_timeout_secs));
+    let db = db::connect::connect_with_backoff(
+        pool_options,
+        &config.database_url,
+        Duration::from_secs(config.db_connect_timeout_secs),
+    )
+    .await?;
 
-    // run migrations if need
+    // run or verify migrations, per MIGRATION_MODE
     tracing::info!(
-        "Connection pool successfully launched. Running database migrations as necessary..."
+        "Connection pool successfully launched. Handling database migrations in {:?} mode...",
+        config.migration_mode
     );
-    sqlx::migrate!().run(&db).await?;
-
-    // set up rate-limiting governor
-    tracing::info!("Configuring the connection rate-limiter...");
-    let governer = GovernorConfigBuilder::default()
-        .per_second(200)
-        .burst_size(400)
-        .finish()
-        .ok_or_else(|| eyre!("Failed to build governor config"))?;
-
-    // launch router with the governor as a service layer (dependency injection is a cool pttern)
-    tracing::info!("Rate-limiting configured. Configuring router...");
-    let state = AppState::new(db, &config);
-    let app = Router::new()
-        .route("/healthz", get(healthz))
-        .route("/ingest", post(ingest_dummy))
-        .route("/ingest-gottcha2", post(ingest_gottcha2))
-        .route("/ingest-stast", post(ingest_stast))
-        .with_state(state)
-        .layer(
-            ServiceBuilder::new()
-                .layer(GovernorLayer::new(Arc::new(governer)))
-                .layer(TimeoutLayer::new(Duration::from_secs(5))),
+    db::migrations::apply(&db, config.migration_mode).await?;
+
+    // build the router; everything but the probe routes is rate-limited
+    tracing::info!("Configuring the rate-limited router...");
+    if let Some(ttl) = config.idempotency_ttl_secs {
+        tracing::info!("Idempotency keys expire after {ttl}s; starting the background pruner.");
+        let _pruner =
+            db::operations::spawn_idempotency_pruner(db.clone(), Duration::from_secs(ttl));
+    }
+
+    // fetch the ingest token if it lives in a secrets manager
+    if config.ingest_token_source != config::TokenSource::Env {
+        tracing::info!(
+            "Fetching the ingest token from {:?}...",
+            config.ingest_token_source
         );
+        config.ingest_token = services::secrets::resolve_ingest_token(&config).await?;
+    }
 
-    // set up certificates
-    tracing::info!("Reading certificates for forming secure TLS connections while NVD runs.");
-    let tls = RustlsConfig::from_pem_file(config.cert_path, config.key_path).await?;
+    let state = AppState::new(db, &config);
+    if config.ingest_token_source != config::TokenSource::Env
+        && let Some(secs) = config.ingest_token_refresh_secs
+    {
+        tracing::info!("Refreshing the ingest token every {secs}s to pick up rotations.");
+        let _refresher =
+            services::secrets::spawn_token_refresher(state.clone(), Duration::from_secs(secs));
+    }
+    if let Some(secs) = config.metrics_log_secs {
+        tracing::info!("Logging a metrics snapshot every {secs}s.");
+        let _metrics_logger =
+            services::metrics::spawn_metrics_logger(state.clone(), Duration::from_secs(secs));
+    }
+    let app = router::build_router(state.clone(), &config)?;
+
+    // drain in-flight ingests on SIGTERM/Ctrl-C instead of dying mid-batch
+    let handle = axum_server::Handle::new();
+    tokio::spawn(shutdown::drain_on_signal(
+        handle.clone(),
+        state,
+        Duration::from_secs(config.shutdown_grace_secs),
+    ));
 
     // but the address and report and run the server
+    let addr = SocketAddr::new(config.bind_address, config.server_port);
+    let main_server = main_listener(&config, addr, app.clone(), handle.clone())?;
     tracing::info!(
-        "All setup is complete. The support car is ready. Now attaching to the 0.0.0.0 address at port {}, where the support care will await requests.",
+        "All setup is complete. The support car is ready. Now attaching to the {} address at port {}, where the support care will await requests.",
+        config.bind_address,
         config.server_port
     );
-    let addr = SocketAddr::from(([0, 0, 0, 0], config.server_port));
-    axum_server::bind_rustls(addr, tls)
-        .serve(app.into_make_service())
-        .await?;
 
+    // optionally serve the same router over plaintext for internal clients
+    if let Some(http_port) = config.http_port {
+        tracing::warn!(
+            "Also serving INSECURE plaintext HTTP on port {http_port}. Bearer tokens and data cross this listener unencrypted; expose it only on trusted internal networks."
+        );
+        let http_addr = SocketAddr::new(config.bind_address, http_port);
+        let mut http_server = axum_server::bind(http_addr).handle(handle);
+        config.limit_headers(&mut http_server);
+        let http_server =
+            http_server.serve(app.into_make_service_with_connect_info::<SocketAddr>());
+        tokio::try_join!(main_server, http_server)?;
+    } else {
+        main_server.await?;
+    }
+
+    tracing::info!("All in-flight requests drained. Goodbye.");
     Ok(())
 }
+
+/// Serves `app` on `addr` over TLS, or as plaintext HTTP when `TLS_ENABLED`
+/// is off.
+///
+/// # Errors
+///
+/// Returns an error if the certificates cannot be loaded.
+fn main_listener(
+    config: &AppConfig,
+    addr: SocketAddr,
+    app: axum::Router,
+    handle: axum_server::Handle,
+) -> Result<BoxFuture<'static, std::io::Result<()>>> {
+    let service = app.into_make_service_with_connect_info::<SocketAddr>();
+    if !config.tls_enabled {
+        tracing::warn!(
+            "TLS_ENABLED is false; serving INSECURE plaintext HTTP on port {}. Bearer tokens and data cross this listener unencrypted. Never run like this in production.",
+            addr.port()
+        );
+        let mut server = axum_server::bind(addr).handle(handle);
+        config.limit_headers(&mut server);
+        return Ok(server.serve(service).boxed());
+    }
+
+    // set up certificates
+    tracing::info!("Reading certificates for forming secure TLS connections while NVD runs.");
+    let tls = RustlsConfig::from_config(Arc::new(config.load_tls_config()?));
+    let max_handshakes = config.max_concurrent_handshakes;
+    let handshake_timeout = Duration::from_millis(config.handshake_queue_timeout_ms);
+    let mut tls_server = axum_server::bind_rustls(addr, tls).handle(handle);
+    config.limit_headers(&mut tls_server);
+    Ok(tls_server
+        .map(move |acceptor| {
+            HandshakeLimitAcceptor::new(acceptor, max_handshakes, handshake_timeout)
+        })
+        .serve(service)
+        .boxed())
+}
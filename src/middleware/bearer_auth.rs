@@ -1,26 +1,115 @@
 use axum::http::HeaderMap;
+use subtle::{Choice, ConstantTimeEq};
 
 use crate::error::AppError;
 use crate::state::AppState;
 
-/// Validates the bearer token from the request headers.
+/// Validates the bearer token from the request headers against every
+/// accepted ingest token.
 ///
 /// # Errors
 ///
 /// Returns `AppError::Unauthorized` if the token is missing or invalid.
 pub fn validate_bearer_token(state: &AppState, headers: &HeaderMap) -> Result<(), AppError> {
-    let auth = headers
+    check_token(headers, &state.ingest_tokens())
+}
+
+/// Validates the bearer token for admin endpoints against `ADMIN_TOKEN`.
+/// Without one configured the admin endpoints don't exist, so an ingest
+/// token can never pause ingestion.
+///
+/// # Errors
+///
+/// Returns `AppError::NotFound` if no admin token is configured, and
+/// `AppError::Unauthorized` if the token is missing or invalid.
+pub fn validate_admin_token(state: &AppState, headers: &HeaderMap) -> Result<(), AppError> {
+    match &state.config.admin_token {
+        Some(expected) => check_token(headers, std::slice::from_ref(expected)),
+        None => Err(AppError::NotFound(
+            "admin endpoints are disabled without ADMIN_TOKEN".to_string(),
+        )),
+    }
+}
+
+/// Extracts the bearer token from the `Authorization` header, if present.
+#[must_use]
+pub fn bearer_token(headers: &HeaderMap) -> Option<&str> {
+    headers
         .get("authorization")
-        .and_then(|v| v.to_str().ok())
-        .unwrap_or("");
+        .and_then(|v| v.to_str().ok())?
+        .strip_prefix("Bearer ")
+}
 
-    let Some(token) = auth.strip_prefix("Bearer ") else {
+fn check_token(headers: &HeaderMap, expected: &[String]) -> Result<(), AppError> {
+    let Some(token) = bearer_token(headers) else {
         return Err(AppError::Unauthorized);
     };
 
-    if token != state.config.ingest_token {
+    // Compared in constant time, and against every token without stopping at
+    // a match, so response timing doesn't reveal how many leading bytes of a
+    // guess were right or which token it resembled; only lengths can leak
+    let matches = expected.iter().fold(Choice::from(0), |matched, expected| {
+        let same_len = Choice::from(u8::from(token.len() == expected.len()));
+        matched | (same_len & token.as_bytes().ct_eq(expected.as_bytes()))
+    });
+    if !bool::from(matches) {
         return Err(AppError::Unauthorized);
     }
 
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn bearer(token: &str) -> HeaderMap {
+        let mut headers = HeaderMap::new();
+        headers.insert(
+            "authorization",
+            format!("Bearer {token}").parse().expect("Invalid header"),
+        );
+        headers
+    }
+
+    fn tokens(tokens: &[&str]) -> Vec<String> {
+        tokens.iter().map(ToString::to_string).collect()
+    }
+
+    #[test]
+    fn matching_token_is_accepted() {
+        assert!(check_token(&bearer("s3cret-token"), &tokens(&["s3cret-token"])).is_ok());
+    }
+
+    #[test]
+    fn any_of_several_tokens_is_accepted() {
+        let accepted = tokens(&["old-token", "new-token"]);
+        assert!(check_token(&bearer("old-token"), &accepted).is_ok());
+        assert!(check_token(&bearer("new-token"), &accepted).is_ok());
+        assert!(matches!(
+            check_token(&bearer("third-token"), &accepted),
+            Err(AppError::Unauthorized)
+        ));
+        assert!(matches!(
+            check_token(&bearer("old-token"), &[]),
+            Err(AppError::Unauthorized)
+        ));
+    }
+
+    #[test]
+    fn mismatched_tokens_are_rejected() {
+        for guess in ["s3cret-tokem", "s3cret", "s3cret-token-and-more", ""] {
+            assert!(
+                matches!(
+                    check_token(&bearer(guess), &tokens(&["s3cret-token"])),
+                    Err(AppError::Unauthorized)
+                ),
+                "{guess:?} should be rejected"
+            );
+        }
+        assert!(matches!(
+            check_token(&HeaderMap::new(), &tokens(&["s3cret-token"])),
+            Err(AppError::Unauthorized)
+        ));
+    }
+}
@@ -0,0 +1,33 @@
+use std::time::Instant;
+
+use axum::{
+    extract::{MatchedPath, Request, State},
+    middleware::Next,
+    response::Response,
+};
+
+use crate::state::AppState;
+
+/// Records each request's duration in the endpoint-labeled latency
+/// histogram. Must be added with `route_layer` so the matched route template
+/// is available as the label.
+pub async fn record_latency(
+    State(state): State<AppState>,
+    request: Request,
+    next: Next,
+) -> Response {
+    let endpoint = request
+        .extensions()
+        .get::<MatchedPath>()
+        .map_or("unmatched", MatchedPath::as_str)
+        .to_string();
+
+    let started = Instant::now();
+    let response = next.run(request).await;
+    state.metrics.observe_request(
+        &endpoint,
+        response.status().as_u16(),
+        started.elapsed().as_secs_f64(),
+    );
+    response
+}
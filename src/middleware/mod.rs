@@ -1,3 +1,9 @@
 pub mod bearer_auth;
+pub mod latency;
+pub mod request_id;
+pub mod server_version;
 
-pub use bearer_auth::validate_bearer_token;
+pub use bearer_auth::{bearer_token, validate_admin_token, validate_bearer_token};
+pub use latency::record_latency;
+pub use request_id::{RequestId, assign_request_id};
+pub use server_version::add_server_version;
@@ -0,0 +1,93 @@
+use axum::{
+    extract::Request,
+    http::{HeaderName, HeaderValue},
+    middleware::Next,
+    response::Response,
+};
+use tracing::Instrument;
+use uuid::Uuid;
+
+/// Header a request's correlation id is read from and echoed back in.
+pub const REQUEST_ID_HEADER: HeaderName = HeaderName::from_static("x-request-id");
+
+/// Longest client-supplied request id that is kept; longer ones, or ones
+/// with anything but visible ASCII, are replaced with a generated id.
+const MAX_REQUEST_ID_LEN: usize = 128;
+
+tokio::task_local! {
+    static CURRENT_REQUEST_ID: RequestId;
+}
+
+/// The correlation id of the request being served, also stored in its
+/// extensions.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RequestId(pub String);
+
+impl RequestId {
+    /// The id of the request whose handler is running on this task, if the
+    /// request came through `assign_request_id`. Work spawned onto other
+    /// tasks has none.
+    #[must_use]
+    pub fn current() -> Option<Self> {
+        CURRENT_REQUEST_ID.try_with(Clone::clone).ok()
+    }
+
+    fn from_header(value: &HeaderValue) -> Option<Self> {
+        let bytes = value.as_bytes();
+        let usable = !bytes.is_empty()
+            && bytes.len() <= MAX_REQUEST_ID_LEN
+            && bytes.iter().all(u8::is_ascii_graphic);
+        usable.then(|| Self(String::from_utf8_lossy(bytes).into_owned()))
+    }
+}
+
+/// Gives every request a correlation id: the client's `X-Request-Id` if it
+/// sent a usable one, otherwise a fresh UUID. The id is stored in the
+/// request's extensions, recorded on a `request` span wrapping everything
+/// logged while serving it, included in error bodies, and echoed back in the
+/// response's `X-Request-Id`, so a client's failure report can be matched
+/// to the server's log lines.
+pub async fn assign_request_id(mut request: Request, next: Next) -> Response {
+    let id = request
+        .headers()
+        .get(&REQUEST_ID_HEADER)
+        .and_then(RequestId::from_header)
+        .unwrap_or_else(|| RequestId(Uuid::new_v4().to_string()));
+    request.extensions_mut().insert(id.clone());
+
+    let span = tracing::info_span!(
+        "request",
+        request_id = %id.0,
+        method = %request.method(),
+        path = %request.uri().path(),
+    );
+    let header = HeaderValue::from_str(&id.0).ok();
+    let mut response = CURRENT_REQUEST_ID
+        .scope(id, next.run(request))
+        .instrument(span)
+        .await;
+    if let Some(header) = header {
+        response.headers_mut().insert(REQUEST_ID_HEADER, header);
+    }
+    response
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn only_short_visible_ascii_ids_are_kept() {
+        let kept = HeaderValue::from_static("client-req-42");
+        assert_eq!(
+            RequestId::from_header(&kept),
+            Some(RequestId("client-req-42".to_string()))
+        );
+
+        let long = "x".repeat(MAX_REQUEST_ID_LEN + 1);
+        for rejected in ["", "has space", long.as_str()] {
+            let value = HeaderValue::from_str(rejected).expect("Invalid header");
+            assert_eq!(RequestId::from_header(&value), None, "{rejected:?}");
+        }
+    }
+}
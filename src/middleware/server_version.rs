@@ -0,0 +1,20 @@
+use axum::{
+    extract::Request,
+    http::{HeaderName, HeaderValue},
+    middleware::Next,
+    response::Response,
+};
+
+/// This build's crate version and short git hash, e.g. `0.1.0+1a2b3c4`.
+pub const SERVER_VERSION: &str = concat!(env!("CARGO_PKG_VERSION"), "+", env!("GIT_HASH"));
+
+/// Stamps every response with `X-Server-Version: SERVER_VERSION`, so a
+/// client can tell which build served it during a mixed-version rollout.
+pub async fn add_server_version(request: Request, next: Next) -> Response {
+    let mut response = next.run(request).await;
+    response.headers_mut().insert(
+        HeaderName::from_static("x-server-version"),
+        HeaderValue::from_static(SERVER_VERSION),
+    );
+    response
+}
@@ -0,0 +1,25 @@
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+use sqlx::FromRow;
+
+/// A line skipped under `InvalidRowPolicy::Skip`, with why it was skipped.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DeadLetter {
+    /// 1-based line number within the upload
+    pub line_number: usize,
+    /// The line as sent, without its trailing newline
+    pub raw_line: String,
+    pub error: String,
+}
+
+/// A stored dead letter as returned by `GET /dead-letters`.
+#[derive(Debug, Clone, FromRow, Serialize)]
+pub struct DeadLetterRow {
+    pub id: i64,
+    /// Route the line was uploaded to, e.g. `ingest-gottcha2`
+    pub endpoint: String,
+    pub line_number: i64,
+    pub raw_line: String,
+    pub error: String,
+    pub created_at: DateTime<Utc>,
+}
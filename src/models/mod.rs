@@ -1 +1,5 @@
+pub mod dead_letter;
 pub mod record;
+pub mod sample_id;
+pub mod taxonomy;
+pub mod validation;
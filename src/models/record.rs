@@ -1,10 +1,33 @@
+use std::ops::RangeInclusive;
+
+use chrono::{DateTime, Utc};
 use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value};
 use sqlx::{FromRow, postgres::PgArguments};
 
-pub trait BulkInsertable: Sized {
+use super::{
+    sample_id::SampleId,
+    taxonomy::TaxonomicLevel,
+    validation::{FieldViolation, check_non_negative, check_percentage, check_unit_interval},
+};
+
+/// Maximum number of bind parameters Postgres accepts in a single statement
+pub const MAX_BIND_PARAMS: usize = 65535;
+
+/// A record type that can be batch-inserted into its table. `Debug` lets
+/// `DEBUG_SQL_PARAMS` log its bound values, and `Send + Sync + 'static` lets
+/// batches insert on their own tasks under `INSERT_PARALLELISM`.
+pub trait BulkInsertable: Sized + std::fmt::Debug + Send + Sync + 'static {
     /// Number of fields that will be inserted
     fn field_count() -> usize;
 
+    /// Largest number of rows that fit in one multi-row INSERT without
+    /// exceeding `MAX_BIND_PARAMS`
+    #[must_use]
+    fn max_batch_size() -> usize {
+        MAX_BIND_PARAMS / Self::field_count()
+    }
+
     /// Table name for the INSERT statement
     fn table_name() -> &'static str;
 
@@ -17,11 +40,177 @@ pub trait BulkInsertable: Sized {
         None
     }
 
-    /// Bind this record's fields to the query
-    fn bind_to(
-        self,
-        query: sqlx::query::Query<'_, sqlx::Postgres, PgArguments>,
-    ) -> sqlx::query::Query<'_, sqlx::Postgres, PgArguments>;
+    /// Value of the `idempotency_key` column, for record types deduplicated
+    /// on one
+    #[must_use]
+    fn idempotency_key(&self) -> Option<&str> {
+        None
+    }
+
+    /// Whether an upload's batches must insert one after another, in upload
+    /// order, because which of two conflicting rows is kept matters; such
+    /// types ignore `INSERT_PARALLELISM`
+    #[must_use]
+    fn ordered_inserts() -> bool {
+        false
+    }
+
+    /// Numeric fields older upstream tool versions may omit, filled with zero
+    /// when `FILL_MISSING_FIELDS` is enabled instead of failing the line
+    #[must_use]
+    fn zero_default_fields() -> &'static [&'static str] {
+        &[]
+    }
+
+    /// Keep the JSONL line this record was parsed from, for record types
+    /// whose table has a `raw_line` column
+    fn set_raw_line(&mut self, _line: String) {}
+
+    /// Whether records of this type have a `sample_id` field, which
+    /// `REQUIRE_SAMPLE_ID` checks for before deserializing
+    #[must_use]
+    fn has_sample_id() -> bool {
+        false
+    }
+
+    /// This record's sample identifier, for record types that carry one, so
+    /// parsing can apply `LOWERCASE_SAMPLE_IDS`
+    fn sample_id_mut(&mut self) -> Option<&mut SampleId> {
+        None
+    }
+
+    /// Record which line of the upload this record came from, for record
+    /// types whose table has a `source_line` column
+    fn set_source_line(&mut self, _line: i64) {}
+
+    /// Keep the hash of this record's fields, for record types whose table
+    /// has a `content_hash` column
+    fn set_content_hash(&mut self, _hash: String) {}
+
+    /// Line layouts this record type can ingest, oldest to newest, chosen by
+    /// `X-Schema-Version` or a per-line `schema_version` field. `None` for
+    /// types whose lines aren't versioned that way.
+    #[must_use]
+    fn schema_versions() -> Option<RangeInclusive<u32>> {
+        None
+    }
+
+    /// Rewrites `fields`, a line in the older layout `version`, into the
+    /// newest one. Only called with versions in `schema_versions`.
+    fn upgrade_fields(_version: u32, _fields: &mut Map<String, Value>) {}
+
+    /// The value Postgres hash-partitions this record's table by; rows
+    /// sharing a key share a partition. Inserts always target the parent
+    /// table and Postgres does the routing, so this only describes it. Empty
+    /// for types whose table isn't partitioned.
+    #[must_use]
+    #[allow(dead_code)] // not consulted by the insert path itself
+    fn partition_key(&self) -> String {
+        String::new()
+    }
+
+    /// The fields that should identify a record within one upload, for record
+    /// types where a repeat means the upstream tool misbehaved, checked by
+    /// `STRICT_DUPLICATE_KEYS`
+    #[must_use]
+    fn duplicate_key(&self) -> Option<String> {
+        None
+    }
+
+    /// Bind this record's fields to the query, borrowing them so a retried
+    /// batch can be bound again
+    fn bind_to<'q>(
+        &'q self,
+        query: sqlx::query::Query<'q, sqlx::Postgres, PgArguments>,
+    ) -> sqlx::query::Query<'q, sqlx::Postgres, PgArguments>;
+}
+
+/// A record type that can also be loaded with `COPY ... FROM STDIN`, which
+/// skips per-row parameter binding. COPY can't honor a `conflict_clause`, so
+/// types with one are still inserted with VALUES.
+pub trait CopyInsertable: BulkInsertable {
+    /// Appends this record to `out` as one line of COPY text format, its
+    /// columns in `column_names()` order
+    fn write_copy_row(&self, out: &mut String);
+}
+
+/// Writes one line of COPY text format: tab-separated columns, `\N` for
+/// NULL, and backslashes, tabs, and line breaks in text escaped.
+pub struct CopyRow<'a> {
+    out: &'a mut String,
+    empty: bool,
+}
+
+impl<'a> CopyRow<'a> {
+    pub fn new(out: &'a mut String) -> Self {
+        CopyRow { out, empty: true }
+    }
+
+    fn separate(&mut self) {
+        if !self.empty {
+            self.out.push('\t');
+        }
+        self.empty = false;
+    }
+
+    /// A text column
+    pub fn text(&mut self, value: &str) -> &mut Self {
+        self.separate();
+        for c in value.chars() {
+            match c {
+                '\\' => self.out.push_str("\\\\"),
+                '\t' => self.out.push_str("\\t"),
+                '\n' => self.out.push_str("\\n"),
+                '\r' => self.out.push_str("\\r"),
+                c => self.out.push(c),
+            }
+        }
+        self
+    }
+
+    /// A numeric or other column whose `Debug` form Postgres parses as is;
+    /// for floats that form round-trips and uses exponents for extremes
+    pub fn value(&mut self, value: impl std::fmt::Debug) -> &mut Self {
+        use std::fmt::Write;
+
+        self.separate();
+        // Writing to a String can't fail
+        let _ = write!(self.out, "{value:?}");
+        self
+    }
+
+    /// A NULL column
+    pub fn null(&mut self) -> &mut Self {
+        self.separate();
+        self.out.push_str("\\N");
+        self
+    }
+
+    /// A nullable text column
+    pub fn optional_text(&mut self, value: Option<&str>) -> &mut Self {
+        match value {
+            Some(value) => self.text(value),
+            None => self.null(),
+        }
+    }
+
+    /// A nullable numeric column
+    pub fn optional_value(&mut self, value: Option<impl std::fmt::Debug>) -> &mut Self {
+        match value {
+            Some(value) => self.value(value),
+            None => self.null(),
+        }
+    }
+
+    /// A nullable timestamp column
+    pub fn optional_timestamp(&mut self, value: Option<&DateTime<Utc>>) -> &mut Self {
+        self.optional_text(value.map(DateTime::to_rfc3339).as_deref())
+    }
+
+    /// Ends the line
+    pub fn finish(&mut self) {
+        self.out.push('\n');
+    }
 }
 
 #[derive(Serialize, Deserialize, Debug, Clone)]
@@ -36,7 +225,7 @@ pub struct DummyRecord {
 
 #[derive(Serialize, Deserialize, Debug, Clone, FromRow)]
 pub struct Gottcha2FullRecord {
-    pub sample_id: String,    // extracted from filename
+    pub sample_id: SampleId,  // extracted from filename
     pub level: String,        // LEVEL
     pub name: String,         // NAME
     pub taxid: String,        // TAXID
@@ -47,12 +236,29 @@ pub struct Gottcha2FullRecord {
     pub best_sig_cov: f64,    // BEST_SIG_COV
     pub depth: f64,           // DEPTH
     pub rel_abundance: f64,   // REL_ABUNDANCE
+    /// Original JSONL line, kept only when `STORE_RAW_LINE` is enabled
+    #[serde(skip)]
+    pub raw_line: Option<String>,
+    /// 1-based line of the upload this record came from, kept only when
+    /// `PRESERVE_INPUT_ORDER` is enabled. Assigned by the parser, never read
+    /// from the upload.
+    #[serde(skip_deserializing, skip_serializing_if = "Option::is_none")]
+    pub source_line: Option<i64>,
+    /// Hex blake3 hash of the record's fields, kept only when
+    /// `STORE_CONTENT_HASH` is enabled. Assigned by the parser, never read
+    /// from the upload.
+    #[serde(skip_deserializing, skip_serializing_if = "Option::is_none")]
+    pub content_hash: Option<String>,
+    /// When the upstream analysis ran, from the line's `observed_at` (or
+    /// `timestamp`) field, as distinct from `created_at`'s ingest time
+    #[serde(default, alias = "timestamp", skip_serializing_if = "Option::is_none")]
+    pub observed_at: Option<DateTime<Utc>>,
 }
 
 #[derive(Serialize, Deserialize, Debug, Clone, FromRow)]
 pub struct StastRecord {
     pub task: String,
-    pub sample_id: String, // renamed from 'sample' for consistency
+    pub sample_id: SampleId, // renamed from 'sample' for consistency
     pub qseqid: String,
     pub qlen: i64,
     pub sseqid: String,
@@ -64,6 +270,140 @@ pub struct StastRecord {
     pub sscinames: String,
     pub staxids: String,
     pub rank: String,
+    /// Original JSONL line, kept only when `STORE_RAW_LINE` is enabled
+    #[serde(skip)]
+    pub raw_line: Option<String>,
+    /// 1-based line of the upload this record came from, kept only when
+    /// `PRESERVE_INPUT_ORDER` is enabled. Assigned by the parser, never read
+    /// from the upload.
+    #[serde(skip_deserializing, skip_serializing_if = "Option::is_none")]
+    pub source_line: Option<i64>,
+    /// Hex blake3 hash of the record's fields, kept only when
+    /// `STORE_CONTENT_HASH` is enabled. Assigned by the parser, never read
+    /// from the upload.
+    #[serde(skip_deserializing, skip_serializing_if = "Option::is_none")]
+    pub content_hash: Option<String>,
+    /// When the upstream analysis ran, from the line's `observed_at` (or
+    /// `timestamp`) field, as distinct from `created_at`'s ingest time
+    #[serde(default, alias = "timestamp", skip_serializing_if = "Option::is_none")]
+    pub observed_at: Option<DateTime<Utc>>,
+}
+
+/// One line of a Kraken2 report, in the standard report's column order.
+#[derive(Serialize, Deserialize, Debug, Clone, FromRow)]
+pub struct Kraken2Record {
+    pub sample_id: SampleId,
+    pub percentage: f64,   // reads in this taxon's clade, as a percentage
+    pub clade_reads: i64,  // reads in this taxon's clade
+    pub taxon_reads: i64,  // reads assigned to this taxon directly
+    pub rank_code: String, // U, R, D, K, P, C, O, F, G, or S, e.g. S1 below
+    pub taxid: String,     // NCBI taxonomy ID
+    pub name: String,      // scientific name
+    /// Original JSONL line, kept only when `STORE_RAW_LINE` is enabled
+    #[serde(skip)]
+    pub raw_line: Option<String>,
+    /// 1-based line of the upload this record came from, kept only when
+    /// `PRESERVE_INPUT_ORDER` is enabled. Assigned by the parser, never read
+    /// from the upload.
+    #[serde(skip_deserializing, skip_serializing_if = "Option::is_none")]
+    pub source_line: Option<i64>,
+    /// Hex blake3 hash of the record's fields, kept only when
+    /// `STORE_CONTENT_HASH` is enabled. Assigned by the parser, never read
+    /// from the upload.
+    #[serde(skip_deserializing, skip_serializing_if = "Option::is_none")]
+    pub content_hash: Option<String>,
+    /// When the upstream analysis ran, from the line's `observed_at` (or
+    /// `timestamp`) field, as distinct from `created_at`'s ingest time
+    #[serde(default, alias = "timestamp", skip_serializing_if = "Option::is_none")]
+    pub observed_at: Option<DateTime<Utc>>,
+}
+
+impl Gottcha2FullRecord {
+    /// Checks `level` against the known taxonomic ranks.
+    ///
+    /// # Errors
+    ///
+    /// Returns a message naming the offending level.
+    pub fn validate_level(&self) -> Result<(), String> {
+        TaxonomicLevel::parse(&self.level).map(|_| ())
+    }
+
+    /// Checks that counts aren't negative and that coverage and abundance
+    /// fractions fall in `[0, 1]`.
+    ///
+    /// # Errors
+    ///
+    /// Returns the first field found out of range.
+    pub fn validate_ranges(&self) -> Result<(), FieldViolation> {
+        check_non_negative("read_count", self.read_count)?;
+        check_non_negative("total_bp_mapped", self.total_bp_mapped)?;
+        check_non_negative("covered_sig_len", self.covered_sig_len)?;
+        check_non_negative("depth", self.depth)?;
+        check_unit_interval("ani_ci95", self.ani_ci95)?;
+        check_unit_interval("best_sig_cov", self.best_sig_cov)?;
+        check_unit_interval("rel_abundance", self.rel_abundance)
+    }
+}
+
+impl StastRecord {
+    /// Checks the rank portion of `rank` (formatted `rank` or `rank:name`)
+    /// against the known taxonomic ranks.
+    ///
+    /// # Errors
+    ///
+    /// Returns a message naming the offending rank.
+    pub fn validate_rank(&self) -> Result<(), String> {
+        let rank = self
+            .rank
+            .split_once(':')
+            .map_or(self.rank.as_str(), |(r, _)| r);
+        TaxonomicLevel::parse(rank).map(|_| ())
+    }
+
+    /// Checks that lengths, e-values, and bit scores aren't negative and
+    /// that `pident` is a percentage in `[0, 100]`.
+    ///
+    /// # Errors
+    ///
+    /// Returns the first field found out of range.
+    pub fn validate_ranges(&self) -> Result<(), FieldViolation> {
+        check_non_negative("qlen", self.qlen)?;
+        check_non_negative("length", self.length)?;
+        check_percentage("pident", self.pident)?;
+        check_non_negative("evalue", self.evalue)?;
+        check_non_negative("bitscore", self.bitscore)
+    }
+}
+
+impl Kraken2Record {
+    /// Checks `rank_code` against Kraken2's rank letters, optionally followed
+    /// by a depth below that rank (`S1`, `G2`).
+    ///
+    /// # Errors
+    ///
+    /// Returns a message naming the offending rank code.
+    pub fn validate_rank_code(&self) -> Result<(), String> {
+        let mut chars = self.rank_code.chars();
+        let known = chars.next().is_some_and(|rank| "URDKPCOFGS".contains(rank))
+            && chars.all(|c| c.is_ascii_digit());
+        if known {
+            Ok(())
+        } else {
+            Err(format!("unknown Kraken2 rank code {:?}", self.rank_code))
+        }
+    }
+
+    /// Checks that read counts aren't negative and that `percentage` falls in
+    /// `[0, 100]`.
+    ///
+    /// # Errors
+    ///
+    /// Returns the first field found out of range.
+    pub fn validate_ranges(&self) -> Result<(), FieldViolation> {
+        check_percentage("percentage", self.percentage)?;
+        check_non_negative("clade_reads", self.clade_reads)?;
+        check_non_negative("taxon_reads", self.taxon_reads)
+    }
 }
 
 impl BulkInsertable for DummyRecord {
@@ -83,42 +423,91 @@ impl BulkInsertable for DummyRecord {
         Some(" ON CONFLICT (idempotency_key) DO NOTHING")
     }
 
-    fn bind_to(
-        self,
-        query: sqlx::query::Query<'_, sqlx::Postgres, PgArguments>,
-    ) -> sqlx::query::Query<'_, sqlx::Postgres, PgArguments> {
+    fn idempotency_key(&self) -> Option<&str> {
+        Some(&self.idempotency_key)
+    }
+
+    /// The first record sent under an idempotency key is the one kept
+    fn ordered_inserts() -> bool {
+        true
+    }
+
+    fn bind_to<'q>(
+        &'q self,
+        query: sqlx::query::Query<'q, sqlx::Postgres, PgArguments>,
+    ) -> sqlx::query::Query<'q, sqlx::Postgres, PgArguments> {
         query
-            .bind(self.run_id)
-            .bind(self.task_id)
+            .bind(&self.run_id)
+            .bind(&self.task_id)
             .bind(self.shard)
-            .bind(self.idempotency_key)
+            .bind(&self.idempotency_key)
             .bind(self.schema_version)
-            .bind(self.payload)
+            .bind(&self.payload)
     }
 }
 
 impl BulkInsertable for Gottcha2FullRecord {
     fn field_count() -> usize {
-        11
+        15
     }
 
     fn table_name() -> &'static str {
         "gottcha2_results"
     }
 
+    fn zero_default_fields() -> &'static [&'static str] {
+        &[
+            "total_bp_mapped",
+            "ani_ci95",
+            "covered_sig_len",
+            "best_sig_cov",
+            "depth",
+        ]
+    }
+
     fn column_names() -> &'static str {
-        "sample_id, level, name, taxid, read_count, total_bp_mapped, ani_ci95, covered_sig_len, best_sig_cov, depth, rel_abundance"
+        "sample_id, level, name, taxid, read_count, total_bp_mapped, ani_ci95, covered_sig_len, best_sig_cov, depth, rel_abundance, raw_line, source_line, content_hash, observed_at"
+    }
+
+    /// A retried upload re-sends rows the sample already holds; the unique
+    /// index on these columns lets them be skipped.
+    fn conflict_clause() -> Option<&'static str> {
+        Some(" ON CONFLICT (sample_id, taxid, level) DO NOTHING")
+    }
+
+    /// A repeated taxon and level keeps the row uploaded first
+    fn ordered_inserts() -> bool {
+        true
+    }
+
+    /// Version 1 predates the mapping statistics in `zero_default_fields`;
+    /// version 2 is the current layout.
+    fn schema_versions() -> Option<RangeInclusive<u32>> {
+        Some(1..=2)
+    }
+
+    fn upgrade_fields(version: u32, fields: &mut Map<String, Value>) {
+        if version < 2 {
+            for field in Self::zero_default_fields() {
+                fields.entry(*field).or_insert_with(|| Value::from(0));
+            }
+        }
     }
 
-    fn bind_to(
-        self,
-        query: sqlx::query::Query<'_, sqlx::Postgres, PgArguments>,
-    ) -> sqlx::query::Query<'_, sqlx::Postgres, PgArguments> {
+    /// `gottcha2_results` is hash-partitioned on `sample_id`
+    fn partition_key(&self) -> String {
+        self.sample_id.to_string()
+    }
+
+    fn bind_to<'q>(
+        &'q self,
+        query: sqlx::query::Query<'q, sqlx::Postgres, PgArguments>,
+    ) -> sqlx::query::Query<'q, sqlx::Postgres, PgArguments> {
         query
-            .bind(self.sample_id)
-            .bind(self.level)
-            .bind(self.name)
-            .bind(self.taxid)
+            .bind(&self.sample_id)
+            .bind(&self.level)
+            .bind(&self.name)
+            .bind(&self.taxid)
             .bind(self.read_count)
             .bind(self.total_bp_mapped)
             .bind(self.ani_ci95)
@@ -126,12 +515,36 @@ impl BulkInsertable for Gottcha2FullRecord {
             .bind(self.best_sig_cov)
             .bind(self.depth)
             .bind(self.rel_abundance)
+            .bind(&self.raw_line)
+            .bind(self.source_line)
+            .bind(&self.content_hash)
+            .bind(self.observed_at)
+    }
+
+    fn set_raw_line(&mut self, line: String) {
+        self.raw_line = Some(line);
+    }
+
+    fn set_source_line(&mut self, line: i64) {
+        self.source_line = Some(line);
+    }
+
+    fn set_content_hash(&mut self, hash: String) {
+        self.content_hash = Some(hash);
+    }
+
+    fn has_sample_id() -> bool {
+        true
+    }
+
+    fn sample_id_mut(&mut self) -> Option<&mut SampleId> {
+        Some(&mut self.sample_id)
     }
 }
 
 impl BulkInsertable for StastRecord {
     fn field_count() -> usize {
-        13
+        17
     }
 
     fn table_name() -> &'static str {
@@ -139,27 +552,193 @@ impl BulkInsertable for StastRecord {
     }
 
     fn column_names() -> &'static str {
-        "task, sample_id, qseqid, qlen, sseqid, stitle, length, pident, evalue, bitscore, sscinames, staxids, rank"
+        "task, sample_id, qseqid, qlen, sseqid, stitle, length, pident, evalue, bitscore, sscinames, staxids, rank, raw_line, source_line, content_hash, observed_at"
     }
 
-    fn bind_to(
-        self,
-        query: sqlx::query::Query<'_, sqlx::Postgres, PgArguments>,
-    ) -> sqlx::query::Query<'_, sqlx::Postgres, PgArguments> {
+    fn bind_to<'q>(
+        &'q self,
+        query: sqlx::query::Query<'q, sqlx::Postgres, PgArguments>,
+    ) -> sqlx::query::Query<'q, sqlx::Postgres, PgArguments> {
         query
-            .bind(self.task)
-            .bind(self.sample_id)
-            .bind(self.qseqid)
+            .bind(&self.task)
+            .bind(&self.sample_id)
+            .bind(&self.qseqid)
             .bind(self.qlen)
-            .bind(self.sseqid)
-            .bind(self.stitle)
+            .bind(&self.sseqid)
+            .bind(&self.stitle)
             .bind(self.length)
             .bind(self.pident)
             .bind(self.evalue)
             .bind(self.bitscore)
-            .bind(self.sscinames)
-            .bind(self.staxids)
-            .bind(self.rank)
+            .bind(&self.sscinames)
+            .bind(&self.staxids)
+            .bind(&self.rank)
+            .bind(&self.raw_line)
+            .bind(self.source_line)
+            .bind(&self.content_hash)
+            .bind(self.observed_at)
+    }
+
+    fn set_raw_line(&mut self, line: String) {
+        self.raw_line = Some(line);
+    }
+
+    fn set_source_line(&mut self, line: i64) {
+        self.source_line = Some(line);
+    }
+
+    fn set_content_hash(&mut self, hash: String) {
+        self.content_hash = Some(hash);
+    }
+
+    fn has_sample_id() -> bool {
+        true
+    }
+
+    fn sample_id_mut(&mut self) -> Option<&mut SampleId> {
+        Some(&mut self.sample_id)
+    }
+
+    fn schema_versions() -> Option<RangeInclusive<u32>> {
+        Some(1..=1)
+    }
+
+    fn duplicate_key(&self) -> Option<String> {
+        Some(format!(
+            "(sample_id, qseqid, sseqid) = ({}, {}, {})",
+            self.sample_id, self.qseqid, self.sseqid
+        ))
+    }
+}
+
+impl BulkInsertable for Kraken2Record {
+    fn field_count() -> usize {
+        11
+    }
+
+    fn table_name() -> &'static str {
+        "kraken2_results"
+    }
+
+    fn column_names() -> &'static str {
+        "sample_id, percentage, clade_reads, taxon_reads, rank_code, taxid, name, raw_line, source_line, content_hash, observed_at"
+    }
+
+    /// A report lists each taxon once; the unique index on these columns
+    /// lets a retried upload's rows be skipped.
+    fn conflict_clause() -> Option<&'static str> {
+        Some(" ON CONFLICT (sample_id, taxid) DO NOTHING")
+    }
+
+    /// A repeated taxon keeps the row uploaded first
+    fn ordered_inserts() -> bool {
+        true
+    }
+
+    fn bind_to<'q>(
+        &'q self,
+        query: sqlx::query::Query<'q, sqlx::Postgres, PgArguments>,
+    ) -> sqlx::query::Query<'q, sqlx::Postgres, PgArguments> {
+        query
+            .bind(&self.sample_id)
+            .bind(self.percentage)
+            .bind(self.clade_reads)
+            .bind(self.taxon_reads)
+            .bind(&self.rank_code)
+            .bind(&self.taxid)
+            .bind(&self.name)
+            .bind(&self.raw_line)
+            .bind(self.source_line)
+            .bind(&self.content_hash)
+            .bind(self.observed_at)
+    }
+
+    fn set_raw_line(&mut self, line: String) {
+        self.raw_line = Some(line);
+    }
+
+    fn set_source_line(&mut self, line: i64) {
+        self.source_line = Some(line);
+    }
+
+    fn set_content_hash(&mut self, hash: String) {
+        self.content_hash = Some(hash);
+    }
+
+    fn has_sample_id() -> bool {
+        true
+    }
+
+    fn sample_id_mut(&mut self) -> Option<&mut SampleId> {
+        Some(&mut self.sample_id)
+    }
+
+    fn schema_versions() -> Option<RangeInclusive<u32>> {
+        Some(1..=1)
+    }
+}
+
+impl CopyInsertable for Gottcha2FullRecord {
+    fn write_copy_row(&self, out: &mut String) {
+        CopyRow::new(out)
+            .text(self.sample_id.as_str())
+            .text(&self.level)
+            .text(&self.name)
+            .text(&self.taxid)
+            .value(self.read_count)
+            .value(self.total_bp_mapped)
+            .value(self.ani_ci95)
+            .value(self.covered_sig_len)
+            .value(self.best_sig_cov)
+            .value(self.depth)
+            .value(self.rel_abundance)
+            .optional_text(self.raw_line.as_deref())
+            .optional_value(self.source_line)
+            .optional_text(self.content_hash.as_deref())
+            .optional_timestamp(self.observed_at.as_ref())
+            .finish();
+    }
+}
+
+impl CopyInsertable for StastRecord {
+    fn write_copy_row(&self, out: &mut String) {
+        CopyRow::new(out)
+            .text(&self.task)
+            .text(self.sample_id.as_str())
+            .text(&self.qseqid)
+            .value(self.qlen)
+            .text(&self.sseqid)
+            .text(&self.stitle)
+            .value(self.length)
+            .value(self.pident)
+            .value(self.evalue)
+            .value(self.bitscore)
+            .text(&self.sscinames)
+            .text(&self.staxids)
+            .text(&self.rank)
+            .optional_text(self.raw_line.as_deref())
+            .optional_value(self.source_line)
+            .optional_text(self.content_hash.as_deref())
+            .optional_timestamp(self.observed_at.as_ref())
+            .finish();
+    }
+}
+
+impl CopyInsertable for Kraken2Record {
+    fn write_copy_row(&self, out: &mut String) {
+        CopyRow::new(out)
+            .text(self.sample_id.as_str())
+            .value(self.percentage)
+            .value(self.clade_reads)
+            .value(self.taxon_reads)
+            .text(&self.rank_code)
+            .text(&self.taxid)
+            .text(&self.name)
+            .optional_text(self.raw_line.as_deref())
+            .optional_value(self.source_line)
+            .optional_text(self.content_hash.as_deref())
+            .optional_timestamp(self.observed_at.as_ref())
+            .finish();
     }
 }
 
@@ -197,6 +776,42 @@ mod tests {
         );
     }
 
+    #[test]
+    fn kraken2_field_count_matches_columns() {
+        let column_count = Kraken2Record::column_names().split(", ").count();
+        assert_eq!(
+            Kraken2Record::field_count(),
+            column_count,
+            "field_count() should match the number of columns"
+        );
+    }
+
+    #[test]
+    fn max_batch_size_stays_under_parameter_limit() {
+        let widths = [
+            DummyRecord::field_count(),
+            Gottcha2FullRecord::field_count(),
+            StastRecord::field_count(),
+            Kraken2Record::field_count(),
+        ];
+        let batch_sizes = [
+            DummyRecord::max_batch_size(),
+            Gottcha2FullRecord::max_batch_size(),
+            StastRecord::max_batch_size(),
+            Kraken2Record::max_batch_size(),
+        ];
+        for (width, batch_size) in widths.into_iter().zip(batch_sizes) {
+            assert!(
+                width * batch_size <= MAX_BIND_PARAMS,
+                "{batch_size} rows of {width} fields exceeds the parameter limit"
+            );
+            assert!(
+                width * (batch_size + 1) > MAX_BIND_PARAMS,
+                "max_batch_size() should pack as many rows as the limit allows"
+            );
+        }
+    }
+
     #[test]
     fn dummy_record_table_name() {
         assert_eq!(DummyRecord::table_name(), "results");
@@ -212,6 +827,11 @@ mod tests {
         assert_eq!(StastRecord::table_name(), "stast_results");
     }
 
+    #[test]
+    fn kraken2_table_name() {
+        assert_eq!(Kraken2Record::table_name(), "kraken2_results");
+    }
+
     #[test]
     fn dummy_record_has_conflict_clause() {
         assert!(
@@ -227,14 +847,277 @@ mod tests {
     }
 
     #[test]
-    fn gottcha2_and_stast_have_no_conflict_clause() {
-        assert!(
-            Gottcha2FullRecord::conflict_clause().is_none(),
-            "Gottcha2FullRecord should not have a conflict clause"
+    fn gottcha2_conflicts_on_sample_taxon_and_level() {
+        assert_eq!(
+            Gottcha2FullRecord::conflict_clause(),
+            Some(" ON CONFLICT (sample_id, taxid, level) DO NOTHING")
+        );
+    }
+
+    #[test]
+    fn kraken2_conflicts_on_sample_and_taxon() {
+        assert_eq!(
+            Kraken2Record::conflict_clause(),
+            Some(" ON CONFLICT (sample_id, taxid) DO NOTHING")
+        );
+    }
+
+    #[test]
+    fn kraken2_rank_codes() {
+        let with_rank = |rank_code: &str| Kraken2Record {
+            sample_id: "s1".parse().expect("Invalid sample id"),
+            percentage: 12.5,
+            clade_reads: 100,
+            taxon_reads: 40,
+            rank_code: rank_code.to_string(),
+            taxid: "562".to_string(),
+            name: "Escherichia coli".to_string(),
+            raw_line: None,
+            source_line: None,
+            content_hash: None,
+            observed_at: None,
+        };
+        for code in ["U", "R", "D", "S", "S1", "G2"] {
+            assert!(with_rank(code).validate_rank_code().is_ok(), "{code}");
+        }
+        for code in ["", "X", "s", "S-1", "1"] {
+            assert!(with_rank(code).validate_rank_code().is_err(), "{code}");
+        }
+        assert!(with_rank("S").validate_ranges().is_ok());
+        let over = Kraken2Record {
+            percentage: 100.5,
+            ..with_rank("S")
+        };
+        assert_eq!(
+            over.validate_ranges().map_err(|v| v.field),
+            Err("percentage")
         );
+    }
+
+    #[test]
+    fn stast_has_no_conflict_clause() {
         assert!(
             StastRecord::conflict_clause().is_none(),
             "StastRecord should not have a conflict clause"
         );
     }
+
+    #[test]
+    fn copy_rows_escape_text_and_mark_nulls() {
+        let mut out = String::new();
+        CopyRow::new(&mut out)
+            .text("tab\there")
+            .text("back\\slash\nnewline")
+            .value(1e-50)
+            .value(42_i64)
+            .optional_text(None)
+            .optional_value(Some(0.5))
+            .finish();
+        assert_eq!(
+            out,
+            "tab\\there\tback\\\\slash\\nnewline\t1e-50\t42\t\\N\t0.5\n"
+        );
+    }
+
+    fn stast_record() -> StastRecord {
+        StastRecord {
+            task: "megablast".to_string(),
+            sample_id: "s1".parse().expect("Invalid sample id"),
+            qseqid: "NODE_1".to_string(),
+            qlen: 100,
+            sseqid: "gi|1|".to_string(),
+            stitle: "title".to_string(),
+            length: 90,
+            pident: 99.0,
+            evalue: 1e-10,
+            bitscore: 200.0,
+            sscinames: "virus".to_string(),
+            staxids: "1".to_string(),
+            rank: "species:virus".to_string(),
+            raw_line: None,
+            source_line: Some(3),
+            content_hash: None,
+            observed_at: None,
+        }
+    }
+
+    #[test]
+    fn copy_rows_have_a_column_per_field() {
+        let record = stast_record();
+        let mut out = String::new();
+        record.write_copy_row(&mut out);
+        let columns: Vec<&str> = out.trim_end_matches('\n').split('\t').collect();
+        assert_eq!(columns.len(), StastRecord::field_count());
+        assert_eq!(columns[14], "3");
+        assert_eq!(columns[16], "\\N");
+    }
+
+    #[test]
+    fn stast_range_boundaries() {
+        let valid = stast_record();
+        assert!(valid.validate_ranges().is_ok());
+
+        let at_bounds = StastRecord {
+            qlen: 0,
+            length: 0,
+            pident: 100.0,
+            evalue: 0.0,
+            bitscore: 0.0,
+            ..stast_record()
+        };
+        assert!(at_bounds.validate_ranges().is_ok());
+        assert!(
+            StastRecord {
+                pident: 0.0,
+                ..stast_record()
+            }
+            .validate_ranges()
+            .is_ok()
+        );
+
+        for (field, record) in [
+            (
+                "qlen",
+                StastRecord {
+                    qlen: -1,
+                    ..stast_record()
+                },
+            ),
+            (
+                "length",
+                StastRecord {
+                    length: -1,
+                    ..stast_record()
+                },
+            ),
+            (
+                "pident",
+                StastRecord {
+                    pident: 100.5,
+                    ..stast_record()
+                },
+            ),
+            (
+                "pident",
+                StastRecord {
+                    pident: -1.0,
+                    ..stast_record()
+                },
+            ),
+            (
+                "evalue",
+                StastRecord {
+                    evalue: -1e-5,
+                    ..stast_record()
+                },
+            ),
+            (
+                "bitscore",
+                StastRecord {
+                    bitscore: -0.5,
+                    ..stast_record()
+                },
+            ),
+            (
+                "bitscore",
+                StastRecord {
+                    bitscore: f64::NAN,
+                    ..stast_record()
+                },
+            ),
+        ] {
+            let violation = record.validate_ranges().expect_err("out of range");
+            assert_eq!(violation.field, field);
+        }
+    }
+
+    fn gottcha2_record() -> Gottcha2FullRecord {
+        Gottcha2FullRecord {
+            sample_id: "s1".parse().expect("Invalid sample id"),
+            level: "species".to_string(),
+            name: "Escherichia coli".to_string(),
+            taxid: "562".to_string(),
+            read_count: 10,
+            total_bp_mapped: 1500,
+            ani_ci95: 0.5,
+            covered_sig_len: 300,
+            best_sig_cov: 0.5,
+            depth: 2.0,
+            rel_abundance: 0.5,
+            raw_line: None,
+            source_line: None,
+            content_hash: None,
+            observed_at: None,
+        }
+    }
+
+    #[test]
+    fn gottcha2_range_boundaries() {
+        let at_bounds = Gottcha2FullRecord {
+            read_count: 0,
+            total_bp_mapped: 0,
+            covered_sig_len: 0,
+            depth: 0.0,
+            ani_ci95: 1.0,
+            best_sig_cov: 0.0,
+            rel_abundance: 1.0,
+            ..gottcha2_record()
+        };
+        assert!(at_bounds.validate_ranges().is_ok());
+
+        for (field, record) in [
+            (
+                "read_count",
+                Gottcha2FullRecord {
+                    read_count: -1,
+                    ..gottcha2_record()
+                },
+            ),
+            (
+                "total_bp_mapped",
+                Gottcha2FullRecord {
+                    total_bp_mapped: -1,
+                    ..gottcha2_record()
+                },
+            ),
+            (
+                "covered_sig_len",
+                Gottcha2FullRecord {
+                    covered_sig_len: -1,
+                    ..gottcha2_record()
+                },
+            ),
+            (
+                "depth",
+                Gottcha2FullRecord {
+                    depth: -0.1,
+                    ..gottcha2_record()
+                },
+            ),
+            (
+                "ani_ci95",
+                Gottcha2FullRecord {
+                    ani_ci95: 1.01,
+                    ..gottcha2_record()
+                },
+            ),
+            (
+                "best_sig_cov",
+                Gottcha2FullRecord {
+                    best_sig_cov: -0.01,
+                    ..gottcha2_record()
+                },
+            ),
+            (
+                "rel_abundance",
+                Gottcha2FullRecord {
+                    rel_abundance: 7.2,
+                    ..gottcha2_record()
+                },
+            ),
+        ] {
+            let violation = record.validate_ranges().expect_err("out of range");
+            assert_eq!(violation.field, field);
+        }
+    }
 }
@@ -0,0 +1,135 @@
+use std::{fmt, ops::Deref, str::FromStr};
+
+use serde::{Deserialize, Serialize};
+
+/// A sample identifier, trimmed of surrounding whitespace and guaranteed
+/// non-empty, so `" SRR123"` and `"SRR123"` land as the same sample.
+///
+/// Lowercasing is opt-in (`LOWERCASE_SAMPLE_IDS`) since some pipelines treat
+/// case as significant.
+#[derive(
+    Serialize, Deserialize, Debug, Clone, PartialEq, Eq, Hash, PartialOrd, Ord, sqlx::Type,
+)]
+#[serde(try_from = "String", into = "String")]
+#[sqlx(transparent)]
+pub struct SampleId(String);
+
+impl SampleId {
+    /// Trims `raw` and checks that something printable is left.
+    ///
+    /// # Errors
+    ///
+    /// Returns a message naming the offending value if it is blank or
+    /// contains control characters.
+    pub fn new(raw: &str) -> Result<Self, String> {
+        let trimmed = raw.trim();
+        if trimmed.is_empty() {
+            return Err("sample_id must not be empty".to_string());
+        }
+        if trimmed.chars().any(char::is_control) {
+            return Err(format!(
+                "sample_id {raw:?} must not contain control characters"
+            ));
+        }
+        Ok(SampleId(trimmed.to_string()))
+    }
+
+    /// Like `new`, additionally lowercasing when `lowercase` is set.
+    ///
+    /// # Errors
+    ///
+    /// Returns a message naming the offending value if it is invalid.
+    pub fn normalized(raw: &str, lowercase: bool) -> Result<Self, String> {
+        let mut id = Self::new(raw)?;
+        if lowercase {
+            id.make_lowercase();
+        }
+        Ok(id)
+    }
+
+    /// Lowercases the identifier in place.
+    pub fn make_lowercase(&mut self) {
+        self.0 = self.0.to_lowercase();
+    }
+
+    #[must_use]
+    pub fn as_str(&self) -> &str {
+        &self.0
+    }
+}
+
+impl TryFrom<String> for SampleId {
+    type Error = String;
+
+    fn try_from(raw: String) -> Result<Self, Self::Error> {
+        Self::new(&raw)
+    }
+}
+
+impl FromStr for SampleId {
+    type Err = String;
+
+    fn from_str(raw: &str) -> Result<Self, Self::Err> {
+        Self::new(raw)
+    }
+}
+
+impl From<SampleId> for String {
+    fn from(id: SampleId) -> Self {
+        id.0
+    }
+}
+
+impl Deref for SampleId {
+    type Target = str;
+
+    fn deref(&self) -> &str {
+        &self.0
+    }
+}
+
+impl fmt::Display for SampleId {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.0)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn surrounding_whitespace_is_trimmed() {
+        let id = SampleId::new("  SRR123\t").expect("Failed to parse sample id");
+        assert_eq!(id.as_str(), "SRR123");
+    }
+
+    #[test]
+    fn lowercasing_is_opt_in() {
+        let kept = SampleId::normalized("SRR123", false).expect("Failed to parse sample id");
+        let lowered = SampleId::normalized(" SRR123 ", true).expect("Failed to parse sample id");
+        assert_eq!(kept.as_str(), "SRR123");
+        assert_eq!(lowered.as_str(), "srr123");
+    }
+
+    #[test]
+    fn empty_and_blank_ids_are_rejected() {
+        for raw in ["", "   ", "\t\n"] {
+            let err = SampleId::new(raw).expect_err("blank sample id should not parse");
+            assert!(err.contains("empty"), "got: {err}");
+        }
+    }
+
+    #[test]
+    fn control_characters_are_rejected() {
+        let err = SampleId::new("SRR\u{0}123").expect_err("control character should not parse");
+        assert!(err.contains("control"), "got: {err}");
+    }
+
+    #[test]
+    fn deserializing_normalizes_and_validates() {
+        let id: SampleId = serde_json::from_str(r#"" SRR123 ""#).expect("Failed to deserialize");
+        assert_eq!(id.as_str(), "SRR123");
+        assert!(serde_json::from_str::<SampleId>(r#""""#).is_err());
+    }
+}
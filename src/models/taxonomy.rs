@@ -0,0 +1,48 @@
+use serde::{Deserialize, Serialize, de::IntoDeserializer};
+
+/// Taxonomic ranks GOTTCHA2 and STAST report, used when strict level
+/// validation is enabled.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum TaxonomicLevel {
+    Superkingdom,
+    Phylum,
+    Class,
+    Order,
+    Family,
+    Genus,
+    Species,
+    Strain,
+}
+
+impl TaxonomicLevel {
+    /// Parses a level through the same serde representation used on the wire.
+    ///
+    /// # Errors
+    ///
+    /// Returns a message naming the offending value if it isn't a known level.
+    pub fn parse(value: &str) -> Result<Self, String> {
+        Self::deserialize(value.into_deserializer())
+            .map_err(|_: serde::de::value::Error| format!("unknown taxonomic level {value:?}"))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn known_levels_parse() {
+        assert_eq!(TaxonomicLevel::parse("genus"), Ok(TaxonomicLevel::Genus));
+        assert_eq!(
+            TaxonomicLevel::parse("superkingdom"),
+            Ok(TaxonomicLevel::Superkingdom)
+        );
+    }
+
+    #[test]
+    fn typo_is_rejected_with_value() {
+        let err = TaxonomicLevel::parse("spesies").expect_err("typo should not parse");
+        assert!(err.contains("\"spesies\""), "got: {err}");
+    }
+}
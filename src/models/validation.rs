@@ -0,0 +1,134 @@
+use serde::Serialize;
+use serde_json::Value;
+
+/// A field that failed a range check, before its position in the upload is
+/// known.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FieldViolation {
+    pub field: &'static str,
+    pub value: Value,
+    pub constraint: &'static str,
+}
+
+impl FieldViolation {
+    /// Attaches the 1-based line number the offending record came from.
+    #[must_use]
+    pub fn at_line(self, line: usize) -> FieldError {
+        FieldError {
+            line,
+            field: self.field,
+            value: self.value,
+            constraint: self.constraint,
+        }
+    }
+}
+
+/// A validation failure as reported to the client, e.g.
+/// `{"line":42,"field":"rel_abundance","value":1.5,"constraint":"0..=1"}`.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct FieldError {
+    pub line: usize,
+    pub field: &'static str,
+    pub value: Value,
+    pub constraint: &'static str,
+}
+
+/// Checks that a fraction-valued field lies in `[0, 1]`.
+///
+/// # Errors
+///
+/// Returns the violation if `value` is outside the range or not a number.
+pub fn check_unit_interval(field: &'static str, value: f64) -> Result<(), FieldViolation> {
+    if (0.0..=1.0).contains(&value) {
+        Ok(())
+    } else {
+        Err(FieldViolation {
+            field,
+            value: Value::from(value),
+            constraint: "0..=1",
+        })
+    }
+}
+
+/// Checks that a percentage-valued field lies in `[0, 100]`.
+///
+/// # Errors
+///
+/// Returns the violation if `value` is outside the range or not a number.
+pub fn check_percentage(field: &'static str, value: f64) -> Result<(), FieldViolation> {
+    if (0.0..=100.0).contains(&value) {
+        Ok(())
+    } else {
+        Err(FieldViolation {
+            field,
+            value: Value::from(value),
+            constraint: "0..=100",
+        })
+    }
+}
+
+/// Checks that a count or measurement is not negative.
+///
+/// # Errors
+///
+/// Returns the violation if `value` is below zero or not a number.
+pub fn check_non_negative<T>(field: &'static str, value: T) -> Result<(), FieldViolation>
+where
+    T: PartialOrd + Default + Into<Value>,
+{
+    // `>=` is false for NaN, so NaN fails the check too
+    if value >= T::default() {
+        Ok(())
+    } else {
+        Err(FieldViolation {
+            field,
+            value: value.into(),
+            constraint: "0..",
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn unit_interval_bounds_are_inclusive() {
+        assert!(check_unit_interval("f", 0.0).is_ok());
+        assert!(check_unit_interval("f", 1.0).is_ok());
+        assert!(check_unit_interval("f", 1.5).is_err());
+        assert!(check_unit_interval("f", f64::NAN).is_err());
+    }
+
+    #[test]
+    fn percentage_bounds_are_inclusive() {
+        assert!(check_percentage("pident", 0.0).is_ok());
+        assert!(check_percentage("pident", 100.0).is_ok());
+        assert!(check_percentage("pident", -0.1).is_err());
+        assert!(check_percentage("pident", 100.1).is_err());
+        assert!(check_percentage("pident", f64::NAN).is_err());
+    }
+
+    #[test]
+    fn violation_carries_line_and_value() {
+        let err = check_unit_interval("rel_abundance", 1.5)
+            .expect_err("1.5 is out of range")
+            .at_line(42);
+        assert_eq!(
+            serde_json::to_value(&err).expect("Failed to serialize"),
+            serde_json::json!({
+                "line": 42,
+                "field": "rel_abundance",
+                "value": 1.5,
+                "constraint": "0..=1"
+            })
+        );
+    }
+
+    #[test]
+    fn negative_counts_are_rejected() {
+        assert!(check_non_negative("read_count", 0_i64).is_ok());
+        assert!(check_non_negative("read_count", -1_i64).is_err());
+        assert!(check_non_negative("depth", f64::NAN).is_err());
+    }
+}
@@ -1,34 +1,103 @@
-use std::{env, fs::File, io::Write, num::NonZeroUsize, thread};
+use std::{env, fs::File, io::Write, num::NonZeroUsize, path::Path, thread};
 
-use tracing::{info, warn};
-use tracing_subscriber::{layer::SubscriberExt, util::SubscriberInitExt};
+use chrono::{DateTime, TimeDelta, Utc};
+use color_eyre::eyre::{Context, eyre};
+use serde::Deserialize;
+use tracing::{Subscriber, info, warn};
+use tracing_subscriber::{
+    EnvFilter, Layer, fmt::MakeWriter, layer::SubscriberExt, util::SubscriberInitExt,
+};
 
-pub fn setup_tracing() {
-    tracing_subscriber::registry()
-        .with(
-            tracing_subscriber::EnvFilter::try_from_default_env()
-                .unwrap_or_else(|_| format!("{}=debug", env!("CARGO_CRATE_NAME")).into()),
-        )
-        .with(tracing_subscriber::fmt::layer())
-        .init();
+use crate::config::AppConfig;
+
+/// How log lines are written, set with `LOG_FORMAT`.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum LogFormat {
+    /// Human-readable lines
+    #[default]
+    Pretty,
+    /// One JSON object per line, for log aggregators
+    Json,
+}
+
+impl LogFormat {
+    /// Reads `LOG_FORMAT` from the environment. Tracing starts before the
+    /// rest of the configuration is loaded, so it's read on its own.
+    pub fn from_env() -> color_eyre::Result<Self> {
+        #[derive(Deserialize)]
+        struct LoggingEnv {
+            #[serde(default)]
+            log_format: LogFormat,
+        }
+
+        envy::from_env::<LoggingEnv>()
+            .map(|env| env.log_format)
+            .map_err(|e| eyre!("LOG_FORMAT must be pretty or json: {e}"))
+    }
+}
+
+pub fn setup_tracing(format: LogFormat) {
+    subscriber(format, std::io::stdout).init();
+}
+
+/// The subscriber `setup_tracing` installs, writing to `writer`. JSON lines
+/// carry an RFC 3339 `timestamp`, the event's fields as top-level keys, and
+/// the fields of the current span (`span`) and its parents (`spans`) as
+/// nested objects.
+fn subscriber<W>(format: LogFormat, writer: W) -> impl Subscriber + Send + Sync
+where
+    W: for<'w> MakeWriter<'w> + Send + Sync + 'static,
+{
+    let fmt_layer = match format {
+        LogFormat::Pretty => tracing_subscriber::fmt::layer().with_writer(writer).boxed(),
+        LogFormat::Json => tracing_subscriber::fmt::layer()
+            .json()
+            .flatten_event(true)
+            .with_current_span(true)
+            .with_span_list(true)
+            .with_writer(writer)
+            .boxed(),
+    };
+    tracing_subscriber::registry().with(fmt_layer).with(
+        EnvFilter::try_from_default_env()
+            .unwrap_or_else(|_| format!("{}=debug", env!("CARGO_CRATE_NAME")).into()),
+    )
 }
 
 pub fn init_error_formatter() -> color_eyre::Result<()> {
     color_eyre::install()
 }
 
-pub fn checks() {
+/// Runs the startup checks in parallel. Most only warn; an expired TLS
+/// certificate fails startup.
+///
+/// # Errors
+///
+/// Returns an error if the TLS certificate can't be read or has expired.
+pub fn checks(config: &AppConfig) -> color_eyre::Result<()> {
+    let mut cert_check = Ok(());
     rayon::scope(|s| {
         s.spawn(|_| check_cpu_cores());
-        s.spawn(|_| check_storage_type());
+        s.spawn(|_| check_storage_type(config.assume_ssd));
         s.spawn(|_| check_temp_directory());
+        if let Some(cert_path) = config.cert_path.as_deref().filter(|_| config.tls_enabled) {
+            s.spawn(|_| cert_check = check_cert_expiry(cert_path));
+        }
     });
+    cert_check
 }
 
-fn check_cpu_cores() {
-    let cores = thread::available_parallelism()
+/// Number of CPU cores available to this process, falling back to 1 when it
+/// can't be determined.
+pub fn available_cores() -> usize {
+    thread::available_parallelism()
         .map(NonZeroUsize::get)
-        .unwrap_or(1);
+        .unwrap_or(1)
+}
+
+fn check_cpu_cores() {
+    let cores = available_cores();
 
     info!("Detected {cores} CPU cores");
 
@@ -45,101 +114,141 @@ fn check_cpu_cores() {
     }
 }
 
-fn check_storage_type() {
-    let is_likely_ssd = check_if_ssd();
+/// What storage detection could tell about the disk.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum StorageKind {
+    Ssd,
+    Rotational,
+    /// Detection failed or was blocked, as it often is in sandboxed
+    /// containers
+    Unknown,
+}
 
-    if is_likely_ssd {
-        info!("Storage appears to be SSD (optimal for performance)");
-    } else {
-        warn!(
-            "Storage may not be an SSD. This tool performs significantly better on SSDs \
-            due to intensive I/O operations. Consider using SSD storage for optimal performance."
-        );
+fn check_storage_type(assume_ssd: bool) {
+    if assume_ssd {
+        info!("ASSUME_SSD is set; skipping storage detection");
+        return;
+    }
+
+    let kind = detect_storage_kind();
+    match storage_warning(kind) {
+        Some(warning) => warn!("{warning}"),
+        None if kind == StorageKind::Ssd => {
+            info!("Storage appears to be SSD (optimal for performance)");
+        }
+        None => {
+            info!("Could not determine the storage type; set ASSUME_SSD=true to skip this check");
+        }
     }
 }
 
-fn check_if_ssd() -> bool {
+/// The startup warning for `kind`, if any. Only a disk positively detected
+/// as rotational is warned about.
+fn storage_warning(kind: StorageKind) -> Option<&'static str> {
+    (kind == StorageKind::Rotational).then_some(
+        "Storage may not be an SSD. This tool performs significantly better on SSDs \
+        due to intensive I/O operations. Consider using SSD storage for optimal performance.",
+    )
+}
+
+fn detect_storage_kind() -> StorageKind {
     #[cfg(target_os = "linux")]
     {
-        check_linux_storage_type()
+        linux_storage_kind(|path| std::fs::read_to_string(path))
     }
 
     #[cfg(target_os = "macos")]
     {
-        check_macos_storage_type()
+        macos_storage_kind(command_stdout("diskutil", &["info", "/"]).as_deref())
     }
 
     #[cfg(target_os = "windows")]
     {
-        check_windows_storage_type()
+        windows_storage_kind(
+            command_stdout(
+                "powershell",
+                &[
+                    "-NoProfile",
+                    "-NonInteractive",
+                    "-Command",
+                    "(Get-PhysicalDisk | Where MediaType -eq 'SSD').Count -gt 0",
+                ],
+            )
+            .as_deref(),
+        )
     }
 
     #[cfg(not(any(target_os = "linux", target_os = "macos", target_os = "windows")))]
     {
-        true
+        StorageKind::Unknown
     }
 }
 
-#[cfg(target_os = "linux")]
-fn check_linux_storage_type() -> bool {
+/// Stdout of a command that ran and succeeded, or `None` if it couldn't be
+/// started (missing, or blocked by a seccomp profile) or failed.
+#[cfg(any(target_os = "macos", target_os = "windows"))]
+fn command_stdout(program: &str, args: &[&str]) -> Option<String> {
+    let output = std::process::Command::new(program)
+        .args(args)
+        .output()
+        .ok()?;
+    output
+        .status
+        .success()
+        .then(|| String::from_utf8_lossy(&output.stdout).into_owned())
+}
+
+/// Classifies the disk from the `rotational` flags under `/sys/block`, read
+/// with `read`. Devices whose flag can't be read are ignored.
+#[cfg(any(target_os = "linux", test))]
+fn linux_storage_kind(read: impl Fn(&str) -> std::io::Result<String>) -> StorageKind {
+    let mut kind = StorageKind::Unknown;
     for device in ["sda", "nvme0n1", "vda", "xvda"] {
         let rotational_path = format!("/sys/block/{device}/queue/rotational");
-        if let Ok(content) = std::fs::read_to_string(&rotational_path)
-            && content.trim() == "0"
-        {
-            return true;
+        match read(&rotational_path).as_deref().map(str::trim) {
+            Ok("0") => return StorageKind::Ssd,
+            Ok("1") => kind = StorageKind::Rotational,
+            _ => {}
         }
     }
 
-    false
+    kind
 }
 
-#[cfg(target_os = "macos")]
-fn check_macos_storage_type() -> bool {
-    let output = std::process::Command::new("diskutil")
-        .args(["info", "/"])
-        .output()
-        .ok();
-
-    if let Some(output) = output {
-        let output_str = String::from_utf8_lossy(&output.stdout);
-        let normalized = output_str.replace('\t', " ");
-        let lines: Vec<&str> = normalized.lines().collect();
+/// Classifies the disk from `diskutil info /` output, if it ran.
+#[cfg(any(target_os = "macos", test))]
+fn macos_storage_kind(stdout: Option<&str>) -> StorageKind {
+    let Some(stdout) = stdout else {
+        return StorageKind::Unknown;
+    };
+    let normalized = stdout.replace('\t', " ");
 
-        for line in lines {
-            if line.contains("Solid State") && line.contains("Yes") {
-                return true;
-            }
-            if line.contains("Media Type") && line.contains("SSD") {
-                return true;
-            }
-            if line.contains("Protocol") && (line.contains("PCI-Express") || line.contains("NVMe"))
-            {
-                return true;
-            }
+    let mut kind = StorageKind::Unknown;
+    for line in normalized.lines() {
+        if line.contains("Solid State") && line.contains("Yes") {
+            return StorageKind::Ssd;
+        }
+        if line.contains("Media Type") && line.contains("SSD") {
+            return StorageKind::Ssd;
+        }
+        if line.contains("Protocol") && (line.contains("PCI-Express") || line.contains("NVMe")) {
+            return StorageKind::Ssd;
+        }
+        if line.contains("Solid State") && line.contains("No") {
+            kind = StorageKind::Rotational;
         }
     }
 
-    true
+    kind
 }
 
-#[cfg(target_os = "windows")]
-fn check_windows_storage_type() -> bool {
-    let output = std::process::Command::new("powershell")
-        .args(&[
-            "-NoProfile",
-            "-NonInteractive",
-            "-Command",
-            "(Get-PhysicalDisk | Where MediaType -eq 'SSD').Count -gt 0",
-        ])
-        .output()
-        .ok();
-
-    if let Some(output) = output {
-        let output_str = String::from_utf8_lossy(&output.stdout);
-        matches!(output_str.trim(), "True" | "true")
-    } else {
-        false
+/// Classifies the disk from the `Get-PhysicalDisk` query's output, if it ran.
+#[cfg(any(target_os = "windows", test))]
+fn windows_storage_kind(stdout: Option<&str>) -> StorageKind {
+    match stdout.map(str::trim) {
+        Some("True" | "true") => StorageKind::Ssd,
+        Some("False" | "false") => StorageKind::Rotational,
+        _ => StorageKind::Unknown,
     }
 }
 
@@ -160,3 +269,203 @@ fn check_temp_directory() {
         );
     }
 }
+
+/// How close to its expiry a certificate gets before startup warns.
+const CERT_EXPIRY_WARNING: TimeDelta = TimeDelta::days(30);
+
+fn check_cert_expiry(cert_path: &Path) -> color_eyre::Result<()> {
+    let pem = std::fs::read(cert_path)
+        .wrap_err_with(|| format!("cannot read certificate {}", cert_path.display()))?;
+    if let Some(warning) = cert_expiry_warning(&pem, Utc::now())? {
+        warn!("{warning}");
+    } else {
+        info!(
+            "TLS certificate is valid for at least another {} days",
+            CERT_EXPIRY_WARNING.num_days()
+        );
+    }
+    Ok(())
+}
+
+/// The warning for a PEM certificate chain whose leaf certificate expires
+/// within `CERT_EXPIRY_WARNING` of `now`, if it does.
+///
+/// # Errors
+///
+/// Returns an error if the PEM holds no parseable certificate, or the leaf
+/// certificate has already expired.
+fn cert_expiry_warning(pem: &[u8], now: DateTime<Utc>) -> color_eyre::Result<Option<String>> {
+    let der = rustls_pemfile::certs(&mut &pem[..])
+        .next()
+        .ok_or_else(|| eyre!("no certificate found in CERT_PATH"))??;
+    let (_, cert) = x509_parser::parse_x509_certificate(&der)
+        .map_err(|e| eyre!("cannot parse the CERT_PATH certificate: {e}"))?;
+    let not_after = DateTime::from_timestamp(cert.validity().not_after.timestamp(), 0)
+        .ok_or_else(|| eyre!("the CERT_PATH certificate has an out-of-range expiry"))?;
+
+    if not_after <= now {
+        return Err(eyre!(
+            "the CERT_PATH certificate expired at {not_after}; clients will fail the TLS \
+            handshake until it is renewed"
+        ));
+    }
+    let remaining = not_after - now;
+    Ok((remaining < CERT_EXPIRY_WARNING).then(|| {
+        format!(
+            "The TLS certificate expires at {not_after}, in {} days. Renew it before clients \
+            start failing the TLS handshake.",
+            remaining.num_days()
+        )
+    }))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        io,
+        sync::{Arc, Mutex},
+    };
+
+    use super::*;
+
+    /// Collects formatted log lines in memory.
+    #[derive(Clone, Default)]
+    struct CapturedLogs(Arc<Mutex<Vec<u8>>>);
+
+    impl Write for CapturedLogs {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            self.0
+                .lock()
+                .expect("Log buffer poisoned")
+                .extend_from_slice(buf);
+            Ok(buf.len())
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    fn log_sample_event(format: LogFormat) -> String {
+        let logs = CapturedLogs::default();
+        let writer = logs.clone();
+        tracing::subscriber::with_default(subscriber(format, move || writer.clone()), || {
+            let span = tracing::info_span!("request", request_id = "req-1");
+            let _entered = span.enter();
+            info!(sample_id = "SRR123", rows = 3, "Ingested sample");
+        });
+        let bytes = logs.0.lock().expect("Log buffer poisoned").clone();
+        String::from_utf8(bytes).expect("Logs are not UTF-8")
+    }
+
+    #[test]
+    fn pretty_logs_are_human_readable() {
+        let output = log_sample_event(LogFormat::Pretty);
+        assert!(output.contains("Ingested sample"), "{output}");
+        assert!(serde_json::from_str::<serde_json::Value>(output.trim()).is_err());
+    }
+
+    #[test]
+    fn json_logs_carry_fields_as_keys() {
+        let output = log_sample_event(LogFormat::Json);
+        let line: serde_json::Value =
+            serde_json::from_str(output.trim()).expect("Log line is not JSON");
+
+        assert_eq!(line["message"], "Ingested sample");
+        assert_eq!(line["sample_id"], "SRR123");
+        assert_eq!(line["rows"], 3);
+        assert_eq!(line["span"]["request_id"], "req-1");
+        assert!(
+            line["timestamp"]
+                .as_str()
+                .is_some_and(|ts| ts.contains('T') && ts.ends_with('Z')),
+            "{line}"
+        );
+    }
+
+    /// A PEM certificate valid from 2030-01-01 to 2030-01-15.
+    fn short_lived_cert() -> String {
+        let mut params =
+            rcgen::CertificateParams::new(vec!["localhost".to_string()]).expect("Invalid params");
+        params.not_before = rcgen::date_time_ymd(2030, 1, 1);
+        params.not_after = rcgen::date_time_ymd(2030, 1, 15);
+        let key = rcgen::KeyPair::generate().expect("Failed to generate key");
+        params
+            .self_signed(&key)
+            .expect("Failed to sign certificate")
+            .pem()
+    }
+
+    fn at(date: &str) -> DateTime<Utc> {
+        format!("{date}T00:00:00Z").parse().expect("Invalid date")
+    }
+
+    #[test]
+    fn cert_expiry_warns_within_thirty_days_and_fails_once_expired() {
+        let pem = short_lived_cert();
+
+        let far_off = cert_expiry_warning(pem.as_bytes(), at("2029-12-01"));
+        assert_eq!(far_off.expect("Check failed"), None);
+
+        let warning = cert_expiry_warning(pem.as_bytes(), at("2030-01-05"))
+            .expect("Check failed")
+            .expect("A cert ten days from expiry should warn");
+        assert!(warning.contains("in 10 days"), "{warning}");
+
+        let expired = cert_expiry_warning(pem.as_bytes(), at("2030-02-01"));
+        assert!(
+            expired.is_err_and(|e| e.to_string().contains("expired")),
+            "An expired cert should fail the check"
+        );
+    }
+
+    #[test]
+    fn cert_expiry_rejects_a_pem_without_certificates() {
+        assert!(cert_expiry_warning(b"not a certificate", at("2030-01-01")).is_err());
+    }
+
+    #[test]
+    fn blocked_sysfs_is_unknown_rather_than_rotational() {
+        let kind = linux_storage_kind(|_| Err(io::Error::from(io::ErrorKind::PermissionDenied)));
+        assert_eq!(kind, StorageKind::Unknown);
+        assert_eq!(storage_warning(kind), None);
+    }
+
+    #[test]
+    fn failed_detection_commands_do_not_warn() {
+        assert_eq!(storage_warning(macos_storage_kind(None)), None);
+        assert_eq!(storage_warning(windows_storage_kind(None)), None);
+        assert_eq!(storage_warning(windows_storage_kind(Some(""))), None);
+    }
+
+    #[test]
+    fn detected_disks_are_still_classified() {
+        let rotational = linux_storage_kind(|path| {
+            if path.contains("/sda/") {
+                Ok("1\n".to_string())
+            } else {
+                Err(io::Error::from(io::ErrorKind::NotFound))
+            }
+        });
+        assert_eq!(rotational, StorageKind::Rotational);
+        assert!(storage_warning(rotational).is_some());
+
+        let ssd = linux_storage_kind(|path| {
+            Ok(if path.contains("nvme0n1") {
+                "0\n"
+            } else {
+                "1\n"
+            }
+            .to_string())
+        });
+        assert_eq!(ssd, StorageKind::Ssd);
+        assert_eq!(
+            macos_storage_kind(Some("   Solid State:   Yes")),
+            StorageKind::Ssd
+        );
+        assert_eq!(
+            windows_storage_kind(Some("False\r\n")),
+            StorageKind::Rotational
+        );
+    }
+}
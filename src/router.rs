@@ -0,0 +1,110 @@
+use std::{sync::Arc, time::Duration};
+
+use axum::{
+    Router,
+    middleware::{from_fn, from_fn_with_state},
+    routing::{MethodRouter, delete, get, patch, post},
+};
+use color_eyre::eyre::{Result, eyre};
+use tower_governor::{GovernorLayer, governor::GovernorConfigBuilder};
+use tower_http::timeout::TimeoutLayer;
+
+use crate::{
+    config::AppConfig,
+    handlers::{
+        capabilities, clear_dead_letters, count_gottcha2, delete_sample, export_gottcha2,
+        export_stast, healthz, ingest_dummy, ingest_gottcha2, ingest_kraken2, ingest_record_type,
+        ingest_stast, list_dead_letters, metrics, patch_gottcha2, pause_ingestion, readyz,
+        replace_gottcha2, resume_ingestion, sample_gottcha2, stream_events,
+    },
+    middleware::{add_server_version, assign_request_id, record_latency},
+    state::AppState,
+};
+
+/// Upload routes and their handlers, also listed by `GET /capabilities` so
+/// the advertised routes can't drift from the registered ones.
+pub fn ingest_routes() -> Vec<(&'static str, MethodRouter<AppState>)> {
+    vec![
+        ("/ingest", post(ingest_dummy)),
+        ("/ingest-gottcha2", post(ingest_gottcha2)),
+        ("/ingest-stast", post(ingest_stast)),
+        ("/ingest-kraken2", post(ingest_kraken2)),
+        ("/ingest/{record_type}", post(ingest_record_type)),
+    ]
+}
+
+/// Builds the application router.
+///
+/// Probe routes (health, readiness, and metrics) are merged in
+/// outside the rate limiter so frequent Kubernetes probes and scrapes never
+/// spend the budget meant for real traffic, or get throttled alongside it.
+/// Everything else is governed. Every route's latency is recorded by
+/// endpoint and status class.
+///
+/// The governor keys on the peer address, so the router must be served with
+/// `into_make_service_with_connect_info::<SocketAddr>()`.
+///
+/// With `SERVER_VERSION_HEADER` enabled, every response, probes included,
+/// carries `X-Server-Version`.
+///
+/// # Errors
+///
+/// Returns an error if the rate-limiter configuration is invalid, e.g. a
+/// zero burst size.
+pub fn build_router(state: AppState, config: &AppConfig) -> Result<Router> {
+    // The builder takes the interval between replenished requests, not a rate
+    let governor = GovernorConfigBuilder::default()
+        .period(Duration::from_secs(1) / config.rate_limit_rps.max(1))
+        .burst_size(config.rate_limit_burst())
+        .finish()
+        .ok_or_else(|| eyre!("Failed to build governor config"))?;
+
+    let governed = ingest_routes()
+        .into_iter()
+        .fold(Router::new(), |router, (path, route)| {
+            router.route(path, route)
+        })
+        .route("/capabilities", get(capabilities))
+        .route("/admin/pause", post(pause_ingestion))
+        .route("/admin/resume", post(resume_ingestion))
+        .route("/gottcha2/count", get(count_gottcha2))
+        .route("/gottcha2/export", get(export_gottcha2))
+        // One template: PATCH takes a row id, PUT a sample ID
+        .route(
+            "/gottcha2/{id}",
+            patch(patch_gottcha2).put(replace_gottcha2),
+        )
+        .route("/stast/export", get(export_stast))
+        .route("/samples/{sample_id}", delete(delete_sample))
+        .route("/samples/{sample_id}/gottcha2", get(sample_gottcha2))
+        .route("/events", get(stream_events))
+        .route(
+            "/dead-letters",
+            get(list_dead_letters).delete(clear_dead_letters),
+        )
+        .layer(GovernorLayer::new(Arc::new(governor)));
+
+    let probes = Router::new()
+        .route("/healthz", get(healthz))
+        .route("/readyz", get(readyz))
+        .route("/metrics", get(metrics));
+
+    let router = Router::new()
+        .merge(probes)
+        .merge(governed)
+        // Outside the governor so throttled requests are timed too
+        .route_layer(from_fn_with_state(state.clone(), record_latency))
+        .with_state(state)
+        .layer(TimeoutLayer::new(Duration::from_secs(
+            config.request_timeout_secs,
+        )));
+
+    // Outside the timeout, so throttled and timed-out responses are stamped too
+    let router = if config.server_version_header {
+        router.layer(from_fn(add_server_version))
+    } else {
+        router
+    };
+    // Outermost, so every response, and everything logged serving it, carries it
+    Ok(router.layer(from_fn(assign_request_id)))
+}
@@ -0,0 +1,32 @@
+use tokio::runtime::{Builder, Runtime};
+
+/// Builds the multi-threaded tokio runtime the server runs on, sized to
+/// `worker_threads` rather than tokio's default of one thread per visible core
+/// (which over-provisions inside CPU-limited containers).
+///
+/// # Errors
+///
+/// Returns an error if the runtime cannot be created.
+pub fn build_runtime(worker_threads: usize) -> std::io::Result<Runtime> {
+    Builder::new_multi_thread()
+        .worker_threads(worker_threads.max(1))
+        .enable_all()
+        .build()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn configured_worker_threads_are_honored() {
+        let runtime = build_runtime(3).expect("Failed to build runtime");
+        assert_eq!(runtime.metrics().num_workers(), 3);
+    }
+
+    #[test]
+    fn zero_worker_threads_falls_back_to_one() {
+        let runtime = build_runtime(0).expect("Failed to build runtime");
+        assert_eq!(runtime.metrics().num_workers(), 1);
+    }
+}
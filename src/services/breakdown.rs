@@ -0,0 +1,182 @@
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    io, iter,
+};
+
+use axum::body::Bytes;
+use futures_util::{Stream, stream};
+use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value};
+
+/// Samples serialized per body chunk when a breakdown is streamed.
+const SAMPLES_PER_CHUNK: usize = 1024;
+
+/// Query flag asking an ingest endpoint to report accepted records per sample.
+#[derive(Debug, Default, Deserialize)]
+pub struct BreakdownQuery {
+    #[serde(default)]
+    pub breakdown: bool,
+}
+
+/// Running tally of accepted records by `sample_id`, returned to the client as
+/// `{"by_sample": {...}}` so it can reconcile multi-sample uploads.
+#[derive(Debug, Default, Serialize)]
+#[serde(transparent)]
+pub struct SampleCounts(BTreeMap<String, u64>);
+
+impl SampleCounts {
+    pub fn record(&mut self, sample_id: &str) {
+        // Avoid allocating a key for every record of an already-seen sample
+        if let Some(count) = self.0.get_mut(sample_id) {
+            *count += 1;
+        } else {
+            self.0.insert(sample_id.to_string(), 1);
+        }
+    }
+
+    /// Streams `fields` plus these counts as `"by_sample"` in one JSON
+    /// object, rendering the counts a chunk of samples at a time so a huge
+    /// breakdown is never serialized into a single buffer.
+    pub fn into_json_stream(
+        self,
+        fields: Map<String, Value>,
+    ) -> impl Stream<Item = io::Result<Bytes>> {
+        let mut head = String::from("{");
+        for (key, value) in fields {
+            head.push_str(&Value::from(key).to_string());
+            head.push(':');
+            head.push_str(&value.to_string());
+            head.push(',');
+        }
+        head.push_str("\"by_sample\":{");
+
+        let mut samples = self.0.into_iter();
+        let mut first = true;
+        let entries = iter::from_fn(move || {
+            let mut chunk = String::new();
+            for (sample_id, count) in samples.by_ref().take(SAMPLES_PER_CHUNK) {
+                if !first {
+                    chunk.push(',');
+                }
+                first = false;
+                chunk.push_str(&Value::from(sample_id).to_string());
+                chunk.push(':');
+                chunk.push_str(&count.to_string());
+            }
+            (!chunk.is_empty()).then(|| Bytes::from(chunk))
+        });
+
+        stream::iter(
+            iter::once(Bytes::from(head))
+                .chain(entries)
+                .chain(iter::once(Bytes::from_static(b"}}")))
+                .map(Ok),
+        )
+    }
+}
+
+/// Query flag asking `/ingest-stast` to list the distinct subject taxids it
+/// accepted.
+#[derive(Debug, Default, Deserialize)]
+pub struct DistinctTaxidsQuery {
+    #[serde(default)]
+    pub distinct_taxids: bool,
+}
+
+/// The distinct subject taxids seen in an upload, up to `max`. A STAST
+/// `staxids` value can hold several taxids separated by `;`, and each counts
+/// on its own.
+#[derive(Debug)]
+pub struct DistinctTaxids {
+    seen: BTreeSet<String>,
+    max: usize,
+    truncated: bool,
+}
+
+impl DistinctTaxids {
+    #[must_use]
+    pub fn new(max: usize) -> Self {
+        DistinctTaxids {
+            seen: BTreeSet::new(),
+            max,
+            truncated: false,
+        }
+    }
+
+    pub fn record(&mut self, staxids: &str) {
+        for taxid in staxids.split(';').map(str::trim) {
+            if taxid.is_empty() || self.seen.contains(taxid) {
+                continue;
+            }
+            if self.seen.len() >= self.max {
+                self.truncated = true;
+                return;
+            }
+            self.seen.insert(taxid.to_string());
+        }
+    }
+
+    /// Whether taxids past `max` were left out
+    #[must_use]
+    pub fn truncated(&self) -> bool {
+        self.truncated
+    }
+
+    /// The taxids in sorted order
+    #[must_use]
+    pub fn into_sorted(self) -> Vec<String> {
+        self.seen.into_iter().collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn counts_records_per_sample() {
+        let mut counts = SampleCounts::default();
+        for sample_id in ["SRR124", "SRR123", "SRR124"] {
+            counts.record(sample_id);
+        }
+        assert_eq!(
+            serde_json::to_value(&counts).expect("Failed to serialize"),
+            serde_json::json!({"SRR123": 1, "SRR124": 2})
+        );
+    }
+
+    #[test]
+    fn distinct_taxids_split_multi_taxid_hits_and_stop_at_the_cap() {
+        let mut taxids = DistinctTaxids::new(3);
+        for staxids in ["9606", "9606;9598", " 10090 ", "", "9598"] {
+            taxids.record(staxids);
+        }
+        assert!(!taxids.truncated());
+
+        taxids.record("10116;9606");
+        assert!(taxids.truncated());
+        assert_eq!(taxids.into_sorted(), ["10090", "9598", "9606"]);
+    }
+
+    #[tokio::test]
+    async fn streamed_json_matches_serialized_counts() {
+        use futures_util::TryStreamExt;
+
+        let mut counts = SampleCounts::default();
+        for i in 0..(SAMPLES_PER_CHUNK * 2 + 3) {
+            counts.record(&format!("SRR\"{i}"));
+        }
+        let expected = serde_json::to_value(&counts).expect("Failed to serialize");
+        let mut fields = Map::new();
+        fields.insert("inserted".to_string(), Value::from(7));
+
+        let chunks: Vec<Bytes> = counts
+            .into_json_stream(fields)
+            .try_collect()
+            .await
+            .expect("Stream should not fail");
+        let body: Value = serde_json::from_slice(&chunks.concat()).expect("Invalid JSON");
+        assert_eq!(body["inserted"], 7);
+        assert_eq!(body["by_sample"], expected);
+    }
+}
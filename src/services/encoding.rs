@@ -0,0 +1,154 @@
+use std::io;
+
+use async_compression::tokio::bufread::{GzipEncoder, ZstdEncoder};
+use axum::{
+    body::{Body, Bytes},
+    http::{HeaderMap, HeaderValue, StatusCode, header},
+    response::{IntoResponse, Response},
+};
+use futures_util::Stream;
+use serde::Deserialize;
+use tokio_util::io::{ReaderStream, StreamReader};
+
+/// Compression applied to a response body on the read/export endpoints.
+#[derive(Deserialize, Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum ResponseEncoding {
+    #[default]
+    Gzip,
+    Zstd,
+    None,
+}
+
+impl ResponseEncoding {
+    /// Picks the encoding for a response. An explicit `?encoding=` wins;
+    /// otherwise the client's `Accept-Encoding` is honored, and a client that
+    /// sends no preference at all gets gzip.
+    #[must_use]
+    pub fn negotiate(requested: Option<Self>, headers: &HeaderMap) -> Self {
+        requested
+            .or_else(|| Self::accepted(headers))
+            .unwrap_or_default()
+    }
+
+    /// The encoding the client's `Accept-Encoding` asks for, preferring zstd
+    /// over gzip, or `None` if the header is absent.
+    #[must_use]
+    pub fn accepted(headers: &HeaderMap) -> Option<Self> {
+        let accept = headers
+            .get(header::ACCEPT_ENCODING)
+            .and_then(|value| value.to_str().ok())?;
+
+        let accepted: Vec<&str> = accept
+            .split(',')
+            .filter_map(|item| {
+                let mut parts = item.split(';').map(str::trim);
+                let coding = parts.next()?;
+                let refused = parts.any(|param| {
+                    param
+                        .strip_prefix("q=")
+                        .and_then(|q| q.parse::<f32>().ok())
+                        .is_some_and(|q| q <= 0.0)
+                });
+                (!refused).then_some(coding)
+            })
+            .collect();
+        let accepts = |coding: &str| {
+            accepted
+                .iter()
+                .any(|item| item.eq_ignore_ascii_case(coding) || *item == "*")
+        };
+
+        Some(if accepts("zstd") {
+            Self::Zstd
+        } else if accepts("gzip") {
+            Self::Gzip
+        } else {
+            Self::None
+        })
+    }
+
+    /// The `Content-Encoding` value to send, if any.
+    #[must_use]
+    pub fn content_encoding(self) -> Option<&'static str> {
+        match self {
+            Self::Gzip => Some("gzip"),
+            Self::Zstd => Some("zstd"),
+            Self::None => None,
+        }
+    }
+
+    /// Wraps a byte stream in this encoding, compressing on the fly so large
+    /// exports are never buffered in full.
+    pub fn encode<S>(self, stream: S) -> Body
+    where
+        S: Stream<Item = io::Result<Bytes>> + Send + 'static,
+    {
+        match self {
+            Self::Gzip => Body::from_stream(ReaderStream::new(GzipEncoder::new(
+                StreamReader::new(stream),
+            ))),
+            Self::Zstd => Body::from_stream(ReaderStream::new(ZstdEncoder::new(
+                StreamReader::new(stream),
+            ))),
+            Self::None => Body::from_stream(stream),
+        }
+    }
+
+    /// A `200 OK` streaming `stream` in this encoding, with `Content-Type`
+    /// and the matching `Content-Encoding` set.
+    pub fn respond<S>(self, content_type: &'static str, stream: S) -> Response
+    where
+        S: Stream<Item = io::Result<Bytes>> + Send + 'static,
+    {
+        let mut response = (
+            StatusCode::OK,
+            [(header::CONTENT_TYPE, content_type)],
+            self.encode(stream),
+        )
+            .into_response();
+        if let Some(coding) = self.content_encoding() {
+            response
+                .headers_mut()
+                .insert(header::CONTENT_ENCODING, HeaderValue::from_static(coding));
+        }
+        response
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn accepting(value: &'static str) -> HeaderMap {
+        let mut headers = HeaderMap::new();
+        headers.insert(header::ACCEPT_ENCODING, HeaderValue::from_static(value));
+        headers
+    }
+
+    #[test]
+    fn explicit_encoding_overrides_accept_encoding() {
+        let headers = accepting("gzip");
+        assert_eq!(
+            ResponseEncoding::negotiate(Some(ResponseEncoding::Zstd), &headers),
+            ResponseEncoding::Zstd
+        );
+        assert_eq!(
+            ResponseEncoding::negotiate(Some(ResponseEncoding::None), &headers),
+            ResponseEncoding::None
+        );
+    }
+
+    #[test]
+    fn accept_encoding_is_honored_when_no_encoding_is_requested() {
+        let negotiate = |headers: &HeaderMap| ResponseEncoding::negotiate(None, headers);
+        assert_eq!(negotiate(&HeaderMap::new()), ResponseEncoding::Gzip);
+        assert_eq!(negotiate(&accepting("gzip, zstd")), ResponseEncoding::Zstd);
+        assert_eq!(
+            negotiate(&accepting("zstd;q=0, gzip")),
+            ResponseEncoding::Gzip
+        );
+        assert_eq!(negotiate(&accepting("identity")), ResponseEncoding::None);
+        assert_eq!(ResponseEncoding::accepted(&HeaderMap::new()), None);
+    }
+}
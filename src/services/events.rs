@@ -0,0 +1,87 @@
+use std::future::Future;
+
+use axum::response::{IntoResponse, Response};
+use serde::Serialize;
+
+use crate::{error::AppError, services::parsing::ParseSummary, state::AppState};
+
+/// How many events a slow `/events` subscriber may fall behind before it
+/// starts missing the oldest ones.
+pub const EVENT_BUFFER: usize = 256;
+
+/// A structured ingest lifecycle event, streamed to `/events` subscribers.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+#[serde(tag = "event", rename_all = "snake_case")]
+pub enum IngestEvent {
+    Start {
+        endpoint: &'static str,
+    },
+    Complete {
+        endpoint: &'static str,
+        accepted: usize,
+        inserted: u64,
+    },
+    Error {
+        endpoint: &'static str,
+        status: u16,
+    },
+}
+
+impl IngestEvent {
+    /// The SSE event name, matching the `event` field of the JSON payload.
+    #[must_use]
+    pub fn name(&self) -> &'static str {
+        match self {
+            IngestEvent::Start { .. } => "start",
+            IngestEvent::Complete { .. } => "complete",
+            IngestEvent::Error { .. } => "error",
+        }
+    }
+}
+
+/// Runs an ingest, publishing start, complete, and error events around it.
+/// Failures are turned into their response here so the published status
+/// matches what the client sees.
+///
+/// # Errors
+///
+/// Returns the error response if the ingest fails.
+pub async fn track<F>(
+    state: &AppState,
+    endpoint: &'static str,
+    ingest: F,
+) -> Result<(ParseSummary, u64), Response>
+where
+    F: Future<Output = Result<(ParseSummary, u64), AppError>>,
+{
+    state.publish(IngestEvent::Start { endpoint });
+    match ingest.await {
+        Ok((summary, inserted)) => {
+            state.metrics.record_ingest(endpoint, inserted);
+            state
+                .metrics
+                .record_parse_errors(endpoint, summary.skipped as u64);
+            state.publish(IngestEvent::Complete {
+                endpoint,
+                accepted: summary.accepted,
+                inserted,
+            });
+            Ok((summary, inserted))
+        }
+        Err(e) => {
+            // An upload rejected over one bad line counts that line
+            if matches!(e, AppError::BadRequest(_) | AppError::InvalidField(_)) {
+                state.metrics.record_parse_errors(endpoint, 1);
+            }
+            let response = e.into_response();
+            state
+                .metrics
+                .record_ingest_failure(endpoint, response.status().as_u16());
+            state.publish(IngestEvent::Error {
+                endpoint,
+                status: response.status().as_u16(),
+            });
+            Err(response)
+        }
+    }
+}
@@ -0,0 +1,276 @@
+use std::time::Duration;
+
+use prometheus::{
+    Encoder, HistogramOpts, HistogramVec, IntCounterVec, IntGauge, Opts, Registry, TextEncoder,
+    proto::MetricType,
+};
+use serde_json::{Map, Value};
+use tokio::task::JoinHandle;
+
+use crate::state::AppState;
+
+/// Request duration buckets, in seconds. Health checks and queries finish in
+/// milliseconds while large ingests stream for minutes, so the buckets run
+/// roughly logarithmically from 5ms to 5 minutes to keep percentiles
+/// meaningful at both ends.
+pub const LATENCY_BUCKETS: &[f64] = &[
+    0.005, 0.01, 0.025, 0.05, 0.1, 0.25, 0.5, 1.0, 2.5, 5.0, 10.0, 30.0, 60.0, 120.0, 300.0,
+];
+
+/// Prometheus metrics for one server instance. Each `AppState` owns its own
+/// registry rather than using the process-global default, so several servers
+/// in one process (as in the tests) don't share series.
+#[derive(Clone)]
+pub struct Metrics {
+    registry: Registry,
+    request_duration: HistogramVec,
+    ingested_records: IntCounterVec,
+    ingest_failures: IntCounterVec,
+    parse_errors: IntCounterVec,
+    pool_connections: IntGauge,
+    pool_idle_connections: IntGauge,
+}
+
+impl Metrics {
+    /// Creates the registry and registers every metric.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the metric definitions are invalid, which would be a
+    /// programming error caught by any test.
+    #[must_use]
+    pub fn new() -> Self {
+        let registry = Registry::new();
+        let request_duration = HistogramVec::new(
+            HistogramOpts::new(
+                "http_request_duration_seconds",
+                "Time to serve a request, by route and status class",
+            )
+            .buckets(LATENCY_BUCKETS.to_vec()),
+            &["endpoint", "status_class"],
+        )
+        .expect("Invalid request duration histogram");
+        let ingested_records = IntCounterVec::new(
+            Opts::new("ingested_records_total", "Rows inserted, by ingest route"),
+            &["endpoint"],
+        )
+        .expect("Invalid ingested records counter");
+        let ingest_failures = IntCounterVec::new(
+            Opts::new(
+                "ingest_failures_total",
+                "Failed ingests, by route and status class",
+            ),
+            &["endpoint", "status_class"],
+        )
+        .expect("Invalid ingest failures counter");
+        let parse_errors = IntCounterVec::new(
+            Opts::new(
+                "parse_errors_total",
+                "Malformed or invalid records, skipped or rejected, by ingest route",
+            ),
+            &["endpoint"],
+        )
+        .expect("Invalid parse errors counter");
+        let pool_connections = IntGauge::new(
+            "db_pool_connections",
+            "Open database connections, idle or in use",
+        )
+        .expect("Invalid pool connections gauge");
+        let pool_idle_connections =
+            IntGauge::new("db_pool_idle_connections", "Idle database connections")
+                .expect("Invalid idle pool connections gauge");
+        registry
+            .register(Box::new(request_duration.clone()))
+            .expect("Failed to register request duration histogram");
+        registry
+            .register(Box::new(ingested_records.clone()))
+            .expect("Failed to register ingested records counter");
+        registry
+            .register(Box::new(ingest_failures.clone()))
+            .expect("Failed to register ingest failures counter");
+        registry
+            .register(Box::new(parse_errors.clone()))
+            .expect("Failed to register parse errors counter");
+        registry
+            .register(Box::new(pool_connections.clone()))
+            .expect("Failed to register pool connections gauge");
+        registry
+            .register(Box::new(pool_idle_connections.clone()))
+            .expect("Failed to register idle pool connections gauge");
+
+        Metrics {
+            registry,
+            request_duration,
+            ingested_records,
+            ingest_failures,
+            parse_errors,
+            pool_connections,
+            pool_idle_connections,
+        }
+    }
+
+    /// Records how long a request to `endpoint` (the route template, e.g.
+    /// `/gottcha2/{id}`, to keep label cardinality bounded) took to answer
+    /// with `status`.
+    pub fn observe_request(&self, endpoint: &str, status: u16, seconds: f64) {
+        self.request_duration
+            .with_label_values(&[endpoint, status_class(status)])
+            .observe(seconds);
+    }
+
+    /// Counts the rows an ingest to `endpoint` inserted.
+    pub fn record_ingest(&self, endpoint: &str, inserted: u64) {
+        self.ingested_records
+            .with_label_values(&[endpoint])
+            .inc_by(inserted);
+    }
+
+    /// Counts an ingest to `endpoint` that failed with `status`.
+    pub fn record_ingest_failure(&self, endpoint: &str, status: u16) {
+        self.ingest_failures
+            .with_label_values(&[endpoint, status_class(status)])
+            .inc();
+    }
+
+    /// Counts `count` records an ingest to `endpoint` couldn't parse or
+    /// validate, whether they were skipped or failed the upload.
+    pub fn record_parse_errors(&self, endpoint: &str, count: u64) {
+        self.parse_errors
+            .with_label_values(&[endpoint])
+            .inc_by(count);
+    }
+
+    /// Samples the connection pool's size and idle count into their gauges.
+    pub fn observe_pool(&self, size: u32, idle: usize) {
+        self.pool_connections.set(i64::from(size));
+        self.pool_idle_connections
+            .set(i64::try_from(idle).unwrap_or(i64::MAX));
+    }
+
+    /// Every counter and gauge, plus each histogram's observation count, as
+    /// one JSON object keyed by metric name and then by label set, e.g.
+    /// `{"ingested_records_total": {"endpoint=/ingest-stast": 12}}`.
+    #[must_use]
+    pub fn snapshot(&self) -> Value {
+        let mut snapshot = Map::new();
+        for family in self.registry.gather() {
+            let kind = family.get_field_type();
+            let mut series = Map::new();
+            for metric in family.get_metric() {
+                let value = match kind {
+                    MetricType::COUNTER => Value::from(metric.get_counter().get_value()),
+                    MetricType::GAUGE => Value::from(metric.get_gauge().get_value()),
+                    MetricType::HISTOGRAM => Value::from(metric.get_histogram().get_sample_count()),
+                    _ => continue,
+                };
+                let labels: Vec<String> = metric
+                    .get_label()
+                    .iter()
+                    .map(|label| format!("{}={}", label.name(), label.value()))
+                    .collect();
+                series.insert(labels.join(","), value);
+            }
+            let name = match kind {
+                MetricType::HISTOGRAM => format!("{}_count", family.name()),
+                _ => family.name().to_string(),
+            };
+            snapshot.insert(name, Value::Object(series));
+        }
+        Value::Object(snapshot)
+    }
+
+    /// Renders every metric in the Prometheus text exposition format.
+    #[must_use]
+    pub fn render(&self) -> String {
+        let mut buf = Vec::new();
+        // Encoding our own well-formed metric families can't fail
+        let _ = TextEncoder::new().encode(&self.registry.gather(), &mut buf);
+        String::from_utf8_lossy(&buf).into_owned()
+    }
+}
+
+impl Default for Metrics {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Spawns a background task that logs `Metrics::snapshot` every `period`,
+/// for deployments that only collect logs. The pool gauges are sampled just
+/// before each snapshot.
+#[must_use]
+pub fn spawn_metrics_logger(state: AppState, period: Duration) -> JoinHandle<()> {
+    let period = period.max(Duration::from_secs(1));
+    tokio::spawn(async move {
+        let mut ticker = tokio::time::interval(period);
+        // The first tick fires immediately, before anything has happened
+        ticker.tick().await;
+        loop {
+            ticker.tick().await;
+            state
+                .metrics
+                .observe_pool(state.db.size(), state.db.num_idle());
+            tracing::info!(snapshot = %state.metrics.snapshot(), "Metrics snapshot");
+        }
+    })
+}
+
+/// Collapses a status code to its class, e.g. `404` to `"4xx"`.
+fn status_class(status: u16) -> &'static str {
+    match status {
+        100..=199 => "1xx",
+        200..=299 => "2xx",
+        300..=399 => "3xx",
+        400..=499 => "4xx",
+        _ => "5xx",
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn observations_render_with_labels() {
+        let metrics = Metrics::new();
+        metrics.observe_request("/ingest-gottcha2", 200, 0.3);
+        metrics.observe_request("/ingest-gottcha2", 503, 12.0);
+
+        let rendered = metrics.render();
+        assert!(rendered.contains(
+            r#"http_request_duration_seconds_bucket{endpoint="/ingest-gottcha2",status_class="2xx",le="0.5"} 1"#
+        ));
+        assert!(rendered.contains(
+            r#"http_request_duration_seconds_count{endpoint="/ingest-gottcha2",status_class="5xx"} 1"#
+        ));
+    }
+
+    #[test]
+    fn snapshot_collects_counters_gauges_and_request_counts() {
+        let metrics = Metrics::new();
+        metrics.observe_request("/ingest-stast", 200, 0.3);
+        metrics.record_ingest("/ingest-stast", 12);
+        metrics.record_ingest_failure("/ingest-stast", 503);
+        metrics.record_parse_errors("/ingest-stast", 2);
+        metrics.observe_pool(4, 3);
+
+        let snapshot = metrics.snapshot();
+        assert_eq!(
+            snapshot["ingested_records_total"]["endpoint=/ingest-stast"],
+            12.0
+        );
+        assert_eq!(
+            snapshot["ingest_failures_total"]["endpoint=/ingest-stast,status_class=5xx"],
+            1.0
+        );
+        assert_eq!(
+            snapshot["parse_errors_total"]["endpoint=/ingest-stast"],
+            2.0
+        );
+        assert_eq!(snapshot["db_pool_idle_connections"][""], 3.0);
+        assert_eq!(
+            snapshot["http_request_duration_seconds_count"]["endpoint=/ingest-stast,status_class=2xx"],
+            1
+        );
+    }
+}
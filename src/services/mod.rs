@@ -1 +1,7 @@
+pub mod breakdown;
+pub mod encoding;
+pub mod events;
+pub mod metrics;
 pub mod parsing;
+pub mod pipeline;
+pub mod secrets;
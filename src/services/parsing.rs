@@ -1,43 +1,2070 @@
-use async_compression::tokio::bufread::GzipDecoder;
-use axum::body::Body;
-use futures_util::StreamExt;
-use tokio::io::{AsyncBufReadExt, BufReader};
-use tokio::sync::mpsc;
+use std::collections::{HashMap, HashSet, hash_map::Entry};
+use std::hash::{BuildHasher, RandomState};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::Duration;
+
+use async_compression::tokio::bufread::{GzipDecoder, ZstdDecoder};
+use axum::{
+    body::Body,
+    http::{HeaderMap, header},
+};
+use futures_util::{Stream, StreamExt, stream};
+use rayon::iter::{IntoParallelIterator, ParallelIterator};
+use tokio::io::{AsyncBufRead, AsyncBufReadExt, AsyncWriteExt, BufReader};
+use tokio::sync::{mpsc, oneshot};
 use tokio_util::io::StreamReader;
 
-use crate::error::AppError;
+use crate::{
+    config::{AppConfig, InvalidRowPolicy},
+    error::AppError,
+    models::{
+        dead_letter::DeadLetter, record::BulkInsertable, sample_id::SampleId,
+        validation::FieldViolation,
+    },
+};
 
-/// Parses a gzipped JSONL body and sends each deserialized record to a channel.
+/// `Content-Encoding`s the ingest endpoints can decode.
+pub const CONTENT_ENCODINGS: &[&str] = &["gzip", "zstd", "identity"];
+
+/// `Content-Type`s the ingest endpoints accept; uploads may also omit it.
+pub const CONTENT_TYPES: &[&str] = &[
+    "application/gzip",
+    "application/x-gzip",
+    "application/zstd",
+    "application/octet-stream",
+    "application/x-ndjson",
+    "application/json",
+    "text/tab-separated-values",
+    "text/csv",
+];
+
+/// Checks an upload's `Content-Type`, ignoring case and parameters such as
+/// `charset`, so a form post or other payload fails up front rather than as
+/// a confusing decode error. A missing `Content-Type` is accepted.
 ///
 /// # Errors
 ///
-/// Returns an error if decompression fails, JSON parsing fails, or the channel is closed.
-pub async fn parse_gzipped_jsonl<T>(body: Body, tx: mpsc::Sender<T>) -> Result<(), AppError>
+/// Returns `AppError::UnsupportedMediaType` for a type outside
+/// `CONTENT_TYPES`.
+pub fn check_content_type(headers: &HeaderMap) -> Result<(), AppError> {
+    let Some(value) = headers.get(header::CONTENT_TYPE) else {
+        return Ok(());
+    };
+    let value = value.to_str().unwrap_or_default();
+    let media_type = value.split(';').next().unwrap_or_default().trim();
+    if CONTENT_TYPES
+        .iter()
+        .any(|accepted| media_type.eq_ignore_ascii_case(accepted))
+    {
+        Ok(())
+    } else {
+        Err(AppError::UnsupportedMediaType(format!(
+            "unsupported Content-Type {value:?}; expected one of {}",
+            CONTENT_TYPES.join(", ")
+        )))
+    }
+}
+
+/// The two bytes every gzip stream starts with.
+const GZIP_MAGIC: [u8; 2] = [0x1f, 0x8b];
+
+/// How an upload's body is compressed.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Compression {
+    /// Undeclared: gzip if the body starts with gzip's magic number,
+    /// otherwise plain JSONL
+    #[default]
+    Auto,
+    Gzip,
+    Zstd,
+    /// Plain, uncompressed JSONL
+    Identity,
+}
+
+impl Compression {
+    /// The compression a request's `Content-Encoding` declares. Requests
+    /// without the header are sniffed, so tooling that posts plain JSONL
+    /// and tooling that posts gzip can share an endpoint.
+    ///
+    /// # Errors
+    ///
+    /// Returns `AppError::BadRequest` for an encoding other than those in
+    /// `CONTENT_ENCODINGS`.
+    pub fn from_headers(headers: &HeaderMap) -> Result<Self, AppError> {
+        match headers.get(header::CONTENT_ENCODING) {
+            None => Ok(Self::Auto),
+            Some(value) => Self::from_content_encoding(value.to_str().unwrap_or_default()),
+        }
+    }
+
+    /// Maps one `Content-Encoding` value, ignoring case, to its compression.
+    ///
+    /// # Errors
+    ///
+    /// Returns `AppError::BadRequest` for an encoding other than those in
+    /// `CONTENT_ENCODINGS`.
+    pub fn from_content_encoding(value: &str) -> Result<Self, AppError> {
+        let value = value.trim();
+        if value.eq_ignore_ascii_case("gzip") || value.eq_ignore_ascii_case("x-gzip") {
+            Ok(Self::Gzip)
+        } else if value.eq_ignore_ascii_case("zstd") {
+            Ok(Self::Zstd)
+        } else if value.eq_ignore_ascii_case("identity") {
+            Ok(Self::Identity)
+        } else {
+            Err(AppError::BadRequest(format!(
+                "unsupported Content-Encoding {value:?}; expected one of {}",
+                CONTENT_ENCODINGS.join(", ")
+            )))
+        }
+    }
+
+    /// The `Content-Encoding` value naming this compression.
+    #[must_use]
+    pub fn content_encoding(self) -> &'static str {
+        match self {
+            Self::Auto => "auto-detected",
+            Self::Gzip => "gzip",
+            Self::Zstd => "zstd",
+            Self::Identity => "identity",
+        }
+    }
+}
+
+/// How an upload's records are written, once decompressed.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, serde::Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum InputFormat {
+    /// One JSON record per line, or a single JSON array of records
+    #[default]
+    Jsonl,
+    /// Tab-separated rows under a header row naming each column's field, as
+    /// GOTTCHA2 and BLAST write them
+    Tsv,
+    /// Comma-separated rows under a header row, quoted as RFC 4180 allows
+    Csv,
+}
+
+/// Query parameter choosing an upload's `InputFormat`, e.g. `?format=tsv`.
+#[derive(Debug, Default, serde::Deserialize)]
+pub struct FormatQuery {
+    pub format: Option<InputFormat>,
+}
+
+impl InputFormat {
+    /// The format an upload asks for: `?format=` if given, else the one its
+    /// `Content-Type` names, else JSONL.
+    #[must_use]
+    pub fn from_request(headers: &HeaderMap, query: Option<Self>) -> Self {
+        if let Some(format) = query {
+            return format;
+        }
+        let media_type = headers
+            .get(header::CONTENT_TYPE)
+            .and_then(|value| value.to_str().ok())
+            .and_then(|value| value.split(';').next())
+            .unwrap_or_default()
+            .trim();
+        if media_type.eq_ignore_ascii_case("text/tab-separated-values") {
+            Self::Tsv
+        } else if media_type.eq_ignore_ascii_case("text/csv") {
+            Self::Csv
+        } else {
+            Self::Jsonl
+        }
+    }
+
+    /// The name errors cite rows of this format by.
+    fn name(self) -> &'static str {
+        match self {
+            Self::Jsonl => "json",
+            Self::Tsv => "tsv",
+            Self::Csv => "csv",
+        }
+    }
+
+    /// A reader for one delimited row; `None` for JSONL. TSV is read
+    /// unquoted, as the tools that write it never quote fields, so a stray
+    /// `"` in a name stays part of it.
+    fn row_reader(self, row: &[u8]) -> Option<csv::Reader<&[u8]>> {
+        let (delimiter, quoting) = match self {
+            Self::Jsonl => return None,
+            Self::Tsv => (b'\t', false),
+            Self::Csv => (b',', true),
+        };
+        Some(
+            csv::ReaderBuilder::new()
+                .delimiter(delimiter)
+                .quoting(quoting)
+                .has_headers(false)
+                .buffer_capacity(row.len() + 1)
+                .from_reader(row),
+        )
+    }
+}
+
+/// Tunables for `parse_gzipped_jsonl`.
+// Mirrors the independent on/off switches in `AppConfig`
+#[allow(clippy::struct_excessive_bools)]
+#[derive(Debug, Clone)]
+pub struct ParseOptions {
+    /// Largest single line, in bytes, that will be buffered before giving up
+    pub max_line_bytes: usize,
+    /// Most decompressed bytes the body may hold in all
+    pub max_decompressed_bytes: u64,
+    /// Reject records whose taxonomic level isn't a known rank
+    pub strict_taxonomic_levels: bool,
+    /// Abort the upload if no body bytes arrive for this long
+    pub read_idle_timeout: Duration,
+    /// Debugging only: also append every accepted record to this JSONL file
+    pub debug_sink_path: Option<PathBuf>,
+    /// Keep each record's original line for its `raw_line` column
+    pub store_raw_line: bool,
+    /// Hash each record's fields for its `content_hash` column
+    pub store_content_hash: bool,
+    /// Tag each record with its line number for its `source_line` column
+    pub preserve_input_order: bool,
+    /// Whether a record failing its check fails the upload or is skipped
+    pub on_invalid_row: InvalidRowPolicy,
+    /// Keep skipped lines in `ParseSummary::dead_letters`
+    pub store_dead_letters: bool,
+    /// Zero-fill numeric fields listed in `zero_default_fields` when absent
+    pub fill_missing_fields: bool,
+    /// Skip lines that are byte-for-byte repeats of an earlier line
+    pub dedup_identical_lines: bool,
+    /// Fail the upload when two records share a `duplicate_key`
+    pub reject_duplicate_keys: bool,
+    /// Lowercase each record's sample ID once it has been trimmed
+    pub lowercase_sample_ids: bool,
+    /// Reject, citing the line, records whose `sample_id` is missing or blank
+    pub require_sample_id: bool,
+    /// Reject a body whose last line isn't terminated by `\n`
+    pub strict_final_newline: bool,
+    /// Layout of lines that don't declare their own `schema_version`, from
+    /// `X-Schema-Version`; `None` means the newest
+    pub schema_version: Option<u32>,
+    /// Replaces every record's `sample_id`, which lines may then leave out,
+    /// as `PUT /gottcha2/{sample_id}` does
+    pub sample_id_override: Option<SampleId>,
+    /// Fail the upload unless it holds exactly this many records, from
+    /// `X-Expected-Records`
+    pub expected_records: Option<usize>,
+    /// How the body is compressed, from `Content-Encoding`
+    pub compression: Compression,
+    /// How the decompressed body's records are written
+    pub format: InputFormat,
+    /// Deserialize batches of lines across the rayon thread pool rather than
+    /// one at a time on the request's task
+    pub parallel_decode: bool,
+}
+
+impl ParseOptions {
+    #[must_use]
+    pub fn from_config(config: &AppConfig) -> Self {
+        ParseOptions {
+            max_line_bytes: config.max_line_bytes,
+            max_decompressed_bytes: config.max_decompressed_bytes,
+            strict_taxonomic_levels: config.strict_taxonomic_levels,
+            read_idle_timeout: Duration::from_secs(config.read_idle_secs),
+            debug_sink_path: config.debug_sink_path.clone(),
+            store_raw_line: config.store_raw_line,
+            store_content_hash: config.store_content_hash,
+            preserve_input_order: config.preserve_input_order,
+            on_invalid_row: config.on_invalid_row,
+            store_dead_letters: config.store_dead_letters,
+            fill_missing_fields: config.fill_missing_fields,
+            dedup_identical_lines: config.dedup_identical_lines,
+            reject_duplicate_keys: config.strict_duplicate_keys,
+            lowercase_sample_ids: config.lowercase_sample_ids,
+            require_sample_id: config.require_sample_id,
+            strict_final_newline: config.strict_final_newline,
+            schema_version: None,
+            sample_id_override: None,
+            expected_records: None,
+            compression: Compression::Auto,
+            format: InputFormat::Jsonl,
+            parallel_decode: true,
+        }
+    }
+}
+
+/// Skipped lines one upload keeps as dead letters; any past this are only
+/// counted, so a wholly invalid file can't balloon memory
+const MAX_DEAD_LETTERS: usize = 1000;
+
+/// Counts of what happened to the records in a parsed body.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ParseSummary {
+    /// Records forwarded to the channel for insertion
+    pub accepted: usize,
+    /// Records that parsed cleanly but were dropped by a filter
+    pub filtered: usize,
+    /// Invalid records dropped under `InvalidRowPolicy::Skip`
+    pub skipped: usize,
+    /// Repeated lines dropped under `dedup_identical_lines`
+    pub duplicate_lines: usize,
+    /// The first `MAX_DEAD_LETTERS` skipped lines, under `store_dead_letters`
+    pub dead_letters: Vec<DeadLetter>,
+    /// Decompressed bytes read, not counting newlines
+    pub decompressed_bytes: u64,
+}
+
+impl ParseSummary {
+    /// Non-blank lines seen, whatever became of them
+    #[must_use]
+    pub fn records(&self) -> usize {
+        self.accepted + self.filtered + self.skipped + self.duplicate_lines
+    }
+
+    /// Adds a line of `bytes` to the running total, failing once the body
+    /// has expanded past `max_decompressed_bytes`.
+    fn count_bytes(&mut self, bytes: usize, options: &ParseOptions) -> Result<(), AppError> {
+        self.decompressed_bytes += bytes as u64;
+        if self.decompressed_bytes > options.max_decompressed_bytes {
+            return Err(AppError::PayloadTooLarge(format!(
+                "payload too large after decompression; the limit is {} bytes",
+                options.max_decompressed_bytes
+            )));
+        }
+        Ok(())
+    }
+
+    /// Fails if the upload already holds every record `X-Expected-Records`
+    /// declared, counting the `pending` ones read but not yet decoded, before
+    /// another one is taken.
+    fn check_room_for_another(
+        &self,
+        options: &ParseOptions,
+        pending: usize,
+    ) -> Result<(), AppError> {
+        match options.expected_records {
+            Some(expected) if self.records() + pending >= expected => Err(AppError::BadRequest(
+                format!("X-Expected-Records is {expected} but the body holds more records"),
+            )),
+            _ => Ok(()),
+        }
+    }
+
+    /// Counts a line skipped under `InvalidRowPolicy::Skip`, keeping it as a
+    /// dead letter if `options` asks for that.
+    fn skip(&mut self, options: &ParseOptions, line: &[u8], line_number: usize, error: String) {
+        self.skipped += 1;
+        if options.store_dead_letters && self.dead_letters.len() < MAX_DEAD_LETTERS {
+            self.dead_letters.push(DeadLetter {
+                line_number,
+                raw_line: String::from_utf8_lossy(line).trim_end().to_string(),
+                error,
+            });
+        }
+    }
+}
+
+/// What a record check decided about a record that deserialized cleanly.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Verdict {
+    /// Forward the record for insertion
+    Keep,
+    /// Silently drop the record, counting it as filtered
+    Filter,
+    /// Fail the whole upload with this reason
+    Reject(String),
+    /// Fail the whole upload, reporting exactly which field was out of range
+    Invalid(FieldViolation),
+}
+
+/// Outcome of reading one newline-delimited line into a reusable buffer.
+enum LineRead {
+    Line,
+    /// The body ended mid-line, without a closing `\n`
+    Unterminated,
+    TooLong,
+    Eof,
+}
+
+/// The body's decompressed bytes, decoded as `options.compression` says,
+/// along with the compression applied once `Compression::Auto` has been
+/// resolved by peeking at the body's first bytes.
+async fn decompressed(
+    body: Body,
+    options: &ParseOptions,
+) -> std::io::Result<(Box<dyn AsyncBufRead + Send + Unpin>, Compression)> {
+    let body_stream = Box::pin(idle_timeout_stream(body, options.read_idle_timeout));
+    let mut compressed = BufReader::new(StreamReader::new(body_stream));
+    let compression = match options.compression {
+        Compression::Auto => sniff_compression(&mut compressed).await?,
+        declared => declared,
+    };
+    let reader: Box<dyn AsyncBufRead + Send + Unpin> = match compression {
+        Compression::Gzip => Box::new(BufReader::new(GzipDecoder::new(compressed))),
+        Compression::Zstd => Box::new(BufReader::new(ZstdDecoder::new(compressed))),
+        Compression::Auto | Compression::Identity => Box::new(compressed),
+    };
+    Ok((reader, compression))
+}
+
+/// Gzip if the buffered body starts with gzip's magic number, otherwise
+/// plain text. Nothing is consumed, so the chosen decoder sees every byte.
+/// Only the first chunk is inspected; no gzip encoder emits one shorter than
+/// its header.
+async fn sniff_compression<R: AsyncBufRead + Unpin>(
+    reader: &mut R,
+) -> std::io::Result<Compression> {
+    let head = reader.fill_buf().await?;
+    Ok(if head.starts_with(&GZIP_MAGIC) {
+        Compression::Gzip
+    } else {
+        Compression::Identity
+    })
+}
+
+/// Turns the request body into a byte stream that fails with
+/// `io::ErrorKind::TimedOut` if the client goes quiet for longer than
+/// `idle`, so a stalled upload is cut off promptly even when the overall
+/// request timeout is generous. The stream is fused, as readers may poll
+/// again after the body ends.
+fn idle_timeout_stream(
+    body: Body,
+    idle: Duration,
+) -> impl Stream<Item = std::io::Result<axum::body::Bytes>> {
+    stream::unfold(Some(body.into_data_stream()), move |data| async move {
+        let mut data = data?;
+        match tokio::time::timeout(idle, data.next()).await {
+            Ok(Some(chunk)) => Some((chunk.map_err(std::io::Error::other), Some(data))),
+            Ok(None) => None,
+            // Yield the error once, then end the stream
+            Err(_) => Some((
+                Err(std::io::Error::new(
+                    std::io::ErrorKind::TimedOut,
+                    "request body stalled",
+                )),
+                None,
+            )),
+        }
+    })
+    .fuse()
+}
+
+/// Maps a failure reading the body: a stalled client is a timeout, anything
+/// else a body that doesn't decode as `compression`.
+fn read_error(e: &std::io::Error, compression: Compression) -> AppError {
+    if e.kind() == std::io::ErrorKind::TimedOut {
+        AppError::ReadTimeout
+    } else {
+        let encoding = compression.content_encoding();
+        AppError::BadRequest(format!("invalid {encoding} stream: {e}"))
+    }
+}
+
+/// Opens the debug sink for appending, creating it if needed.
+async fn open_debug_sink(path: &Path) -> Result<tokio::fs::File, AppError> {
+    tokio::fs::OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(path)
+        .await
+        .map_err(|e| {
+            AppError::InternalServerError(format!(
+                "failed to open debug sink {}: {e}",
+                path.display()
+            ))
+        })
+}
+
+/// Appends one record to the debug sink as a single JSONL line. The line is
+/// written in one call so concurrent requests don't interleave mid-record.
+async fn write_debug_record<T: serde::Serialize>(
+    sink: &mut tokio::fs::File,
+    record: &T,
+) -> Result<(), AppError> {
+    let mut line = serde_json::to_vec(record).map_err(|e| {
+        AppError::InternalServerError(format!("failed to serialize debug record: {e}"))
+    })?;
+    line.push(b'\n');
+    sink.write_all(&line)
+        .await
+        .map_err(|e| AppError::InternalServerError(format!("failed to write debug sink: {e}")))
+}
+
+/// Deserializes `line` via a JSON value, first upgrading it from the older
+/// layout `upgrade_from` if given, then zero-filling any of
+/// `T::zero_default_fields` it still leaves out when `fill_missing` is set,
+/// and finally replacing its `sample_id` with `sample_id` if given.
+fn from_slice_adjusted<T>(
+    line: &[u8],
+    fill_missing: bool,
+    upgrade_from: Option<u32>,
+    sample_id: Option<&SampleId>,
+) -> serde_json::Result<T>
 where
-    T: serde::de::DeserializeOwned,
+    T: serde::de::DeserializeOwned + BulkInsertable,
 {
-    let body_stream = body
-        .into_data_stream()
-        .map(|res| res.map_err(std::io::Error::other));
+    let mut value: serde_json::Value = serde_json::from_slice(line)?;
+    if let serde_json::Value::Object(fields) = &mut value {
+        if let Some(version) = upgrade_from {
+            T::upgrade_fields(version, fields);
+        }
+        if fill_missing {
+            for field in T::zero_default_fields() {
+                fields
+                    .entry(*field)
+                    .or_insert_with(|| serde_json::Value::from(0));
+            }
+        }
+        if let Some(id) = sample_id {
+            fields.insert("sample_id".to_string(), id.as_str().into());
+        }
+    }
+    serde_json::from_value(value)
+}
+
+/// Just the `schema_version` of a line, ignoring every other field.
+#[derive(serde::Deserialize)]
+struct SchemaVersionProbe {
+    schema_version: Option<u32>,
+}
+
+/// The layout `line` is in: its own `schema_version` if it declares one,
+/// else the upload's `X-Schema-Version`, else the newest `T` supports. `None`
+/// for record types without versioned layouts.
+fn line_schema_version<T: BulkInsertable>(
+    line: &[u8],
+    line_number: usize,
+    options: &ParseOptions,
+) -> Result<Option<u32>, AppError> {
+    // Only lines that mention the field pay for a second parse
+    const FIELD: &[u8] = b"\"schema_version\"";
+
+    let Some(versions) = T::schema_versions() else {
+        return Ok(None);
+    };
+
+    let declared = if line.windows(FIELD.len()).any(|window| window == FIELD) {
+        serde_json::from_slice::<SchemaVersionProbe>(line)
+            .ok()
+            .and_then(|probe| probe.schema_version)
+    } else {
+        None
+    };
+
+    let version = declared
+        .or(options.schema_version)
+        .unwrap_or(*versions.end());
+    if !versions.contains(&version) {
+        return Err(AppError::BadRequest(format!(
+            "line {line_number}: unsupported schema_version {version}; expected {}..={}",
+            versions.start(),
+            versions.end()
+        )));
+    }
+    Ok(Some(version))
+}
+
+/// Just the `sample_id` of a line, ignoring every other field.
+#[derive(serde::Deserialize)]
+struct SampleIdProbe {
+    sample_id: Option<String>,
+}
+
+/// Whether `line` is well-formed JSON lacking a non-blank string `sample_id`.
+/// Malformed lines return `false` so the full parse reports the real error.
+fn lacks_sample_id(line: &[u8]) -> bool {
+    serde_json::from_slice::<SampleIdProbe>(line)
+        .is_ok_and(|probe| probe.sample_id.is_none_or(|id| id.trim().is_empty()))
+}
+
+/// Deserializes one non-blank line according to `options`. The inner result
+/// is the JSON itself failing to parse, which `InvalidRowPolicy::Skip` may
+/// pass over; the outer one fails the upload regardless.
+fn decode_line<T>(
+    line: &[u8],
+    line_number: usize,
+    options: &ParseOptions,
+) -> Result<Result<T, serde_json::Error>, AppError>
+where
+    T: serde::de::DeserializeOwned + BulkInsertable,
+{
+    let sample_id = options
+        .sample_id_override
+        .as_ref()
+        .filter(|_| T::has_sample_id());
+    if options.require_sample_id
+        && T::has_sample_id()
+        && sample_id.is_none()
+        && lacks_sample_id(line)
+    {
+        return Err(AppError::BadRequest(format!(
+            "line {line_number}: record has no sample_id"
+        )));
+    }
 
-    let stream_reader = StreamReader::new(body_stream);
-    let buf_reader = BufReader::new(stream_reader);
-    let decoder = GzipDecoder::new(buf_reader);
+    let upgrade_from = line_schema_version::<T>(line, line_number, options)?
+        .filter(|version| T::schema_versions().is_some_and(|v| version < v.end()));
+    let fill_missing = options.fill_missing_fields && !T::zero_default_fields().is_empty();
+    let parsed = if fill_missing || upgrade_from.is_some() || sample_id.is_some() {
+        from_slice_adjusted::<T>(line, fill_missing, upgrade_from, sample_id)
+    } else {
+        serde_json::from_slice::<T>(line)
+    };
+    let mut rec = match parsed {
+        Ok(rec) => rec,
+        Err(e) => return Ok(Err(e)),
+    };
 
-    let mut jsonl_lines = BufReader::new(decoder).lines();
+    // Before any record check, so breakdowns and filters see the stored ID
+    if options.lowercase_sample_ids
+        && let Some(sample_id) = rec.sample_id_mut()
+    {
+        sample_id.make_lowercase();
+    }
+
+    Ok(Ok(rec))
+}
 
-    while let Ok(Some(line)) = jsonl_lines.next_line().await {
-        if line.trim().is_empty() {
-            continue;
+/// What decoding made of one line. The inner error, a line that doesn't
+/// parse, is one `InvalidRowPolicy::Skip` may pass over; the outer one fails
+/// the upload regardless.
+type Decoded<T> = Result<Result<T, String>, AppError>;
+
+/// How a delimited body's rows map onto `T`'s fields.
+struct DelimitedLayout {
+    format: InputFormat,
+    /// The field each column deserializes into: the header's columns, then
+    /// any `zero_default_fields` it lacks under `fill_missing_fields`, then
+    /// `sample_id` if an override supplies one the header lacks
+    columns: csv::StringRecord,
+    /// Columns each row must hold, the header's own
+    width: usize,
+    zero_filled: usize,
+    /// Where each row keeps its `sample_id`, if the header has one
+    sample_id_column: Option<usize>,
+    /// The ID every row is stored under, from `sample_id_override`
+    sample_id: Option<String>,
+}
+
+impl DelimitedLayout {
+    fn new<T: BulkInsertable>(header: &csv::StringRecord, options: &ParseOptions) -> Self {
+        let mut columns = header.clone();
+        let mut zero_filled = 0;
+        if options.fill_missing_fields {
+            for field in T::zero_default_fields() {
+                if !header.iter().any(|column| column == *field) {
+                    columns.push_field(field);
+                    zero_filled += 1;
+                }
+            }
+        }
+        let sample_id_column = header.iter().position(|column| column == "sample_id");
+        let sample_id = options
+            .sample_id_override
+            .as_ref()
+            .filter(|_| T::has_sample_id())
+            .map(|id| id.as_str().to_string());
+        if sample_id.is_some() && sample_id_column.is_none() {
+            columns.push_field("sample_id");
+        }
+        Self {
+            format: options.format,
+            columns,
+            width: header.len(),
+            zero_filled,
+            sample_id_column,
+            sample_id,
+        }
+    }
+
+    /// Deserializes one non-blank row according to `options`, as
+    /// `decode_line` does a JSON line.
+    fn decode<T>(&self, line: &[u8], line_number: usize, options: &ParseOptions) -> Decoded<T>
+    where
+        T: serde::de::DeserializeOwned + BulkInsertable,
+    {
+        let malformed = |e: &dyn std::fmt::Display| {
+            format!(
+                "invalid {} row on line {line_number}: {e}",
+                self.format.name()
+            )
+        };
+
+        let mut row = csv::StringRecord::new();
+        if let Some(mut reader) = self.format.row_reader(line)
+            && let Err(e) = reader.read_record(&mut row)
+        {
+            return Ok(Err(malformed(&e)));
+        }
+        if row.len() != self.width {
+            let found = format!("expected {} columns, found {}", self.width, row.len());
+            return Ok(Err(malformed(&found)));
+        }
+        if options.require_sample_id
+            && T::has_sample_id()
+            && self.sample_id.is_none()
+            && self
+                .sample_id_column
+                .is_none_or(|i| row[i].trim().is_empty())
+        {
+            return Err(AppError::BadRequest(format!(
+                "line {line_number}: record has no sample_id"
+            )));
+        }
+
+        for _ in 0..self.zero_filled {
+            row.push_field("0");
+        }
+        if let Some(id) = &self.sample_id {
+            match self.sample_id_column {
+                Some(column) => {
+                    row = row
+                        .iter()
+                        .enumerate()
+                        .map(|(i, value)| if i == column { id.as_str() } else { value })
+                        .collect();
+                }
+                None => row.push_field(id),
+            }
+        }
+
+        let mut rec: T = match row.deserialize(Some(&self.columns)) {
+            Ok(rec) => rec,
+            Err(e) => return Ok(Err(malformed(&e))),
+        };
+        if options.lowercase_sample_ids
+            && let Some(sample_id) = rec.sample_id_mut()
+        {
+            sample_id.make_lowercase();
+        }
+        Ok(Ok(rec))
+    }
+}
+
+/// Decodes a batch of numbered lines, keeping each alongside its result in
+/// order: as JSON, or as rows under `header` for a delimited body. Large
+/// batches are spread across the rayon thread pool, so wide records don't
+/// leave ingest bound to one core.
+///
+/// # Errors
+///
+/// Returns an internal error if a decoding thread panics.
+async fn decode_batch<T>(
+    lines: Vec<(usize, Vec<u8>)>,
+    header: Option<Arc<csv::StringRecord>>,
+    options: &Arc<ParseOptions>,
+) -> Result<Vec<((usize, Vec<u8>), Decoded<T>)>, AppError>
+where
+    T: serde::de::DeserializeOwned + BulkInsertable,
+{
+    let layout = header.map(|header| DelimitedLayout::new::<T>(&header, options));
+    let decode = |options: &ParseOptions,
+                  layout: Option<&DelimitedLayout>,
+                  (line_number, line): (usize, Vec<u8>)| {
+        let decoded = match layout {
+            Some(layout) => layout.decode::<T>(&line, line_number, options),
+            None => decode_line::<T>(&line, line_number, options).map(|parsed| {
+                parsed.map_err(|e| format!("invalid json on line {line_number}: {e}"))
+            }),
+        };
+        ((line_number, line), decoded)
+    };
+
+    if !options.parallel_decode || lines.len() < PARALLEL_DECODE_MIN_LINES {
+        return Ok(lines
+            .into_iter()
+            .map(|line| decode(options, layout.as_ref(), line))
+            .collect());
+    }
+
+    let (tx, rx) = oneshot::channel();
+    let options = Arc::clone(options);
+    rayon::spawn(move || {
+        let decoded = lines
+            .into_par_iter()
+            .map(|line| decode(&options, layout.as_ref(), line))
+            .collect();
+        let _ = tx.send(decoded);
+    });
+    rx.await
+        .map_err(|_| AppError::InternalServerError("record decoding panicked".to_string()))
+}
+
+/// Remembers `rec`'s `duplicate_key`, failing if an earlier line had it too.
+fn check_duplicate_key<T: BulkInsertable>(
+    seen: &mut HashMap<String, usize>,
+    rec: &T,
+    line_number: usize,
+) -> Result<(), AppError> {
+    let Some(key) = rec.duplicate_key() else {
+        return Ok(());
+    };
+    match seen.entry(key) {
+        Entry::Occupied(first) => Err(AppError::BadRequest(format!(
+            "line {line_number}: duplicate key {} already used on line {}",
+            first.key(),
+            first.get()
+        ))),
+        Entry::Vacant(slot) => {
+            slot.insert(line_number);
+            Ok(())
+        }
+    }
+}
+
+/// The error for a line longer than `MAX_LINE_BYTES` allows.
+fn line_too_long(line_number: usize, max_bytes: usize) -> AppError {
+    AppError::BadRequest(format!(
+        "json line {line_number} exceeds the {max_bytes} byte limit"
+    ))
+}
+
+/// The error for a last line cut off before its `\n`, which under
+/// `strict_final_newline` is taken as a sign the transfer was truncated.
+fn truncated_final_line(line_number: usize) -> AppError {
+    AppError::BadRequest(format!(
+        "line {line_number}: final line has no trailing newline; the upload may be truncated"
+    ))
+}
+
+/// Reads bytes up to (but not including) the next `\n` into `buf`, stopping
+/// as soon as the line would exceed `max_bytes` so a single enormous line
+/// can't grow the buffer without bound.
+async fn read_line_bounded<R>(
+    reader: &mut R,
+    buf: &mut Vec<u8>,
+    max_bytes: usize,
+) -> std::io::Result<LineRead>
+where
+    R: AsyncBufRead + Unpin,
+{
+    buf.clear();
+
+    loop {
+        let available = reader.fill_buf().await?;
+        if available.is_empty() {
+            return Ok(if buf.is_empty() {
+                LineRead::Eof
+            } else {
+                LineRead::Unterminated
+            });
+        }
+
+        let newline = available.iter().position(|&b| b == b'\n');
+        let chunk = &available[..newline.unwrap_or(available.len())];
+        if buf.len() + chunk.len() > max_bytes {
+            return Ok(LineRead::TooLong);
+        }
+        buf.extend_from_slice(chunk);
+
+        let consumed = newline.map_or(available.len(), |i| i + 1);
+        reader.consume(consumed);
+
+        if newline.is_some() {
+            return Ok(LineRead::Line);
+        }
+    }
+}
+
+/// How the body's records are delimited, decided by its first
+/// non-whitespace byte.
+enum Framing {
+    /// Newline-delimited JSON, one record per line
+    Lines,
+    /// A single JSON array of records, whose opening `[` has been consumed;
+    /// `closed` once its `]` has been read
+    Array { closed: bool },
+}
+
+impl Framing {
+    /// Peeks at the body's first chunk: a `[` after any leading whitespace
+    /// opens an array and is consumed, while anything else is JSONL and
+    /// nothing is consumed, so line numbers stay accurate.
+    async fn detect<R: AsyncBufRead + Unpin>(reader: &mut R) -> std::io::Result<Self> {
+        let available = reader.fill_buf().await?;
+        match available.iter().position(|b| !b.is_ascii_whitespace()) {
+            Some(i) if available[i] == b'[' => {
+                reader.consume(i + 1);
+                Ok(Self::Array { closed: false })
+            }
+            _ => Ok(Self::Lines),
         }
+    }
+}
 
-        let rec = serde_json::from_str::<T>(&line)
-            .map_err(|e| AppError::BadRequest(format!("invalid json line: {e}")))?;
+/// The body's records as raw JSON, decompressed and framed.
+struct RecordReader {
+    reader: Box<dyn AsyncBufRead + Send + Unpin>,
+    /// The compression applied, with `Compression::Auto` resolved
+    compression: Compression,
+    framing: Framing,
+}
 
-        tx.send(rec)
+impl RecordReader {
+    async fn open(body: Body, options: &ParseOptions) -> Result<Self, AppError> {
+        let (mut reader, compression) = decompressed(body, options)
             .await
-            .map_err(|_| AppError::InternalServerError("channel closed".to_string()))?;
+            .map_err(|e| read_error(&e, options.compression))?;
+        // Only JSON bodies may be arrays
+        let framing = match options.format {
+            InputFormat::Jsonl => Framing::detect(&mut reader)
+                .await
+                .map_err(|e| read_error(&e, compression))?,
+            InputFormat::Tsv | InputFormat::Csv => Framing::Lines,
+        };
+        Ok(Self {
+            reader,
+            compression,
+            framing,
+        })
+    }
+
+    /// Reads the next record's raw JSON into `buf`: a line, or an array
+    /// element. Array elements are numbered like lines.
+    async fn read(&mut self, buf: &mut Vec<u8>, max_bytes: usize) -> Result<LineRead, AppError> {
+        let (reader, compression) = (&mut self.reader, self.compression);
+        match &mut self.framing {
+            Framing::Lines => read_line_bounded(reader, buf, max_bytes)
+                .await
+                .map_err(|e| read_error(&e, compression)),
+            Framing::Array { closed } => {
+                read_array_element(reader, buf, max_bytes, closed, compression).await
+            }
+        }
+    }
+}
+
+/// Most lines read ahead of decoding, so they can be decoded together.
+const DECODE_BATCH_LINES: usize = 1024;
+
+/// Most bytes of lines read ahead of decoding; a batch always holds at least
+/// one line, however long.
+const DECODE_BATCH_BYTES: usize = 8 * 1024 * 1024;
+
+/// Batches shorter than this are decoded inline, where handing them to the
+/// thread pool would cost more than it saves.
+const PARALLEL_DECODE_MIN_LINES: usize = 64;
+
+/// Lines read ahead of decoding, with their line numbers.
+#[derive(Default)]
+struct LineBatch {
+    lines: Vec<(usize, Vec<u8>)>,
+    bytes: usize,
+    /// The header row the lines are delimited under; `None` for JSONL
+    header: Option<Arc<csv::StringRecord>>,
+    /// Why reading stopped before the batch filled: the body ended, or an
+    /// error that fails the upload once the batch's lines are handled
+    end: Option<Result<(), AppError>>,
+}
+
+/// The body's non-blank lines, checked against the limits that don't need
+/// the line decoded, and with repeats dropped under `dedup_identical_lines`.
+struct LineSource {
+    records: RecordReader,
+    line: Vec<u8>,
+    line_number: usize,
+    // Only hashes are kept, so memory stays at a few bytes per distinct line
+    hasher: RandomState,
+    seen_lines: Option<HashSet<u64>>,
+    /// A delimited body's header row, once read
+    header: Option<Arc<csv::StringRecord>>,
+}
+
+impl LineSource {
+    async fn open(body: Body, options: &ParseOptions) -> Result<Self, AppError> {
+        Ok(Self {
+            records: RecordReader::open(body, options).await?,
+            line: Vec::new(),
+            line_number: 0,
+            hasher: RandomState::new(),
+            seen_lines: options.dedup_identical_lines.then(HashSet::new),
+            header: None,
+        })
+    }
+
+    /// Reads lines until the batch is full, the body ends, or a line fails
+    /// the upload.
+    async fn next_batch(
+        &mut self,
+        summary: &mut ParseSummary,
+        options: &ParseOptions,
+    ) -> LineBatch {
+        let mut batch = LineBatch::default();
+        while batch.lines.len() < DECODE_BATCH_LINES && batch.bytes < DECODE_BATCH_BYTES {
+            match self.next_line(summary, options, batch.lines.len()).await {
+                Ok(Some(line)) => {
+                    batch.bytes += line.1.len();
+                    batch.lines.push(line);
+                }
+                Ok(None) => {
+                    batch.end = Some(Ok(()));
+                    break;
+                }
+                Err(e) => {
+                    batch.end = Some(Err(e));
+                    break;
+                }
+            }
+        }
+        batch.header.clone_from(&self.header);
+        batch
+    }
+
+    /// The next line to decode and its number, or `None` once the body ends.
+    /// `pending` lines have been read but not yet decoded.
+    async fn next_line(
+        &mut self,
+        summary: &mut ParseSummary,
+        options: &ParseOptions,
+        pending: usize,
+    ) -> Result<Option<(usize, Vec<u8>)>, AppError> {
+        loop {
+            let read = self
+                .records
+                .read(&mut self.line, options.max_line_bytes)
+                .await?;
+            self.line_number += 1;
+
+            match read {
+                LineRead::Eof => return Ok(None),
+                LineRead::TooLong => {
+                    return Err(line_too_long(self.line_number, options.max_line_bytes));
+                }
+                LineRead::Line | LineRead::Unterminated => {}
+            }
+            // CRLF files: the `\r` belongs to the line ending, not the record
+            if self.line.last() == Some(&b'\r') {
+                self.line.pop();
+            }
+            summary.count_bytes(self.line.len(), options)?;
+
+            if self.line.iter().all(u8::is_ascii_whitespace) {
+                continue;
+            }
+
+            // A delimited body's first row names its columns
+            if self.header.is_none()
+                && let Some(mut reader) = options.format.row_reader(&self.line)
+            {
+                let header = read_header(&mut reader, options.format, self.line_number)?;
+                self.header = Some(Arc::new(header));
+                continue;
+            }
+
+            // Fails a too-long upload now rather than after reading all of it
+            summary.check_room_for_another(options, pending)?;
+
+            if options.strict_final_newline && matches!(read, LineRead::Unterminated) {
+                return Err(truncated_final_line(self.line_number));
+            }
+
+            if let Some(seen) = self.seen_lines.as_mut()
+                && !seen.insert(self.hasher.hash_one(&self.line))
+            {
+                summary.duplicate_lines += 1;
+                continue;
+            }
+
+            return Ok(Some((self.line_number, std::mem::take(&mut self.line))));
+        }
+    }
+}
+
+/// Reads a delimited body's header row, trimming and lowercasing each column
+/// name so `READ_COUNT` names the `read_count` field.
+fn read_header(
+    reader: &mut csv::Reader<&[u8]>,
+    format: InputFormat,
+    line_number: usize,
+) -> Result<csv::StringRecord, AppError> {
+    let mut header = csv::StringRecord::new();
+    reader.read_record(&mut header).map_err(|e| {
+        AppError::BadRequest(format!(
+            "line {line_number}: invalid {} header: {e}",
+            format.name()
+        ))
+    })?;
+    Ok(header
+        .iter()
+        .map(|column| column.trim().to_lowercase())
+        .collect())
+}
+
+/// Reads the next element of a JSON array into `buf` as raw JSON text,
+/// trimmed of surrounding whitespace and bounded like `read_line_bounded`.
+/// Elements end at a `,` or `]` outside any string, object, or nested
+/// array. Once the array is `closed`, only trailing whitespace may follow.
+async fn read_array_element<R>(
+    reader: &mut R,
+    buf: &mut Vec<u8>,
+    max_bytes: usize,
+    closed: &mut bool,
+    compression: Compression,
+) -> Result<LineRead, AppError>
+where
+    R: AsyncBufRead + Unpin,
+{
+    buf.clear();
+    if *closed {
+        expect_only_whitespace(reader, compression).await?;
+        return Ok(LineRead::Eof);
     }
 
+    let (mut depth, mut in_string, mut escaped) = (0_usize, false, false);
+    loop {
+        let available = reader
+            .fill_buf()
+            .await
+            .map_err(|e| read_error(&e, compression))?;
+        if available.is_empty() {
+            return Err(AppError::BadRequest(
+                "JSON array body ended before its closing ]".to_string(),
+            ));
+        }
+
+        let mut terminator = None;
+        for (i, &byte) in available.iter().enumerate() {
+            if in_string {
+                match byte {
+                    _ if escaped => escaped = false,
+                    b'\\' => escaped = true,
+                    b'"' => in_string = false,
+                    _ => {}
+                }
+                continue;
+            }
+            match byte {
+                b'"' => in_string = true,
+                b'{' | b'[' => depth += 1,
+                b'}' | b']' if depth > 0 => depth -= 1,
+                b',' | b']' if depth == 0 => {
+                    terminator = Some(i);
+                    break;
+                }
+                _ => {}
+            }
+        }
+
+        let chunk = &available[..terminator.unwrap_or(available.len())];
+        if buf.len() + chunk.len() > max_bytes {
+            return Ok(LineRead::TooLong);
+        }
+        buf.extend_from_slice(chunk);
+
+        if let Some(i) = terminator {
+            *closed = available[i] == b']';
+            reader.consume(i + 1);
+            let start = buf
+                .iter()
+                .position(|b| !b.is_ascii_whitespace())
+                .unwrap_or(buf.len());
+            buf.drain(..start);
+            while buf.last().is_some_and(u8::is_ascii_whitespace) {
+                buf.pop();
+            }
+            return Ok(LineRead::Line);
+        }
+        let consumed = available.len();
+        reader.consume(consumed);
+    }
+}
+
+/// Drains the rest of the body, failing if anything but whitespace follows
+/// a JSON array's closing `]`.
+async fn expect_only_whitespace<R>(reader: &mut R, compression: Compression) -> Result<(), AppError>
+where
+    R: AsyncBufRead + Unpin,
+{
+    loop {
+        let available = reader
+            .fill_buf()
+            .await
+            .map_err(|e| read_error(&e, compression))?;
+        if available.is_empty() {
+            return Ok(());
+        }
+        if !available.iter().all(u8::is_ascii_whitespace) {
+            return Err(AppError::BadRequest(
+                "unexpected data after the JSON array body".to_string(),
+            ));
+        }
+        let consumed = available.len();
+        reader.consume(consumed);
+    }
+}
+
+/// Hex blake3 hash of `record`'s JSON serialization. Provenance the parser
+/// adds (`raw_line`, `source_line`, and the hash itself) is never part of
+/// it, so identical records hash identically wherever they came from.
+///
+/// # Errors
+///
+/// Returns an error if the record can't be serialized.
+pub fn content_hash<T: serde::Serialize>(record: &T) -> Result<String, AppError> {
+    let canonical = serde_json::to_vec(record).map_err(|e| {
+        AppError::InternalServerError(format!("failed to serialize record for hashing: {e}"))
+    })?;
+    Ok(blake3::hash(&canonical).to_hex().to_string())
+}
+
+/// Attaches the provenance `options` asks for to a record parsed from
+/// `line`. The content hash is taken first, before anything else is set.
+fn annotate<T>(
+    rec: &mut T,
+    line: &[u8],
+    line_number: usize,
+    options: &ParseOptions,
+) -> Result<(), AppError>
+where
+    T: serde::Serialize + BulkInsertable,
+{
+    if options.store_content_hash {
+        rec.set_content_hash(content_hash(rec)?);
+    }
+
+    if options.store_raw_line {
+        rec.set_raw_line(String::from_utf8_lossy(line).into_owned());
+    }
+
+    if options.preserve_input_order {
+        rec.set_source_line(i64::try_from(line_number).unwrap_or(i64::MAX));
+    }
     Ok(())
 }
+
+/// Fails unless the upload held exactly the `expected` records its client
+/// declared with `X-Expected-Records`.
+///
+/// # Errors
+///
+/// Returns `AppError::BadRequest` naming both counts on a mismatch.
+pub fn check_record_count(expected: usize, summary: &ParseSummary) -> Result<(), AppError> {
+    let held = summary.records();
+    if held == expected {
+        return Ok(());
+    }
+    Err(AppError::BadRequest(format!(
+        "X-Expected-Records is {expected} but the body held {held} records"
+    )))
+}
+
+/// Parses a gzipped JSONL body and sends each deserialized record to a channel.
+///
+/// # Errors
+///
+/// Returns an error if decompression fails, JSON parsing fails, a line exceeds
+/// `options.max_line_bytes`, or the channel is closed.
+#[allow(dead_code)] // every route checks its records, with parse_gzipped_jsonl_with
+pub async fn parse_gzipped_jsonl<T>(
+    body: Body,
+    tx: mpsc::Sender<T>,
+    options: ParseOptions,
+) -> Result<ParseSummary, AppError>
+where
+    T: serde::de::DeserializeOwned + serde::Serialize + BulkInsertable,
+{
+    parse_gzipped_jsonl_with(body, tx, options, |_| Verdict::Keep).await
+}
+
+/// Like `parse_gzipped_jsonl`, but runs `check` on every record before it is
+/// forwarded, so callers can filter or reject records as they stream past.
+///
+/// Lines are read ahead in batches, which `options.parallel_decode` has
+/// deserialized across the rayon thread pool. Records are still checked and
+/// forwarded, and failures reported, in line order.
+///
+/// # Errors
+///
+/// Returns an error if decompression fails, JSON parsing fails, a line exceeds
+/// `options.max_line_bytes`, `check` rejects a record, or the channel is closed.
+pub async fn parse_gzipped_jsonl_with<T, F>(
+    body: Body,
+    tx: mpsc::Sender<T>,
+    options: ParseOptions,
+    mut check: F,
+) -> Result<ParseSummary, AppError>
+where
+    T: serde::de::DeserializeOwned + serde::Serialize + BulkInsertable,
+    F: FnMut(&T) -> Verdict,
+{
+    let mut debug_sink = match &options.debug_sink_path {
+        Some(path) => Some(open_debug_sink(path).await?),
+        None => None,
+    };
+
+    // Shared with the decoding threads
+    let options = Arc::new(options);
+    let mut source = LineSource::open(body, &options).await?;
+    let mut summary = ParseSummary::default();
+    let mut seen_keys = options.reject_duplicate_keys.then(HashMap::new);
+
+    loop {
+        let batch = source.next_batch(&mut summary, &options).await;
+        let decoded = decode_batch::<T>(batch.lines, batch.header, &options).await?;
+        for ((line_number, line), decoded) in decoded {
+            let mut rec = match decoded? {
+                Ok(rec) => rec,
+                Err(error) => {
+                    if options.on_invalid_row == InvalidRowPolicy::Reject {
+                        return Err(AppError::BadRequest(error));
+                    }
+                    tracing::warn!("Skipping malformed record: {error}");
+                    summary.skip(&options, &line, line_number, error);
+                    continue;
+                }
+            };
+
+            match check(&rec) {
+                Verdict::Keep => {}
+                Verdict::Filter => {
+                    summary.filtered += 1;
+                    continue;
+                }
+                Verdict::Reject(reason) => {
+                    if options.on_invalid_row == InvalidRowPolicy::Reject {
+                        return Err(AppError::BadRequest(format!(
+                            "line {line_number}: {reason}"
+                        )));
+                    }
+                    tracing::warn!("Skipping invalid record on line {line_number}: {reason}");
+                    summary.skip(&options, &line, line_number, reason);
+                    continue;
+                }
+                Verdict::Invalid(violation) => {
+                    let error = violation.at_line(line_number);
+                    if options.on_invalid_row == InvalidRowPolicy::Reject {
+                        return Err(AppError::InvalidField(error));
+                    }
+                    tracing::warn!("Skipping invalid record: {error:?}");
+                    let error = serde_json::to_string(&error).unwrap_or_default();
+                    summary.skip(&options, &line, line_number, error);
+                    continue;
+                }
+            }
+
+            if let Some(seen) = seen_keys.as_mut() {
+                check_duplicate_key(seen, &rec, line_number)?;
+            }
+
+            annotate(&mut rec, &line, line_number, &options)?;
+
+            if let Some(sink) = debug_sink.as_mut() {
+                write_debug_record(sink, &rec).await?;
+            }
+
+            tx.send(rec).await.map_err(|_| AppError::ChannelClosed)?;
+            summary.accepted += 1;
+        }
+
+        // Only now, so errors on the batch's earlier lines are reported first
+        if let Some(end) = batch.end {
+            end?;
+            break;
+        }
+    }
+
+    if let Some(expected) = options.expected_records {
+        check_record_count(expected, &summary)?;
+    }
+
+    // tokio finishes file writes in the background; wait for them to land
+    if let Some(sink) = debug_sink.as_mut() {
+        sink.flush().await.map_err(|e| {
+            AppError::InternalServerError(format!("failed to flush debug sink: {e}"))
+        })?;
+    }
+
+    Ok(summary)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Write;
+
+    use flate2::write::GzEncoder;
+
+    use super::*;
+    use crate::models::record::{DummyRecord, Gottcha2FullRecord};
+
+    fn gzip(data: &[u8]) -> Vec<u8> {
+        let mut encoder = GzEncoder::new(Vec::new(), flate2::Compression::default());
+        encoder.write_all(data).expect("Failed to write to encoder");
+        encoder.finish().expect("Failed to finish compression")
+    }
+
+    fn dummy_line(payload_len: usize) -> String {
+        let record = DummyRecord {
+            run_id: "run".to_string(),
+            task_id: "task".to_string(),
+            shard: 0,
+            idempotency_key: "key".to_string(),
+            schema_version: 1,
+            payload: serde_json::Value::String("x".repeat(payload_len)),
+        };
+        serde_json::to_string(&record).expect("Failed to serialize")
+    }
+
+    /// Options with every optional check and feature off, for tests to
+    /// override the fields they exercise.
+    fn test_options() -> ParseOptions {
+        ParseOptions {
+            max_line_bytes: 1024,
+            max_decompressed_bytes: u64::MAX,
+            strict_taxonomic_levels: false,
+            read_idle_timeout: Duration::from_secs(5),
+            debug_sink_path: None,
+            store_raw_line: false,
+            store_content_hash: false,
+            preserve_input_order: false,
+            on_invalid_row: InvalidRowPolicy::Reject,
+            store_dead_letters: false,
+            fill_missing_fields: false,
+            dedup_identical_lines: false,
+            reject_duplicate_keys: false,
+            lowercase_sample_ids: false,
+            require_sample_id: false,
+            strict_final_newline: false,
+            schema_version: None,
+            sample_id_override: None,
+            expected_records: None,
+            compression: Compression::Gzip,
+            format: InputFormat::Jsonl,
+            parallel_decode: true,
+        }
+    }
+
+    async fn parse_all(
+        data: &[u8],
+        options: ParseOptions,
+    ) -> (Result<ParseSummary, AppError>, Vec<DummyRecord>) {
+        parse_all_as(data, options).await
+    }
+
+    async fn parse_all_as<T>(
+        data: &[u8],
+        options: ParseOptions,
+    ) -> (Result<ParseSummary, AppError>, Vec<T>)
+    where
+        T: serde::de::DeserializeOwned + serde::Serialize + BulkInsertable,
+    {
+        let (tx, mut rx) = mpsc::channel(16);
+        let parse = parse_gzipped_jsonl(Body::from(gzip(data)), tx, options);
+        let collect = async {
+            let mut records = Vec::new();
+            while let Some(record) = rx.recv().await {
+                records.push(record);
+            }
+            records
+        };
+        tokio::join!(parse, collect)
+    }
+
+    #[tokio::test]
+    async fn large_line_under_limit_is_parsed() {
+        let line = dummy_line(512 * 1024);
+        let options = ParseOptions {
+            max_line_bytes: 1024 * 1024,
+            ..test_options()
+        };
+
+        let (result, records) = parse_all(format!("{line}\n{line}\n").as_bytes(), options).await;
+
+        assert!(result.is_ok(), "Lines under the limit should parse");
+        assert_eq!(records.len(), 2);
+        assert_eq!(records[0].payload.as_str().map(str::len), Some(512 * 1024));
+    }
+
+    #[tokio::test]
+    async fn line_over_limit_is_rejected() {
+        let line = dummy_line(2 * 1024 * 1024);
+        let options = ParseOptions {
+            max_line_bytes: 1024 * 1024,
+            ..test_options()
+        };
+
+        let (result, records) = parse_all(line.as_bytes(), options).await;
+
+        assert!(
+            matches!(result, Err(AppError::BadRequest(ref msg)) if msg.contains("byte limit")),
+            "Oversized line should be rejected, got {result:?}"
+        );
+        assert!(records.is_empty());
+    }
+
+    #[tokio::test]
+    async fn stalled_body_times_out_promptly() {
+        let data = gzip(format!("{}\n{}\n", dummy_line(16), dummy_line(16)).as_bytes());
+        let (head, _) = data.split_at(data.len() / 2);
+        let chunks = stream::iter([Ok::<_, std::io::Error>(axum::body::Bytes::copy_from_slice(
+            head,
+        ))])
+        .chain(stream::pending());
+        let options = ParseOptions {
+            read_idle_timeout: Duration::from_millis(100),
+            ..test_options()
+        };
+
+        let (tx, _rx) = mpsc::channel::<DummyRecord>(16);
+        let result = tokio::time::timeout(
+            Duration::from_secs(2),
+            parse_gzipped_jsonl(Body::from_stream(chunks), tx, options),
+        )
+        .await
+        .expect("Stalled body should not hang the parser");
+
+        assert!(
+            matches!(result, Err(AppError::ReadTimeout)),
+            "expected a read timeout, got {result:?}"
+        );
+    }
+
+    #[tokio::test]
+    async fn debug_sink_mirrors_accepted_records() {
+        let dir = tempfile::tempdir().expect("Failed to create temp dir");
+        let sink_path = dir.path().join("sink.jsonl");
+        let options = ParseOptions {
+            debug_sink_path: Some(sink_path.clone()),
+            ..test_options()
+        };
+
+        let input = format!("{}\n\n{}\n", dummy_line(8), dummy_line(32));
+        let (result, records) = parse_all(input.as_bytes(), options).await;
+        assert_eq!(result.expect("Parse failed").accepted, 2);
+
+        let sink = std::fs::read_to_string(&sink_path).expect("Failed to read debug sink");
+        let expected: String = records
+            .iter()
+            .map(|r| serde_json::to_string(r).expect("Failed to serialize") + "\n")
+            .collect();
+        assert_eq!(sink, expected);
+    }
+
+    #[tokio::test]
+    async fn identical_lines_are_deduplicated() {
+        let options = ParseOptions {
+            dedup_identical_lines: true,
+            ..test_options()
+        };
+
+        let (a, b) = (dummy_line(8), dummy_line(9));
+        let input = format!("{a}\n{b}\n{a}\n{a}\n{b}\n");
+        let (result, records) = parse_all(input.as_bytes(), options).await;
+
+        let summary = result.expect("Parse failed");
+        assert_eq!(summary.accepted, 2);
+        assert_eq!(summary.duplicate_lines, 3);
+        assert_eq!(records.len(), 2);
+    }
+
+    fn final_newline_options(strict_final_newline: bool) -> ParseOptions {
+        ParseOptions {
+            strict_final_newline,
+            ..test_options()
+        }
+    }
+
+    #[tokio::test]
+    async fn newline_terminated_final_line_passes_strict_mode() {
+        let line = dummy_line(8);
+        let input = format!("{line}\n{line}\n");
+
+        let (result, records) = parse_all(input.as_bytes(), final_newline_options(true)).await;
+
+        assert_eq!(result.expect("Parse failed").accepted, 2);
+        assert_eq!(records.len(), 2);
+    }
+
+    #[tokio::test]
+    async fn unterminated_final_line_is_rejected_only_in_strict_mode() {
+        let line = dummy_line(8);
+        let input = format!("{line}\n{line}");
+
+        let (result, records) = parse_all(input.as_bytes(), final_newline_options(false)).await;
+        assert_eq!(result.expect("Parse failed").accepted, 2);
+        assert_eq!(records.len(), 2);
+
+        let (result, records) = parse_all(input.as_bytes(), final_newline_options(true)).await;
+        assert!(
+            matches!(result, Err(AppError::BadRequest(ref msg))
+                if msg.starts_with("line 2:") && msg.contains("truncated")),
+            "Unterminated final line should be rejected, got {result:?}"
+        );
+        assert_eq!(records.len(), 1, "only the terminated line is forwarded");
+    }
+
+    #[tokio::test]
+    async fn crlf_lines_parse_like_lf_lines() {
+        let (first, second) = (dummy_line(8), dummy_line(16));
+        let lf = format!("{first}\n\n{second}\n");
+        let crlf = format!("{first}\r\n\r\n{second}\r\n");
+
+        let options = ParseOptions {
+            strict_final_newline: true,
+            ..test_options()
+        };
+
+        let (lf_result, lf_records) = parse_all(lf.as_bytes(), options.clone()).await;
+        let (crlf_result, crlf_records) = parse_all(crlf.as_bytes(), options).await;
+
+        assert_eq!(
+            crlf_result.expect("CRLF parse failed"),
+            lf_result.expect("LF parse failed")
+        );
+        let values = |records: &[DummyRecord]| {
+            records
+                .iter()
+                .map(|record| serde_json::to_value(record).expect("Failed to serialize"))
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(values(&crlf_records), values(&lf_records));
+        assert_eq!(crlf_records.len(), 2);
+    }
+
+    #[tokio::test]
+    async fn parallel_decode_matches_sequential_decode() {
+        // Several batches' worth, with a malformed line every so often
+        let input: String = (0..2500)
+            .map(|i| {
+                if i % 397 == 13 {
+                    "{\"truncated\": \n".to_string()
+                } else {
+                    format!("{}\n", dummy_line(i % 32))
+                }
+            })
+            .collect();
+        let mut options = ParseOptions {
+            on_invalid_row: InvalidRowPolicy::Skip,
+            store_dead_letters: true,
+            ..test_options()
+        };
+
+        let (parallel, parallel_records) = parse_all(input.as_bytes(), options.clone()).await;
+        options.parallel_decode = false;
+        let (sequential, sequential_records) = parse_all(input.as_bytes(), options).await;
+
+        let parallel = parallel.expect("Parallel parse failed");
+        assert_eq!(parallel, sequential.expect("Sequential parse failed"));
+        assert_eq!(parallel.skipped, 7);
+        let skipped_lines: Vec<_> = parallel
+            .dead_letters
+            .iter()
+            .map(|d| d.line_number)
+            .collect();
+        assert_eq!(skipped_lines, [14, 411, 808, 1205, 1602, 1999, 2396]);
+
+        let payloads = |records: &[DummyRecord]| {
+            records
+                .iter()
+                .map(|record| record.payload.clone())
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(parallel_records.len(), 2493);
+        assert_eq!(payloads(&parallel_records), payloads(&sequential_records));
+    }
+
+    #[tokio::test]
+    async fn parallel_decode_reports_the_first_failing_line() {
+        let line = dummy_line(8);
+        let lines = |count| format!("{line}\n").repeat(count);
+        let input = format!(
+            "{}{{\"truncated\": \n{}{}\n",
+            lines(199),
+            lines(100),
+            dummy_line(2048)
+        );
+
+        let (result, records) = parse_all(input.as_bytes(), test_options()).await;
+
+        assert!(
+            matches!(result, Err(AppError::BadRequest(ref msg))
+                if msg.starts_with("invalid json on line 200: ")),
+            "The malformed line should be reported before the later long one, got {result:?}"
+        );
+        assert_eq!(records.len(), 199);
+    }
+
+    fn gottcha2(name: &str, read_count: i64) -> Gottcha2FullRecord {
+        Gottcha2FullRecord {
+            sample_id: "sample_a".parse().expect("Invalid sample id"),
+            level: "species".to_string(),
+            name: name.to_string(),
+            taxid: "12345".to_string(),
+            read_count,
+            total_bp_mapped: 15_000,
+            ani_ci95: 0.97,
+            covered_sig_len: 1_200,
+            best_sig_cov: 0.8,
+            depth: 12.5,
+            rel_abundance: 0.25,
+            raw_line: None,
+            source_line: None,
+            content_hash: None,
+            observed_at: None,
+        }
+    }
+
+    const GOTTCHA2_TSV_HEADER: &str = "SAMPLE_ID\tLEVEL\tNAME\tTAXID\tREAD_COUNT\t\
+        TOTAL_BP_MAPPED\tANI_CI95\tCOVERED_SIG_LEN\tBEST_SIG_COV\tDEPTH\tREL_ABUNDANCE";
+
+    fn gottcha2_tsv_row(record: &Gottcha2FullRecord) -> String {
+        format!(
+            "{}\tspecies\t{}\t12345\t{}\t15000\t0.97\t1200\t0.8\t12.5\t0.25",
+            record.sample_id, record.name, record.read_count
+        )
+    }
+
+    fn values<T: serde::Serialize>(records: &[T]) -> Vec<serde_json::Value> {
+        records
+            .iter()
+            .map(|record| serde_json::to_value(record).expect("Failed to serialize"))
+            .collect()
+    }
+
+    #[tokio::test]
+    async fn tsv_rows_decode_like_jsonl_lines() {
+        let records = [
+            gottcha2("Test virus", 100),
+            gottcha2("Test \"quoted\" virus", 200),
+        ];
+        let jsonl = records
+            .iter()
+            .map(|r| serde_json::to_string(r).expect("Failed to serialize") + "\n")
+            .collect::<String>();
+        let tsv = std::iter::once(GOTTCHA2_TSV_HEADER.to_string())
+            .chain(records.iter().map(gottcha2_tsv_row))
+            .map(|line| line + "\r\n")
+            .collect::<String>();
+
+        let (result, from_jsonl) =
+            parse_all_as::<Gottcha2FullRecord>(jsonl.as_bytes(), test_options()).await;
+        assert_eq!(result.expect("JSONL parse failed").accepted, 2);
+
+        let options = ParseOptions {
+            format: InputFormat::Tsv,
+            ..test_options()
+        };
+        let (result, from_tsv) = parse_all_as::<Gottcha2FullRecord>(tsv.as_bytes(), options).await;
+        assert_eq!(result.expect("TSV parse failed").accepted, 2);
+        assert_eq!(values(&from_tsv), values(&from_jsonl));
+    }
+
+    #[tokio::test]
+    async fn csv_rows_may_quote_fields() {
+        let header = GOTTCHA2_TSV_HEADER.replace('\t', ",");
+        let csv = format!(
+            "{header}\nsample_a,species,\"Virus, strain A\",12345,100,15000,0.97,1200,0.8,12.5,0.25\n"
+        );
+        let options = ParseOptions {
+            format: InputFormat::Csv,
+            ..test_options()
+        };
+
+        let (result, records) = parse_all_as::<Gottcha2FullRecord>(csv.as_bytes(), options).await;
+
+        assert_eq!(result.expect("CSV parse failed").accepted, 1);
+        assert_eq!(records[0].name, "Virus, strain A");
+    }
+
+    #[tokio::test]
+    async fn tsv_rows_with_the_wrong_column_count_are_malformed() {
+        let good = gottcha2_tsv_row(&gottcha2("Test virus", 100));
+        let tsv = format!("{GOTTCHA2_TSV_HEADER}\n{good}\nsample_a\tspecies\n{good}\n");
+        let mut options = ParseOptions {
+            format: InputFormat::Tsv,
+            ..test_options()
+        };
+
+        let (result, _) = parse_all_as::<Gottcha2FullRecord>(tsv.as_bytes(), options.clone()).await;
+        assert!(
+            matches!(result, Err(AppError::BadRequest(ref msg))
+                if msg == "invalid tsv row on line 3: expected 11 columns, found 2"),
+            "Short row should fail the upload, citing it, got {result:?}"
+        );
+
+        options.on_invalid_row = InvalidRowPolicy::Skip;
+        let (result, records) = parse_all_as::<Gottcha2FullRecord>(tsv.as_bytes(), options).await;
+        let summary = result.expect("Parse failed");
+        assert_eq!((summary.accepted, summary.skipped), (2, 1));
+        assert_eq!(records.len(), 2);
+    }
+
+    #[tokio::test]
+    async fn tsv_rows_take_the_sample_id_override_and_zero_fill() {
+        // As GOTTCHA2 writes it: no sample ID, and an older tool version
+        // without TOTAL_BP_MAPPED
+        let tsv = "LEVEL\tNAME\tTAXID\tREAD_COUNT\tANI_CI95\tCOVERED_SIG_LEN\t\
+            BEST_SIG_COV\tDEPTH\tREL_ABUNDANCE\n\
+            species\tTest virus\t12345\t100\t0.97\t1200\t0.8\t12.5\t0.25\n";
+        let options = ParseOptions {
+            format: InputFormat::Tsv,
+            fill_missing_fields: true,
+            require_sample_id: true,
+            sample_id_override: Some("from_path".parse().expect("Invalid sample id")),
+            ..test_options()
+        };
+
+        let (result, records) = parse_all_as::<Gottcha2FullRecord>(tsv.as_bytes(), options).await;
+
+        assert_eq!(result.expect("TSV parse failed").accepted, 1);
+        assert_eq!(records[0].sample_id.as_str(), "from_path");
+        assert_eq!(records[0].total_bp_mapped, 0);
+    }
+
+    #[tokio::test]
+    async fn malformed_lines_are_skipped_only_under_skip_policy() {
+        let line = dummy_line(8);
+        let input = format!("{line}\n{{\"truncated\": \n{line}\n");
+
+        let (result, records) = parse_all(input.as_bytes(), test_options()).await;
+        assert!(
+            matches!(result, Err(AppError::BadRequest(ref msg))
+                if msg.starts_with("invalid json on line 2: ")),
+            "Malformed line should fail a strict upload, citing it, got {result:?}"
+        );
+        assert_eq!(records.len(), 1);
+
+        let options = ParseOptions {
+            on_invalid_row: InvalidRowPolicy::Skip,
+            store_dead_letters: true,
+            ..test_options()
+        };
+        let (result, records) = parse_all(input.as_bytes(), options).await;
+        let summary = result.expect("Parse failed");
+        assert_eq!((summary.accepted, summary.skipped), (2, 1));
+        assert_eq!(summary.dead_letters[0].line_number, 2);
+        assert_eq!(records.len(), 2);
+    }
+
+    #[tokio::test]
+    async fn body_expanding_past_the_limit_is_rejected() {
+        let line = dummy_line(64);
+        let input = format!("{line}\n{line}\n");
+
+        let mut options = ParseOptions {
+            max_decompressed_bytes: 2 * line.len() as u64,
+            ..test_options()
+        };
+        let (result, records) = parse_all(input.as_bytes(), options.clone()).await;
+        assert_eq!(
+            result.expect("Parse failed").decompressed_bytes,
+            2 * line.len() as u64
+        );
+        assert_eq!(records.len(), 2);
+
+        options.max_decompressed_bytes -= 1;
+        let (result, records) = parse_all(input.as_bytes(), options).await;
+        assert!(
+            matches!(result, Err(AppError::PayloadTooLarge(_))),
+            "Body past the limit should be rejected, got {result:?}"
+        );
+        assert_eq!(
+            records.len(),
+            1,
+            "lines under the limit are still forwarded"
+        );
+    }
+
+    #[tokio::test]
+    async fn corrupt_gzip_is_rejected() {
+        let line = dummy_line(8);
+        let compressed = gzip(format!("{line}\n{line}\n").as_bytes());
+        let truncated = compressed[..compressed.len() - 8].to_vec();
+
+        for body in [vec![1, 2, 3, 4, 5], truncated] {
+            let (tx, _rx) = mpsc::channel::<DummyRecord>(16);
+            let result = parse_gzipped_jsonl(Body::from(body), tx, test_options()).await;
+            assert!(
+                matches!(result, Err(AppError::BadRequest(ref msg)) if msg.contains("gzip")),
+                "Corrupt gzip should be rejected, got {result:?}"
+            );
+        }
+    }
+
+    #[test]
+    fn content_encoding_selects_the_decoder() {
+        let mut headers = HeaderMap::new();
+        assert_eq!(
+            Compression::from_headers(&headers).ok(),
+            Some(Compression::Auto)
+        );
+
+        headers.insert(
+            header::CONTENT_ENCODING,
+            "identity".parse().expect("Invalid header"),
+        );
+        assert_eq!(
+            Compression::from_headers(&headers).ok(),
+            Some(Compression::Identity)
+        );
+
+        headers.insert(
+            header::CONTENT_ENCODING,
+            "ZSTD".parse().expect("Invalid header"),
+        );
+        assert_eq!(
+            Compression::from_headers(&headers).ok(),
+            Some(Compression::Zstd)
+        );
+
+        headers.insert(
+            header::CONTENT_ENCODING,
+            "br".parse().expect("Invalid header"),
+        );
+        assert!(matches!(
+            Compression::from_headers(&headers),
+            Err(AppError::BadRequest(ref msg)) if msg.contains("\"br\"")
+        ));
+    }
+
+    #[test]
+    fn content_type_is_checked_against_the_accepted_types() {
+        let mut headers = HeaderMap::new();
+        assert!(check_content_type(&headers).is_ok());
+
+        for accepted in ["application/gzip", "Application/X-NDJSON; charset=utf-8"] {
+            headers.insert(
+                header::CONTENT_TYPE,
+                accepted.parse().expect("Invalid header"),
+            );
+            assert!(check_content_type(&headers).is_ok(), "{accepted}");
+        }
+
+        for rejected in ["application/x-www-form-urlencoded", "text/plain", ""] {
+            headers.insert(
+                header::CONTENT_TYPE,
+                rejected.parse().expect("Invalid header"),
+            );
+            assert!(
+                matches!(
+                    check_content_type(&headers),
+                    Err(AppError::UnsupportedMediaType(ref msg)) if msg.contains("application/gzip")
+                ),
+                "{rejected:?} should be rejected"
+            );
+        }
+    }
+
+    #[tokio::test]
+    async fn undeclared_compression_is_sniffed_from_the_body() {
+        let line = dummy_line(8);
+        let input = format!("{line}\n{line}\n");
+        let options = ParseOptions {
+            compression: Compression::Auto,
+            ..test_options()
+        };
+
+        for body in [gzip(input.as_bytes()), input.clone().into_bytes()] {
+            let (tx, mut rx) = mpsc::channel::<DummyRecord>(16);
+            let result = parse_gzipped_jsonl(Body::from(body), tx, options.clone()).await;
+            assert_eq!(result.expect("Parse failed").accepted, 2);
+            let record = rx.recv().await.expect("No record forwarded");
+            assert_eq!(
+                serde_json::to_string(&record).expect("Failed to serialize"),
+                line
+            );
+        }
+
+        // A declared encoding is trusted rather than sniffed
+        let (tx, _rx) = mpsc::channel::<DummyRecord>(16);
+        let result = parse_gzipped_jsonl(Body::from(input.into_bytes()), tx, test_options()).await;
+        assert!(
+            matches!(result, Err(AppError::BadRequest(ref msg)) if msg.contains("gzip")),
+            "Plain JSONL declared as gzip should be rejected, got {result:?}"
+        );
+    }
+
+    #[tokio::test]
+    async fn zstd_body_round_trips() {
+        use async_compression::tokio::bufread::ZstdEncoder;
+        use tokio::io::AsyncReadExt;
+
+        let line = dummy_line(64);
+        let input = format!("{line}\n{line}\n");
+        let mut compressed = Vec::new();
+        ZstdEncoder::new(input.as_bytes())
+            .read_to_end(&mut compressed)
+            .await
+            .expect("Failed to compress");
+
+        let options = ParseOptions {
+            compression: Compression::Zstd,
+            ..test_options()
+        };
+        let (tx, mut rx) = mpsc::channel::<DummyRecord>(16);
+        let result = parse_gzipped_jsonl(Body::from(compressed), tx, options.clone()).await;
+        assert_eq!(result.expect("Parse failed").accepted, 2);
+        let record = rx.recv().await.expect("No record forwarded");
+        assert_eq!(
+            serde_json::to_string(&record).expect("Failed to serialize"),
+            line
+        );
+
+        // A gzip body sent as zstd is rejected rather than read as empty
+        let (tx, _rx) = mpsc::channel::<DummyRecord>(16);
+        let result = parse_gzipped_jsonl(Body::from(gzip(input.as_bytes())), tx, options).await;
+        assert!(
+            matches!(result, Err(AppError::BadRequest(ref msg)) if msg.contains("zstd")),
+            "got {result:?}"
+        );
+    }
+
+    fn tricky_line(payload: &str) -> String {
+        let record = DummyRecord {
+            run_id: "run".to_string(),
+            task_id: "task".to_string(),
+            shard: 0,
+            idempotency_key: "key".to_string(),
+            schema_version: 1,
+            payload: serde_json::json!({ "text": payload, "list": [1, [2]] }),
+        };
+        serde_json::to_string(&record).expect("Failed to serialize")
+    }
+
+    #[tokio::test]
+    async fn json_array_body_yields_the_same_records_as_jsonl() {
+        let lines = [
+            dummy_line(8),
+            tricky_line("commas, ] brackets } and \"quotes\" \\"),
+            tricky_line("["),
+        ];
+        let jsonl = format!("{}\n", lines.join("\n"));
+        let array = format!("  \n[\n  {},\n{} ,{}\n]\n", lines[0], lines[1], lines[2]);
+
+        let (jsonl_result, jsonl_records) = parse_all(jsonl.as_bytes(), test_options()).await;
+        let (array_result, array_records) = parse_all(array.as_bytes(), test_options()).await;
+
+        assert_eq!(jsonl_result.expect("JSONL parse failed").accepted, 3);
+        assert_eq!(array_result.expect("Array parse failed").accepted, 3);
+        let serialize = |records: &[DummyRecord]| {
+            records
+                .iter()
+                .map(|record| serde_json::to_string(record).expect("Failed to serialize"))
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(serialize(&array_records), serialize(&jsonl_records));
+        assert_eq!(serialize(&array_records), lines);
+    }
+
+    #[tokio::test]
+    async fn empty_json_array_yields_no_records() {
+        for body in ["[]", " [ ]\n", "[\n]"] {
+            let (result, records) = parse_all(body.as_bytes(), test_options()).await;
+            assert_eq!(result.expect("Parse failed").accepted, 0, "{body:?}");
+            assert!(records.is_empty());
+        }
+    }
+
+    #[tokio::test]
+    async fn malformed_json_array_is_rejected() {
+        let line = dummy_line(8);
+        let cases = [
+            (format!("[{line},{line}"), "closing ]"),
+            (format!("[{line}] {line}"), "after the JSON array"),
+            (format!("[{line},notjson]"), "line 2"),
+        ];
+
+        for (body, expected) in cases {
+            let (result, _) = parse_all(body.as_bytes(), test_options()).await;
+            assert!(
+                matches!(result, Err(AppError::BadRequest(ref msg)) if msg.contains(expected)),
+                "{body:?} should be rejected with {expected:?}, got {result:?}"
+            );
+        }
+    }
+}
@@ -0,0 +1,78 @@
+use std::future::Future;
+
+use crate::error::AppError;
+
+/// Drives a parser and an inserter concurrently, like `tokio::try_join!`, but
+/// makes sure the inserter's error wins when the two race.
+///
+/// When the inserter bails out (say, on a constraint violation) it drops its
+/// receiver, and the parser's next send fails with `AppError::ChannelClosed`.
+/// Reporting that would hide the real cause, so in that case we wait for the
+/// inserter and return its error instead. Any other parser error still
+/// cancels the inserter immediately.
+///
+/// # Errors
+///
+/// Returns the first meaningful error from either stage.
+pub async fn join_ingest<A, B, P, I>(parser: P, inserter: I) -> Result<(A, B), AppError>
+where
+    P: Future<Output = Result<A, AppError>>,
+    I: Future<Output = Result<B, AppError>>,
+{
+    tokio::pin!(parser, inserter);
+
+    tokio::select! {
+        parser_result = &mut parser => match parser_result {
+            Ok(a) => inserter.await.map(|b| (a, b)),
+            Err(AppError::ChannelClosed) => Err(inserter
+                .await
+                .err()
+                .unwrap_or(AppError::ChannelClosed)),
+            Err(e) => Err(e),
+        },
+        inserter_result = &mut inserter => {
+            let b = inserter_result?;
+            parser.await.map(|a| (a, b))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::*;
+
+    #[tokio::test]
+    async fn inserter_error_wins_over_channel_closed() {
+        let parser = async { Err::<(), _>(AppError::ChannelClosed) };
+        let inserter = async {
+            tokio::time::sleep(Duration::from_millis(10)).await;
+            Err::<(), _>(AppError::Conflict("duplicate key".to_string()))
+        };
+
+        let result = join_ingest(parser, inserter).await;
+
+        assert!(
+            matches!(result, Err(AppError::Conflict(ref msg)) if msg == "duplicate key"),
+            "expected the inserter's conflict, got {result:?}"
+        );
+    }
+
+    #[tokio::test]
+    async fn parser_error_is_reported_as_is() {
+        let parser = async { Err::<(), _>(AppError::BadRequest("bad line".to_string())) };
+        let inserter = std::future::pending::<Result<(), AppError>>();
+
+        let result = join_ingest(parser, inserter).await;
+
+        assert!(matches!(result, Err(AppError::BadRequest(_))));
+    }
+
+    #[tokio::test]
+    async fn both_stages_succeeding_returns_both_outputs() {
+        let result = join_ingest(async { Ok(3) }, async { Ok("done") }).await;
+
+        assert!(matches!(result, Ok((3, "done"))));
+    }
+}
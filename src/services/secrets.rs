@@ -0,0 +1,101 @@
+use std::time::Duration;
+
+use color_eyre::eyre::{OptionExt, Result, WrapErr, bail, eyre};
+use serde_json::Value;
+use tokio::task::JoinHandle;
+
+use crate::{
+    config::{AppConfig, TokenSource},
+    state::AppState,
+};
+
+/// How long one secrets backend request may take before startup (or a
+/// refresh) gives up on it.
+const FETCH_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Resolves the ingest token from wherever `INGEST_TOKEN_SOURCE` says it
+/// lives.
+///
+/// # Errors
+///
+/// Returns an error if the backend is misconfigured, unreachable, refuses
+/// the request, or holds no usable token.
+pub async fn resolve_ingest_token(config: &AppConfig) -> Result<String> {
+    match config.ingest_token_source {
+        TokenSource::Env => Ok(config.ingest_token.clone()),
+        TokenSource::Vault => fetch_vault_secret(config).await,
+    }
+}
+
+/// Reads `VAULT_SECRET_FIELD` of the secret at `VAULT_SECRET_PATH`. Both KV
+/// v2 (`data.data.<field>`) and KV v1 (`data.<field>`) responses are
+/// understood.
+async fn fetch_vault_secret(config: &AppConfig) -> Result<String> {
+    let addr = config
+        .vault_addr
+        .as_deref()
+        .ok_or_eyre("INGEST_TOKEN_SOURCE=vault requires VAULT_ADDR")?;
+    let path = config
+        .vault_secret_path
+        .as_deref()
+        .ok_or_eyre("INGEST_TOKEN_SOURCE=vault requires VAULT_SECRET_PATH")?;
+    let vault_token = config
+        .vault_token
+        .as_deref()
+        .ok_or_eyre("INGEST_TOKEN_SOURCE=vault requires VAULT_TOKEN")?;
+    let url = format!(
+        "{}/v1/{}",
+        addr.trim_end_matches('/'),
+        path.trim_start_matches('/')
+    );
+
+    let response = reqwest::Client::builder()
+        .timeout(FETCH_TIMEOUT)
+        .build()?
+        .get(&url)
+        .header("X-Vault-Token", vault_token)
+        .send()
+        .await
+        .wrap_err_with(|| format!("failed to reach Vault at {url}"))?;
+    let status = response.status();
+    if !status.is_success() {
+        bail!("Vault returned {status} for {url}");
+    }
+    let body: Value = response
+        .json()
+        .await
+        .wrap_err("Vault returned a malformed response")?;
+
+    let field = &config.vault_secret_field;
+    let token = body
+        .pointer(&format!("/data/data/{field}"))
+        .or_else(|| body.pointer(&format!("/data/{field}")))
+        .and_then(Value::as_str)
+        .filter(|token| !token.is_empty())
+        .ok_or_else(|| eyre!("Vault secret {path} has no {field:?} string"))?;
+    Ok(token.to_string())
+}
+
+/// Spawns a background task that re-resolves the ingest token every
+/// `period` and swaps it into `state`. A failed refresh is logged and the
+/// current token kept, so a brief backend outage doesn't lock clients out.
+#[must_use]
+pub fn spawn_token_refresher(state: AppState, period: Duration) -> JoinHandle<()> {
+    let period = period.max(Duration::from_secs(1));
+    tokio::spawn(async move {
+        let mut ticker = tokio::time::interval(period);
+        // The first tick fires immediately; startup already fetched the token
+        ticker.tick().await;
+        loop {
+            ticker.tick().await;
+            match resolve_ingest_token(&state.config).await {
+                Ok(token) if state.ingest_tokens() != [token.as_str()] => {
+                    state.set_ingest_token(token);
+                    tracing::info!("Ingest token rotated");
+                }
+                Ok(_) => {}
+                Err(e) => tracing::warn!("Failed to refresh the ingest token: {e:?}"),
+            }
+        }
+    })
+}
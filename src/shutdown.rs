@@ -0,0 +1,57 @@
+use std::time::Duration;
+
+use axum_server::Handle;
+
+use crate::state::AppState;
+
+/// Resolves when the process is asked to stop: Ctrl-C, or SIGTERM on Unix
+/// (what Kubernetes sends during a rolling deploy).
+pub async fn signal() {
+    let ctrl_c = async {
+        if let Err(e) = tokio::signal::ctrl_c().await {
+            tracing::error!("Failed to listen for Ctrl-C: {e}");
+            std::future::pending::<()>().await;
+        }
+    };
+
+    #[cfg(unix)]
+    let terminate = async {
+        match tokio::signal::unix::signal(tokio::signal::unix::SignalKind::terminate()) {
+            Ok(mut sigterm) => {
+                sigterm.recv().await;
+            }
+            Err(e) => {
+                tracing::error!("Failed to listen for SIGTERM: {e}");
+                std::future::pending::<()>().await;
+            }
+        }
+    };
+    #[cfg(not(unix))]
+    let terminate = std::future::pending::<()>();
+
+    tokio::select! {
+        () = ctrl_c => {},
+        () = terminate => {},
+    }
+}
+
+/// Starts a graceful shutdown: new ingests are refused, listeners stop
+/// accepting connections, and requests already in flight get up to `grace`
+/// to finish. Because each ingest handler drives its own parser and
+/// inserter, an in-flight upload keeps draining its channel and committing
+/// batches until it completes; only work still running when `grace` runs out
+/// is aborted.
+pub fn begin(handle: &Handle, state: &AppState, grace: Duration) {
+    state.begin_draining(grace);
+    handle.graceful_shutdown(Some(grace));
+}
+
+/// Waits for a shutdown signal, then calls [`begin`].
+pub async fn drain_on_signal(handle: Handle, state: AppState, grace: Duration) {
+    signal().await;
+    tracing::info!(
+        "Shutdown requested; draining in-flight ingests for up to {}s",
+        grace.as_secs()
+    );
+    begin(&handle, &state, grace);
+}
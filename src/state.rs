@@ -1,9 +1,43 @@
-use crate::config::AppConfig;
+use std::{
+    collections::HashMap,
+    sync::{
+        Arc, Mutex, OnceLock, PoisonError, RwLock,
+        atomic::{AtomicBool, Ordering},
+    },
+    time::{Duration, Instant},
+};
+
+use tokio::sync::{OwnedSemaphorePermit, Semaphore, broadcast};
+
+use crate::{
+    config::AppConfig,
+    error::AppError,
+    services::{
+        events::{EVENT_BUFFER, IngestEvent},
+        metrics::Metrics,
+    },
+};
 
 #[derive(Clone)]
 pub struct AppState {
     pub db: sqlx::PgPool,
     pub config: AppConfig,
+    pub metrics: Metrics,
+    paused: Arc<AtomicBool>,
+    /// The operator's explanation for the current pause, if any
+    maintenance: Arc<RwLock<Option<MaintenanceNotice>>>,
+    /// When the shutdown grace window ends; unset until shutdown begins
+    drain_deadline: Arc<OnceLock<Instant>>,
+    events: broadcast::Sender<IngestEvent>,
+    /// In-flight ingest count per bearer token
+    ingests_in_flight: Arc<Mutex<HashMap<String, usize>>>,
+    /// Query slots, one per `max_concurrent_reads`; unset when unlimited
+    reads: Option<Arc<Semaphore>>,
+    /// The accepted ingest tokens; start as `config.accepted_ingest_tokens()`
+    /// and are swapped when a secrets backend rotates the token
+    ingest_tokens: Arc<RwLock<Vec<String>>>,
+    /// When the state was built, i.e. roughly when the server started
+    started_at: Instant,
 }
 
 impl AppState {
@@ -12,6 +46,185 @@ impl AppState {
         AppState {
             db,
             config: config.clone(),
+            metrics: Metrics::new(),
+            paused: Arc::new(AtomicBool::new(false)),
+            maintenance: Arc::default(),
+            drain_deadline: Arc::new(OnceLock::new()),
+            events: broadcast::channel(EVENT_BUFFER).0,
+            ingests_in_flight: Arc::default(),
+            reads: config
+                .max_concurrent_reads
+                .map(|limit| Arc::new(Semaphore::new(limit))),
+            ingest_tokens: Arc::new(RwLock::new(config.accepted_ingest_tokens())),
+            started_at: Instant::now(),
+        }
+    }
+
+    /// How long the server has been up.
+    #[must_use]
+    pub fn uptime(&self) -> Duration {
+        self.started_at.elapsed()
+    }
+
+    /// The bearer tokens ingest and query requests may present, any one of
+    /// which is accepted.
+    #[must_use]
+    pub fn ingest_tokens(&self) -> Vec<String> {
+        self.ingest_tokens
+            .read()
+            .unwrap_or_else(PoisonError::into_inner)
+            .clone()
+    }
+
+    /// Replaces the accepted ingest tokens with `token` alone, e.g. after a
+    /// rotation.
+    pub fn set_ingest_token(&self, token: String) {
+        *self
+            .ingest_tokens
+            .write()
+            .unwrap_or_else(PoisonError::into_inner) = vec![token];
+    }
+
+    /// Whether ingest endpoints are currently rejecting new uploads.
+    #[must_use]
+    pub fn is_paused(&self) -> bool {
+        self.paused.load(Ordering::Acquire)
+    }
+
+    /// Toggles whether ingest endpoints reject new uploads.
+    pub fn set_paused(&self, paused: bool) {
+        self.paused.store(paused, Ordering::Release);
+    }
+
+    /// Sets or clears the notice shown to clients while paused.
+    pub fn set_maintenance(&self, notice: Option<MaintenanceNotice>) {
+        *self
+            .maintenance
+            .write()
+            .unwrap_or_else(PoisonError::into_inner) = notice;
+    }
+
+    /// Why an upload is being refused while paused: the operator's
+    /// maintenance notice if there is one, else a bare `IngestionPaused`.
+    #[must_use]
+    pub fn pause_error(&self) -> AppError {
+        let notice = self
+            .maintenance
+            .read()
+            .unwrap_or_else(PoisonError::into_inner)
+            .clone();
+        match notice {
+            Some(notice) => AppError::Maintenance {
+                message: notice.message,
+                retry_after_secs: notice.retry_after_secs,
+            },
+            None => AppError::IngestionPaused,
+        }
+    }
+
+    /// Marks the server as draining for up to `grace`; new ingests are turned
+    /// away from here on. Later calls keep the original deadline.
+    pub fn begin_draining(&self, grace: Duration) {
+        let _ = self.drain_deadline.set(Instant::now() + grace);
+    }
+
+    /// Seconds until the grace window closes and a replacement instance
+    /// should be serving, rounded up and never less than one. `None` unless
+    /// draining.
+    #[must_use]
+    pub fn drain_retry_after_secs(&self) -> Option<u64> {
+        let remaining = self
+            .drain_deadline
+            .get()?
+            .saturating_duration_since(Instant::now());
+        let secs = remaining.as_secs() + u64::from(remaining.subsec_nanos() > 0);
+        Some(secs.max(1))
+    }
+
+    /// Broadcasts an ingest event to any `/events` subscribers.
+    pub fn publish(&self, event: IngestEvent) {
+        // Having no subscribers is the normal case, not an error
+        let _ = self.events.send(event);
+    }
+
+    /// Subscribes to ingest events published from now on.
+    #[must_use]
+    pub fn subscribe(&self) -> broadcast::Receiver<IngestEvent> {
+        self.events.subscribe()
+    }
+
+    /// Claims one of `token`'s concurrent ingest slots for as long as the
+    /// returned guard lives.
+    ///
+    /// # Errors
+    ///
+    /// Returns `AppError::TooManyIngests` if `token` is already at
+    /// `max_concurrent_ingests_per_token`.
+    pub fn acquire_ingest_slot(&self, token: &str) -> Result<IngestSlot, AppError> {
+        let mut in_flight = self
+            .ingests_in_flight
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner);
+        let count = in_flight.entry(token.to_string()).or_default();
+        if self
+            .config
+            .max_concurrent_ingests_per_token
+            .is_some_and(|limit| *count >= limit)
+        {
+            return Err(AppError::TooManyIngests);
+        }
+        *count += 1;
+
+        Ok(IngestSlot {
+            token: token.to_string(),
+            in_flight: Arc::clone(&self.ingests_in_flight),
+        })
+    }
+
+    /// Claims one of the `max_concurrent_reads` query slots for as long as
+    /// the returned permit lives; `None` when reads are unlimited.
+    ///
+    /// # Errors
+    ///
+    /// Returns `AppError::TooManyReads` if every slot is taken.
+    pub fn acquire_read_slot(&self) -> Result<Option<OwnedSemaphorePermit>, AppError> {
+        self.reads
+            .as_ref()
+            .map(|reads| {
+                Arc::clone(reads)
+                    .try_acquire_owned()
+                    .map_err(|_| AppError::TooManyReads)
+            })
+            .transpose()
+    }
+}
+
+/// An operator's explanation for pausing ingestion.
+#[derive(Debug, Clone)]
+pub struct MaintenanceNotice {
+    pub message: String,
+    /// Seconds clients should wait before retrying, if the operator knows
+    pub retry_after_secs: Option<u64>,
+}
+
+/// Releases its token's ingest slot when dropped, so every exit path from a
+/// handler, including errors and client disconnects, gives the slot back.
+pub struct IngestSlot {
+    token: String,
+    in_flight: Arc<Mutex<HashMap<String, usize>>>,
+}
+
+impl Drop for IngestSlot {
+    fn drop(&mut self) {
+        let mut in_flight = self
+            .in_flight
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner);
+        if let Some(count) = in_flight.get_mut(&self.token) {
+            *count -= 1;
+            if *count == 0 {
+                in_flight.remove(&self.token);
+            }
         }
     }
 }
@@ -0,0 +1,95 @@
+use std::{io, sync::Arc, time::Duration};
+
+use axum_server::accept::Accept;
+use futures_util::future::BoxFuture;
+use rustls::{
+    server::{ClientHello, ResolvesServerCert},
+    sign::CertifiedKey,
+};
+use tokio::sync::Semaphore;
+
+/// Wraps another acceptor (normally `RustlsAcceptor`) so that at most
+/// `max_concurrent` handshakes run at once. TLS handshakes are CPU-heavy, and
+/// a connection storm would otherwise starve the runtime of cycles for the
+/// ingests already in flight. Connections that can't get a slot within
+/// `queue_timeout` are dropped.
+#[derive(Clone)]
+pub struct HandshakeLimitAcceptor<A> {
+    inner: A,
+    permits: Arc<Semaphore>,
+    queue_timeout: Duration,
+}
+
+impl<A> HandshakeLimitAcceptor<A> {
+    #[must_use]
+    pub fn new(inner: A, max_concurrent: usize, queue_timeout: Duration) -> Self {
+        HandshakeLimitAcceptor {
+            inner,
+            permits: Arc::new(Semaphore::new(max_concurrent.max(1))),
+            queue_timeout,
+        }
+    }
+}
+
+impl<A, I, S> Accept<I, S> for HandshakeLimitAcceptor<A>
+where
+    A: Accept<I, S> + Clone + Send + 'static,
+    A::Future: Send,
+    I: Send + 'static,
+    S: Send + 'static,
+{
+    type Stream = A::Stream;
+    type Service = A::Service;
+    type Future = BoxFuture<'static, io::Result<(Self::Stream, Self::Service)>>;
+
+    fn accept(&self, stream: I, service: S) -> Self::Future {
+        let inner = self.inner.clone();
+        let permits = Arc::clone(&self.permits);
+        let queue_timeout = self.queue_timeout;
+
+        Box::pin(async move {
+            let _permit = tokio::time::timeout(queue_timeout, permits.acquire_owned())
+                .await
+                .map_err(|_| {
+                    tracing::warn!("Dropping connection: TLS handshake queue is full");
+                    io::Error::new(io::ErrorKind::TimedOut, "TLS handshake queue full")
+                })?
+                .map_err(io::Error::other)?;
+
+            inner.accept(stream, service).await
+        })
+    }
+}
+
+/// Refuses TLS handshakes whose SNI isn't one of `allowed` (compared
+/// case-insensitively), including handshakes that offer no SNI at all, and
+/// otherwise defers to `inner`. With no certificate to present, rustls aborts
+/// the handshake with an alert.
+#[derive(Debug)]
+pub struct SniAllowList {
+    inner: Arc<dyn ResolvesServerCert>,
+    allowed: Vec<String>,
+}
+
+impl SniAllowList {
+    #[must_use]
+    pub fn new(inner: Arc<dyn ResolvesServerCert>, allowed: Vec<String>) -> Self {
+        SniAllowList { inner, allowed }
+    }
+}
+
+impl ResolvesServerCert for SniAllowList {
+    fn resolve(&self, client_hello: ClientHello<'_>) -> Option<Arc<CertifiedKey>> {
+        let offered = client_hello.server_name();
+        if offered.is_some_and(|name| {
+            self.allowed
+                .iter()
+                .any(|allowed| allowed.trim().eq_ignore_ascii_case(name))
+        }) {
+            self.inner.resolve(client_hello)
+        } else {
+            tracing::warn!("Refusing TLS handshake for unexpected SNI {offered:?}");
+            None
+        }
+    }
+}
@@ -57,6 +57,35 @@ impl TestCertificates {
 
         Ok(client)
     }
+
+    /// A bare rustls client trusting the test CA, for inspecting handshakes.
+    pub fn create_rustls_client_config(
+        &self,
+    ) -> Result<std::sync::Arc<rustls::ClientConfig>, Box<dyn std::error::Error>> {
+        let mut roots = rustls::RootCertStore::empty();
+        for cert in rustls_pemfile::certs(&mut self.ca_cert_pem.as_slice()) {
+            roots.add(cert?)?;
+        }
+        let config = rustls::ClientConfig::builder()
+            .with_root_certificates(roots)
+            .with_no_client_auth();
+        Ok(std::sync::Arc::new(config))
+    }
+
+    /// A client that leaves response bodies compressed, for asserting on
+    /// exactly what the server sent.
+    pub fn create_raw_reqwest_client(&self) -> Result<reqwest::Client, Box<dyn std::error::Error>> {
+        let cert = reqwest::Certificate::from_pem(&self.ca_cert_pem)?;
+
+        let client = reqwest::Client::builder()
+            .use_rustls_tls()
+            .add_root_certificate(cert)
+            .timeout(std::time::Duration::from_secs(10))
+            .no_gzip()
+            .build()?;
+
+        Ok(client)
+    }
 }
 
 #[cfg(test)]
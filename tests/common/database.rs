@@ -6,12 +6,35 @@ use testcontainers_modules::postgres::Postgres;
 pub struct TestDatabase {
     pub pool: PgPool,
     pub database_url: String,
-    _container: ContainerAsync<Postgres>,
+    _container: Option<ContainerAsync<Postgres>>,
 }
 
 #[allow(dead_code)]
 impl TestDatabase {
     pub async fn new() -> Result<Self, Box<dyn std::error::Error>> {
+        if std::env::var("LOCAL_PG").is_ok() {
+            let name = format!(
+                "t_{}_{}",
+                std::process::id(),
+                std::time::SystemTime::now()
+                    .duration_since(std::time::UNIX_EPOCH)?
+                    .as_nanos()
+            );
+            let admin =
+                PgPool::connect("postgresql://postgres@localhost:5432/postgres?host=/tmp/pg")
+                    .await?;
+            sqlx::query(&format!("CREATE DATABASE {name}"))
+                .execute(&admin)
+                .await?;
+            let database_url = format!("postgresql://postgres@localhost:5432/{name}?host=/tmp/pg");
+            let pool = PgPool::connect(&database_url).await?;
+            sqlx::migrate!("./migrations").run(&pool).await?;
+            return Ok(TestDatabase {
+                pool,
+                database_url,
+                _container: None,
+            });
+        }
         let postgres_image = Postgres::default()
             .with_db_name("nvd_test")
             .with_user("postgres")
@@ -31,7 +54,7 @@ impl TestDatabase {
         Ok(TestDatabase {
             pool,
             database_url,
-            _container: container,
+            _container: Some(container),
         })
     }
 
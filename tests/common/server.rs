@@ -1,14 +1,11 @@
 use crate::common::certificates::TestCertificates;
-use axum::Router;
 use axum_server::tls_rustls::RustlsConfig;
 use nvd_support_car::{
-    config::AppConfig,
-    handlers::{healthz, ingest_dummy, ingest_gottcha2, ingest_stast},
-    state::AppState,
+    config::AppConfig, router::build_router, shutdown, state::AppState, tls::HandshakeLimitAcceptor,
 };
 use sqlx::PgPool;
 use std::net::SocketAddr;
-use std::sync::Once;
+use std::sync::{Arc, Once};
 use std::time::Duration;
 use tokio::task::JoinHandle;
 
@@ -26,14 +23,27 @@ fn init_crypto_provider() {
 pub struct TestServer {
     pub addr: SocketAddr,
     pub base_url: String,
+    /// Set when the config enables the plaintext listener
+    pub http_base_url: Option<String>,
     pub bearer_token: String,
     pub certs: TestCertificates,
     handle: JoinHandle<()>,
+    http_handle: Option<JoinHandle<()>>,
+    server_handle: axum_server::Handle,
+    state: AppState,
 }
 
 #[allow(dead_code)]
 impl TestServer {
     pub async fn start_with_tls(db_pool: PgPool) -> Result<Self, Box<dyn std::error::Error>> {
+        Self::start_with_tls_config(db_pool, |_| {}).await
+    }
+
+    /// Starts the server after letting the caller adjust the default test config.
+    pub async fn start_with_tls_config(
+        db_pool: PgPool,
+        configure: impl FnOnce(&mut AppConfig),
+    ) -> Result<Self, Box<dyn std::error::Error>> {
         init_crypto_provider();
 
         let certs = TestCertificates::generate()?;
@@ -44,51 +54,136 @@ impl TestServer {
         let addr = std_listener.local_addr()?;
         std_listener.set_nonblocking(true)?;
 
-        let config = AppConfig {
+        let mut config = AppConfig {
             database_url: "unused_in_tests".to_string(),
             ingest_token: bearer_token.clone(),
             server_port: addr.port(),
-            cert_path: certs.cert_path.clone(),
-            key_path: certs.key_path.clone(),
+            cert_path: Some(certs.cert_path.clone()),
+            key_path: Some(certs.key_path.clone()),
             rate_limit_rps: 200,
+            ..AppConfig::default()
         };
+        configure(&mut config);
 
         let state = AppState::new(db_pool, &config);
 
-        let app = Router::new()
-            .route("/healthz", axum::routing::get(healthz))
-            .route("/ingest", axum::routing::post(ingest_dummy))
-            .route("/ingest-gottcha2", axum::routing::post(ingest_gottcha2))
-            .route("/ingest-stast", axum::routing::post(ingest_stast))
-            .with_state(state);
-
-        let tls_config = RustlsConfig::from_pem_file(&certs.cert_path, &certs.key_path).await?;
-
-        let handle = tokio::spawn(async move {
-            axum_server::from_tcp_rustls(std_listener, tls_config)
-                .serve(app.into_make_service())
-                .await
-                .expect("Server failed to start");
+        let app = build_router(state.clone(), &config)?;
+        let server_handle = axum_server::Handle::new();
+
+        // Bind the plaintext listener on an ephemeral port whenever one is requested
+        let http_listener = match config.http_port {
+            Some(_) => {
+                let listener = std::net::TcpListener::bind("127.0.0.1:0")?;
+                listener.set_nonblocking(true)?;
+                Some(listener)
+            }
+            None => None,
+        };
+        let http_base_url = http_listener
+            .as_ref()
+            .map(|listener| {
+                listener
+                    .local_addr()
+                    .map(|a| format!("http://localhost:{}", a.port()))
+            })
+            .transpose()?;
+        let http_handle = http_listener.map(|listener| {
+            let app = app.clone();
+            let mut server = axum_server::from_tcp(listener).handle(server_handle.clone());
+            config.limit_headers(&mut server);
+            tokio::spawn(async move {
+                server
+                    .serve(app.into_make_service_with_connect_info::<SocketAddr>())
+                    .await
+                    .expect("Plaintext server failed to start");
+            })
         });
 
+        let handle = if config.tls_enabled {
+            let tls_config = RustlsConfig::from_config(Arc::new(config.load_tls_config()?));
+
+            let max_handshakes = config.max_concurrent_handshakes;
+            let handshake_timeout = Duration::from_millis(config.handshake_queue_timeout_ms);
+
+            let mut tls_server = axum_server::from_tcp_rustls(std_listener, tls_config)
+                .handle(server_handle.clone());
+            config.limit_headers(&mut tls_server);
+            tokio::spawn(async move {
+                tls_server
+                    .map(|acceptor| {
+                        HandshakeLimitAcceptor::new(acceptor, max_handshakes, handshake_timeout)
+                    })
+                    .serve(app.into_make_service_with_connect_info::<SocketAddr>())
+                    .await
+                    .expect("Server failed to start");
+            })
+        } else {
+            let mut server = axum_server::from_tcp(std_listener).handle(server_handle.clone());
+            config.limit_headers(&mut server);
+            tokio::spawn(async move {
+                server
+                    .serve(app.into_make_service_with_connect_info::<SocketAddr>())
+                    .await
+                    .expect("Server failed to start");
+            })
+        };
+        let scheme = if config.tls_enabled { "https" } else { "http" };
+
         tokio::time::sleep(Duration::from_millis(200)).await;
 
         Ok(TestServer {
             addr,
-            base_url: format!("https://localhost:{}", addr.port()),
+            base_url: format!("{scheme}://localhost:{}", addr.port()),
+            http_base_url,
             bearer_token,
             certs,
             handle,
+            http_handle,
+            server_handle,
+            state,
         })
     }
 
+    pub fn state(&self) -> &AppState {
+        &self.state
+    }
+
+    /// Triggers the same graceful shutdown the server runs on SIGTERM.
+    pub fn shutdown(&self, grace: Duration) {
+        shutdown::begin(&self.server_handle, &self.state, grace);
+    }
+
+    /// Marks the server as draining without closing its listeners, as during
+    /// the window where a load balancer may still route new requests here.
+    pub fn begin_draining(&self, grace: Duration) {
+        self.state.begin_draining(grace);
+    }
+
+    /// Resolves once the main listener has fully stopped.
+    pub async fn wait_stopped(&mut self) {
+        (&mut self.handle).await.expect("Server task panicked");
+    }
+
     pub fn create_http_client(&self) -> Result<reqwest::Client, Box<dyn std::error::Error>> {
         self.certs.create_reqwest_client()
     }
+
+    pub fn create_raw_http_client(&self) -> Result<reqwest::Client, Box<dyn std::error::Error>> {
+        self.certs.create_raw_reqwest_client()
+    }
+
+    pub fn create_tls_client_config(
+        &self,
+    ) -> Result<Arc<rustls::ClientConfig>, Box<dyn std::error::Error>> {
+        self.certs.create_rustls_client_config()
+    }
 }
 
 impl Drop for TestServer {
     fn drop(&mut self) {
         self.handle.abort();
+        if let Some(handle) = &self.http_handle {
+            handle.abort();
+        }
     }
 }
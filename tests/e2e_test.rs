@@ -2,7 +2,10 @@ mod common;
 
 use common::{database::TestDatabase, server::TestServer};
 use flate2::{Compression, write::GzEncoder};
-use nvd_support_car::models::record::{Gottcha2FullRecord, StastRecord};
+use nvd_support_car::models::record::{
+    BulkInsertable, Gottcha2FullRecord, Kraken2Record, StastRecord,
+};
+use nvd_support_car::services::parsing::content_hash;
 use reqwest::StatusCode;
 use std::io::Write;
 
@@ -20,7 +23,7 @@ async fn test_e2e_gottcha2_ingestion_with_tls() {
 
     let records = vec![
         Gottcha2FullRecord {
-            sample_id: "e2e_test_sample_001".to_string(),
+            sample_id: "e2e_test_sample_001".parse().expect("Invalid sample id"),
             level: "phylum".to_string(),
             name: "Proteobacteria".to_string(),
             taxid: "1224".to_string(),
@@ -31,9 +34,13 @@ async fn test_e2e_gottcha2_ingestion_with_tls() {
             best_sig_cov: 0.85,
             depth: 10.5,
             rel_abundance: 0.25,
+            raw_line: None,
+            source_line: None,
+            content_hash: None,
+            observed_at: None,
         },
         Gottcha2FullRecord {
-            sample_id: "e2e_test_sample_001".to_string(),
+            sample_id: "e2e_test_sample_001".parse().expect("Invalid sample id"),
             level: "genus".to_string(),
             name: "Escherichia".to_string(),
             taxid: "561".to_string(),
@@ -44,6 +51,10 @@ async fn test_e2e_gottcha2_ingestion_with_tls() {
             best_sig_cov: 0.90,
             depth: 15.2,
             rel_abundance: 0.12,
+            raw_line: None,
+            source_line: None,
+            content_hash: None,
+            observed_at: None,
         },
     ];
 
@@ -70,10 +81,8 @@ async fn test_e2e_gottcha2_ingestion_with_tls() {
         .expect("Request failed");
 
     assert_eq!(response.status(), StatusCode::OK);
-    assert_eq!(
-        response.text().await.expect("Failed to read body"),
-        "ingested"
-    );
+    let body: serde_json::Value = response.json().await.expect("Failed to parse response");
+    assert_eq!(body, serde_json::json!({"inserted": 2}));
 
     let count = db
         .count_records("gottcha2_results")
@@ -104,7 +113,7 @@ async fn test_e2e_stast_ingestion_with_tls() {
 
     let records = vec![StastRecord {
         task: "megablast".to_string(),
-        sample_id: "stast_e2e_test_001".to_string(),
+        sample_id: "stast_e2e_test_001".parse().expect("Invalid sample id"),
         qseqid: "NODE_1_length_1000".to_string(),
         qlen: 1000,
         sseqid: "gi|123456|ref|NC_000001.1|".to_string(),
@@ -116,6 +125,10 @@ async fn test_e2e_stast_ingestion_with_tls() {
         sscinames: "Test virus".to_string(),
         staxids: "12345".to_string(),
         rank: "species:Test virus".to_string(),
+        raw_line: None,
+        source_line: None,
+        content_hash: None,
+        observed_at: None,
     }];
 
     let jsonl = records
@@ -149,6 +162,96 @@ async fn test_e2e_stast_ingestion_with_tls() {
     assert_eq!(count, 1);
 }
 
+#[tokio::test]
+async fn test_e2e_kraken2_ingestion_with_tls() {
+    let db = TestDatabase::new()
+        .await
+        .expect("Failed to create test database");
+    let server = TestServer::start_with_tls(db.pool.clone())
+        .await
+        .expect("Failed to start server");
+    let client = server
+        .create_http_client()
+        .expect("Failed to create client");
+
+    let kraken2_record = |rank_code: &str, taxid: &str, name: &str, clade_reads| Kraken2Record {
+        sample_id: "kraken2_e2e_test_001".parse().expect("Invalid sample id"),
+        percentage: 12.5,
+        clade_reads,
+        taxon_reads: clade_reads / 2,
+        rank_code: rank_code.to_string(),
+        taxid: taxid.to_string(),
+        name: name.to_string(),
+        raw_line: None,
+        source_line: None,
+        content_hash: None,
+        observed_at: None,
+    };
+    let records = vec![
+        kraken2_record("U", "0", "unclassified", 250),
+        kraken2_record("G", "561", "Escherichia", 1000),
+        kraken2_record("S1", "83333", "Escherichia coli K-12", 400),
+    ];
+
+    let jsonl = records
+        .iter()
+        .map(|r| serde_json::to_string(r).expect("Failed to serialize"))
+        .collect::<Vec<_>>()
+        .join("\n");
+
+    let mut encoder = GzEncoder::new(Vec::new(), Compression::default());
+    encoder
+        .write_all(jsonl.as_bytes())
+        .expect("Failed to write to encoder");
+    let compressed = encoder.finish().expect("Failed to finish compression");
+
+    let base_url = &server.base_url;
+    let response = client
+        .post(format!("{base_url}/ingest-kraken2"))
+        .header("Authorization", format!("Bearer {}", server.bearer_token))
+        .header("Content-Type", "application/gzip")
+        .body(compressed.clone())
+        .send()
+        .await
+        .expect("Request failed");
+
+    assert_eq!(response.status(), StatusCode::OK);
+    let body: serde_json::Value = response.json().await.expect("Failed to parse response");
+    assert_eq!(body, serde_json::json!({"inserted": 3}));
+
+    let (rank_code, clade_reads, taxon_reads): (String, i64, i64) = sqlx::query_as(
+        "SELECT rank_code, clade_reads, taxon_reads FROM kraken2_results \
+         WHERE sample_id = $1 AND taxid = $2",
+    )
+    .bind("kraken2_e2e_test_001")
+    .bind("83333")
+    .fetch_one(&db.pool)
+    .await
+    .expect("Failed to query");
+    assert_eq!(
+        (rank_code.as_str(), clade_reads, taxon_reads),
+        ("S1", 400, 200)
+    );
+
+    // A retried upload lists the same taxa, which are skipped
+    let response = client
+        .post(format!("{base_url}/ingest/kraken2"))
+        .header("Authorization", format!("Bearer {}", server.bearer_token))
+        .body(compressed)
+        .send()
+        .await
+        .expect("Request failed");
+    assert_eq!(response.status(), StatusCode::OK);
+    let body: serde_json::Value = response.json().await.expect("Failed to parse response");
+    assert_eq!(body, serde_json::json!({"inserted": 0}));
+
+    let count = db
+        .count_records("kraken2_results")
+        .await
+        .expect("Failed to count");
+    assert_eq!(count, 3, "Expected 3 records in database");
+}
+
 #[tokio::test]
 async fn test_e2e_authentication_over_tls() {
     let db = TestDatabase::new()
@@ -187,8 +290,8 @@ async fn test_e2e_authentication_over_tls() {
         .expect("Request failed");
     assert_eq!(
         response.status(),
-        StatusCode::OK,
-        "Invalid gzip currently succeeds with 0 records (known behavior)"
+        StatusCode::BAD_REQUEST,
+        "Invalid gzip should be rejected"
     );
 
     let count_after = db
@@ -218,7 +321,9 @@ async fn test_e2e_concurrent_ingestion() {
 
         let handle = tokio::spawn(async move {
             let record = Gottcha2FullRecord {
-                sample_id: format!("concurrent_sample_{i:03}"),
+                sample_id: format!("concurrent_sample_{i:03}")
+                    .parse()
+                    .expect("Invalid sample id"),
                 level: "species".to_string(),
                 name: format!("Species_{i}"),
                 taxid: format!("{}", 10000 + i),
@@ -229,6 +334,10 @@ async fn test_e2e_concurrent_ingestion() {
                 best_sig_cov: 0.85,
                 depth: 10.0,
                 rel_abundance: 0.1,
+                raw_line: None,
+                source_line: None,
+                content_hash: None,
+                observed_at: None,
             };
 
             let jsonl = serde_json::to_string(&record).expect("Failed to serialize");
@@ -325,8 +434,8 @@ async fn test_e2e_malformed_data_handling() {
         .expect("Request failed");
     assert_eq!(
         response.status(),
-        StatusCode::OK,
-        "Invalid gzip currently succeeds with 0 records"
+        StatusCode::BAD_REQUEST,
+        "Invalid gzip should be rejected"
     );
 
     let invalid_json = "{ invalid json }\nnot even json\n";
@@ -344,6 +453,11 @@ async fn test_e2e_malformed_data_handling() {
         .await
         .expect("Request failed");
     assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+    let message = response.text().await.expect("Failed to read body");
+    assert!(
+        message.starts_with("invalid json on line 1: "),
+        "The error should cite the bad line: {message}"
+    );
 }
 
 #[tokio::test]
@@ -399,7 +513,9 @@ async fn test_e2e_large_payload() {
     let mut records = Vec::new();
     for i in 0..100 {
         records.push(Gottcha2FullRecord {
-            sample_id: format!("large_payload_{i:03}"),
+            sample_id: format!("large_payload_{i:03}")
+                .parse()
+                .expect("Invalid sample id"),
             level: "species".to_string(),
             name: format!("Species_{i}"),
             taxid: format!("{}", 40000 + i),
@@ -410,6 +526,10 @@ async fn test_e2e_large_payload() {
             best_sig_cov: 0.85,
             depth: 10.0,
             rel_abundance: 0.1,
+            raw_line: None,
+            source_line: None,
+            content_hash: None,
+            observed_at: None,
         });
     }
 
@@ -488,8 +608,20 @@ async fn test_server_health_check_over_tls() {
 
     assert_eq!(response.status(), 200, "Health check should return 200");
 
+    let body: serde_json::Value = response.json().await.expect("Failed to parse health check");
+    assert_eq!(body["status"], "ok", "{body}");
+    assert_eq!(body["version"], env!("CARGO_PKG_VERSION"), "{body}");
+    assert!(body["uptime_secs"].is_u64(), "{body}");
+    assert!(body.get("git_commit").is_some(), "{body}");
+
+    let response = client
+        .get(format!("{base_url}/healthz?format=plain"))
+        .send()
+        .await
+        .expect("Plain health check request failed");
+    assert_eq!(response.status(), 200);
     let body = response.text().await.expect("Failed to read response body");
-    assert_eq!(body, "ok", "Health check should return 'ok'");
+    assert_eq!(body, "ok", "Plain health check should return 'ok'");
 }
 
 #[tokio::test]
@@ -630,3 +762,4534 @@ async fn test_tls_without_trusted_ca_fails() {
         );
     }
 }
+
+#[tokio::test]
+async fn test_e2e_pause_and_resume_ingestion() {
+    let db = TestDatabase::new()
+        .await
+        .expect("Failed to create test database");
+    let server = TestServer::start_with_tls_config(db.pool.clone(), |config| {
+        config.admin_token = Some("admin-secret".to_string());
+    })
+    .await
+    .expect("Failed to start server");
+    let client = server
+        .create_http_client()
+        .expect("Failed to create client");
+
+    let record = Gottcha2FullRecord {
+        sample_id: "pause_test_001".parse().expect("Invalid sample id"),
+        level: "species".to_string(),
+        name: "Test".to_string(),
+        taxid: "1".to_string(),
+        read_count: 100,
+        total_bp_mapped: 5000,
+        ani_ci95: 0.95,
+        covered_sig_len: 1000,
+        best_sig_cov: 0.85,
+        depth: 10.0,
+        rel_abundance: 0.1,
+        raw_line: None,
+        source_line: None,
+        content_hash: None,
+        observed_at: None,
+    };
+    let jsonl = serde_json::to_string(&record).expect("Failed to serialize");
+    let mut encoder = GzEncoder::new(Vec::new(), Compression::default());
+    encoder
+        .write_all(jsonl.as_bytes())
+        .expect("Failed to write");
+    let compressed = encoder.finish().expect("Failed to compress");
+
+    let base_url = &server.base_url;
+    let auth = format!("Bearer {}", server.bearer_token);
+    let admin_auth = "Bearer admin-secret";
+
+    let response = client
+        .post(format!("{base_url}/admin/pause"))
+        .send()
+        .await
+        .expect("Request failed");
+    assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+
+    // An ingest token can't stand in for the admin token
+    let response = client
+        .post(format!("{base_url}/admin/pause"))
+        .header("Authorization", &auth)
+        .send()
+        .await
+        .expect("Request failed");
+    assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+
+    let response = client
+        .post(format!("{base_url}/admin/pause"))
+        .header("Authorization", admin_auth)
+        .send()
+        .await
+        .expect("Request failed");
+    assert_eq!(response.status(), StatusCode::OK);
+
+    let response = client
+        .post(format!("{base_url}/ingest-gottcha2"))
+        .header("Authorization", &auth)
+        .body(compressed.clone())
+        .send()
+        .await
+        .expect("Request failed");
+    assert_eq!(response.status(), StatusCode::SERVICE_UNAVAILABLE);
+    let body: serde_json::Value = response.json().await.expect("Failed to read body");
+    assert_eq!(body["error"], "ingestion_paused");
+
+    let response = client
+        .get(format!("{base_url}/healthz"))
+        .send()
+        .await
+        .expect("Request failed");
+    assert_eq!(
+        response.status(),
+        StatusCode::OK,
+        "Health should be unaffected by pause"
+    );
+
+    let response = client
+        .post(format!("{base_url}/admin/resume"))
+        .header("Authorization", admin_auth)
+        .send()
+        .await
+        .expect("Request failed");
+    assert_eq!(response.status(), StatusCode::OK);
+
+    let response = client
+        .post(format!("{base_url}/ingest-gottcha2"))
+        .header("Authorization", &auth)
+        .body(compressed)
+        .send()
+        .await
+        .expect("Request failed");
+    assert_eq!(response.status(), StatusCode::OK);
+
+    let count = db
+        .count_records("gottcha2_results")
+        .await
+        .expect("Failed to count");
+    assert_eq!(count, 1, "Only the post-resume ingest should be stored");
+}
+
+#[tokio::test]
+async fn test_e2e_admin_endpoints_are_disabled_without_admin_token() {
+    let db = TestDatabase::new()
+        .await
+        .expect("Failed to create test database");
+    let server = TestServer::start_with_tls(db.pool.clone())
+        .await
+        .expect("Failed to start server");
+    let client = server
+        .create_http_client()
+        .expect("Failed to create client");
+
+    for route in ["pause", "resume"] {
+        let response = client
+            .post(format!("{}/admin/{route}", server.base_url))
+            .header("Authorization", format!("Bearer {}", server.bearer_token))
+            .send()
+            .await
+            .expect("Request failed");
+        assert_eq!(response.status(), StatusCode::NOT_FOUND, "{route}");
+    }
+}
+
+fn gzip_jsonl<T: serde::Serialize>(records: &[T]) -> Vec<u8> {
+    let jsonl = records
+        .iter()
+        .map(|r| serde_json::to_string(r).expect("Failed to serialize"))
+        .collect::<Vec<_>>()
+        .join("\n");
+    let mut encoder = GzEncoder::new(Vec::new(), Compression::default());
+    encoder
+        .write_all(jsonl.as_bytes())
+        .expect("Failed to write");
+    encoder.finish().expect("Failed to compress")
+}
+
+fn gottcha2_record(sample_id: &str, level: &str, taxid: &str) -> Gottcha2FullRecord {
+    Gottcha2FullRecord {
+        sample_id: sample_id.parse().expect("Invalid sample id"),
+        level: level.to_string(),
+        name: format!("Taxon_{taxid}"),
+        taxid: taxid.to_string(),
+        read_count: 100,
+        total_bp_mapped: 5000,
+        ani_ci95: 0.95,
+        covered_sig_len: 1000,
+        best_sig_cov: 0.85,
+        depth: 10.0,
+        rel_abundance: 0.1,
+        raw_line: None,
+        source_line: None,
+        content_hash: None,
+        observed_at: None,
+    }
+}
+
+/// The stored GOTTCHA2 rows, less their ids and ingest times, in taxid order.
+async fn stored_gottcha2_rows(db: &TestDatabase) -> serde_json::Value {
+    sqlx::query_scalar(
+        "SELECT jsonb_agg(to_jsonb(g) - 'id' - 'created_at' ORDER BY taxid, level) \
+         FROM gottcha2_results g",
+    )
+    .fetch_one(&db.pool)
+    .await
+    .expect("Failed to fetch rows")
+}
+
+#[tokio::test]
+async fn test_e2e_gottcha2_tsv_ingests_the_same_rows_as_jsonl() {
+    let db = TestDatabase::new()
+        .await
+        .expect("Failed to create test database");
+    let server = TestServer::start_with_tls(db.pool.clone())
+        .await
+        .expect("Failed to start server");
+    let client = server
+        .create_http_client()
+        .expect("Failed to create client");
+    let base_url = &server.base_url;
+    let auth = format!("Bearer {}", server.bearer_token);
+
+    let records = vec![
+        gottcha2_record("tsv_sample", "species", "1"),
+        gottcha2_record("tsv_sample", "genus", "2"),
+        gottcha2_record("tsv_sample", "family", "3"),
+    ];
+    let tsv = records.iter().fold(
+        "SAMPLE_ID\tLEVEL\tNAME\tTAXID\tREAD_COUNT\tTOTAL_BP_MAPPED\tANI_CI95\t\
+         COVERED_SIG_LEN\tBEST_SIG_COV\tDEPTH\tREL_ABUNDANCE\n"
+            .to_string(),
+        |tsv, r| {
+            tsv + &format!(
+                "{}\t{}\t{}\t{}\t{}\t{}\t{}\t{}\t{}\t{}\t{}\n",
+                r.sample_id,
+                r.level,
+                r.name,
+                r.taxid,
+                r.read_count,
+                r.total_bp_mapped,
+                r.ani_ci95,
+                r.covered_sig_len,
+                r.best_sig_cov,
+                r.depth,
+                r.rel_abundance
+            )
+        },
+    );
+    let mut encoder = GzEncoder::new(Vec::new(), Compression::default());
+    encoder
+        .write_all(tsv.as_bytes())
+        .expect("Failed to write to encoder");
+    let gzipped_tsv = encoder.finish().expect("Failed to finish compression");
+
+    let response = client
+        .post(format!("{base_url}/ingest-gottcha2"))
+        .header("Authorization", &auth)
+        .header("Content-Type", "application/gzip")
+        .body(gzip_jsonl(&records))
+        .send()
+        .await
+        .expect("Request failed");
+    assert_eq!(response.status(), StatusCode::OK);
+    let from_jsonl = stored_gottcha2_rows(&db).await;
+    assert_eq!(from_jsonl.as_array().map(Vec::len), Some(3), "{from_jsonl}");
+
+    let purge = || {
+        client
+            .delete(format!("{base_url}/samples/tsv_sample"))
+            .header("Authorization", &auth)
+            .send()
+    };
+
+    // Selected by Content-Type, compression sniffed
+    purge().await.expect("Purge failed");
+    let response = client
+        .post(format!("{base_url}/ingest-gottcha2"))
+        .header("Authorization", &auth)
+        .header("Content-Type", "text/tab-separated-values")
+        .body(gzipped_tsv)
+        .send()
+        .await
+        .expect("Request failed");
+    assert_eq!(response.status(), StatusCode::OK);
+    let body: serde_json::Value = response.json().await.expect("Failed to parse response");
+    assert_eq!(body["inserted"], 3, "{body}");
+    assert_eq!(stored_gottcha2_rows(&db).await, from_jsonl);
+
+    // Selected by ?format=, uncompressed
+    purge().await.expect("Purge failed");
+    let response = client
+        .post(format!("{base_url}/ingest-gottcha2?format=tsv"))
+        .header("Authorization", &auth)
+        .body(tsv)
+        .send()
+        .await
+        .expect("Request failed");
+    assert_eq!(response.status(), StatusCode::OK);
+    assert_eq!(stored_gottcha2_rows(&db).await, from_jsonl);
+}
+
+#[tokio::test]
+async fn test_e2e_gottcha2_count_with_filters() {
+    let db = TestDatabase::new()
+        .await
+        .expect("Failed to create test database");
+    let server = TestServer::start_with_tls(db.pool.clone())
+        .await
+        .expect("Failed to start server");
+    let client = server
+        .create_http_client()
+        .expect("Failed to create client");
+
+    let records = vec![
+        gottcha2_record("count_a", "genus", "561"),
+        gottcha2_record("count_a", "species", "562"),
+        gottcha2_record("count_a", "species", "563"),
+        gottcha2_record("count_b", "genus", "561"),
+    ];
+
+    let base_url = &server.base_url;
+    let auth = format!("Bearer {}", server.bearer_token);
+    let response = client
+        .post(format!("{base_url}/ingest-gottcha2"))
+        .header("Authorization", &auth)
+        .body(gzip_jsonl(&records))
+        .send()
+        .await
+        .expect("Request failed");
+    assert_eq!(response.status(), StatusCode::OK);
+
+    for (query, expected) in [
+        ("", 4),
+        ("?level=genus", 2),
+        ("?sample_id=count_a", 3),
+        ("?sample_id=count_a&level=species", 2),
+        ("?sample_id=count_b&level=species", 0),
+    ] {
+        let response = client
+            .get(format!("{base_url}/gottcha2/count{query}"))
+            .header("Authorization", &auth)
+            .send()
+            .await
+            .expect("Request failed");
+        assert_eq!(response.status(), StatusCode::OK, "query {query:?}");
+        let body: serde_json::Value = response.json().await.expect("Failed to read body");
+        assert_eq!(body["count"], expected, "query {query:?}");
+    }
+
+    let response = client
+        .get(format!("{base_url}/gottcha2/count?read_count=1"))
+        .header("Authorization", &auth)
+        .send()
+        .await
+        .expect("Request failed");
+    assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+
+    let response = client
+        .get(format!("{base_url}/gottcha2/count?level=genus"))
+        .send()
+        .await
+        .expect("Request failed");
+    assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+}
+
+fn stast_record(qseqid: &str, bitscore: f64, evalue: f64) -> StastRecord {
+    StastRecord {
+        task: "megablast".to_string(),
+        sample_id: "stast_filter_test".parse().expect("Invalid sample id"),
+        qseqid: qseqid.to_string(),
+        qlen: 1000,
+        sseqid: "gi|123456|ref|NC_000001.1|".to_string(),
+        stitle: "Test virus genome".to_string(),
+        length: 950,
+        pident: 99.5,
+        evalue,
+        bitscore,
+        sscinames: "Test virus".to_string(),
+        staxids: "12345".to_string(),
+        rank: "species:Test virus".to_string(),
+        raw_line: None,
+        source_line: None,
+        content_hash: None,
+        observed_at: None,
+    }
+}
+
+#[tokio::test]
+async fn test_e2e_stast_rejects_out_of_range_hits() {
+    let db = TestDatabase::new()
+        .await
+        .expect("Failed to create test database");
+    let server = TestServer::start_with_tls(db.pool.clone())
+        .await
+        .expect("Failed to start server");
+    let client = server
+        .create_http_client()
+        .expect("Failed to create client");
+
+    let records = vec![
+        stast_record("NODE_1", 100.0, 1e-10),
+        StastRecord {
+            pident: 150.0,
+            ..stast_record("NODE_2", 100.0, 1e-10)
+        },
+    ];
+    let response = client
+        .post(format!("{}/ingest-stast", server.base_url))
+        .header("Authorization", format!("Bearer {}", server.bearer_token))
+        .body(gzip_jsonl(&records))
+        .send()
+        .await
+        .expect("Failed to send request");
+
+    assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+    let body: serde_json::Value = response.json().await.expect("Failed to parse response");
+    assert_eq!(body["line"], 2);
+    assert_eq!(body["field"], "pident");
+    assert_eq!(body["constraint"], "0..=100");
+}
+
+#[tokio::test]
+async fn test_e2e_stast_score_filters() {
+    let db = TestDatabase::new()
+        .await
+        .expect("Failed to create test database");
+    let server = TestServer::start_with_tls(db.pool.clone())
+        .await
+        .expect("Failed to start server");
+    let client = server
+        .create_http_client()
+        .expect("Failed to create client");
+
+    let records = vec![
+        stast_record("high", 1800.0, 0.0),
+        stast_record("at_threshold", 100.0, 1e-5),
+        stast_record("low_score", 99.9, 1e-10),
+        stast_record("high_evalue", 500.0, 0.01),
+    ];
+
+    let base_url = &server.base_url;
+    let response = client
+        .post(format!(
+            "{base_url}/ingest-stast?min_bitscore=100&max_evalue=0.00001"
+        ))
+        .header("Authorization", format!("Bearer {}", server.bearer_token))
+        .body(gzip_jsonl(&records))
+        .send()
+        .await
+        .expect("Request failed");
+
+    assert_eq!(response.status(), StatusCode::OK);
+    let body: serde_json::Value = response.json().await.expect("Failed to read body");
+    assert_eq!(body["inserted"], 2);
+    assert_eq!(body["filtered"], 2);
+
+    let stored: Vec<String> =
+        sqlx::query_scalar("SELECT qseqid FROM stast_results ORDER BY qseqid")
+            .fetch_all(&db.pool)
+            .await
+            .expect("Failed to query");
+    assert_eq!(stored, vec!["at_threshold", "high"]);
+}
+
+#[tokio::test]
+async fn test_e2e_stast_echoes_distinct_taxids() {
+    let db = TestDatabase::new()
+        .await
+        .expect("Failed to create test database");
+    let server = TestServer::start_with_tls(db.pool.clone())
+        .await
+        .expect("Failed to start server");
+    let client = server
+        .create_http_client()
+        .expect("Failed to create client");
+
+    let records: Vec<StastRecord> = [
+        ("hit1", "10244", 500.0),
+        ("hit2", "10244;10245", 500.0),
+        ("hit3", "2697049", 500.0),
+        ("filtered_out", "99999", 10.0),
+    ]
+    .into_iter()
+    .map(|(qseqid, staxids, bitscore)| StastRecord {
+        staxids: staxids.to_string(),
+        ..stast_record(qseqid, bitscore, 0.0)
+    })
+    .collect();
+
+    let base_url = &server.base_url;
+    let response = client
+        .post(format!(
+            "{base_url}/ingest-stast?distinct_taxids=true&min_bitscore=100"
+        ))
+        .header("Authorization", format!("Bearer {}", server.bearer_token))
+        .body(gzip_jsonl(&records))
+        .send()
+        .await
+        .expect("Request failed");
+
+    assert_eq!(response.status(), StatusCode::OK);
+    let body: serde_json::Value = response.json().await.expect("Failed to read body");
+    assert_eq!(body["inserted"], 3);
+    assert_eq!(
+        body["distinct_taxids"],
+        serde_json::json!(["10244", "10245", "2697049"])
+    );
+    assert!(body.get("distinct_taxids_truncated").is_none());
+
+    // Without the flag the response is unchanged
+    let response = client
+        .post(format!("{base_url}/ingest-stast"))
+        .header("Authorization", format!("Bearer {}", server.bearer_token))
+        .body(gzip_jsonl(&records))
+        .send()
+        .await
+        .expect("Request failed");
+    let body: serde_json::Value = response.json().await.expect("Failed to read body");
+    assert!(body.get("distinct_taxids").is_none());
+}
+
+#[tokio::test]
+async fn test_e2e_insert_failure_reports_database_error() {
+    let db = TestDatabase::new()
+        .await
+        .expect("Failed to create test database");
+    let server = TestServer::start_with_tls(db.pool.clone())
+        .await
+        .expect("Failed to start server");
+    let client = server
+        .create_http_client()
+        .expect("Failed to create client");
+
+    // Same (run_id, task_id, shard) primary key under different idempotency
+    // keys, so ON CONFLICT (idempotency_key) doesn't absorb the collision
+    let records: Vec<serde_json::Value> = (0..2)
+        .map(|i| {
+            serde_json::json!({
+                "run_id": "run_1",
+                "task_id": "task_1",
+                "shard": 0,
+                "idempotency_key": format!("key_{i}"),
+                "schema_version": 1,
+                "payload": {}
+            })
+        })
+        .collect();
+
+    let base_url = &server.base_url;
+    let response = client
+        .post(format!("{base_url}/ingest"))
+        .header("Authorization", format!("Bearer {}", server.bearer_token))
+        .body(gzip_jsonl(&records))
+        .send()
+        .await
+        .expect("Request failed");
+
+    assert_eq!(response.status(), StatusCode::CONFLICT);
+    let body = response.text().await.expect("Failed to read body");
+    assert!(
+        body.contains("duplicate key"),
+        "Response should carry the database error, got: {body}"
+    );
+    assert!(!body.contains("channel closed"));
+}
+
+#[tokio::test]
+async fn test_e2e_handshake_limit_keeps_server_responsive() {
+    let db = TestDatabase::new()
+        .await
+        .expect("Failed to create test database");
+    let server = TestServer::start_with_tls_config(db.pool.clone(), |config| {
+        config.max_concurrent_handshakes = 2;
+        config.handshake_queue_timeout_ms = 5000;
+    })
+    .await
+    .expect("Failed to start server");
+
+    let mut handles = vec![];
+    for _ in 0..40 {
+        // A fresh client per request forces a new connection and handshake
+        let client = server
+            .create_http_client()
+            .expect("Failed to create client");
+        let url = format!("{}/healthz", server.base_url);
+        handles.push(tokio::spawn(async move {
+            client
+                .get(&url)
+                .send()
+                .await
+                .map(|response| response.status())
+        }));
+    }
+
+    for handle in handles {
+        let status = handle
+            .await
+            .expect("Task panicked")
+            .expect("Queued handshake should eventually complete");
+        assert_eq!(status, StatusCode::OK);
+    }
+
+    let client = server
+        .create_http_client()
+        .expect("Failed to create client");
+    let response = client
+        .get(format!("{}/healthz", server.base_url))
+        .send()
+        .await
+        .expect("Server should stay responsive after the burst");
+    assert_eq!(response.status(), StatusCode::OK);
+}
+
+#[tokio::test]
+async fn test_e2e_strict_levels_accept_known_level() {
+    let db = TestDatabase::new()
+        .await
+        .expect("Failed to create test database");
+    let server = TestServer::start_with_tls_config(db.pool.clone(), |config| {
+        config.strict_taxonomic_levels = true;
+    })
+    .await
+    .expect("Failed to start server");
+    let client = server
+        .create_http_client()
+        .expect("Failed to create client");
+
+    let records = vec![
+        gottcha2_record("strict_ok", "genus", "561"),
+        gottcha2_record("strict_ok", "species", "562"),
+    ];
+
+    let response = client
+        .post(format!("{}/ingest-gottcha2", server.base_url))
+        .header("Authorization", format!("Bearer {}", server.bearer_token))
+        .body(gzip_jsonl(&records))
+        .send()
+        .await
+        .expect("Request failed");
+
+    assert_eq!(response.status(), StatusCode::OK);
+    let count = db
+        .count_records("gottcha2_results")
+        .await
+        .expect("Failed to count");
+    assert_eq!(count, 2);
+}
+
+#[tokio::test]
+async fn test_e2e_strict_levels_reject_typo() {
+    let db = TestDatabase::new()
+        .await
+        .expect("Failed to create test database");
+    let server = TestServer::start_with_tls_config(db.pool.clone(), |config| {
+        config.strict_taxonomic_levels = true;
+    })
+    .await
+    .expect("Failed to start server");
+    let client = server
+        .create_http_client()
+        .expect("Failed to create client");
+
+    let records = vec![
+        gottcha2_record("strict_typo", "genus", "561"),
+        gottcha2_record("strict_typo", "spesies", "562"),
+    ];
+
+    let response = client
+        .post(format!("{}/ingest-gottcha2", server.base_url))
+        .header("Authorization", format!("Bearer {}", server.bearer_token))
+        .body(gzip_jsonl(&records))
+        .send()
+        .await
+        .expect("Request failed");
+
+    assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+    let body = response.text().await.expect("Failed to read body");
+    assert!(
+        body.contains("line 2"),
+        "Error should cite the line: {body}"
+    );
+    assert!(
+        body.contains("spesies"),
+        "Error should cite the value: {body}"
+    );
+}
+
+fn dummy_record(key: &str, shard: i32) -> serde_json::Value {
+    serde_json::json!({
+        "run_id": "dedup_run",
+        "task_id": "dedup_task",
+        "shard": shard,
+        "idempotency_key": key,
+        "schema_version": 1,
+        "payload": {"shard": shard}
+    })
+}
+
+#[tokio::test]
+async fn test_e2e_dummy_ingest_reports_dedup_stats() {
+    let db = TestDatabase::new()
+        .await
+        .expect("Failed to create test database");
+    let server = TestServer::start_with_tls(db.pool.clone())
+        .await
+        .expect("Failed to start server");
+    let client = server
+        .create_http_client()
+        .expect("Failed to create client");
+
+    let first: Vec<_> = (0..3).map(|i| dummy_record(&format!("k{i}"), i)).collect();
+    let second: Vec<_> = (1..5).map(|i| dummy_record(&format!("k{i}"), i)).collect();
+
+    let url = format!("{}/ingest", server.base_url);
+    let auth = format!("Bearer {}", server.bearer_token);
+
+    let response = client
+        .post(&url)
+        .header("Authorization", &auth)
+        .body(gzip_jsonl(&first))
+        .send()
+        .await
+        .expect("Request failed");
+    assert_eq!(response.status(), StatusCode::OK);
+    let body: serde_json::Value = response.json().await.expect("Failed to read body");
+    assert_eq!(
+        body,
+        serde_json::json!({"received": 3, "inserted": 3, "deduplicated": 0})
+    );
+
+    let response = client
+        .post(&url)
+        .header("Authorization", &auth)
+        .body(gzip_jsonl(&second))
+        .send()
+        .await
+        .expect("Request failed");
+    assert_eq!(response.status(), StatusCode::OK);
+    let body: serde_json::Value = response.json().await.expect("Failed to read body");
+    assert_eq!(
+        body,
+        serde_json::json!({"received": 4, "inserted": 2, "deduplicated": 2})
+    );
+
+    // A declared record count is checked as on the other ingest routes
+    let response = client
+        .post(&url)
+        .header("Authorization", &auth)
+        .header("X-Expected-Records", "5")
+        .body(gzip_jsonl(&second))
+        .send()
+        .await
+        .expect("Request failed");
+    assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+
+    let count = db.count_records("results").await.expect("Failed to count");
+    assert_eq!(count, 5);
+}
+
+#[tokio::test]
+async fn test_e2e_healthz_is_never_rate_limited() {
+    let db = TestDatabase::new()
+        .await
+        .expect("Failed to create test database");
+    let server = TestServer::start_with_tls_config(db.pool.clone(), |config| {
+        config.rate_limit_rps = 1;
+        config.rate_limit_burst = Some(3);
+    })
+    .await
+    .expect("Failed to start server");
+    let client = server
+        .create_http_client()
+        .expect("Failed to create client");
+
+    // Well past the governor's burst of 3
+    for _ in 0..50 {
+        let response = client
+            .get(format!("{}/healthz", server.base_url))
+            .send()
+            .await
+            .expect("Failed to send request");
+        assert_eq!(response.status(), StatusCode::OK);
+    }
+
+    // The same volume against a governed route does get throttled; at 1 rps
+    // nothing replenishes in time
+    let mut throttled = 0;
+    for _ in 0..50 {
+        let response = client
+            .get(format!("{}/gottcha2/count", server.base_url))
+            .send()
+            .await
+            .expect("Failed to send request");
+        if response.status() == StatusCode::TOO_MANY_REQUESTS {
+            throttled += 1;
+        }
+    }
+    assert!(
+        throttled > 0,
+        "Governed routes should still be rate-limited"
+    );
+}
+
+#[tokio::test]
+async fn test_e2e_configured_rate_limit_is_enforced() {
+    let db = TestDatabase::new()
+        .await
+        .expect("Failed to create test database");
+    let server = TestServer::start_with_tls_config(db.pool.clone(), |config| {
+        config.rate_limit_rps = 1;
+        config.rate_limit_burst = Some(3);
+    })
+    .await
+    .expect("Failed to start server");
+    let client = server
+        .create_http_client()
+        .expect("Failed to create client");
+
+    let mut statuses = Vec::new();
+    for _ in 0..5 {
+        let response = client
+            .get(format!("{}/gottcha2/count", server.base_url))
+            .send()
+            .await
+            .expect("Failed to send request");
+        statuses.push(response.status());
+    }
+
+    // The burst of 3 is spent at once; at 1 rps nothing replenishes in time
+    assert!(
+        statuses[..3]
+            .iter()
+            .all(|status| *status != StatusCode::TOO_MANY_REQUESTS)
+    );
+    assert_eq!(statuses[3..], [StatusCode::TOO_MANY_REQUESTS; 2]);
+}
+
+#[tokio::test]
+async fn test_e2e_injected_insert_failure_is_transient() {
+    let db = TestDatabase::new()
+        .await
+        .expect("Failed to create test database");
+    let server = TestServer::start_with_tls_config(db.pool.clone(), |config| {
+        config.fault_injection = true;
+        config.fault_failure_rate = 1.0;
+    })
+    .await
+    .expect("Failed to start server");
+    let client = server
+        .create_http_client()
+        .expect("Failed to create client");
+
+    let body = gzip_jsonl(&[gottcha2_record("faulty", "genus", "561")]);
+    let response = client
+        .post(format!("{}/ingest-gottcha2", server.base_url))
+        .header("Authorization", format!("Bearer {}", server.bearer_token))
+        .body(body)
+        .send()
+        .await
+        .expect("Failed to send request");
+
+    // Injected failures surface as retryable, not as a server bug
+    assert_eq!(response.status(), StatusCode::SERVICE_UNAVAILABLE);
+
+    let count: i64 =
+        sqlx::query_scalar("SELECT COUNT(*) FROM gottcha2_results WHERE sample_id = 'faulty'")
+            .fetch_one(&db.pool)
+            .await
+            .expect("Failed to count records");
+    assert_eq!(count, 0);
+}
+
+#[tokio::test]
+async fn test_e2e_injected_insert_failures_are_retried() {
+    let db = TestDatabase::new()
+        .await
+        .expect("Failed to create test database");
+    // Every attempt rolls again, so with this many retries a batch failing
+    // half its attempts still goes in; without retries most uploads wouldn't
+    let server = TestServer::start_with_tls_config(db.pool.clone(), |config| {
+        config.fault_injection = true;
+        config.fault_failure_rate = 0.5;
+        config.insert_max_retries = 20;
+        config.gottcha2_batch_size = Some(1);
+    })
+    .await
+    .expect("Failed to start server");
+    let client = server
+        .create_http_client()
+        .expect("Failed to create client");
+
+    let records: Vec<_> = (0..8)
+        .map(|i| gottcha2_record("flaky", "species", &i.to_string()))
+        .collect();
+    let response = client
+        .post(format!("{}/ingest-gottcha2", server.base_url))
+        .header("Authorization", format!("Bearer {}", server.bearer_token))
+        .body(gzip_jsonl(&records))
+        .send()
+        .await
+        .expect("Failed to send request");
+    assert_eq!(response.status(), StatusCode::OK);
+    assert_eq!(count_sample_rows(&db, "flaky").await, 8);
+}
+
+#[tokio::test]
+async fn test_e2e_gottcha2_breakdown_by_sample() {
+    let db = TestDatabase::new()
+        .await
+        .expect("Failed to create test database");
+    let server = TestServer::start_with_tls(db.pool.clone())
+        .await
+        .expect("Failed to start server");
+    let client = server
+        .create_http_client()
+        .expect("Failed to create client");
+
+    let mut records: Vec<_> = (0..3)
+        .map(|i| gottcha2_record("SRR123", "species", &format!("{}", 100 + i)))
+        .collect();
+    records.push(gottcha2_record("SRR124", "genus", "561"));
+    let url = format!("{}/ingest-gottcha2", server.base_url);
+    let auth = format!("Bearer {}", server.bearer_token);
+
+    let response = client
+        .post(format!("{url}?breakdown=true"))
+        .header("Authorization", &auth)
+        .body(gzip_jsonl(&records))
+        .send()
+        .await
+        .expect("Failed to send request");
+    assert_eq!(response.status(), StatusCode::OK);
+    let body: serde_json::Value = response.json().await.expect("Failed to parse response");
+    assert_eq!(
+        body,
+        serde_json::json!({"inserted": 4, "by_sample": {"SRR123": 3, "SRR124": 1}})
+    );
+
+    // Without the flag only the count is reported
+    let response = client
+        .post(&url)
+        .header("Authorization", &auth)
+        .body(gzip_jsonl(&[gottcha2_record("SRR125", "genus", "561")]))
+        .send()
+        .await
+        .expect("Failed to send request");
+    assert_eq!(response.status(), StatusCode::OK);
+    let body: serde_json::Value = response.json().await.expect("Failed to parse response");
+    assert_eq!(body, serde_json::json!({"inserted": 1}));
+}
+
+#[tokio::test]
+async fn test_e2e_expired_idempotency_key_is_processed_fresh() {
+    let db = TestDatabase::new()
+        .await
+        .expect("Failed to create test database");
+    let server = TestServer::start_with_tls_config(db.pool.clone(), |config| {
+        config.idempotency_ttl_secs = Some(1);
+    })
+    .await
+    .expect("Failed to start server");
+    let client = server
+        .create_http_client()
+        .expect("Failed to create client");
+
+    let url = format!("{}/ingest", server.base_url);
+    let auth = format!("Bearer {}", server.bearer_token);
+    let send = |body: Vec<u8>| {
+        client
+            .post(&url)
+            .header("Authorization", &auth)
+            .body(body)
+            .send()
+    };
+
+    let body = gzip_jsonl(&[dummy_record("ttl_key", 0)]);
+    let first: serde_json::Value = send(body.clone())
+        .await
+        .expect("Failed to send request")
+        .json()
+        .await
+        .expect("Failed to parse response");
+    assert_eq!(first["inserted"], 1);
+
+    // An immediate replay is still deduplicated
+    let replay: serde_json::Value = send(body.clone())
+        .await
+        .expect("Failed to send request")
+        .json()
+        .await
+        .expect("Failed to parse response");
+    assert_eq!(replay["deduplicated"], 1);
+
+    tokio::time::sleep(std::time::Duration::from_millis(1500)).await;
+
+    let late: serde_json::Value = send(body)
+        .await
+        .expect("Failed to send request")
+        .json()
+        .await
+        .expect("Failed to parse response");
+    assert_eq!(late["inserted"], 1);
+    assert_eq!(late["deduplicated"], 0);
+}
+
+#[tokio::test]
+async fn test_e2e_tls_and_plaintext_listeners_share_router() {
+    let db = TestDatabase::new()
+        .await
+        .expect("Failed to create test database");
+    let server = TestServer::start_with_tls_config(db.pool.clone(), |config| {
+        config.http_port = Some(0);
+    })
+    .await
+    .expect("Failed to start server");
+
+    let tls_client = server
+        .create_http_client()
+        .expect("Failed to create client");
+    let response = tls_client
+        .get(format!("{}/healthz", server.base_url))
+        .send()
+        .await
+        .expect("Failed to reach TLS listener");
+    assert_eq!(response.status(), StatusCode::OK);
+
+    let http_base_url = server
+        .http_base_url
+        .as_ref()
+        .expect("Plaintext listener should be running");
+    let response = reqwest::Client::new()
+        .get(format!("{http_base_url}/healthz?format=plain"))
+        .send()
+        .await
+        .expect("Failed to reach plaintext listener");
+    assert_eq!(response.status(), StatusCode::OK);
+    assert_eq!(response.text().await.expect("Failed to read body"), "ok");
+}
+
+#[tokio::test]
+async fn test_e2e_tls_disabled_serves_plaintext_without_certificates() {
+    let db = TestDatabase::new()
+        .await
+        .expect("Failed to create test database");
+    let server = TestServer::start_with_tls_config(db.pool.clone(), |config| {
+        config.tls_enabled = false;
+        config.cert_path = None;
+        config.key_path = None;
+    })
+    .await
+    .expect("Failed to start server");
+    assert!(
+        server.base_url.starts_with("http://"),
+        "{}",
+        server.base_url
+    );
+
+    let client = reqwest::Client::new();
+    let response = client
+        .get(format!("{}/healthz?format=plain", server.base_url))
+        .send()
+        .await
+        .expect("Failed to reach plaintext listener");
+    assert_eq!(response.status(), StatusCode::OK);
+    assert_eq!(response.text().await.expect("Failed to read body"), "ok");
+
+    let response = client
+        .post(format!("{}/ingest-gottcha2", server.base_url))
+        .header("Authorization", format!("Bearer {}", server.bearer_token))
+        .body(gzip_jsonl(&[gottcha2_record(
+            "plaintext",
+            "species",
+            "562",
+        )]))
+        .send()
+        .await
+        .expect("Failed to send request");
+    assert_eq!(response.status(), StatusCode::OK);
+}
+
+#[tokio::test]
+async fn test_e2e_out_of_range_field_reports_structured_error() {
+    let db = TestDatabase::new()
+        .await
+        .expect("Failed to create test database");
+    let server = TestServer::start_with_tls(db.pool.clone())
+        .await
+        .expect("Failed to start server");
+    let client = server
+        .create_http_client()
+        .expect("Failed to create client");
+
+    let mut bad = gottcha2_record("ranges", "genus", "561");
+    bad.rel_abundance = 1.5;
+    let records = vec![gottcha2_record("ranges", "species", "562"), bad];
+
+    let response = client
+        .post(format!("{}/ingest-gottcha2", server.base_url))
+        .header("Authorization", format!("Bearer {}", server.bearer_token))
+        .body(gzip_jsonl(&records))
+        .send()
+        .await
+        .expect("Failed to send request");
+
+    assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+    let body: serde_json::Value = response.json().await.expect("Failed to parse response");
+    assert_eq!(
+        body,
+        serde_json::json!({
+            "line": 2,
+            "field": "rel_abundance",
+            "value": 1.5,
+            "constraint": "0..=1"
+        })
+    );
+}
+
+#[tokio::test]
+async fn test_e2e_store_raw_line_preserves_input() {
+    let db = TestDatabase::new()
+        .await
+        .expect("Failed to create test database");
+    let server = TestServer::start_with_tls_config(db.pool.clone(), |config| {
+        config.store_raw_line = true;
+    })
+    .await
+    .expect("Failed to start server");
+    let client = server
+        .create_http_client()
+        .expect("Failed to create client");
+
+    // Unusual spacing, key order, and an unknown field are all kept verbatim
+    let raw = r#"{ "taxid":"561", "sample_id":"raw_sample","level":"genus","name":"Escherichia","read_count":5,"total_bp_mapped":500,"ani_ci95":0.9,"covered_sig_len":50,"best_sig_cov":0.5,"depth":1.5, "rel_abundance":0.01, "extra":"ignored" }"#;
+    let mut encoder = GzEncoder::new(Vec::new(), Compression::default());
+    encoder
+        .write_all(format!("{raw}\n").as_bytes())
+        .expect("Failed to write to encoder");
+    let body = encoder.finish().expect("Failed to finish compression");
+
+    let response = client
+        .post(format!("{}/ingest-gottcha2", server.base_url))
+        .header("Authorization", format!("Bearer {}", server.bearer_token))
+        .body(body)
+        .send()
+        .await
+        .expect("Failed to send request");
+    assert_eq!(response.status(), StatusCode::OK);
+
+    let stored: Option<String> =
+        sqlx::query_scalar("SELECT raw_line FROM gottcha2_results WHERE sample_id = 'raw_sample'")
+            .fetch_one(&db.pool)
+            .await
+            .expect("Failed to read raw_line");
+    assert_eq!(stored.as_deref(), Some(raw));
+}
+
+#[tokio::test]
+async fn test_e2e_shutdown_drains_in_flight_ingest() {
+    let db = TestDatabase::new()
+        .await
+        .expect("Failed to create test database");
+    // Stall every insert so the upload is still in flight when shutdown starts
+    let mut server = TestServer::start_with_tls_config(db.pool.clone(), |config| {
+        config.fault_injection = true;
+        config.fault_delay_rate = 1.0;
+        config.fault_delay_ms = 1000;
+    })
+    .await
+    .expect("Failed to start server");
+    let client = server
+        .create_http_client()
+        .expect("Failed to create client");
+
+    let records: Vec<_> = (0..50)
+        .map(|i| gottcha2_record("draining", "species", &format!("{i}")))
+        .collect();
+    let request = client
+        .post(format!("{}/ingest-gottcha2", server.base_url))
+        .header("Authorization", format!("Bearer {}", server.bearer_token))
+        .body(gzip_jsonl(&records))
+        .send();
+    let in_flight = tokio::spawn(request);
+
+    tokio::time::sleep(std::time::Duration::from_millis(300)).await;
+    server.shutdown(std::time::Duration::from_secs(10));
+
+    let response = in_flight
+        .await
+        .expect("Request task panicked")
+        .expect("In-flight request should complete during shutdown");
+    assert_eq!(response.status(), StatusCode::OK);
+
+    // The listener stops once the drained request has finished
+    tokio::time::timeout(std::time::Duration::from_secs(5), server.wait_stopped())
+        .await
+        .expect("Server should stop after draining");
+
+    let count: i64 =
+        sqlx::query_scalar("SELECT COUNT(*) FROM gottcha2_results WHERE sample_id = 'draining'")
+            .fetch_one(&db.pool)
+            .await
+            .expect("Failed to count records");
+    assert_eq!(count, 50);
+}
+
+#[tokio::test]
+async fn test_e2e_shutdown_aborts_ingest_still_running_after_grace() {
+    let db = TestDatabase::new()
+        .await
+        .expect("Failed to create test database");
+    // Each insert stalls far longer than the grace period allows
+    let mut server = TestServer::start_with_tls_config(db.pool.clone(), |config| {
+        config.fault_injection = true;
+        config.fault_delay_rate = 1.0;
+        config.fault_delay_ms = 10_000;
+    })
+    .await
+    .expect("Failed to start server");
+    let client = server
+        .create_http_client()
+        .expect("Failed to create client");
+
+    let records = vec![gottcha2_record("aborted", "species", "1")];
+    let request = client
+        .post(format!("{}/ingest-gottcha2", server.base_url))
+        .header("Authorization", format!("Bearer {}", server.bearer_token))
+        .body(gzip_jsonl(&records))
+        .send();
+    let in_flight = tokio::spawn(request);
+
+    tokio::time::sleep(std::time::Duration::from_millis(300)).await;
+    server.shutdown(std::time::Duration::from_millis(500));
+
+    tokio::time::timeout(std::time::Duration::from_secs(5), server.wait_stopped())
+        .await
+        .expect("Server should stop once the grace period runs out");
+    let result = in_flight.await.expect("Request task panicked");
+    assert!(
+        result.is_err(),
+        "The stalled request should be cut off, got {result:?}"
+    );
+}
+
+fn gottcha2_with_invalid_rows() -> Vec<Gottcha2FullRecord> {
+    let mut negative = gottcha2_record("policy", "genus", "2");
+    negative.read_count = -5;
+    let mut too_abundant = gottcha2_record("policy", "genus", "4");
+    too_abundant.rel_abundance = 7.2;
+    vec![
+        gottcha2_record("policy", "species", "1"),
+        negative,
+        gottcha2_record("policy", "species", "3"),
+        too_abundant,
+        gottcha2_record("policy", "species", "5"),
+    ]
+}
+
+async fn count_policy_rows(db: &TestDatabase) -> i64 {
+    sqlx::query_scalar("SELECT COUNT(*) FROM gottcha2_results WHERE sample_id = 'policy'")
+        .fetch_one(&db.pool)
+        .await
+        .expect("Failed to count records")
+}
+
+#[tokio::test]
+async fn test_e2e_invalid_row_policy_reject() {
+    let db = TestDatabase::new()
+        .await
+        .expect("Failed to create test database");
+    let server = TestServer::start_with_tls(db.pool.clone())
+        .await
+        .expect("Failed to start server");
+    let client = server
+        .create_http_client()
+        .expect("Failed to create client");
+
+    let response = client
+        .post(format!("{}/ingest-gottcha2", server.base_url))
+        .header("Authorization", format!("Bearer {}", server.bearer_token))
+        .body(gzip_jsonl(&gottcha2_with_invalid_rows()))
+        .send()
+        .await
+        .expect("Failed to send request");
+
+    assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+    let body: serde_json::Value = response.json().await.expect("Failed to parse response");
+    assert_eq!(body["line"], 2);
+    assert_eq!(body["field"], "read_count");
+}
+
+#[tokio::test]
+async fn test_e2e_invalid_row_policy_skip() {
+    let db = TestDatabase::new()
+        .await
+        .expect("Failed to create test database");
+    let server = TestServer::start_with_tls_config(db.pool.clone(), |config| {
+        config.on_invalid_row = nvd_support_car::config::InvalidRowPolicy::Skip;
+    })
+    .await
+    .expect("Failed to start server");
+    let client = server
+        .create_http_client()
+        .expect("Failed to create client");
+
+    let response = client
+        .post(format!("{}/ingest-gottcha2", server.base_url))
+        .header("Authorization", format!("Bearer {}", server.bearer_token))
+        .body(gzip_jsonl(&gottcha2_with_invalid_rows()))
+        .send()
+        .await
+        .expect("Failed to send request");
+
+    assert_eq!(response.status(), StatusCode::OK);
+    let body: serde_json::Value = response.json().await.expect("Failed to parse response");
+    assert_eq!(body, serde_json::json!({"inserted": 3, "skipped": 2}));
+    assert_eq!(count_policy_rows(&db).await, 3);
+}
+
+#[tokio::test]
+async fn test_e2e_metrics_count_ingested_records_and_parse_errors() {
+    let db = TestDatabase::new()
+        .await
+        .expect("Failed to create test database");
+    let server = TestServer::start_with_tls_config(db.pool.clone(), |config| {
+        config.on_invalid_row = nvd_support_car::config::InvalidRowPolicy::Skip;
+    })
+    .await
+    .expect("Failed to start server");
+    let client = server
+        .create_http_client()
+        .expect("Failed to create client");
+
+    let response = client
+        .post(format!("{}/ingest-gottcha2", server.base_url))
+        .header("Authorization", format!("Bearer {}", server.bearer_token))
+        .body(gzip_jsonl(&gottcha2_with_invalid_rows()))
+        .send()
+        .await
+        .expect("Failed to send request");
+    assert_eq!(response.status(), StatusCode::OK);
+    let response = client
+        .post(format!("{}/ingest-gottcha2", server.base_url))
+        .header("Authorization", format!("Bearer {}", server.bearer_token))
+        .header("X-Ingest-Mode", "strict")
+        .body(gzip_jsonl(&["not json"]))
+        .send()
+        .await
+        .expect("Failed to send request");
+    assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+
+    let exposition = client
+        .get(format!("{}/metrics", server.base_url))
+        .send()
+        .await
+        .expect("Failed to send request")
+        .text()
+        .await
+        .expect("Failed to read body");
+    for series in [
+        r#"ingested_records_total{endpoint="/ingest-gottcha2"} 3"#,
+        // Two skipped rows plus the line that failed the second upload
+        r#"parse_errors_total{endpoint="/ingest-gottcha2"} 3"#,
+    ] {
+        assert!(
+            exposition.contains(series),
+            "missing {series} in:\n{exposition}"
+        );
+    }
+}
+
+#[tokio::test]
+async fn test_e2e_lenient_ingest_mode_skips_malformed_lines() {
+    let db = TestDatabase::new()
+        .await
+        .expect("Failed to create test database");
+    let server = TestServer::start_with_tls(db.pool.clone())
+        .await
+        .expect("Failed to start server");
+    let client = server
+        .create_http_client()
+        .expect("Failed to create client");
+    let url = format!("{}/ingest-gottcha2", server.base_url);
+    let auth = format!("Bearer {}", server.bearer_token);
+
+    let good = |taxid| {
+        serde_json::to_string(&gottcha2_record("lenient", "species", taxid))
+            .expect("Failed to serialize")
+    };
+    let body = format!(
+        "{}\n{{\"sample_id\": \"lenient\", \n{}\n",
+        good("1"),
+        good("2")
+    );
+    let gzipped = || {
+        let mut encoder = GzEncoder::new(Vec::new(), Compression::default());
+        encoder
+            .write_all(body.as_bytes())
+            .expect("Failed to compress");
+        encoder.finish().expect("Failed to compress")
+    };
+
+    // Strict stays the default
+    let response = client
+        .post(&url)
+        .header("Authorization", &auth)
+        .body(gzipped())
+        .send()
+        .await
+        .expect("Failed to send request");
+    assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+
+    let response = client
+        .post(&url)
+        .header("Authorization", &auth)
+        .header("X-Ingest-Mode", "lenient")
+        .body(gzipped())
+        .send()
+        .await
+        .expect("Failed to send request");
+    assert_eq!(response.status(), StatusCode::OK);
+    let body: serde_json::Value = response.json().await.expect("Failed to parse response");
+    assert_eq!(body, serde_json::json!({"inserted": 2, "skipped": 1}));
+
+    let response = client
+        .post(&url)
+        .header("Authorization", &auth)
+        .header("X-Ingest-Mode", "sloppy")
+        .body(gzipped())
+        .send()
+        .await
+        .expect("Failed to send request");
+    assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+}
+
+#[tokio::test]
+async fn test_e2e_dead_letters_can_be_read_back_and_cleared() {
+    let db = TestDatabase::new()
+        .await
+        .expect("Failed to create test database");
+    let server = TestServer::start_with_tls_config(db.pool.clone(), |config| {
+        config.on_invalid_row = nvd_support_car::config::InvalidRowPolicy::Skip;
+        config.store_dead_letters = true;
+        config.dead_letters_max_limit = 1;
+    })
+    .await
+    .expect("Failed to start server");
+    let client = server
+        .create_http_client()
+        .expect("Failed to create client");
+    let auth = format!("Bearer {}", server.bearer_token);
+
+    let response = client
+        .post(format!("{}/ingest-gottcha2", server.base_url))
+        .header("Authorization", &auth)
+        .body(gzip_jsonl(&gottcha2_with_invalid_rows()))
+        .send()
+        .await
+        .expect("Failed to send request");
+    assert_eq!(response.status(), StatusCode::OK);
+
+    let read = |limit: u32| {
+        client
+            .get(format!(
+                "{}/dead-letters?endpoint=ingest-gottcha2&limit={limit}&encoding=none",
+                server.base_url
+            ))
+            .header("Authorization", &auth)
+            .send()
+    };
+
+    // The configured maximum caps whatever limit the client asks for
+    let response = read(100).await.expect("Failed to send request");
+    assert_eq!(response.status(), StatusCode::OK);
+    let body = response.text().await.expect("Failed to read body");
+    let letters: Vec<serde_json::Value> = body
+        .lines()
+        .map(|line| serde_json::from_str(line).expect("Dead letter should be JSON"))
+        .collect();
+    assert_eq!(letters.len(), 1);
+    assert_eq!(letters[0]["endpoint"], "ingest-gottcha2");
+    assert_eq!(letters[0]["line_number"], 2);
+    assert!(
+        letters[0]["raw_line"]
+            .as_str()
+            .is_some_and(|line| line.contains("\"read_count\":-5"))
+    );
+    assert!(
+        letters[0]["error"]
+            .as_str()
+            .is_some_and(|error| error.contains("read_count"))
+    );
+
+    let stored: i64 = sqlx::query_scalar("SELECT COUNT(*) FROM dead_letters")
+        .fetch_one(&db.pool)
+        .await
+        .expect("Failed to count dead letters");
+    assert_eq!(stored, 2);
+
+    let response = client
+        .delete(format!("{}/dead-letters", server.base_url))
+        .header("Authorization", &auth)
+        .send()
+        .await
+        .expect("Failed to send request");
+    assert_eq!(response.status(), StatusCode::OK);
+    let body: serde_json::Value = response.json().await.expect("Failed to parse response");
+    assert_eq!(body, serde_json::json!({"deleted": 2}));
+
+    let response = read(100).await.expect("Failed to send request");
+    assert_eq!(response.text().await.expect("Failed to read body"), "");
+}
+
+#[tokio::test]
+async fn test_e2e_server_version_header_is_stamped_when_enabled() {
+    use nvd_support_car::middleware::server_version::SERVER_VERSION;
+
+    let db = TestDatabase::new()
+        .await
+        .expect("Failed to create test database");
+    let server = TestServer::start_with_tls_config(db.pool.clone(), |config| {
+        config.server_version_header = true;
+    })
+    .await
+    .expect("Failed to start server");
+    let client = server
+        .create_http_client()
+        .expect("Failed to create client");
+
+    let health = client
+        .get(format!("{}/healthz", server.base_url))
+        .send()
+        .await
+        .expect("Failed to send request");
+    let ingest = client
+        .post(format!("{}/ingest-gottcha2", server.base_url))
+        .header("Authorization", format!("Bearer {}", server.bearer_token))
+        .body(gzip_jsonl(&[gottcha2_record("versioned", "genus", "561")]))
+        .send()
+        .await
+        .expect("Failed to send request");
+
+    assert!(SERVER_VERSION.starts_with(concat!(env!("CARGO_PKG_VERSION"), "+")));
+    for response in [health, ingest] {
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(
+            response
+                .headers()
+                .get("x-server-version")
+                .and_then(|value| value.to_str().ok()),
+            Some(SERVER_VERSION)
+        );
+    }
+
+    let default_server = TestServer::start_with_tls(db.pool.clone())
+        .await
+        .expect("Failed to start server");
+    let response = default_server
+        .create_http_client()
+        .expect("Failed to create client")
+        .get(format!("{}/healthz", default_server.base_url))
+        .send()
+        .await
+        .expect("Failed to send request");
+    assert!(response.headers().get("x-server-version").is_none());
+}
+
+#[tokio::test]
+async fn test_e2e_put_replaces_a_sample_atomically() {
+    let db = TestDatabase::new()
+        .await
+        .expect("Failed to create test database");
+    let server = TestServer::start_with_tls(db.pool.clone())
+        .await
+        .expect("Failed to start server");
+    let client = server
+        .create_http_client()
+        .expect("Failed to create client");
+    let put = |body: Vec<u8>| {
+        client
+            .put(format!("{}/gottcha2/reprocessed", server.base_url))
+            .header("Authorization", format!("Bearer {}", server.bearer_token))
+            .body(body)
+            .send()
+    };
+
+    // An untouched neighbour, and a body naming the wrong sample
+    let response = client
+        .post(format!("{}/ingest-gottcha2", server.base_url))
+        .header("Authorization", format!("Bearer {}", server.bearer_token))
+        .body(gzip_jsonl(&[gottcha2_record("neighbour", "genus", "9")]))
+        .send()
+        .await
+        .expect("Failed to send request");
+    assert_eq!(response.status(), StatusCode::OK);
+    let first = [
+        gottcha2_record("mislabeled", "genus", "1"),
+        gottcha2_record("mislabeled", "genus", "2"),
+    ];
+    let response = put(gzip_jsonl(&first))
+        .await
+        .expect("Failed to send request");
+    assert_eq!(response.status(), StatusCode::OK);
+    let body: serde_json::Value = response.json().await.expect("Failed to parse response");
+    assert_eq!(body, serde_json::json!({"deleted": 0, "inserted": 2}));
+
+    let second = [
+        gottcha2_record("reprocessed", "species", "3"),
+        gottcha2_record("reprocessed", "species", "4"),
+        gottcha2_record("reprocessed", "species", "5"),
+    ];
+    for _ in 0..2 {
+        let response = put(gzip_jsonl(&second))
+            .await
+            .expect("Failed to send request");
+        assert_eq!(response.status(), StatusCode::OK);
+    }
+
+    let taxids: Vec<String> = sqlx::query_scalar(
+        "SELECT taxid FROM gottcha2_results WHERE sample_id = 'reprocessed' ORDER BY taxid",
+    )
+    .fetch_all(&db.pool)
+    .await
+    .expect("Failed to query records");
+    assert_eq!(taxids, ["3", "4", "5"]);
+    let others: Vec<String> = sqlx::query_scalar(
+        "SELECT sample_id FROM gottcha2_results WHERE sample_id <> 'reprocessed'",
+    )
+    .fetch_all(&db.pool)
+    .await
+    .expect("Failed to query records");
+    assert_eq!(others, ["neighbour"]);
+
+    // A bad line rolls the whole replacement back, delete included
+    let mut invalid = gottcha2_record("reprocessed", "species", "6");
+    invalid.rel_abundance = 7.2;
+    let response = put(gzip_jsonl(&[
+        gottcha2_record("reprocessed", "species", "6"),
+        invalid,
+    ]))
+    .await
+    .expect("Failed to send request");
+    assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+    let remaining: i64 =
+        sqlx::query_scalar("SELECT COUNT(*) FROM gottcha2_results WHERE sample_id = 'reprocessed'")
+            .fetch_one(&db.pool)
+            .await
+            .expect("Failed to count records");
+    assert_eq!(remaining, 3);
+}
+
+#[tokio::test]
+async fn test_e2e_put_uses_the_configured_insert_options() {
+    let db = TestDatabase::new()
+        .await
+        .expect("Failed to create test database");
+    let records = gzip_jsonl(&[gottcha2_record("isolated", "species", "562")]);
+
+    // The isolation level is set ahead of the replacement's advisory lock
+    let server = TestServer::start_with_tls_config(db.pool.clone(), |config| {
+        config.ingest_isolation_level = nvd_support_car::config::IsolationLevel::Serializable;
+    })
+    .await
+    .expect("Failed to start server");
+    let client = server
+        .create_http_client()
+        .expect("Failed to create client");
+    let response = client
+        .put(format!("{}/gottcha2/isolated", server.base_url))
+        .header("Authorization", format!("Bearer {}", server.bearer_token))
+        .body(records.clone())
+        .send()
+        .await
+        .expect("Failed to send request");
+    assert_eq!(response.status(), StatusCode::OK);
+
+    // Fault injection reaches replacements too
+    let server = TestServer::start_with_tls_config(db.pool.clone(), |config| {
+        config.fault_injection = true;
+        config.fault_failure_rate = 1.0;
+    })
+    .await
+    .expect("Failed to start server");
+    let client = server
+        .create_http_client()
+        .expect("Failed to create client");
+    let response = client
+        .put(format!("{}/gottcha2/isolated", server.base_url))
+        .header("Authorization", format!("Bearer {}", server.bearer_token))
+        .body(records)
+        .send()
+        .await
+        .expect("Failed to send request");
+    assert_eq!(response.status(), StatusCode::SERVICE_UNAVAILABLE);
+    assert_eq!(count_sample_rows(&db, "isolated").await, 1);
+}
+
+#[tokio::test]
+async fn test_e2e_put_honors_expected_records_and_idempotency_key() {
+    let db = TestDatabase::new()
+        .await
+        .expect("Failed to create test database");
+    let server = TestServer::start_with_tls(db.pool.clone())
+        .await
+        .expect("Failed to start server");
+    let client = server
+        .create_http_client()
+        .expect("Failed to create client");
+    let put = |records: &[Gottcha2FullRecord], headers: &[(&str, &str)]| {
+        let mut request = client
+            .put(format!("{}/gottcha2/resent", server.base_url))
+            .header("Authorization", format!("Bearer {}", server.bearer_token))
+            .body(gzip_jsonl(records));
+        for (name, value) in headers {
+            request = request.header(*name, *value);
+        }
+        request.send()
+    };
+    let old = [gottcha2_record("resent", "genus", "1")];
+    let new = [
+        gottcha2_record("resent", "species", "2"),
+        gottcha2_record("resent", "species", "3"),
+    ];
+    let response = put(&old, &[]).await.expect("Failed to send request");
+    assert_eq!(response.status(), StatusCode::OK);
+
+    // A short upload is refused and the old rows are kept
+    let response = put(&new[..1], &[("X-Expected-Records", "2")])
+        .await
+        .expect("Failed to send request");
+    assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+    assert_eq!(count_sample_rows(&db, "resent").await, 1);
+
+    let headers = [("X-Expected-Records", "2"), ("Idempotency-Key", "resent-1")];
+    let response = put(&new, &headers).await.expect("Failed to send request");
+    assert_eq!(response.status(), StatusCode::OK);
+    let body: serde_json::Value = response.json().await.expect("Failed to parse response");
+    assert_eq!(body, serde_json::json!({"deleted": 1, "inserted": 2}));
+
+    // The resend is answered from the log without replacing anything
+    let response = put(&old, &headers).await.expect("Failed to send request");
+    assert_eq!(response.status(), StatusCode::OK);
+    let body: serde_json::Value = response.json().await.expect("Failed to parse response");
+    assert_eq!(
+        body,
+        serde_json::json!({"inserted": 2, "status": "duplicate"})
+    );
+    assert_eq!(count_sample_rows(&db, "resent").await, 2);
+}
+
+async fn post_with_expected_records(
+    server: &TestServer,
+    sample_id: &str,
+    records: usize,
+    expected: usize,
+) -> (StatusCode, String) {
+    let records: Vec<_> = (0..records)
+        .map(|i| gottcha2_record(sample_id, "genus", &i.to_string()))
+        .collect();
+    let response = server
+        .create_http_client()
+        .expect("Failed to create client")
+        .post(format!("{}/ingest-gottcha2", server.base_url))
+        .header("Authorization", format!("Bearer {}", server.bearer_token))
+        .header("X-Expected-Records", expected.to_string())
+        .body(gzip_jsonl(&records))
+        .send()
+        .await
+        .expect("Failed to send request");
+    let status = response.status();
+    (status, response.text().await.expect("Failed to read body"))
+}
+
+async fn count_sample_rows(db: &TestDatabase, sample_id: &str) -> i64 {
+    sqlx::query_scalar("SELECT COUNT(*) FROM gottcha2_results WHERE sample_id = $1")
+        .bind(sample_id)
+        .fetch_one(&db.pool)
+        .await
+        .expect("Failed to count records")
+}
+
+#[tokio::test]
+async fn test_e2e_delete_sample_purges_its_rows() {
+    let db = TestDatabase::new()
+        .await
+        .expect("Failed to create test database");
+    let server = TestServer::start_with_tls(db.pool.clone())
+        .await
+        .expect("Failed to start server");
+    let client = server
+        .create_http_client()
+        .expect("Failed to create client");
+    let base_url = &server.base_url;
+    let auth = format!("Bearer {}", server.bearer_token);
+
+    let records = vec![
+        gottcha2_record("purge_me", "species", "1"),
+        gottcha2_record("purge_me", "genus", "2"),
+        gottcha2_record("keep_me", "species", "1"),
+    ];
+    let response = client
+        .post(format!("{base_url}/ingest-gottcha2"))
+        .header("Authorization", &auth)
+        .header("Content-Type", "application/gzip")
+        .body(gzip_jsonl(&records))
+        .send()
+        .await
+        .expect("Request failed");
+    assert_eq!(response.status(), StatusCode::OK);
+
+    let stast = StastRecord {
+        sample_id: "purge_me".parse().expect("Invalid sample id"),
+        ..stast_record("NODE_1", 100.0, 1e-10)
+    };
+    let response = client
+        .post(format!("{base_url}/ingest-stast"))
+        .header("Authorization", &auth)
+        .header("Content-Type", "application/gzip")
+        .body(gzip_jsonl(&[stast]))
+        .send()
+        .await
+        .expect("Request failed");
+    assert_eq!(response.status(), StatusCode::OK);
+
+    let response = client
+        .delete(format!("{base_url}/samples/purge_me"))
+        .send()
+        .await
+        .expect("Request failed");
+    assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+
+    let response = client
+        .delete(format!("{base_url}/samples/purge_me?table=stast"))
+        .header("Authorization", &auth)
+        .send()
+        .await
+        .expect("Request failed");
+    assert_eq!(response.status(), StatusCode::OK);
+    let body: serde_json::Value = response.json().await.expect("Failed to parse response");
+    assert_eq!(body, serde_json::json!({"deleted": 1}));
+    assert_eq!(count_sample_rows(&db, "purge_me").await, 2);
+
+    let response = client
+        .delete(format!("{base_url}/samples/purge_me"))
+        .header("Authorization", &auth)
+        .send()
+        .await
+        .expect("Request failed");
+    assert_eq!(response.status(), StatusCode::OK);
+    let body: serde_json::Value = response.json().await.expect("Failed to parse response");
+    assert_eq!(body, serde_json::json!({"deleted": 2}));
+
+    assert_eq!(count_sample_rows(&db, "purge_me").await, 0);
+    assert_eq!(
+        db.count_records("stast_results")
+            .await
+            .expect("Failed to count"),
+        0
+    );
+    assert_eq!(count_sample_rows(&db, "keep_me").await, 1);
+
+    let response = client
+        .delete(format!("{base_url}/samples/purge_me?table=nope"))
+        .header("Authorization", &auth)
+        .send()
+        .await
+        .expect("Request failed");
+    assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+}
+
+#[tokio::test]
+async fn test_e2e_expected_records_mismatch_is_rejected_before_commit() {
+    let db = TestDatabase::new()
+        .await
+        .expect("Failed to create test database");
+    let server = TestServer::start_with_tls_config(db.pool.clone(), |config| {
+        config.expected_records_check = nvd_support_car::config::RecordCountCheck::Before;
+        config.gottcha2_batch_size = Some(2);
+    })
+    .await
+    .expect("Failed to start server");
+
+    let (status, _) = post_with_expected_records(&server, "matching", 5, 5).await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(count_sample_rows(&db, "matching").await, 5);
+
+    // Earlier batches were already sent to Postgres, but never committed
+    let (status, body) = post_with_expected_records(&server, "truncated", 5, 6).await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+    assert!(body.contains("held 5 records"), "{body}");
+    assert_eq!(count_sample_rows(&db, "truncated").await, 0);
+
+    let (status, body) = post_with_expected_records(&server, "overlong", 5, 4).await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+    assert!(body.contains("more records"), "{body}");
+    assert_eq!(count_sample_rows(&db, "overlong").await, 0);
+}
+
+#[tokio::test]
+async fn test_e2e_expected_records_mismatch_is_reported_after_insert() {
+    let db = TestDatabase::new()
+        .await
+        .expect("Failed to create test database");
+    let server = TestServer::start_with_tls(db.pool.clone())
+        .await
+        .expect("Failed to start server");
+
+    let (status, _) = post_with_expected_records(&server, "matching", 3, 3).await;
+    assert_eq!(status, StatusCode::OK);
+
+    let (status, body) = post_with_expected_records(&server, "truncated", 3, 4).await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+    assert!(body.contains("held 3 records"), "{body}");
+    assert_eq!(count_sample_rows(&db, "truncated").await, 3);
+
+    let (status, body) = post_with_expected_records(&server, "overlong", 3, 2).await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+    assert!(body.contains("held 3 records"), "{body}");
+    assert_eq!(count_sample_rows(&db, "overlong").await, 3);
+}
+
+#[tokio::test]
+async fn test_e2e_atomic_ingest_rolls_back_a_failed_upload() {
+    let db = TestDatabase::new()
+        .await
+        .expect("Failed to create test database");
+    let server = TestServer::start_with_tls_config(db.pool.clone(), |config| {
+        config.atomic_ingest = true;
+        config.gottcha2_batch_size = Some(10);
+    })
+    .await
+    .expect("Failed to start server");
+    let client = server
+        .create_http_client()
+        .expect("Failed to create client");
+    let auth = format!("Bearer {}", server.bearer_token);
+
+    // Many batches go to Postgres before the parser reaches the bad line
+    let records: Vec<_> = (0..200)
+        .map(|i| gottcha2_record("atomic", "species", &i.to_string()))
+        .collect();
+    let mut jsonl = records
+        .iter()
+        .map(|r| serde_json::to_string(r).expect("Failed to serialize"))
+        .collect::<Vec<_>>()
+        .join("\n");
+    jsonl.push_str("\nnot json\n");
+    let mut encoder = GzEncoder::new(Vec::new(), Compression::default());
+    encoder
+        .write_all(jsonl.as_bytes())
+        .expect("Failed to write");
+    let response = client
+        .post(format!("{}/ingest-gottcha2", server.base_url))
+        .header("Authorization", &auth)
+        .body(encoder.finish().expect("Failed to compress"))
+        .send()
+        .await
+        .expect("Failed to send request");
+    assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+    assert_eq!(count_sample_rows(&db, "atomic").await, 0);
+
+    let response = client
+        .post(format!("{}/ingest-gottcha2", server.base_url))
+        .header("Authorization", &auth)
+        .body(gzip_jsonl(&records))
+        .send()
+        .await
+        .expect("Failed to send request");
+    assert_eq!(response.status(), StatusCode::OK);
+    assert_eq!(count_sample_rows(&db, "atomic").await, 200);
+}
+
+/// Collects formatted log lines in memory.
+#[derive(Clone, Default)]
+struct CapturedLogs(std::sync::Arc<std::sync::Mutex<Vec<u8>>>);
+
+impl std::io::Write for CapturedLogs {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        self.0
+            .lock()
+            .expect("Log buffer poisoned")
+            .extend_from_slice(buf);
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        Ok(())
+    }
+}
+
+#[tokio::test]
+async fn test_e2e_metrics_snapshot_is_logged_periodically() {
+    let logs = CapturedLogs::default();
+    let writer = logs.clone();
+    let subscriber = tracing_subscriber::fmt()
+        .with_writer(move || writer.clone())
+        .with_ansi(false)
+        .finish();
+    // The test runtime is single-threaded, so every spawned task logs here
+    let _guard = tracing::subscriber::set_default(subscriber);
+
+    let db = TestDatabase::new()
+        .await
+        .expect("Failed to create test database");
+    let server = TestServer::start_with_tls(db.pool.clone())
+        .await
+        .expect("Failed to start server");
+    let response = server
+        .create_http_client()
+        .expect("Failed to create client")
+        .post(format!("{}/ingest-gottcha2", server.base_url))
+        .header("Authorization", format!("Bearer {}", server.bearer_token))
+        .body(gzip_jsonl(&[
+            gottcha2_record("logged", "genus", "1"),
+            gottcha2_record("logged", "genus", "2"),
+        ]))
+        .send()
+        .await
+        .expect("Failed to send request");
+    assert_eq!(response.status(), StatusCode::OK);
+
+    let logger = nvd_support_car::services::metrics::spawn_metrics_logger(
+        server.state().clone(),
+        std::time::Duration::from_secs(1),
+    );
+    tokio::time::sleep(std::time::Duration::from_millis(1500)).await;
+    logger.abort();
+
+    let logs = String::from_utf8(logs.0.lock().expect("Log buffer poisoned").clone())
+        .expect("Logs should be UTF-8");
+    let snapshot = logs
+        .lines()
+        .find(|line| line.contains("Metrics snapshot"))
+        .expect("No metrics snapshot was logged");
+    assert!(
+        snapshot.contains(r#""ingested_records_total":{"endpoint=/ingest-gottcha2":2.0}"#),
+        "{snapshot}"
+    );
+    assert!(snapshot.contains("db_pool_connections"), "{snapshot}");
+}
+
+#[tokio::test]
+async fn test_e2e_debug_sql_logs_statements_without_params() {
+    let logs = CapturedLogs::default();
+    let writer = logs.clone();
+    let subscriber = tracing_subscriber::fmt()
+        .with_writer(move || writer.clone())
+        .with_ansi(false)
+        .finish();
+    let _guard = tracing::subscriber::set_default(subscriber);
+
+    let db = TestDatabase::new()
+        .await
+        .expect("Failed to create test database");
+    let server = TestServer::start_with_tls_config(db.pool.clone(), |config| {
+        config.debug_sql = true;
+    })
+    .await
+    .expect("Failed to start server");
+    let client = server
+        .create_http_client()
+        .expect("Failed to create client");
+    let upload = |debug: bool| {
+        let request = client
+            .post(format!("{}/ingest-gottcha2", server.base_url))
+            .header("Authorization", format!("Bearer {}", server.bearer_token))
+            .body(gzip_jsonl(&[
+                gottcha2_record(if debug { "debugged" } else { "quiet" }, "genus", "4242"),
+                gottcha2_record(if debug { "debugged" } else { "quiet" }, "genus", "4243"),
+            ]));
+        if debug {
+            request.header("X-Debug-SQL", "true")
+        } else {
+            request
+        }
+    };
+
+    let response = upload(false).send().await.expect("Failed to send request");
+    assert_eq!(response.status(), StatusCode::OK);
+    let read_logs = || {
+        String::from_utf8(logs.0.lock().expect("Log buffer poisoned").clone())
+            .expect("Logs should be UTF-8")
+    };
+    assert!(!read_logs().contains("Executed INSERT"), "{}", read_logs());
+
+    let response = upload(true).send().await.expect("Failed to send request");
+    assert_eq!(response.status(), StatusCode::OK);
+    let logs = read_logs();
+    let line = logs
+        .lines()
+        .find(|line| line.contains("Executed INSERT"))
+        .unwrap_or_else(|| panic!("No SQL was logged: {logs}"));
+    assert!(
+        line.contains("debug_sql{endpoint=\"/ingest-gottcha2\"}"),
+        "{line}"
+    );
+    assert!(line.contains("INSERT INTO gottcha2_results"), "{line}");
+    assert!(line.contains("rows=2"), "{line}");
+    assert!(line.contains("inserted=2"), "{line}");
+    assert!(line.contains("params=\"[redacted]\""), "{line}");
+    assert!(!logs.contains("Taxon_4242"), "{logs}");
+}
+
+#[tokio::test]
+async fn test_e2e_draining_ingest_carries_retry_after() {
+    let db = TestDatabase::new()
+        .await
+        .expect("Failed to create test database");
+    let server = TestServer::start_with_tls(db.pool.clone())
+        .await
+        .expect("Failed to start server");
+    let client = server
+        .create_http_client()
+        .expect("Failed to create client");
+
+    server.begin_draining(std::time::Duration::from_secs(20));
+
+    let response = client
+        .post(format!("{}/ingest-gottcha2", server.base_url))
+        .header("Authorization", format!("Bearer {}", server.bearer_token))
+        .body(gzip_jsonl(&[gottcha2_record("late", "genus", "561")]))
+        .send()
+        .await
+        .expect("Failed to send request");
+
+    assert_eq!(response.status(), StatusCode::SERVICE_UNAVAILABLE);
+    let retry_after: u64 = response
+        .headers()
+        .get("retry-after")
+        .expect("Draining 503 should carry Retry-After")
+        .to_str()
+        .expect("Retry-After should be ASCII")
+        .parse()
+        .expect("Retry-After should be whole seconds");
+    assert!(
+        (1..=20).contains(&retry_after),
+        "Retry-After should reflect the remaining grace window, got {retry_after}"
+    );
+    let body: serde_json::Value = response.json().await.expect("Failed to parse response");
+    assert_eq!(body["error"], "shutting_down");
+}
+
+#[tokio::test]
+async fn test_e2e_missing_optional_field_gets_default() {
+    let db = TestDatabase::new()
+        .await
+        .expect("Failed to create test database");
+    let server = TestServer::start_with_tls_config(db.pool.clone(), |config| {
+        config.fill_missing_fields = true;
+    })
+    .await
+    .expect("Failed to start server");
+    let client = server
+        .create_http_client()
+        .expect("Failed to create client");
+
+    // An older GOTTCHA2 output without covered_sig_len
+    let mut record = serde_json::to_value(gottcha2_record("old_tool", "genus", "561"))
+        .expect("Failed to serialize record");
+    record
+        .as_object_mut()
+        .expect("Record should serialize to an object")
+        .remove("covered_sig_len");
+
+    let response = client
+        .post(format!("{}/ingest-gottcha2", server.base_url))
+        .header("Authorization", format!("Bearer {}", server.bearer_token))
+        .body(gzip_jsonl(&[record]))
+        .send()
+        .await
+        .expect("Failed to send request");
+    assert_eq!(response.status(), StatusCode::OK);
+
+    let covered_sig_len: i64 = sqlx::query_scalar(
+        "SELECT covered_sig_len FROM gottcha2_results WHERE sample_id = 'old_tool'",
+    )
+    .fetch_one(&db.pool)
+    .await
+    .expect("Failed to read record");
+    assert_eq!(covered_sig_len, 0);
+}
+
+#[tokio::test]
+async fn test_e2e_events_stream_reports_ingest_lifecycle() {
+    let db = TestDatabase::new()
+        .await
+        .expect("Failed to create test database");
+    let server = TestServer::start_with_tls(db.pool.clone())
+        .await
+        .expect("Failed to start server");
+    let client = server
+        .create_http_client()
+        .expect("Failed to create client");
+    let auth = format!("Bearer {}", server.bearer_token);
+
+    let unauthorized = client
+        .get(format!("{}/events", server.base_url))
+        .send()
+        .await
+        .expect("Failed to send request");
+    assert_eq!(unauthorized.status(), StatusCode::UNAUTHORIZED);
+
+    let mut events = client
+        .get(format!("{}/events", server.base_url))
+        .header("Authorization", &auth)
+        .send()
+        .await
+        .expect("Failed to subscribe to events");
+    assert_eq!(events.status(), StatusCode::OK);
+
+    let response = client
+        .post(format!("{}/ingest-gottcha2", server.base_url))
+        .header("Authorization", &auth)
+        .body(gzip_jsonl(&[
+            gottcha2_record("events", "genus", "561"),
+            gottcha2_record("events", "species", "562"),
+        ]))
+        .send()
+        .await
+        .expect("Failed to send request");
+    assert_eq!(response.status(), StatusCode::OK);
+
+    let mut received = String::new();
+    tokio::time::timeout(std::time::Duration::from_secs(5), async {
+        while !received.contains("event: complete") {
+            let chunk = events
+                .chunk()
+                .await
+                .expect("Failed to read event stream")
+                .expect("Event stream ended early");
+            received.push_str(&String::from_utf8_lossy(&chunk));
+        }
+    })
+    .await
+    .expect("Timed out waiting for ingest events");
+
+    assert!(received.contains("event: start"), "got: {received}");
+    assert!(
+        received.contains(r#""endpoint":"/ingest-gottcha2""#),
+        "got: {received}"
+    );
+    assert!(received.contains(r#""inserted":2"#), "got: {received}");
+}
+
+#[tokio::test]
+async fn test_e2e_per_token_ingest_limit_rejects_excess() {
+    let db = TestDatabase::new()
+        .await
+        .expect("Failed to create test database");
+    // Stall inserts so the first upload still holds its slot
+    let server = TestServer::start_with_tls_config(db.pool.clone(), |config| {
+        config.max_concurrent_ingests_per_token = Some(1);
+        config.fault_injection = true;
+        config.fault_delay_rate = 1.0;
+        config.fault_delay_ms = 1000;
+    })
+    .await
+    .expect("Failed to start server");
+    let client = server
+        .create_http_client()
+        .expect("Failed to create client");
+    let url = format!("{}/ingest-gottcha2", server.base_url);
+    let auth = format!("Bearer {}", server.bearer_token);
+    let body = gzip_jsonl(&[gottcha2_record("per_token", "species", "562")]);
+
+    let first = tokio::spawn(
+        client
+            .post(&url)
+            .header("Authorization", &auth)
+            .body(body.clone())
+            .send(),
+    );
+    tokio::time::sleep(std::time::Duration::from_millis(300)).await;
+
+    let excess = client
+        .post(&url)
+        .header("Authorization", &auth)
+        .body(body.clone())
+        .send()
+        .await
+        .expect("Failed to send request");
+    assert_eq!(excess.status(), StatusCode::TOO_MANY_REQUESTS);
+
+    let first = first
+        .await
+        .expect("Request task panicked")
+        .expect("Failed to send request");
+    assert_eq!(first.status(), StatusCode::OK);
+
+    // The slot is released once the first ingest completes
+    let after = client
+        .post(&url)
+        .header("Authorization", &auth)
+        .body(body)
+        .send()
+        .await
+        .expect("Failed to send request");
+    assert_eq!(after.status(), StatusCode::OK);
+}
+
+#[tokio::test]
+async fn test_e2e_patch_gottcha2_updates_allowed_columns_only() {
+    let db = TestDatabase::new()
+        .await
+        .expect("Failed to create test database");
+    let server = TestServer::start_with_tls(db.pool.clone())
+        .await
+        .expect("Failed to start server");
+    let client = server
+        .create_http_client()
+        .expect("Failed to create client");
+    let base_url = &server.base_url;
+    let auth = format!("Bearer {}", server.bearer_token);
+
+    let response = client
+        .post(format!("{base_url}/ingest-gottcha2"))
+        .header("Authorization", &auth)
+        .body(gzip_jsonl(&[gottcha2_record("patch", "species", "562")]))
+        .send()
+        .await
+        .expect("Request failed");
+    assert_eq!(response.status(), StatusCode::OK);
+    let id: i64 = sqlx::query_scalar("SELECT id FROM gottcha2_results WHERE sample_id = 'patch'")
+        .fetch_one(&db.pool)
+        .await
+        .expect("Failed to fetch id");
+
+    let response = client
+        .patch(format!("{base_url}/gottcha2/{id}"))
+        .header("Authorization", &auth)
+        .json(&serde_json::json!({ "taxid": "564", "read_count": 7 }))
+        .send()
+        .await
+        .expect("Request failed");
+    assert_eq!(response.status(), StatusCode::OK);
+    let row: serde_json::Value = response.json().await.expect("Failed to read body");
+    assert_eq!(row["id"], id);
+    assert_eq!(row["taxid"], "564");
+    assert_eq!(row["read_count"], 7);
+    assert_eq!(
+        row["level"], "species",
+        "untouched columns keep their value"
+    );
+
+    for body in [
+        serde_json::json!({ "id": 999 }),
+        serde_json::json!({ "created_at": "2020-01-01T00:00:00Z" }),
+        serde_json::json!({ "taxid": "565", "not_a_column": 1 }),
+        serde_json::json!({ "read_count": "lots" }),
+        serde_json::json!({}),
+        // Range checks apply to the row as it would stand after the update
+        serde_json::json!({ "taxid": "565", "rel_abundance": 1.5 }),
+        serde_json::json!({ "read_count": -1 }),
+    ] {
+        let response = client
+            .patch(format!("{base_url}/gottcha2/{id}"))
+            .header("Authorization", &auth)
+            .json(&body)
+            .send()
+            .await
+            .expect("Request failed");
+        assert_eq!(response.status(), StatusCode::BAD_REQUEST, "body {body}");
+    }
+    // An out-of-range value is reported as it is for uploads
+    let response = client
+        .patch(format!("{base_url}/gottcha2/{id}"))
+        .header("Authorization", &auth)
+        .json(&serde_json::json!({ "rel_abundance": 1.5 }))
+        .send()
+        .await
+        .expect("Request failed");
+    assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+    let error: serde_json::Value = response.json().await.expect("Failed to read body");
+    assert_eq!(
+        error,
+        serde_json::json!({
+            "line": 1,
+            "field": "rel_abundance",
+            "value": 1.5,
+            "constraint": "0..=1"
+        })
+    );
+    let taxid: String = sqlx::query_scalar("SELECT taxid FROM gottcha2_results WHERE id = $1")
+        .bind(id)
+        .fetch_one(&db.pool)
+        .await
+        .expect("Failed to fetch taxid");
+    assert_eq!(taxid, "564", "rejected updates must not apply");
+
+    let response = client
+        .patch(format!("{base_url}/gottcha2/{}", id + 1000))
+        .header("Authorization", &auth)
+        .json(&serde_json::json!({ "taxid": "565" }))
+        .send()
+        .await
+        .expect("Request failed");
+    assert_eq!(response.status(), StatusCode::NOT_FOUND);
+
+    let response = client
+        .patch(format!("{base_url}/gottcha2/{id}"))
+        .json(&serde_json::json!({ "taxid": "565" }))
+        .send()
+        .await
+        .expect("Request failed");
+    assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+}
+
+#[tokio::test]
+async fn test_e2e_patch_gottcha2_checks_levels_when_strict() {
+    let db = TestDatabase::new()
+        .await
+        .expect("Failed to create test database");
+    let server = TestServer::start_with_tls_config(db.pool.clone(), |config| {
+        config.strict_taxonomic_levels = true;
+    })
+    .await
+    .expect("Failed to start server");
+    let client = server
+        .create_http_client()
+        .expect("Failed to create client");
+    let base_url = &server.base_url;
+    let auth = format!("Bearer {}", server.bearer_token);
+
+    let response = client
+        .post(format!("{base_url}/ingest-gottcha2"))
+        .header("Authorization", &auth)
+        .body(gzip_jsonl(&[gottcha2_record(
+            "strict_patch",
+            "species",
+            "562",
+        )]))
+        .send()
+        .await
+        .expect("Request failed");
+    assert_eq!(response.status(), StatusCode::OK);
+    let id: i64 =
+        sqlx::query_scalar("SELECT id FROM gottcha2_results WHERE sample_id = 'strict_patch'")
+            .fetch_one(&db.pool)
+            .await
+            .expect("Failed to fetch id");
+
+    for (level, status) in [
+        ("subspecies", StatusCode::BAD_REQUEST),
+        ("genus", StatusCode::OK),
+    ] {
+        let response = client
+            .patch(format!("{base_url}/gottcha2/{id}"))
+            .header("Authorization", &auth)
+            .json(&serde_json::json!({ "level": level }))
+            .send()
+            .await
+            .expect("Request failed");
+        assert_eq!(response.status(), status, "level {level}");
+    }
+    let level: String = sqlx::query_scalar("SELECT level FROM gottcha2_results WHERE id = $1")
+        .bind(id)
+        .fetch_one(&db.pool)
+        .await
+        .expect("Failed to fetch level");
+    assert_eq!(level, "genus");
+}
+
+#[tokio::test]
+async fn test_e2e_patch_gottcha2_conflicts_with_an_existing_taxon() {
+    let db = TestDatabase::new()
+        .await
+        .expect("Failed to create test database");
+    let server = TestServer::start_with_tls(db.pool.clone())
+        .await
+        .expect("Failed to start server");
+    let client = server
+        .create_http_client()
+        .expect("Failed to create client");
+    let base_url = &server.base_url;
+    let auth = format!("Bearer {}", server.bearer_token);
+
+    let response = client
+        .post(format!("{base_url}/ingest-gottcha2"))
+        .header("Authorization", &auth)
+        .body(gzip_jsonl(&[
+            gottcha2_record("collide", "species", "562"),
+            gottcha2_record("collide", "species", "564"),
+        ]))
+        .send()
+        .await
+        .expect("Request failed");
+    assert_eq!(response.status(), StatusCode::OK);
+    let id: i64 = sqlx::query_scalar(
+        "SELECT id FROM gottcha2_results WHERE sample_id = 'collide' AND taxid = '562'",
+    )
+    .fetch_one(&db.pool)
+    .await
+    .expect("Failed to fetch id");
+
+    let response = client
+        .patch(format!("{base_url}/gottcha2/{id}"))
+        .header("Authorization", &auth)
+        .json(&serde_json::json!({ "taxid": "564" }))
+        .send()
+        .await
+        .expect("Request failed");
+    assert_eq!(response.status(), StatusCode::CONFLICT);
+
+    let taxid: String = sqlx::query_scalar("SELECT taxid FROM gottcha2_results WHERE id = $1")
+        .bind(id)
+        .fetch_one(&db.pool)
+        .await
+        .expect("Failed to fetch taxid");
+    assert_eq!(taxid, "562", "a conflicting update must not apply");
+}
+
+#[tokio::test]
+async fn test_e2e_dedup_identical_lines_inserts_each_line_once() {
+    let db = TestDatabase::new()
+        .await
+        .expect("Failed to create test database");
+    let server = TestServer::start_with_tls_config(db.pool.clone(), |config| {
+        config.dedup_identical_lines = true;
+    })
+    .await
+    .expect("Failed to start server");
+    let client = server
+        .create_http_client()
+        .expect("Failed to create client");
+
+    let repeated = gottcha2_record("dedup_lines", "species", "562");
+    let records = [
+        repeated.clone(),
+        repeated.clone(),
+        gottcha2_record("dedup_lines", "genus", "561"),
+        repeated,
+    ];
+    let response = client
+        .post(format!("{}/ingest-gottcha2", server.base_url))
+        .header("Authorization", format!("Bearer {}", server.bearer_token))
+        .body(gzip_jsonl(&records))
+        .send()
+        .await
+        .expect("Failed to send request");
+    assert_eq!(response.status(), StatusCode::OK);
+    let body: serde_json::Value = response.json().await.expect("Failed to read body");
+    assert_eq!(body["inserted"], 2);
+    assert_eq!(body["duplicate_lines"], 2);
+
+    let rows: i64 =
+        sqlx::query_scalar("SELECT COUNT(*) FROM gottcha2_results WHERE sample_id = 'dedup_lines'")
+            .fetch_one(&db.pool)
+            .await
+            .expect("Failed to count");
+    assert_eq!(rows, 2);
+}
+
+#[tokio::test]
+async fn test_e2e_sample_ids_are_normalized_and_blank_ids_rejected() {
+    let db = TestDatabase::new()
+        .await
+        .expect("Failed to create test database");
+    let server = TestServer::start_with_tls_config(db.pool.clone(), |config| {
+        config.lowercase_sample_ids = true;
+    })
+    .await
+    .expect("Failed to start server");
+    let client = server
+        .create_http_client()
+        .expect("Failed to create client");
+    let base_url = &server.base_url;
+    let auth = format!("Bearer {}", server.bearer_token);
+
+    let mut record = serde_json::to_value(gottcha2_record("placeholder", "species", "562"))
+        .expect("Failed to serialize");
+    record["sample_id"] = serde_json::json!("  SRR_Mixed\t");
+    let response = client
+        .post(format!("{base_url}/ingest-gottcha2"))
+        .header("Authorization", &auth)
+        .body(gzip_jsonl(&[record.clone()]))
+        .send()
+        .await
+        .expect("Failed to send request");
+    assert_eq!(response.status(), StatusCode::OK);
+
+    let stored: String = sqlx::query_scalar("SELECT sample_id FROM gottcha2_results")
+        .fetch_one(&db.pool)
+        .await
+        .expect("Failed to fetch sample_id");
+    assert_eq!(stored, "srr_mixed");
+
+    // Queries normalize the same way, so any casing finds the sample
+    let response = client
+        .get(format!("{base_url}/gottcha2/count?sample_id=%20SRR_MIXED"))
+        .header("Authorization", &auth)
+        .send()
+        .await
+        .expect("Failed to send request");
+    assert_eq!(response.status(), StatusCode::OK);
+    let body: serde_json::Value = response.json().await.expect("Failed to read body");
+    assert_eq!(body["count"], 1);
+
+    record["sample_id"] = serde_json::json!("   ");
+    let response = client
+        .post(format!("{base_url}/ingest-gottcha2"))
+        .header("Authorization", &auth)
+        .body(gzip_jsonl(&[record]))
+        .send()
+        .await
+        .expect("Failed to send request");
+    assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+    let message = response.text().await.expect("Failed to read body");
+    assert!(
+        message.contains("sample_id must not be empty"),
+        "got: {message}"
+    );
+
+    let response = client
+        .get(format!("{base_url}/gottcha2/count?sample_id="))
+        .header("Authorization", &auth)
+        .send()
+        .await
+        .expect("Failed to send request");
+    assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+}
+
+#[tokio::test]
+async fn test_e2e_latency_histograms_are_labeled_by_endpoint_and_status() {
+    let db = TestDatabase::new()
+        .await
+        .expect("Failed to create test database");
+    let server = TestServer::start_with_tls(db.pool.clone())
+        .await
+        .expect("Failed to start server");
+    let client = server
+        .create_http_client()
+        .expect("Failed to create client");
+    let auth = format!("Bearer {}", server.bearer_token);
+
+    for size in [1, 100, 2000] {
+        let records: Vec<_> = (0..size)
+            .map(|i| gottcha2_record("latency", "species", &i.to_string()))
+            .collect();
+        let response = client
+            .post(format!("{}/ingest-gottcha2", server.base_url))
+            .header("Authorization", &auth)
+            .body(gzip_jsonl(&records))
+            .send()
+            .await
+            .expect("Failed to send request");
+        assert_eq!(response.status(), StatusCode::OK);
+    }
+    let response = client
+        .post(format!("{}/ingest-gottcha2", server.base_url))
+        .send()
+        .await
+        .expect("Failed to send request");
+    assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+    let response = client
+        .patch(format!("{}/gottcha2/1", server.base_url))
+        .header("Authorization", &auth)
+        .json(&serde_json::json!({ "taxid": "1" }))
+        .send()
+        .await
+        .expect("Failed to send request");
+    // Row 1 is taxid "0" and a later ingest stored taxid "1" for the same
+    // sample and level, so the update collides with it
+    assert_eq!(response.status(), StatusCode::CONFLICT);
+
+    let exposition = client
+        .get(format!("{}/metrics", server.base_url))
+        .send()
+        .await
+        .expect("Failed to send request")
+        .text()
+        .await
+        .expect("Failed to read body");
+
+    for series in [
+        r#"http_request_duration_seconds_count{endpoint="/ingest-gottcha2",status_class="2xx"} 3"#,
+        r#"http_request_duration_seconds_count{endpoint="/ingest-gottcha2",status_class="4xx"} 1"#,
+        r#"http_request_duration_seconds_bucket{endpoint="/ingest-gottcha2",status_class="2xx",le="300"} 3"#,
+        r#"http_request_duration_seconds_bucket{endpoint="/ingest-gottcha2",status_class="2xx",le="+Inf"} 3"#,
+        // Path parameters are labeled by route template, not the raw path
+        r#"http_request_duration_seconds_count{endpoint="/gottcha2/{id}",status_class="4xx"} 1"#,
+    ] {
+        assert!(
+            exposition.contains(series),
+            "missing {series} in:\n{exposition}"
+        );
+    }
+}
+
+#[tokio::test]
+async fn test_e2e_require_sample_id_rejects_records_without_one() {
+    let db = TestDatabase::new()
+        .await
+        .expect("Failed to create test database");
+    let server = TestServer::start_with_tls_config(db.pool.clone(), |config| {
+        config.require_sample_id = true;
+    })
+    .await
+    .expect("Failed to start server");
+    let client = server
+        .create_http_client()
+        .expect("Failed to create client");
+    let auth = format!("Bearer {}", server.bearer_token);
+
+    let valid = serde_json::to_value(gottcha2_record("required", "species", "562"))
+        .expect("Failed to serialize");
+    let mut missing = valid.clone();
+    missing
+        .as_object_mut()
+        .expect("record should be an object")
+        .remove("sample_id");
+    let mut blank = valid.clone();
+    blank["sample_id"] = serde_json::json!("  ");
+
+    for invalid in [missing, blank] {
+        let response = client
+            .post(format!("{}/ingest-gottcha2", server.base_url))
+            .header("Authorization", &auth)
+            .body(gzip_jsonl(&[valid.clone(), invalid]))
+            .send()
+            .await
+            .expect("Failed to send request");
+        assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+        let request_id = response.headers()["x-request-id"]
+            .to_str()
+            .expect("Invalid request id")
+            .to_string();
+        let message = response.text().await.expect("Failed to read body");
+        assert_eq!(
+            message,
+            format!("line 2: record has no sample_id (request id {request_id})")
+        );
+    }
+
+    // Record types without a sample_id are unaffected
+    let response = client
+        .post(format!("{}/ingest", server.base_url))
+        .header("Authorization", &auth)
+        .body(gzip_jsonl(&[dummy_record("no_sample_id", 0)]))
+        .send()
+        .await
+        .expect("Failed to send request");
+    assert_eq!(response.status(), StatusCode::OK);
+}
+
+#[tokio::test]
+async fn test_e2e_plain_and_gzip_uploads_share_an_endpoint() {
+    let db = TestDatabase::new()
+        .await
+        .expect("Failed to create test database");
+    let server = TestServer::start_with_tls(db.pool.clone())
+        .await
+        .expect("Failed to start server");
+    let client = server
+        .create_http_client()
+        .expect("Failed to create client");
+    let url = format!("{}/ingest-gottcha2", server.base_url);
+    let auth = format!("Bearer {}", server.bearer_token);
+
+    let plain = [gottcha2_record("sniffed", "genus", "561")]
+        .iter()
+        .map(|r| serde_json::to_string(r).expect("Failed to serialize") + "\n")
+        .collect::<String>();
+    let gzipped = gzip_jsonl(&[gottcha2_record("sniffed", "species", "562")]);
+
+    // Neither upload declares a Content-Encoding
+    for body in [plain.into_bytes(), gzipped] {
+        let response = client
+            .post(&url)
+            .header("Authorization", &auth)
+            .body(body)
+            .send()
+            .await
+            .expect("Failed to send request");
+        assert_eq!(response.status(), StatusCode::OK);
+    }
+    assert_eq!(
+        db.count_records("gottcha2_results")
+            .await
+            .expect("Failed to count"),
+        2
+    );
+}
+
+#[tokio::test]
+async fn test_e2e_decompression_bomb_is_rejected() {
+    let db = TestDatabase::new()
+        .await
+        .expect("Failed to create test database");
+    let server = TestServer::start_with_tls_config(db.pool.clone(), |config| {
+        config.max_decompressed_bytes = 64 * 1024;
+    })
+    .await
+    .expect("Failed to start server");
+    let client = server
+        .create_http_client()
+        .expect("Failed to create client");
+
+    // Highly repetitive, so it compresses to a small fraction of the limit
+    let records: Vec<_> = (0..2000)
+        .map(|_| gottcha2_record("bomb", "species", "562"))
+        .collect();
+    let body = gzip_jsonl(&records);
+    assert!(body.len() < 64 * 1024);
+
+    let response = client
+        .post(format!("{}/ingest-gottcha2", server.base_url))
+        .header("Authorization", format!("Bearer {}", server.bearer_token))
+        .body(body)
+        .send()
+        .await
+        .expect("Failed to send request");
+    assert_eq!(response.status(), StatusCode::PAYLOAD_TOO_LARGE);
+    let message = response.text().await.expect("Failed to read body");
+    assert!(
+        message.contains("payload too large after decompression"),
+        "{message}"
+    );
+}
+
+#[tokio::test]
+async fn test_e2e_zstd_upload_is_decoded_by_content_encoding() {
+    use async_compression::tokio::bufread::ZstdEncoder;
+    use tokio::io::AsyncReadExt;
+
+    let db = TestDatabase::new()
+        .await
+        .expect("Failed to create test database");
+    let server = TestServer::start_with_tls(db.pool.clone())
+        .await
+        .expect("Failed to start server");
+    let client = server
+        .create_http_client()
+        .expect("Failed to create client");
+    let url = format!("{}/ingest-gottcha2", server.base_url);
+    let auth = format!("Bearer {}", server.bearer_token);
+
+    let jsonl = [
+        gottcha2_record("zstd_sample", "genus", "561"),
+        gottcha2_record("zstd_sample", "species", "562"),
+    ]
+    .iter()
+    .map(|r| serde_json::to_string(r).expect("Failed to serialize") + "\n")
+    .collect::<String>();
+    let mut compressed = Vec::new();
+    ZstdEncoder::new(jsonl.as_bytes())
+        .read_to_end(&mut compressed)
+        .await
+        .expect("Failed to compress");
+
+    let response = client
+        .post(&url)
+        .header("Authorization", &auth)
+        .header("Content-Encoding", "zstd")
+        .body(compressed)
+        .send()
+        .await
+        .expect("Failed to send request");
+    assert_eq!(response.status(), StatusCode::OK);
+    assert_eq!(
+        db.count_records("gottcha2_results")
+            .await
+            .expect("Failed to count"),
+        2
+    );
+
+    let response = client
+        .post(&url)
+        .header("Authorization", &auth)
+        .header("Content-Encoding", "br")
+        .body(gzip_jsonl(&[gottcha2_record("zstd_sample", "genus", "1")]))
+        .send()
+        .await
+        .expect("Failed to send request");
+    assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+}
+
+#[tokio::test]
+async fn test_e2e_capabilities_reflect_running_config() {
+    let db = TestDatabase::new()
+        .await
+        .expect("Failed to create test database");
+    let server = TestServer::start_with_tls_config(db.pool.clone(), |config| {
+        config.max_line_bytes = 4096;
+        config.max_concurrent_ingests_per_token = Some(3);
+        config.admin_token = Some("admin-secret".to_string());
+    })
+    .await
+    .expect("Failed to start server");
+    let client = server
+        .create_http_client()
+        .expect("Failed to create client");
+
+    // No Authorization header: discovery must work before a client is set up
+    let response = client
+        .get(format!("{}/capabilities", server.base_url))
+        .send()
+        .await
+        .expect("Failed to send request");
+    assert_eq!(response.status(), StatusCode::OK);
+    let caps: serde_json::Value = response.json().await.expect("Failed to read body");
+
+    assert_eq!(
+        caps["content_encodings"],
+        serde_json::json!(["gzip", "zstd", "identity"])
+    );
+    assert!(
+        caps["content_types"]
+            .as_array()
+            .is_some_and(|types| types.contains(&serde_json::json!("application/gzip")))
+    );
+    assert_eq!(
+        caps["ingest_routes"],
+        serde_json::json!([
+            "/ingest",
+            "/ingest-gottcha2",
+            "/ingest-stast",
+            "/ingest-kraken2",
+            "/ingest/{record_type}"
+        ])
+    );
+    assert_eq!(
+        caps["record_types"],
+        serde_json::json!(["dummy", "gottcha2", "stast", "kraken2"])
+    );
+    assert_eq!(caps["limits"]["max_line_bytes"], 4096);
+    assert_eq!(caps["limits"]["max_concurrent_ingests_per_token"], 3);
+    assert_eq!(caps["auth"]["mode"], "bearer");
+    assert_eq!(caps["auth"]["separate_admin_token"], true);
+    assert!(
+        !caps.to_string().contains("admin-secret"),
+        "capabilities must not leak tokens"
+    );
+}
+
+#[tokio::test]
+async fn test_e2e_unsupported_content_type_is_rejected_with_415() {
+    let db = TestDatabase::new()
+        .await
+        .expect("Failed to create test database");
+    let server = TestServer::start_with_tls(db.pool.clone())
+        .await
+        .expect("Failed to start server");
+    let client = server
+        .create_http_client()
+        .expect("Failed to create client");
+    let auth = format!("Bearer {}", server.bearer_token);
+    let body = gzip_jsonl(&[gottcha2_record("typed", "species", "562")]);
+
+    for route in ["/ingest", "/ingest-gottcha2", "/ingest-stast"] {
+        let response = client
+            .post(format!("{}{route}", server.base_url))
+            .header("Authorization", &auth)
+            .header("Content-Type", "application/x-www-form-urlencoded")
+            .body(body.clone())
+            .send()
+            .await
+            .expect("Failed to send request");
+        assert_eq!(
+            response.status(),
+            StatusCode::UNSUPPORTED_MEDIA_TYPE,
+            "{route}"
+        );
+        let message = response.text().await.expect("Failed to read body");
+        assert!(message.contains("application/gzip"), "{message}");
+    }
+
+    let response = client
+        .post(format!("{}/ingest-gottcha2", server.base_url))
+        .header("Authorization", &auth)
+        .header("Content-Type", "application/x-ndjson; charset=utf-8")
+        .body(body)
+        .send()
+        .await
+        .expect("Failed to send request");
+    assert_eq!(response.status(), StatusCode::OK);
+    assert_eq!(
+        db.count_records("gottcha2_results")
+            .await
+            .expect("Failed to count"),
+        1
+    );
+}
+
+#[tokio::test]
+async fn test_e2e_export_preserves_input_order() {
+    let db = TestDatabase::new()
+        .await
+        .expect("Failed to create test database");
+    let server = TestServer::start_with_tls_config(db.pool.clone(), |config| {
+        config.preserve_input_order = true;
+        config.gottcha2_batch_size = Some(3);
+    })
+    .await
+    .expect("Failed to start server");
+    let client = server
+        .create_http_client()
+        .expect("Failed to create client");
+    let auth = format!("Bearer {}", server.bearer_token);
+
+    let taxids = ["9", "3", "7", "1", "8", "2", "6", "4", "5", "0"];
+    let records: Vec<_> = taxids
+        .iter()
+        .map(|taxid| gottcha2_record("ordered", "species", taxid))
+        .collect();
+    let response = client
+        .post(format!("{}/ingest-gottcha2", server.base_url))
+        .header("Authorization", &auth)
+        .body(gzip_jsonl(&records))
+        .send()
+        .await
+        .expect("Failed to send request");
+    assert_eq!(response.status(), StatusCode::OK);
+
+    // Reverse the serial ids so insertion order can't masquerade as file order
+    sqlx::query("UPDATE gottcha2_results SET id = -id")
+        .execute(&db.pool)
+        .await
+        .expect("Failed to reorder ids");
+
+    let response = client
+        .get(format!(
+            "{}/gottcha2/export?sample_id=ordered",
+            server.base_url
+        ))
+        .header("Authorization", &auth)
+        .send()
+        .await
+        .expect("Failed to send request");
+    assert_eq!(response.status(), StatusCode::OK);
+    assert_eq!(response.headers()["content-type"], "application/x-ndjson");
+    let body = response.text().await.expect("Failed to read body");
+    let rows: Vec<serde_json::Value> = body
+        .lines()
+        .map(|line| serde_json::from_str(line).expect("Invalid JSONL row"))
+        .collect();
+
+    let exported: Vec<&str> = rows
+        .iter()
+        .map(|row| row["taxid"].as_str().expect("taxid should be a string"))
+        .collect();
+    assert_eq!(exported, taxids);
+    let lines: Vec<i64> = rows
+        .iter()
+        .map(|row| {
+            row["source_line"]
+                .as_i64()
+                .expect("source_line should be set")
+        })
+        .collect();
+    assert_eq!(lines, (1..=10).collect::<Vec<_>>());
+
+    let response = client
+        .get(format!(
+            "{}/gottcha2/export?sample_id=ordered",
+            server.base_url
+        ))
+        .send()
+        .await
+        .expect("Failed to send request");
+    assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+}
+
+#[tokio::test]
+async fn test_e2e_readyz_runs_configured_health_check_query() {
+    let db = TestDatabase::new()
+        .await
+        .expect("Failed to create test database");
+
+    for (query, expected) in [
+        ("SELECT 1", StatusCode::OK),
+        ("SHOW server_version", StatusCode::OK),
+        (
+            "SELECT 1 FROM no_such_table",
+            StatusCode::SERVICE_UNAVAILABLE,
+        ),
+    ] {
+        let server = TestServer::start_with_tls_config(db.pool.clone(), |config| {
+            config.health_check_query = query.to_string();
+        })
+        .await
+        .expect("Failed to start server");
+        let client = server
+            .create_http_client()
+            .expect("Failed to create client");
+
+        let response = client
+            .get(format!("{}/readyz", server.base_url))
+            .send()
+            .await
+            .expect("Failed to send request");
+        assert_eq!(response.status(), expected, "query {query:?}");
+    }
+}
+
+#[tokio::test]
+async fn test_e2e_readyz_is_unavailable_without_a_database() {
+    // Nothing listens on port 1, so every connection attempt fails
+    let unreachable = sqlx::postgres::PgPoolOptions::new()
+        .acquire_timeout(std::time::Duration::from_millis(500))
+        .connect_lazy("postgres://nobody@127.0.0.1:1/nvd_support")
+        .expect("Failed to build pool");
+    let server = TestServer::start_with_tls(unreachable)
+        .await
+        .expect("Failed to start server");
+    let client = server
+        .create_http_client()
+        .expect("Failed to create client");
+
+    let response = client
+        .get(format!("{}/readyz", server.base_url))
+        .send()
+        .await
+        .expect("Failed to send request");
+    assert_eq!(response.status(), StatusCode::SERVICE_UNAVAILABLE);
+
+    // Liveness doesn't depend on the database
+    let response = client
+        .get(format!("{}/healthz", server.base_url))
+        .send()
+        .await
+        .expect("Failed to send request");
+    assert_eq!(response.status(), StatusCode::OK);
+}
+
+#[tokio::test]
+async fn test_e2e_export_honors_requested_encoding() {
+    use flate2::read::GzDecoder;
+    use std::io::Read;
+    use tokio::io::AsyncReadExt;
+
+    let db = TestDatabase::new()
+        .await
+        .expect("Failed to create test database");
+    let server = TestServer::start_with_tls(db.pool.clone())
+        .await
+        .expect("Failed to start server");
+    let client = server
+        .create_http_client()
+        .expect("Failed to create client");
+    let raw_client = server
+        .create_raw_http_client()
+        .expect("Failed to create client");
+    let auth = format!("Bearer {}", server.bearer_token);
+
+    let records: Vec<_> = ["q1", "q2", "q3"]
+        .iter()
+        .map(|qseqid| stast_record(qseqid, 100.0, 1e-10))
+        .collect();
+    let response = client
+        .post(format!("{}/ingest-stast", server.base_url))
+        .header("Authorization", &auth)
+        .body(gzip_jsonl(&records))
+        .send()
+        .await
+        .expect("Failed to send request");
+    assert_eq!(response.status(), StatusCode::OK);
+
+    for (query, expected) in [
+        ("", Some("gzip")),
+        ("&encoding=gzip", Some("gzip")),
+        ("&encoding=zstd", Some("zstd")),
+        ("&encoding=none", None),
+    ] {
+        let response = raw_client
+            .get(format!(
+                "{}/stast/export?sample_id=stast_filter_test{query}",
+                server.base_url
+            ))
+            .header("Authorization", &auth)
+            .send()
+            .await
+            .expect("Failed to send request");
+        assert_eq!(response.status(), StatusCode::OK, "query {query:?}");
+        let encoding = response
+            .headers()
+            .get("content-encoding")
+            .map(|value| value.to_str().expect("Invalid header").to_string());
+        assert_eq!(encoding.as_deref(), expected, "query {query:?}");
+
+        let body = response.bytes().await.expect("Failed to read body");
+        let mut text = String::new();
+        match expected {
+            Some("gzip") => {
+                GzDecoder::new(&body[..])
+                    .read_to_string(&mut text)
+                    .expect("Body is not valid gzip");
+            }
+            Some("zstd") => {
+                async_compression::tokio::bufread::ZstdDecoder::new(&body[..])
+                    .read_to_string(&mut text)
+                    .await
+                    .expect("Body is not valid zstd");
+            }
+            _ => text = String::from_utf8(body.to_vec()).expect("Body is not UTF-8"),
+        }
+        let qseqids: Vec<String> = text
+            .lines()
+            .map(|line| {
+                let row: serde_json::Value = serde_json::from_str(line).expect("Invalid JSONL row");
+                row["qseqid"].as_str().expect("qseqid").to_string()
+            })
+            .collect();
+        assert_eq!(qseqids, ["q1", "q2", "q3"], "query {query:?}");
+    }
+
+    // Accept-Encoding is honored when no encoding is requested explicitly
+    let response = raw_client
+        .get(format!(
+            "{}/stast/export?sample_id=stast_filter_test",
+            server.base_url
+        ))
+        .header("Authorization", &auth)
+        .header("Accept-Encoding", "zstd")
+        .send()
+        .await
+        .expect("Failed to send request");
+    assert_eq!(response.headers()["content-encoding"], "zstd");
+
+    let response = raw_client
+        .get(format!(
+            "{}/stast/export?sample_id=stast_filter_test&encoding=brotli",
+            server.base_url
+        ))
+        .header("Authorization", &auth)
+        .send()
+        .await
+        .expect("Failed to send request");
+    assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+}
+
+#[tokio::test]
+async fn test_e2e_strict_duplicate_keys_rejects_repeated_stast_alignment() {
+    let db = TestDatabase::new()
+        .await
+        .expect("Failed to create test database");
+    let server = TestServer::start_with_tls_config(db.pool.clone(), |config| {
+        config.strict_duplicate_keys = true;
+    })
+    .await
+    .expect("Failed to start server");
+    let client = server
+        .create_http_client()
+        .expect("Failed to create client");
+    let auth = format!("Bearer {}", server.bearer_token);
+
+    // Same (sample_id, qseqid, sseqid) on lines 1 and 3, differing elsewhere
+    let records = vec![
+        stast_record("NODE_1", 100.0, 1e-10),
+        stast_record("NODE_2", 100.0, 1e-10),
+        stast_record("NODE_1", 250.0, 1e-30),
+    ];
+    let response = client
+        .post(format!("{}/ingest-stast", server.base_url))
+        .header("Authorization", &auth)
+        .body(gzip_jsonl(&records))
+        .send()
+        .await
+        .expect("Failed to send request");
+    assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+    let body = response.text().await.expect("Failed to read body");
+    assert!(body.contains("line 3"), "got: {body}");
+    assert!(body.contains("line 1"), "got: {body}");
+    assert!(body.contains("NODE_1"), "got: {body}");
+
+    // Distinct keys are unaffected
+    let records = vec![
+        stast_record("NODE_1", 100.0, 1e-10),
+        stast_record("NODE_2", 100.0, 1e-10),
+    ];
+    let response = client
+        .post(format!("{}/ingest-stast", server.base_url))
+        .header("Authorization", &auth)
+        .body(gzip_jsonl(&records))
+        .send()
+        .await
+        .expect("Failed to send request");
+    assert_eq!(response.status(), StatusCode::OK);
+}
+
+#[tokio::test]
+async fn test_e2e_large_breakdown_is_compressed_when_accepted() {
+    use flate2::read::GzDecoder;
+    use std::io::Read;
+
+    let db = TestDatabase::new()
+        .await
+        .expect("Failed to create test database");
+    let server = TestServer::start_with_tls(db.pool.clone())
+        .await
+        .expect("Failed to start server");
+    let raw_client = server
+        .create_raw_http_client()
+        .expect("Failed to create client");
+    let url = format!("{}/ingest-gottcha2?breakdown=true", server.base_url);
+    let auth = format!("Bearer {}", server.bearer_token);
+
+    let samples = 5000;
+    let records: Vec<_> = (0..samples)
+        .map(|i| gottcha2_record(&format!("SRR{i:05}"), "species", "562"))
+        .collect();
+    let expected: serde_json::Map<String, serde_json::Value> = (0..samples)
+        .map(|i| (format!("SRR{i:05}"), serde_json::json!(1)))
+        .collect();
+
+    let response = raw_client
+        .post(&url)
+        .header("Authorization", &auth)
+        .header("Accept-Encoding", "gzip")
+        .body(gzip_jsonl(&records))
+        .send()
+        .await
+        .expect("Failed to send request");
+    assert_eq!(response.status(), StatusCode::OK);
+    assert_eq!(response.headers()["content-encoding"], "gzip");
+    assert_eq!(response.headers()["content-type"], "application/json");
+    let compressed = response.bytes().await.expect("Failed to read body");
+    let mut text = String::new();
+    GzDecoder::new(&compressed[..])
+        .read_to_string(&mut text)
+        .expect("Body is not valid gzip");
+    assert!(compressed.len() < text.len(), "body should shrink");
+    let body: serde_json::Value = serde_json::from_str(&text).expect("Invalid JSON");
+    assert_eq!(body["by_sample"], serde_json::Value::Object(expected));
+
+    // Clients that don't advertise compression still get plain JSON
+    let response = raw_client
+        .post(&url)
+        .header("Authorization", &auth)
+        .body(gzip_jsonl(&[gottcha2_record("SRR1", "genus", "561")]))
+        .send()
+        .await
+        .expect("Failed to send request");
+    assert_eq!(response.status(), StatusCode::OK);
+    assert!(response.headers().get("content-encoding").is_none());
+    let body: serde_json::Value = response.json().await.expect("Failed to parse response");
+    assert_eq!(
+        body,
+        serde_json::json!({"inserted": 1, "by_sample": {"SRR1": 1}})
+    );
+}
+
+/// Serves the current value of `secret` as a Vault KV v2 secret at
+/// `/v1/secret/data/nvd`, requiring `X-Vault-Token: vault-root`.
+async fn spawn_mock_vault(secret: std::sync::Arc<std::sync::Mutex<String>>) -> String {
+    use axum::{
+        Json, Router,
+        http::{HeaderMap, StatusCode},
+        routing::get,
+    };
+
+    let app = Router::new().route(
+        "/v1/secret/data/nvd",
+        get(move |headers: HeaderMap| async move {
+            if headers.get("x-vault-token").and_then(|v| v.to_str().ok()) != Some("vault-root") {
+                return Err(StatusCode::FORBIDDEN);
+            }
+            let token = secret.lock().expect("Mock secret lock poisoned").clone();
+            Ok(Json(serde_json::json!({
+                "data": {"data": {"token": token}, "metadata": {"version": 1}}
+            })))
+        }),
+    );
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0")
+        .await
+        .expect("Failed to bind mock Vault");
+    let addr = listener.local_addr().expect("Mock Vault has no address");
+    tokio::spawn(async move {
+        axum::serve(listener, app).await.expect("Mock Vault failed");
+    });
+    format!("http://{addr}")
+}
+
+#[tokio::test]
+async fn test_e2e_any_configured_ingest_token_is_accepted() {
+    let db = TestDatabase::new()
+        .await
+        .expect("Failed to create test database");
+    let server = TestServer::start_with_tls_config(db.pool.clone(), |config| {
+        config.ingest_tokens = vec!["outgoing-token".to_string(), "incoming-token".to_string()];
+    })
+    .await
+    .expect("Failed to start server");
+    let client = server
+        .create_http_client()
+        .expect("Failed to create client");
+    let count_url = format!("{}/gottcha2/count", server.base_url);
+
+    for (bearer, expected) in [
+        ("outgoing-token", StatusCode::OK),
+        ("incoming-token", StatusCode::OK),
+        ("some-other-token", StatusCode::UNAUTHORIZED),
+        // INGEST_TOKENS takes the place of INGEST_TOKEN
+        (server.bearer_token.as_str(), StatusCode::UNAUTHORIZED),
+    ] {
+        let response = client
+            .get(&count_url)
+            .header("Authorization", format!("Bearer {bearer}"))
+            .send()
+            .await
+            .expect("Failed to send request");
+        assert_eq!(response.status(), expected, "token {bearer:?}");
+    }
+}
+
+#[tokio::test]
+async fn test_e2e_ingest_token_fetched_from_vault_authenticates() {
+    use nvd_support_car::{config::TokenSource, services::secrets};
+    use std::sync::{Arc, Mutex};
+
+    let secret = Arc::new(Mutex::new("vault-issued-token-1".to_string()));
+    let vault_addr = spawn_mock_vault(Arc::clone(&secret)).await;
+    let vault_config = |config: &mut nvd_support_car::config::AppConfig| {
+        config.ingest_token = String::new();
+        config.ingest_token_source = TokenSource::Vault;
+        config.vault_addr = Some(vault_addr.clone());
+        config.vault_token = Some("vault-root".to_string());
+        config.vault_secret_path = Some("secret/data/nvd".to_string());
+    };
+
+    // Fetch the token the way startup does, then serve with it
+    let mut config = nvd_support_car::config::AppConfig::default();
+    vault_config(&mut config);
+    let token = secrets::resolve_ingest_token(&config)
+        .await
+        .expect("Failed to fetch token from Vault");
+    assert_eq!(token, "vault-issued-token-1");
+
+    let db = TestDatabase::new()
+        .await
+        .expect("Failed to create test database");
+    let server = TestServer::start_with_tls_config(db.pool.clone(), |config| {
+        vault_config(config);
+        config.ingest_token.clone_from(&token);
+    })
+    .await
+    .expect("Failed to start server");
+    let client = server
+        .create_http_client()
+        .expect("Failed to create client");
+    let count_url = format!("{}/gottcha2/count", server.base_url);
+
+    let status_with = |bearer: &'static str| {
+        let request = client
+            .get(&count_url)
+            .header("Authorization", format!("Bearer {bearer}"));
+        async move {
+            request
+                .send()
+                .await
+                .expect("Failed to send request")
+                .status()
+        }
+    };
+    assert_eq!(status_with("vault-issued-token-1").await, StatusCode::OK);
+    assert_eq!(
+        status_with("test_e2e_token_secure_12345").await,
+        StatusCode::UNAUTHORIZED
+    );
+
+    // A rotation in Vault is picked up by the refresher
+    *secret.lock().expect("Mock secret lock poisoned") = "vault-issued-token-2".to_string();
+    let _refresher =
+        secrets::spawn_token_refresher(server.state().clone(), std::time::Duration::from_secs(1));
+    tokio::time::sleep(std::time::Duration::from_millis(1500)).await;
+    assert_eq!(status_with("vault-issued-token-2").await, StatusCode::OK);
+    assert_eq!(
+        status_with("vault-issued-token-1").await,
+        StatusCode::UNAUTHORIZED
+    );
+
+    // A Vault that refuses the server's credentials fails startup
+    config.vault_token = Some("wrong".to_string());
+    let err = secrets::resolve_ingest_token(&config)
+        .await
+        .expect_err("A forbidden Vault request should fail");
+    assert!(err.to_string().contains("403"), "got: {err}");
+}
+
+#[tokio::test]
+async fn test_e2e_msgpack_responses_when_accepted() {
+    #[derive(serde::Deserialize, Debug, PartialEq)]
+    struct StastSummary {
+        inserted: u64,
+        filtered: u64,
+    }
+
+    #[derive(serde::Deserialize, Debug, PartialEq)]
+    struct Count {
+        count: i64,
+    }
+
+    let db = TestDatabase::new()
+        .await
+        .expect("Failed to create test database");
+    let server = TestServer::start_with_tls(db.pool.clone())
+        .await
+        .expect("Failed to start server");
+    let client = server
+        .create_http_client()
+        .expect("Failed to create client");
+    let auth = format!("Bearer {}", server.bearer_token);
+
+    let records = vec![
+        stast_record("NODE_1", 100.0, 1e-10),
+        stast_record("NODE_2", 10.0, 1e-10),
+    ];
+    let response = client
+        .post(format!("{}/ingest-stast?min_bitscore=50", server.base_url))
+        .header("Authorization", &auth)
+        .header("Accept", "application/msgpack")
+        .body(gzip_jsonl(&records))
+        .send()
+        .await
+        .expect("Failed to send request");
+    assert_eq!(response.status(), StatusCode::OK);
+    assert_eq!(response.headers()["content-type"], "application/msgpack");
+    let body = response.bytes().await.expect("Failed to read body");
+    let summary: StastSummary = rmp_serde::from_slice(&body).expect("Invalid msgpack");
+    assert_eq!(
+        summary,
+        StastSummary {
+            inserted: 1,
+            filtered: 1
+        }
+    );
+
+    let response = client
+        .post(format!("{}/ingest-gottcha2", server.base_url))
+        .header("Authorization", &auth)
+        .body(gzip_jsonl(&[gottcha2_record("SRR1", "genus", "561")]))
+        .send()
+        .await
+        .expect("Failed to send request");
+    assert_eq!(response.status(), StatusCode::OK);
+    let response = client
+        .get(format!("{}/gottcha2/count", server.base_url))
+        .header("Authorization", &auth)
+        .header("Accept", "application/json;q=0.5, application/msgpack")
+        .send()
+        .await
+        .expect("Failed to send request");
+    assert_eq!(response.headers()["content-type"], "application/msgpack");
+    let body = response.bytes().await.expect("Failed to read body");
+    let count: Count = rmp_serde::from_slice(&body).expect("Invalid msgpack");
+    assert_eq!(count, Count { count: 1 });
+
+    // JSON stays the default
+    let response = client
+        .get(format!("{}/gottcha2/count", server.base_url))
+        .header("Authorization", &auth)
+        .send()
+        .await
+        .expect("Failed to send request");
+    assert_eq!(response.headers()["content-type"], "application/json");
+    let json: serde_json::Value = response.json().await.expect("Invalid JSON");
+    assert_eq!(json["count"], count.count);
+}
+
+/// Makes one TLS connection and HTTP request with `config`, returning how
+/// the handshake went. Reading the response to the end lets the client
+/// receive the session tickets the server sends after the handshake.
+async fn tls_handshake_kind(
+    server: &TestServer,
+    config: &std::sync::Arc<rustls::ClientConfig>,
+) -> Option<rustls::HandshakeKind> {
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+    let tcp = tokio::net::TcpStream::connect(server.addr)
+        .await
+        .expect("Failed to connect");
+    let server_name =
+        rustls::pki_types::ServerName::try_from("localhost").expect("Invalid server name");
+    let mut tls = tokio_rustls::TlsConnector::from(std::sync::Arc::clone(config))
+        .connect(server_name, tcp)
+        .await
+        .expect("TLS handshake failed");
+    tls.write_all(b"GET /healthz HTTP/1.1\r\nHost: localhost\r\nConnection: close\r\n\r\n")
+        .await
+        .expect("Failed to send request");
+    let mut response = Vec::new();
+    // The server may close without close_notify; the response is what matters
+    let _ = tls.read_to_end(&mut response).await;
+    assert!(response.starts_with(b"HTTP/1.1 200"), "health check failed");
+    tls.get_ref().1.handshake_kind()
+}
+
+#[tokio::test]
+async fn test_e2e_repeat_tls_client_resumes_session() {
+    let db = TestDatabase::new()
+        .await
+        .expect("Failed to create test database");
+    let server = TestServer::start_with_tls(db.pool.clone())
+        .await
+        .expect("Failed to start server");
+    let client_config = server
+        .create_tls_client_config()
+        .expect("Failed to create client config");
+
+    assert_eq!(
+        tls_handshake_kind(&server, &client_config).await,
+        Some(rustls::HandshakeKind::Full)
+    );
+    assert_eq!(
+        tls_handshake_kind(&server, &client_config).await,
+        Some(rustls::HandshakeKind::Resumed)
+    );
+
+    // With both the cache and tickets off, every connection is a full handshake
+    let server = TestServer::start_with_tls_config(db.pool.clone(), |config| {
+        config.tls_session_cache_size = 0;
+        config.tls_session_tickets = false;
+    })
+    .await
+    .expect("Failed to start server");
+    let client_config = server
+        .create_tls_client_config()
+        .expect("Failed to create client config");
+    for _ in 0..2 {
+        assert_eq!(
+            tls_handshake_kind(&server, &client_config).await,
+            Some(rustls::HandshakeKind::Full)
+        );
+    }
+}
+
+#[tokio::test]
+async fn test_e2e_gottcha2_v1_and_v2_layouts_land_in_current_table() {
+    let db = TestDatabase::new()
+        .await
+        .expect("Failed to create test database");
+    let server = TestServer::start_with_tls(db.pool.clone())
+        .await
+        .expect("Failed to start server");
+    let client = server
+        .create_http_client()
+        .expect("Failed to create client");
+    let url = format!("{}/ingest-gottcha2", server.base_url);
+    let auth = format!("Bearer {}", server.bearer_token);
+
+    // Version 1 lines predate the mapping statistics
+    let v1_line = |sample_id: &str| {
+        serde_json::json!({
+            "sample_id": sample_id,
+            "level": "species",
+            "name": "Taxon_1",
+            "taxid": "1",
+            "read_count": 100,
+            "rel_abundance": 0.1,
+        })
+    };
+    let post = |body: Vec<u8>, version: Option<&'static str>| {
+        let mut request = client.post(&url).header("Authorization", &auth).body(body);
+        if let Some(version) = version {
+            request = request.header("X-Schema-Version", version);
+        }
+        request.send()
+    };
+
+    let response = post(gzip_jsonl(&[v1_line("v1_header")]), Some("1"))
+        .await
+        .expect("Failed to send request");
+    assert_eq!(response.status(), StatusCode::OK);
+
+    let mut declared = v1_line("v1_field");
+    declared["schema_version"] = serde_json::json!(1);
+    let response = post(gzip_jsonl(&[declared]), None)
+        .await
+        .expect("Failed to send request");
+    assert_eq!(response.status(), StatusCode::OK);
+
+    let response = post(
+        gzip_jsonl(&[gottcha2_record("v2", "species", "1")]),
+        Some("2"),
+    )
+    .await
+    .expect("Failed to send request");
+    assert_eq!(response.status(), StatusCode::OK);
+
+    // Without a version, lines are held to the current layout
+    let response = post(gzip_jsonl(&[v1_line("unversioned")]), None)
+        .await
+        .expect("Failed to send request");
+    assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+
+    for sample_id in ["v1_header", "v1_field"] {
+        let (total_bp_mapped, depth): (i64, f64) = sqlx::query_as(
+            "SELECT total_bp_mapped, depth FROM gottcha2_results WHERE sample_id = $1",
+        )
+        .bind(sample_id)
+        .fetch_one(&db.pool)
+        .await
+        .expect("Failed to fetch v1 row");
+        assert_eq!((total_bp_mapped, depth), (0, 0.0));
+    }
+    let total_bp_mapped: i64 =
+        sqlx::query_scalar("SELECT total_bp_mapped FROM gottcha2_results WHERE sample_id = 'v2'")
+            .fetch_one(&db.pool)
+            .await
+            .expect("Failed to fetch v2 row");
+    assert_eq!(total_bp_mapped, 5000);
+
+    // Unknown versions are refused, whether for the upload or one line
+    let response = post(gzip_jsonl(&[v1_line("v9")]), Some("9"))
+        .await
+        .expect("Failed to send request");
+    assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+    let body = response.text().await.expect("Failed to read body");
+    assert!(
+        body.contains("unsupported X-Schema-Version 9"),
+        "got: {body}"
+    );
+
+    let mut declared = v1_line("v9");
+    declared["schema_version"] = serde_json::json!(9);
+    let response = post(gzip_jsonl(&[declared]), None)
+        .await
+        .expect("Failed to send request");
+    assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+    let body = response.text().await.expect("Failed to read body");
+    assert!(
+        body.contains("line 1: unsupported schema_version 9"),
+        "got: {body}"
+    );
+}
+
+#[tokio::test]
+async fn test_e2e_read_limit_rejects_excess_with_503() {
+    let db = TestDatabase::new()
+        .await
+        .expect("Failed to create test database");
+    // Enough rows that an unread export backs up and keeps streaming
+    sqlx::query(
+        "INSERT INTO gottcha2_results
+             (sample_id, level, name, taxid, read_count, total_bp_mapped, ani_ci95,
+              covered_sig_len, best_sig_cov, depth, rel_abundance)
+         SELECT 'heavy', 'species', 'Taxon_' || i, i::text, 100, 5000, 0.95, 1000, 0.85, 10.0, 0.1
+         FROM generate_series(1, 100000) AS i",
+    )
+    .execute(&db.pool)
+    .await
+    .expect("Failed to seed rows");
+    let server = TestServer::start_with_tls_config(db.pool.clone(), |config| {
+        config.max_concurrent_reads = Some(1);
+    })
+    .await
+    .expect("Failed to start server");
+    let client = server
+        .create_http_client()
+        .expect("Failed to create client");
+    let base_url = &server.base_url;
+    let auth = format!("Bearer {}", server.bearer_token);
+    let count = || {
+        client
+            .get(format!("{base_url}/gottcha2/count?sample_id=heavy"))
+            .header("Authorization", &auth)
+            .send()
+    };
+
+    // Holding the export's body unread keeps its read slot taken
+    let export = client
+        .get(format!(
+            "{base_url}/gottcha2/export?sample_id=heavy&encoding=none"
+        ))
+        .header("Authorization", &auth)
+        .send()
+        .await
+        .expect("Failed to send request");
+    assert_eq!(export.status(), StatusCode::OK);
+
+    let excess = count().await.expect("Failed to send request");
+    assert_eq!(excess.status(), StatusCode::SERVICE_UNAVAILABLE);
+    let body: serde_json::Value = excess.json().await.expect("Failed to parse body");
+    assert_eq!(body["error"], "too_many_concurrent_reads");
+
+    // Reads are limited independently of ingests
+    let response = client
+        .post(format!("{base_url}/ingest-gottcha2"))
+        .header("Authorization", &auth)
+        .body(gzip_jsonl(&[gottcha2_record("light", "species", "562")]))
+        .send()
+        .await
+        .expect("Failed to send request");
+    assert_eq!(response.status(), StatusCode::OK);
+
+    // Abandoning the export frees its slot
+    drop(export);
+    let mut after = count().await.expect("Failed to send request");
+    for _ in 0..20 {
+        if after.status() == StatusCode::OK {
+            break;
+        }
+        tokio::time::sleep(std::time::Duration::from_millis(50)).await;
+        after = count().await.expect("Failed to send request");
+    }
+    assert_eq!(after.status(), StatusCode::OK);
+}
+
+#[tokio::test]
+async fn test_e2e_export_as_csv_is_parseable() {
+    let db = TestDatabase::new()
+        .await
+        .expect("Failed to create test database");
+    let server = TestServer::start_with_tls(db.pool.clone())
+        .await
+        .expect("Failed to start server");
+    let client = server
+        .create_http_client()
+        .expect("Failed to create client");
+    let base_url = &server.base_url;
+    let auth = format!("Bearer {}", server.bearer_token);
+
+    let mut awkward = gottcha2_record("csv_sample", "species", "562");
+    awkward.name = "Escherichia coli, \"K-12\"\nsubstr. MG1655".to_string();
+    let records = vec![
+        awkward,
+        gottcha2_record("csv_sample", "genus", "561"),
+        gottcha2_record("csv_sample", "family", "543"),
+    ];
+    let response = client
+        .post(format!("{base_url}/ingest-gottcha2"))
+        .header("Authorization", &auth)
+        .body(gzip_jsonl(&records))
+        .send()
+        .await
+        .expect("Failed to send request");
+    assert_eq!(response.status(), StatusCode::OK);
+
+    let response = client
+        .get(format!(
+            "{base_url}/gottcha2/export?sample_id=csv_sample&format=csv&encoding=none"
+        ))
+        .header("Authorization", &auth)
+        .send()
+        .await
+        .expect("Failed to send request");
+    assert_eq!(response.status(), StatusCode::OK);
+    assert!(
+        response.headers()["content-type"]
+            .to_str()
+            .expect("Invalid header")
+            .starts_with("text/csv")
+    );
+    let body = response.bytes().await.expect("Failed to read body");
+
+    let mut reader = csv::Reader::from_reader(body.as_ref());
+    let header: Vec<String> = reader
+        .headers()
+        .expect("Missing CSV header")
+        .iter()
+        .map(str::to_string)
+        .collect();
+    let expected: Vec<&str> = Gottcha2FullRecord::column_names()
+        .split(',')
+        .map(str::trim)
+        .collect();
+    assert_eq!(header, expected);
+
+    let rows: Vec<csv::StringRecord> = reader
+        .records()
+        .collect::<Result<_, _>>()
+        .expect("Invalid CSV row");
+    assert_eq!(rows.len(), records.len());
+    let name = header
+        .iter()
+        .position(|column| column == "name")
+        .expect("No name column");
+    assert_eq!(&rows[0][name], records[0].name);
+    assert_eq!(&rows[1][name], "Taxon_561");
+}
+
+#[tokio::test]
+async fn test_e2e_oversized_headers_get_431() {
+    let db = TestDatabase::new()
+        .await
+        .expect("Failed to create test database");
+    let server = TestServer::start_with_tls_config(db.pool.clone(), |config| {
+        config.http_port = Some(0);
+        config.max_header_count = 20;
+    })
+    .await
+    .expect("Failed to start server");
+    let http_base_url = server
+        .http_base_url
+        .as_ref()
+        .expect("Plaintext listener should be running");
+    let url = format!("{http_base_url}/healthz");
+    let client = reqwest::Client::new();
+
+    // A typical authenticated request fits comfortably
+    let response = client
+        .get(&url)
+        .header("Authorization", format!("Bearer {}", server.bearer_token))
+        .header("Content-Encoding", "gzip")
+        .send()
+        .await
+        .expect("Failed to send request");
+    assert_eq!(response.status(), StatusCode::OK);
+
+    let mut request = client.get(&url);
+    for i in 0..50 {
+        request = request.header(format!("x-padding-{i}"), "x");
+    }
+    let response = request.send().await.expect("Failed to send request");
+    assert_eq!(
+        response.status(),
+        StatusCode::REQUEST_HEADER_FIELDS_TOO_LARGE
+    );
+
+    let response = client
+        .get(&url)
+        .header("x-padding", "x".repeat(100 * 1024))
+        .send()
+        .await
+        .expect("Failed to send request");
+    assert_eq!(
+        response.status(),
+        StatusCode::REQUEST_HEADER_FIELDS_TOO_LARGE
+    );
+
+    // HTTP/2 over TLS is bounded by size too
+    let response = server
+        .create_http_client()
+        .expect("Failed to create client")
+        .get(format!("{}/healthz", server.base_url))
+        .header("x-padding", "x".repeat(100 * 1024))
+        .send()
+        .await
+        .expect("Failed to send request");
+    assert_eq!(
+        response.status(),
+        StatusCode::REQUEST_HEADER_FIELDS_TOO_LARGE
+    );
+}
+
+#[tokio::test]
+async fn test_e2e_content_hash_tracks_record_changes() {
+    let db = TestDatabase::new()
+        .await
+        .expect("Failed to create test database");
+    // Provenance columns differ per line and must not leak into the hash
+    let server = TestServer::start_with_tls_config(db.pool.clone(), |config| {
+        config.store_content_hash = true;
+        config.store_raw_line = true;
+        config.preserve_input_order = true;
+    })
+    .await
+    .expect("Failed to start server");
+    let client = server
+        .create_http_client()
+        .expect("Failed to create client");
+    let url = format!("{}/ingest-gottcha2", server.base_url);
+    let auth = format!("Bearer {}", server.bearer_token);
+
+    let original = gottcha2_record("hashed", "species", "562");
+    // Another level, as the same taxon and level would be skipped as a repeat
+    let mut changed = original.clone();
+    changed.level = "genus".to_string();
+    for upload in [
+        vec![original.clone(), changed],
+        vec![gottcha2_record("other", "genus", "561"), original.clone()],
+    ] {
+        let response = client
+            .post(&url)
+            .header("Authorization", &auth)
+            .body(gzip_jsonl(&upload))
+            .send()
+            .await
+            .expect("Failed to send request");
+        assert_eq!(response.status(), StatusCode::OK);
+    }
+
+    let hashes: Vec<(i64, String)> = sqlx::query_as(
+        "SELECT read_count, content_hash FROM gottcha2_results
+         WHERE sample_id = 'hashed' ORDER BY id",
+    )
+    .fetch_all(&db.pool)
+    .await
+    .expect("Failed to fetch hashes");
+    assert_eq!(hashes.len(), 2, "the re-sent record should be skipped");
+    let (first, changed) = (&hashes[0], &hashes[1]);
+    assert_eq!(first.1.len(), 64, "expected a hex blake3 hash");
+    assert_eq!(
+        first.1,
+        content_hash(&original).expect("Failed to hash"),
+        "identical records should hash alike"
+    );
+    assert_ne!(first.1, changed.1, "a changed field should change the hash");
+
+    // A corrected row is rehashed as if it had been uploaded that way
+    let id: i64 = sqlx::query_scalar(
+        "SELECT id FROM gottcha2_results WHERE sample_id = 'hashed' AND level = 'species'",
+    )
+    .fetch_one(&db.pool)
+    .await
+    .expect("Failed to fetch id");
+    let mut corrected = original.clone();
+    corrected.read_count += 1;
+    let response = client
+        .patch(format!("{}/gottcha2/{id}", server.base_url))
+        .header("Authorization", &auth)
+        .json(&serde_json::json!({ "read_count": corrected.read_count }))
+        .send()
+        .await
+        .expect("Failed to send request");
+    assert_eq!(response.status(), StatusCode::OK);
+    let row: serde_json::Value = response.json().await.expect("Failed to read body");
+    assert_eq!(
+        row["content_hash"],
+        content_hash(&corrected).expect("Failed to hash"),
+        "a patched row should be rehashed"
+    );
+}
+
+/// Attempts a TLS handshake naming `server_name`, which for an IP address
+/// means the client offers no SNI.
+async fn tls_handshake(
+    server: &TestServer,
+    server_name: &'static str,
+) -> Result<(), std::io::Error> {
+    let config = server
+        .create_tls_client_config()
+        .expect("Failed to build client config");
+    let tcp = tokio::net::TcpStream::connect(server.addr)
+        .await
+        .expect("Failed to connect");
+    let server_name =
+        rustls::pki_types::ServerName::try_from(server_name).expect("Invalid server name");
+    tokio_rustls::TlsConnector::from(config)
+        .connect(server_name, tcp)
+        .await
+        .map(drop)
+}
+
+#[tokio::test]
+async fn test_e2e_strict_sni_rejects_unexpected_server_names() {
+    let db = TestDatabase::new()
+        .await
+        .expect("Failed to create test database");
+
+    // Permissive by default: any SNI, or none, is served
+    let server = TestServer::start_with_tls(db.pool.clone())
+        .await
+        .expect("Failed to start server");
+    tls_handshake(&server, "localhost")
+        .await
+        .expect("Default config should accept localhost");
+    tls_handshake(&server, "127.0.0.1")
+        .await
+        .expect("Default config should accept a handshake without SNI");
+
+    let server = TestServer::start_with_tls_config(db.pool.clone(), |config| {
+        config.tls_allowed_server_names = vec!["nvd.example".to_string(), "LOCALHOST".to_string()];
+    })
+    .await
+    .expect("Failed to start server");
+    tls_handshake(&server, "localhost")
+        .await
+        .expect("An allowed name should be matched case-insensitively");
+    // The client trusts the certificate for 127.0.0.1, so a failure here is
+    // the server refusing the missing SNI
+    let err = tls_handshake(&server, "127.0.0.1")
+        .await
+        .expect_err("A handshake without SNI should be refused");
+    assert!(
+        err.to_string().contains("received fatal alert"),
+        "got: {err}"
+    );
+
+    let server = TestServer::start_with_tls_config(db.pool.clone(), |config| {
+        config.tls_allowed_server_names = vec!["nvd.example".to_string()];
+    })
+    .await
+    .expect("Failed to start server");
+    let err = tls_handshake(&server, "localhost")
+        .await
+        .expect_err("A mismatched SNI should be refused");
+    assert!(
+        err.to_string().contains("received fatal alert"),
+        "got: {err}"
+    );
+}
+
+#[tokio::test]
+async fn test_e2e_pause_with_maintenance_message() {
+    let db = TestDatabase::new()
+        .await
+        .expect("Failed to create test database");
+    let server = TestServer::start_with_tls_config(db.pool.clone(), |config| {
+        config.maintenance_message = Some("Scheduled database maintenance".to_string());
+        config.admin_token = Some("admin-secret".to_string());
+    })
+    .await
+    .expect("Failed to start server");
+    let client = server
+        .create_http_client()
+        .expect("Failed to create client");
+    let base_url = &server.base_url;
+    let auth = format!("Bearer {}", server.bearer_token);
+    let body = gzip_jsonl(&[gottcha2_record("maintenance", "species", "562")]);
+    let ingest = || {
+        client
+            .post(format!("{base_url}/ingest-gottcha2"))
+            .header("Authorization", &auth)
+            .body(body.clone())
+            .send()
+    };
+
+    // Without a message of its own, the pause uses the configured one
+    let response = client
+        .post(format!("{base_url}/admin/pause"))
+        .header("Authorization", "Bearer admin-secret")
+        .send()
+        .await
+        .expect("Request failed");
+    assert_eq!(response.status(), StatusCode::OK);
+    let response = ingest().await.expect("Request failed");
+    assert_eq!(response.status(), StatusCode::SERVICE_UNAVAILABLE);
+    let rejection: serde_json::Value = response.json().await.expect("Failed to read body");
+    assert_eq!(rejection["error"], "maintenance");
+    assert_eq!(rejection["message"], "Scheduled database maintenance");
+    assert!(rejection["retry_after"].is_null());
+
+    let response = client
+        .post(format!("{base_url}/admin/pause"))
+        .header("Authorization", "Bearer admin-secret")
+        .json(&serde_json::json!({
+            "message": "Upgrading to Postgres 17",
+            "retry_after_secs": 600,
+        }))
+        .send()
+        .await
+        .expect("Request failed");
+    assert_eq!(response.status(), StatusCode::OK);
+    let response = ingest().await.expect("Request failed");
+    assert_eq!(response.status(), StatusCode::SERVICE_UNAVAILABLE);
+    assert_eq!(response.headers()["retry-after"], "600");
+    let rejection: serde_json::Value = response.json().await.expect("Failed to read body");
+    assert_eq!(rejection["error"], "maintenance");
+    assert_eq!(rejection["message"], "Upgrading to Postgres 17");
+    assert_eq!(rejection["retry_after"], 600);
+
+    let response = client
+        .post(format!("{base_url}/admin/resume"))
+        .header("Authorization", "Bearer admin-secret")
+        .send()
+        .await
+        .expect("Request failed");
+    assert_eq!(response.status(), StatusCode::OK);
+    let response = ingest().await.expect("Request failed");
+    assert_eq!(response.status(), StatusCode::OK);
+}
+
+#[tokio::test]
+async fn test_e2e_observed_at_is_stored_when_present() {
+    let db = TestDatabase::new()
+        .await
+        .expect("Failed to create test database");
+    let server = TestServer::start_with_tls(db.pool.clone())
+        .await
+        .expect("Failed to start server");
+    let client = server
+        .create_http_client()
+        .expect("Failed to create client");
+    let auth = format!("Bearer {}", server.bearer_token);
+
+    let line = |sample_id: &str, field: Option<(&str, &str)>| {
+        let mut value = serde_json::to_value(gottcha2_record(sample_id, "species", "562"))
+            .expect("Failed to serialize");
+        if let Some((name, timestamp)) = field {
+            value[name] = serde_json::json!(timestamp);
+        }
+        value
+    };
+    let lines = vec![
+        line("observed", Some(("observed_at", "2024-03-01T12:30:00Z"))),
+        line("stamped", Some(("timestamp", "2024-03-02T08:00:00+02:00"))),
+        line("untimed", None),
+    ];
+    let response = client
+        .post(format!("{}/ingest-gottcha2", server.base_url))
+        .header("Authorization", &auth)
+        .body(gzip_jsonl(&lines))
+        .send()
+        .await
+        .expect("Failed to send request");
+    assert_eq!(response.status(), StatusCode::OK);
+
+    let rows: Vec<(String, Option<String>)> = sqlx::query_as(
+        "SELECT sample_id, to_char(observed_at AT TIME ZONE 'UTC', 'YYYY-MM-DD HH24:MI:SS')
+         FROM gottcha2_results ORDER BY sample_id",
+    )
+    .fetch_all(&db.pool)
+    .await
+    .expect("Failed to fetch rows");
+    assert_eq!(
+        rows,
+        vec![
+            (
+                "observed".to_string(),
+                Some("2024-03-01 12:30:00".to_string())
+            ),
+            (
+                "stamped".to_string(),
+                Some("2024-03-02 06:00:00".to_string())
+            ),
+            ("untimed".to_string(), None),
+        ]
+    );
+
+    // A timestamp that isn't RFC 3339 fails the line like any bad field
+    let response = client
+        .post(format!("{}/ingest-gottcha2", server.base_url))
+        .header("Authorization", &auth)
+        .body(gzip_jsonl(&[line(
+            "garbled",
+            Some(("observed_at", "yesterday")),
+        )]))
+        .send()
+        .await
+        .expect("Failed to send request");
+    assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+}
+
+#[tokio::test]
+async fn test_e2e_json_array_body_matches_jsonl() {
+    let db = TestDatabase::new()
+        .await
+        .expect("Failed to create test database");
+    let server = TestServer::start_with_tls_config(db.pool.clone(), |_| {})
+        .await
+        .expect("Failed to start server");
+    let client = server
+        .create_http_client()
+        .expect("Failed to create client");
+    let auth = format!("Bearer {}", server.bearer_token);
+
+    let records = [
+        gottcha2_record("array_sample", "species", "562"),
+        gottcha2_record("array_sample", "genus", "561"),
+        gottcha2_record("array_sample", "family", "543"),
+    ];
+    let array = serde_json::to_string_pretty(&records).expect("Failed to serialize");
+    let mut encoder = GzEncoder::new(Vec::new(), Compression::default());
+    encoder
+        .write_all(array.as_bytes())
+        .expect("Failed to write");
+    let array_body = encoder.finish().expect("Failed to compress");
+
+    let mut stored = Vec::new();
+    for body in [gzip_jsonl(&records), array_body] {
+        let response = client
+            .post(format!("{}/ingest-gottcha2", server.base_url))
+            .header("Authorization", &auth)
+            .body(body)
+            .send()
+            .await
+            .expect("Failed to send request");
+        assert_eq!(response.status(), StatusCode::OK);
+
+        let rows: Vec<(String, String, String, i64, f64)> = sqlx::query_as(
+            "SELECT sample_id, level, taxid, read_count, rel_abundance
+             FROM gottcha2_results ORDER BY taxid",
+        )
+        .fetch_all(&db.pool)
+        .await
+        .expect("Failed to fetch rows");
+        sqlx::query("DELETE FROM gottcha2_results")
+            .execute(&db.pool)
+            .await
+            .expect("Failed to clear rows");
+        stored.push(rows);
+    }
+
+    assert_eq!(stored[0].len(), 3);
+    assert_eq!(stored[0], stored[1]);
+}
+
+#[tokio::test]
+async fn test_e2e_sample_gottcha2_rows_are_read_back() {
+    let db = TestDatabase::new()
+        .await
+        .expect("Failed to create test database");
+    let server = TestServer::start_with_tls_config(db.pool.clone(), |_| {})
+        .await
+        .expect("Failed to start server");
+    let client = server
+        .create_http_client()
+        .expect("Failed to create client");
+    let base_url = &server.base_url;
+    let auth = format!("Bearer {}", server.bearer_token);
+
+    let records = [
+        gottcha2_record("lookup", "species", "562"),
+        gottcha2_record("lookup", "genus", "561"),
+        gottcha2_record("lookup", "family", "543"),
+        gottcha2_record("other", "species", "1280"),
+    ];
+    let response = client
+        .post(format!("{base_url}/ingest-gottcha2"))
+        .header("Authorization", &auth)
+        .body(gzip_jsonl(&records))
+        .send()
+        .await
+        .expect("Failed to send request");
+    assert_eq!(response.status(), StatusCode::OK);
+
+    let response = client
+        .get(format!("{base_url}/samples/lookup/gottcha2"))
+        .header("Authorization", &auth)
+        .send()
+        .await
+        .expect("Failed to send request");
+    assert_eq!(response.status(), StatusCode::OK);
+    let rows: Vec<serde_json::Value> = response.json().await.expect("Failed to read body");
+    let taxids: Vec<&str> = rows
+        .iter()
+        .filter_map(|row| row["taxid"].as_str())
+        .collect();
+    assert_eq!(taxids, ["562", "561", "543"]);
+    assert!(rows.iter().all(|row| row["sample_id"] == "lookup"));
+    assert_eq!(rows[0]["read_count"], 100);
+
+    let response = client
+        .get(format!("{base_url}/samples/lookup/gottcha2?limit=2"))
+        .header("Authorization", &auth)
+        .send()
+        .await
+        .expect("Failed to send request");
+    let rows: Vec<serde_json::Value> = response.json().await.expect("Failed to read body");
+    assert_eq!(rows.len(), 2);
+
+    let response = client
+        .get(format!("{base_url}/samples/missing/gottcha2"))
+        .header("Authorization", &auth)
+        .send()
+        .await
+        .expect("Failed to send request");
+    assert_eq!(response.status(), StatusCode::NOT_FOUND);
+
+    let response = client
+        .get(format!("{base_url}/samples/lookup/gottcha2"))
+        .send()
+        .await
+        .expect("Failed to send request");
+    assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+}
+
+#[tokio::test]
+async fn test_e2e_request_id_is_echoed_and_cited_in_errors() {
+    let db = TestDatabase::new()
+        .await
+        .expect("Failed to create test database");
+    let server = TestServer::start_with_tls(db.pool.clone())
+        .await
+        .expect("Failed to start server");
+    let client = server
+        .create_http_client()
+        .expect("Failed to create client");
+
+    let response = client
+        .post(format!("{}/ingest-gottcha2", server.base_url))
+        .header("Authorization", format!("Bearer {}", server.bearer_token))
+        .header("X-Request-Id", "client-req-42")
+        .body(b"not gzip at all".to_vec())
+        .send()
+        .await
+        .expect("Failed to send request");
+    assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+    assert_eq!(response.headers()["x-request-id"], "client-req-42");
+    let message = response.text().await.expect("Failed to read body");
+    assert!(message.ends_with("(request id client-req-42)"), "{message}");
+
+    // Without one, each response gets a fresh id, probes included
+    let mut generated = Vec::new();
+    for _ in 0..2 {
+        let response = client
+            .get(format!("{}/healthz", server.base_url))
+            .send()
+            .await
+            .expect("Failed to send request");
+        let id = response.headers()["x-request-id"]
+            .to_str()
+            .expect("Invalid request id")
+            .to_string();
+        assert!(uuid::Uuid::parse_str(&id).is_ok(), "{id}");
+        generated.push(id);
+    }
+    assert_ne!(generated[0], generated[1]);
+}
+
+#[tokio::test]
+async fn test_e2e_generic_ingest_route_dispatches_on_record_type() {
+    let db = TestDatabase::new()
+        .await
+        .expect("Failed to create test database");
+    let server = TestServer::start_with_tls(db.pool.clone())
+        .await
+        .expect("Failed to start server");
+    let client = server
+        .create_http_client()
+        .expect("Failed to create client");
+    let auth = format!("Bearer {}", server.bearer_token);
+    let records = [
+        gottcha2_record("generic", "species", "562"),
+        gottcha2_record("generic", "genus", "561"),
+    ];
+
+    // Query parameters reach the type's own handler
+    let response = client
+        .post(format!(
+            "{}/ingest/gottcha2?breakdown=true",
+            server.base_url
+        ))
+        .header("Authorization", &auth)
+        .body(gzip_jsonl(&records))
+        .send()
+        .await
+        .expect("Failed to send request");
+    assert_eq!(response.status(), StatusCode::OK);
+    let body: serde_json::Value = response.json().await.expect("Failed to read body");
+    assert_eq!(body["inserted"], 2);
+    assert_eq!(body["by_sample"]["generic"], 2);
+    assert_eq!(count_sample_rows(&db, "generic").await, 2);
+
+    let response = client
+        .post(format!("{}/ingest/unknown", server.base_url))
+        .header("Authorization", &auth)
+        .body(gzip_jsonl(&records))
+        .send()
+        .await
+        .expect("Failed to send request");
+    assert_eq!(response.status(), StatusCode::NOT_FOUND);
+    let message = response.text().await.expect("Failed to read body");
+    assert!(message.contains("gottcha2"), "{message}");
+
+    for route in ["/ingest/unknown", "/ingest/gottcha2"] {
+        let response = client
+            .post(format!("{}{route}", server.base_url))
+            .body(gzip_jsonl(&records))
+            .send()
+            .await
+            .expect("Failed to send request");
+        assert_eq!(response.status(), StatusCode::UNAUTHORIZED, "{route}");
+    }
+}
+
+#[tokio::test]
+async fn test_e2e_idempotency_key_skips_a_repeated_upload() {
+    let db = TestDatabase::new()
+        .await
+        .expect("Failed to create test database");
+    let server = TestServer::start_with_tls(db.pool.clone())
+        .await
+        .expect("Failed to start server");
+    let client = server
+        .create_http_client()
+        .expect("Failed to create client");
+    let auth = format!("Bearer {}", server.bearer_token);
+    let url = format!("{}/ingest-gottcha2", server.base_url);
+    let records = [
+        gottcha2_record("idem", "species", "562"),
+        gottcha2_record("idem", "genus", "561"),
+    ];
+
+    let response = client
+        .post(&url)
+        .header("Authorization", &auth)
+        .header("Idempotency-Key", "upload-1")
+        .body(gzip_jsonl(&records))
+        .send()
+        .await
+        .expect("Failed to send request");
+    assert_eq!(response.status(), StatusCode::OK);
+    let body: serde_json::Value = response.json().await.expect("Failed to read body");
+    assert_eq!(body["inserted"], 2);
+    assert!(body.get("status").is_none(), "{body}");
+
+    // The replay is answered from the log, so even a body that wouldn't
+    // parse goes unread
+    let response = client
+        .post(&url)
+        .header("Authorization", &auth)
+        .header("Idempotency-Key", "upload-1")
+        .body("not gzip")
+        .send()
+        .await
+        .expect("Failed to send request");
+    assert_eq!(response.status(), StatusCode::OK);
+    let body: serde_json::Value = response.json().await.expect("Failed to read body");
+    assert_eq!(
+        body,
+        serde_json::json!({ "inserted": 2, "status": "duplicate" })
+    );
+    assert_eq!(count_sample_rows(&db, "idem").await, 2);
+    assert_eq!(db.count_records("ingest_log").await.expect("count"), 1);
+
+    // A failed upload isn't recorded, so its retry is processed
+    let response = client
+        .post(&url)
+        .header("Authorization", &auth)
+        .header("Idempotency-Key", "upload-2")
+        .body("not gzip")
+        .send()
+        .await
+        .expect("Failed to send request");
+    assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+    let response = client
+        .post(&url)
+        .header("Authorization", &auth)
+        .header("Idempotency-Key", "upload-2")
+        .body(gzip_jsonl(&[gottcha2_record("idem", "family", "543")]))
+        .send()
+        .await
+        .expect("Failed to send request");
+    assert_eq!(response.status(), StatusCode::OK);
+    let body: serde_json::Value = response.json().await.expect("Failed to read body");
+    assert_eq!(body["inserted"], 1);
+    assert_eq!(count_sample_rows(&db, "idem").await, 3);
+
+    // While another upload holds the key, a second one is refused unread
+    sqlx::query(
+        "INSERT INTO ingest_log (idempotency_key, endpoint) VALUES ('upload-3', 'ingest-gottcha2')",
+    )
+    .execute(&db.pool)
+    .await
+    .expect("Failed to claim key");
+    let response = client
+        .post(&url)
+        .header("Authorization", &auth)
+        .header("Idempotency-Key", "upload-3")
+        .body(gzip_jsonl(&[gottcha2_record("idem", "order", "91347")]))
+        .send()
+        .await
+        .expect("Failed to send request");
+    assert_eq!(response.status(), StatusCode::CONFLICT);
+    assert_eq!(count_sample_rows(&db, "idem").await, 3);
+
+    // Keys are scoped to the endpoint they were sent to
+    let response = client
+        .post(format!("{}/ingest-stast", server.base_url))
+        .header("Authorization", &auth)
+        .header("Idempotency-Key", "upload-1")
+        .body(gzip_jsonl(&[stast_record("NODE_1", 100.0, 1e-10)]))
+        .send()
+        .await
+        .expect("Failed to send request");
+    assert_eq!(response.status(), StatusCode::OK);
+    let body: serde_json::Value = response.json().await.expect("Failed to read body");
+    assert_eq!(body["inserted"], 1);
+
+    let response = client
+        .post(&url)
+        .header("Authorization", &auth)
+        .header("Idempotency-Key", "  ")
+        .body(gzip_jsonl(&records))
+        .send()
+        .await
+        .expect("Failed to send request");
+    assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+}
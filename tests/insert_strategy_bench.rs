@@ -0,0 +1,235 @@
+//! Throughput comparison of insert strategies: the multi-row INSERT used in
+//! production, the same INSERT with an ON CONFLICT (upsert) clause, and COPY,
+//! and of inserting an upload's batches one at a time versus in parallel.
+//!
+//! Ignored by default so it never slows normal test runs. Run it explicitly,
+//! in release mode, with:
+//!
+//! ```sh
+//! cargo test --release --test insert_strategy_bench -- --ignored --nocapture
+//! ```
+#![allow(clippy::print_stdout)] // the report is the point of this harness
+
+mod common;
+
+use std::time::{Duration, Instant};
+
+use common::database::TestDatabase;
+use nvd_support_car::{
+    db::operations::{InsertOptions, batch_insert_from_channel},
+    models::record::{BulkInsertable, CopyInsertable, Gottcha2FullRecord, StastRecord},
+};
+use sqlx::{
+    PgPool,
+    postgres::{PgArguments, PgPoolCopyExt},
+};
+use tokio::sync::mpsc;
+
+const ROWS_PER_RUN: u32 = 20_000;
+const BATCH_SIZES: [usize; 3] = [100, 1_000, 4_000];
+const PARALLELISM: [usize; 4] = [1, 2, 4, 8];
+
+/// Wraps a record so it inserts with `ON CONFLICT DO NOTHING`, measuring what
+/// an upsert-style clause costs on top of a plain INSERT.
+#[derive(Debug, Clone)]
+struct Upsert<T>(T);
+
+impl<T: BulkInsertable> BulkInsertable for Upsert<T> {
+    fn field_count() -> usize {
+        T::field_count()
+    }
+
+    fn table_name() -> &'static str {
+        T::table_name()
+    }
+
+    fn column_names() -> &'static str {
+        T::column_names()
+    }
+
+    fn conflict_clause() -> Option<&'static str> {
+        Some(" ON CONFLICT DO NOTHING")
+    }
+
+    fn bind_to<'q>(
+        &'q self,
+        query: sqlx::query::Query<'q, sqlx::Postgres, PgArguments>,
+    ) -> sqlx::query::Query<'q, sqlx::Postgres, PgArguments> {
+        self.0.bind_to(query)
+    }
+}
+
+/// Record types the harness knows how to generate.
+trait BenchRecord: CopyInsertable + Send + 'static {
+    fn generate(i: usize) -> Self;
+}
+
+impl BenchRecord for Gottcha2FullRecord {
+    fn generate(i: usize) -> Self {
+        Gottcha2FullRecord {
+            sample_id: format!("bench_{}", i % 10)
+                .parse()
+                .expect("Invalid sample id"),
+            level: "species".to_string(),
+            name: format!("Taxon {i}"),
+            taxid: i.to_string(),
+            read_count: 100,
+            total_bp_mapped: 15_000,
+            ani_ci95: 0.97,
+            covered_sig_len: 1_200,
+            best_sig_cov: 0.8,
+            depth: 12.5,
+            rel_abundance: 0.01,
+            raw_line: None,
+            source_line: None,
+            content_hash: None,
+            observed_at: None,
+        }
+    }
+}
+
+impl BenchRecord for StastRecord {
+    fn generate(i: usize) -> Self {
+        StastRecord {
+            task: "megablast".to_string(),
+            sample_id: format!("bench_{}", i % 10)
+                .parse()
+                .expect("Invalid sample id"),
+            qseqid: format!("NODE_{i}_length_1500"),
+            qlen: 1_500,
+            sseqid: format!("gi|{i}|ref|NC_{i:06}.1|"),
+            stitle: "Benchmark virus, complete genome".to_string(),
+            length: 1_450,
+            pident: 98.7,
+            evalue: 1e-50,
+            bitscore: 2_500.0,
+            sscinames: "Benchmark virus".to_string(),
+            staxids: i.to_string(),
+            rank: "species:Benchmark virus".to_string(),
+            raw_line: None,
+            source_line: None,
+            content_hash: None,
+            observed_at: None,
+        }
+    }
+}
+
+async fn time_insert<T: BulkInsertable>(
+    db: &PgPool,
+    records: Vec<T>,
+    options: InsertOptions,
+) -> Duration {
+    let (tx, rx) = mpsc::channel(1000);
+    let started = Instant::now();
+    let producer = tokio::spawn(async move {
+        for record in records {
+            tx.send(record).await.expect("Inserter hung up");
+        }
+    });
+    batch_insert_from_channel(rx, db, options)
+        .await
+        .expect("Insert failed");
+    producer.await.expect("Producer panicked");
+    started.elapsed()
+}
+
+async fn time_copy<T: BenchRecord>(db: &PgPool, records: &[T], batch_size: usize) -> Duration {
+    let statement = format!(
+        "COPY {} ({}) FROM STDIN",
+        T::table_name(),
+        T::column_names()
+    );
+    let started = Instant::now();
+    for chunk in records.chunks(batch_size) {
+        let mut rows = String::new();
+        for record in chunk {
+            record.write_copy_row(&mut rows);
+        }
+        let mut copy = db.copy_in_raw(&statement).await.expect("COPY failed");
+        copy.send(rows.as_bytes()).await.expect("COPY send failed");
+        copy.finish().await.expect("COPY finish failed");
+    }
+    started.elapsed()
+}
+
+async fn truncate<T: BulkInsertable>(db: &PgPool) {
+    sqlx::query(&format!("TRUNCATE {}", T::table_name()))
+        .execute(db)
+        .await
+        .expect("Failed to truncate");
+}
+
+fn report(record: &str, strategy: &str, batch_size: usize, elapsed: Duration) {
+    let rows_per_sec = f64::from(ROWS_PER_RUN) / elapsed.as_secs_f64();
+    println!(
+        "{record:<12} {strategy:<8} {batch_size:>6} {:>10.1} {rows_per_sec:>12.0}",
+        elapsed.as_secs_f64() * 1000.0
+    );
+}
+
+async fn bench_record_type<T: BenchRecord>(db: &PgPool, label: &str) {
+    for batch_size in BATCH_SIZES {
+        let batch_size = batch_size.min(T::max_batch_size());
+        let generate = || {
+            (0..ROWS_PER_RUN as usize)
+                .map(T::generate)
+                .collect::<Vec<_>>()
+        };
+
+        truncate::<T>(db).await;
+        let elapsed = time_insert(db, generate(), InsertOptions::new(batch_size)).await;
+        report(label, "insert", batch_size, elapsed);
+
+        truncate::<T>(db).await;
+        let upserts = generate().into_iter().map(Upsert).collect();
+        let elapsed = time_insert(db, upserts, InsertOptions::new(batch_size)).await;
+        report(label, "upsert", batch_size, elapsed);
+
+        truncate::<T>(db).await;
+        let elapsed = time_copy(db, &generate(), batch_size).await;
+        report(label, "copy", batch_size, elapsed);
+    }
+}
+
+#[tokio::test]
+#[ignore = "benchmark; run explicitly with --ignored --nocapture"]
+async fn bench_insert_strategies() {
+    let db = TestDatabase::new()
+        .await
+        .expect("Failed to create test database");
+
+    println!(
+        "\n{:<12} {:<8} {:>6} {:>10} {:>12}",
+        "record", "strategy", "batch", "ms", "rows/s"
+    );
+    bench_record_type::<Gottcha2FullRecord>(&db.pool, "gottcha2").await;
+    bench_record_type::<StastRecord>(&db.pool, "stast").await;
+}
+
+#[tokio::test]
+#[ignore = "benchmark; run explicitly with --ignored --nocapture"]
+async fn bench_parallel_inserts() {
+    let db = TestDatabase::new()
+        .await
+        .expect("Failed to create test database");
+    let batch_size = 1_000;
+
+    println!(
+        "\n{:<12} {:<8} {:>6} {:>10} {:>12}",
+        "record", "tasks", "batch", "ms", "rows/s"
+    );
+    for parallelism in PARALLELISM {
+        truncate::<StastRecord>(&db.pool).await;
+        let records = (0..ROWS_PER_RUN as usize)
+            .map(StastRecord::generate)
+            .collect();
+        let options = InsertOptions::new(batch_size).with_parallelism(parallelism);
+        let elapsed = time_insert(&db.pool, records, options).await;
+        let stored = sqlx::query_scalar::<_, i64>("SELECT COUNT(*) FROM stast_results")
+            .fetch_one(&db.pool)
+            .await
+            .expect("Failed to count");
+        assert_eq!(stored, i64::from(ROWS_PER_RUN));
+        report("stast", &parallelism.to_string(), batch_size, elapsed);
+    }
+}
@@ -2,9 +2,20 @@ mod common;
 
 use common::database::TestDatabase;
 use nvd_support_car::{
-    db::operations::{batch_insert_gottcha2, batch_insert_stast},
-    models::record::{Gottcha2FullRecord, StastRecord},
+    config::{InsertMethod, IsolationLevel, MigrationMode},
+    db::operations::{
+        InsertOptions, batch_insert_dummy, batch_insert_gottcha2, batch_insert_stast,
+        prune_expired_idempotency_keys,
+    },
+    db::{
+        connect::connect_with_backoff,
+        ingest_log::{IngestClaim, claim_ingest, find_ingest, record_ingest, release_ingest},
+        migrations,
+    },
+    models::record::{BulkInsertable, DummyRecord, Gottcha2FullRecord, StastRecord},
 };
+use sqlx::postgres::PgPoolOptions;
+use std::time::Duration;
 use tokio::sync::mpsc;
 
 #[tokio::test]
@@ -16,11 +27,20 @@ async fn test_batch_insert_gottcha2_with_real_db() {
     let (tx, rx) = mpsc::channel(100);
 
     let pool = db.pool.clone();
-    let insert_handle = tokio::spawn(async move { batch_insert_gottcha2(rx, &pool).await });
+    let insert_handle = tokio::spawn(async move {
+        batch_insert_gottcha2(
+            rx,
+            &pool,
+            InsertOptions::new(Gottcha2FullRecord::max_batch_size()),
+        )
+        .await
+    });
 
     for i in 0..50 {
         let record = Gottcha2FullRecord {
-            sample_id: format!("batch_test_{i:03}"),
+            sample_id: format!("batch_test_{i:03}")
+                .parse()
+                .expect("Invalid sample id"),
             level: "species".to_string(),
             name: format!("Species_{i}"),
             taxid: format!("{}", 20000 + i),
@@ -31,6 +51,10 @@ async fn test_batch_insert_gottcha2_with_real_db() {
             best_sig_cov: 0.85,
             depth: 10.0,
             rel_abundance: 0.1,
+            raw_line: None,
+            source_line: None,
+            content_hash: None,
+            observed_at: None,
         };
         tx.send(record).await.expect("Failed to send record");
     }
@@ -62,12 +86,16 @@ async fn test_batch_insert_stast_with_real_db() {
     let (tx, rx) = mpsc::channel(100);
 
     let pool = db.pool.clone();
-    let insert_handle = tokio::spawn(async move { batch_insert_stast(rx, &pool).await });
+    let insert_handle = tokio::spawn(async move {
+        batch_insert_stast(rx, &pool, InsertOptions::new(StastRecord::max_batch_size())).await
+    });
 
     for i in 0..30 {
         let record = StastRecord {
             task: "megablast".to_string(),
-            sample_id: format!("stast_batch_{i:03}"),
+            sample_id: format!("stast_batch_{i:03}")
+                .parse()
+                .expect("Invalid sample id"),
             qseqid: format!("NODE_{i}_length_1000"),
             qlen: 1000,
             sseqid: format!("gi|{i}|ref|NC_000001.1|"),
@@ -79,6 +107,10 @@ async fn test_batch_insert_stast_with_real_db() {
             sscinames: "Test virus".to_string(),
             staxids: "12345".to_string(),
             rank: "species:Test virus".to_string(),
+            raw_line: None,
+            source_line: None,
+            content_hash: None,
+            observed_at: None,
         };
         tx.send(record).await.expect("Failed to send record");
     }
@@ -94,6 +126,54 @@ async fn test_batch_insert_stast_with_real_db() {
     assert_eq!(count, 30, "Expected 30 records in database");
 }
 
+#[tokio::test]
+async fn test_batch_insert_with_small_batch_size_override() {
+    let db = TestDatabase::new()
+        .await
+        .expect("Failed to create test database");
+
+    let (tx, rx) = mpsc::channel(100);
+
+    let pool = db.pool.clone();
+    let insert_handle =
+        tokio::spawn(async move { batch_insert_stast(rx, &pool, InsertOptions::new(7)).await });
+
+    for i in 0..50 {
+        let record = StastRecord {
+            task: "megablast".to_string(),
+            sample_id: format!("small_batch_{i:03}")
+                .parse()
+                .expect("Invalid sample id"),
+            qseqid: format!("NODE_{i}_length_1000"),
+            qlen: 1000,
+            sseqid: format!("gi|{i}|ref|NC_000001.1|"),
+            stitle: format!("Test virus {i}"),
+            length: 950,
+            pident: 99.5,
+            evalue: 0.0,
+            bitscore: 1800.0,
+            sscinames: "Test virus".to_string(),
+            staxids: "12345".to_string(),
+            rank: "species:Test virus".to_string(),
+            raw_line: None,
+            source_line: None,
+            content_hash: None,
+            observed_at: None,
+        };
+        tx.send(record).await.expect("Failed to send record");
+    }
+
+    drop(tx);
+    let result = insert_handle.await.expect("Insert task panicked");
+    assert!(result.is_ok(), "Batch insert should succeed");
+
+    let count = db
+        .count_records("stast_results")
+        .await
+        .expect("Failed to count records");
+    assert_eq!(count, 50, "Expected 50 records across uneven batches");
+}
+
 #[tokio::test]
 async fn test_concurrent_database_operations() {
     let db = TestDatabase::new()
@@ -108,12 +188,20 @@ async fn test_concurrent_database_operations() {
             let (tx, rx) = mpsc::channel(10);
 
             let insert_pool = pool.clone();
-            let inserter =
-                tokio::spawn(async move { batch_insert_gottcha2(rx, &insert_pool).await });
+            let inserter = tokio::spawn(async move {
+                batch_insert_gottcha2(
+                    rx,
+                    &insert_pool,
+                    InsertOptions::new(Gottcha2FullRecord::max_batch_size()),
+                )
+                .await
+            });
 
             for j in 0..10 {
                 let record = Gottcha2FullRecord {
-                    sample_id: format!("concurrent_g2_{i:02}_{j:02}"),
+                    sample_id: format!("concurrent_g2_{i:02}_{j:02}")
+                        .parse()
+                        .expect("Invalid sample id"),
                     level: "species".to_string(),
                     name: "Test".to_string(),
                     taxid: "1".to_string(),
@@ -124,6 +212,10 @@ async fn test_concurrent_database_operations() {
                     best_sig_cov: 0.85,
                     depth: 10.0,
                     rel_abundance: 0.1,
+                    raw_line: None,
+                    source_line: None,
+                    content_hash: None,
+                    observed_at: None,
                 };
                 tx.send(record).await.expect("Failed to send record");
             }
@@ -139,11 +231,20 @@ async fn test_concurrent_database_operations() {
             let (tx, rx) = mpsc::channel(10);
 
             let insert_pool = pool.clone();
-            let inserter = tokio::spawn(async move { batch_insert_stast(rx, &insert_pool).await });
+            let inserter = tokio::spawn(async move {
+                batch_insert_stast(
+                    rx,
+                    &insert_pool,
+                    InsertOptions::new(StastRecord::max_batch_size()),
+                )
+                .await
+            });
 
             for j in 0..10 {
                 let record = StastRecord {
-                    sample_id: format!("concurrent_st_{i:02}_{j:02}"),
+                    sample_id: format!("concurrent_st_{i:02}_{j:02}")
+                        .parse()
+                        .expect("Invalid sample id"),
                     task: "blast".to_string(),
                     qseqid: "query".to_string(),
                     qlen: 100,
@@ -156,6 +257,10 @@ async fn test_concurrent_database_operations() {
                     sscinames: "species".to_string(),
                     staxids: "1".to_string(),
                     rank: "species".to_string(),
+                    raw_line: None,
+                    source_line: None,
+                    content_hash: None,
+                    observed_at: None,
                 };
                 tx.send(record).await.expect("Failed to send record");
             }
@@ -193,11 +298,20 @@ async fn test_batch_efficiency_large_dataset() {
 
     let pool = db.pool.clone();
     let start = std::time::Instant::now();
-    let insert_handle = tokio::spawn(async move { batch_insert_gottcha2(rx, &pool).await });
+    let insert_handle = tokio::spawn(async move {
+        batch_insert_gottcha2(
+            rx,
+            &pool,
+            InsertOptions::new(Gottcha2FullRecord::max_batch_size()),
+        )
+        .await
+    });
 
     for i in 0..1000 {
         let record = Gottcha2FullRecord {
-            sample_id: format!("large_batch_{i:04}"),
+            sample_id: format!("large_batch_{i:04}")
+                .parse()
+                .expect("Invalid sample id"),
             level: "species".to_string(),
             name: format!("Species_{i}"),
             taxid: format!("{}", 30000 + i),
@@ -208,6 +322,10 @@ async fn test_batch_efficiency_large_dataset() {
             best_sig_cov: 0.85,
             depth: 10.0 + (f64::from(i) * 0.1),
             rel_abundance: 0.1,
+            raw_line: None,
+            source_line: None,
+            content_hash: None,
+            observed_at: None,
         };
         tx.send(record).await.expect("Failed to send record");
     }
@@ -239,10 +357,17 @@ async fn test_data_integrity_after_insert() {
     let (tx, rx) = mpsc::channel(10);
 
     let pool = db.pool.clone();
-    let insert_handle = tokio::spawn(async move { batch_insert_gottcha2(rx, &pool).await });
+    let insert_handle = tokio::spawn(async move {
+        batch_insert_gottcha2(
+            rx,
+            &pool,
+            InsertOptions::new(Gottcha2FullRecord::max_batch_size()),
+        )
+        .await
+    });
 
     let test_record = Gottcha2FullRecord {
-        sample_id: "integrity_test_001".to_string(),
+        sample_id: "integrity_test_001".parse().expect("Invalid sample id"),
         level: "phylum".to_string(),
         name: "Proteobacteria".to_string(),
         taxid: "1224".to_string(),
@@ -253,6 +378,10 @@ async fn test_data_integrity_after_insert() {
         best_sig_cov: 0.85,
         depth: 10.5,
         rel_abundance: 0.25,
+        raw_line: None,
+        source_line: None,
+        content_hash: None,
+        observed_at: None,
     };
 
     tx.send(test_record.clone())
@@ -288,7 +417,7 @@ async fn test_data_integrity_after_insert() {
     .await
     .expect("Failed to retrieve record");
 
-    assert_eq!(retrieved.0, test_record.sample_id);
+    assert_eq!(retrieved.0, test_record.sample_id.as_str());
     assert_eq!(retrieved.1, test_record.level);
     assert_eq!(retrieved.2, test_record.name);
     assert_eq!(retrieved.3, test_record.taxid);
@@ -309,11 +438,20 @@ async fn test_table_cleanup_between_tests() {
 
     let (tx, rx) = mpsc::channel(10);
     let pool = db.pool.clone();
-    let insert_handle = tokio::spawn(async move { batch_insert_gottcha2(rx, &pool).await });
+    let insert_handle = tokio::spawn(async move {
+        batch_insert_gottcha2(
+            rx,
+            &pool,
+            InsertOptions::new(Gottcha2FullRecord::max_batch_size()),
+        )
+        .await
+    });
 
     for i in 0..5 {
         let record = Gottcha2FullRecord {
-            sample_id: format!("cleanup_test_{i}"),
+            sample_id: format!("cleanup_test_{i}")
+                .parse()
+                .expect("Invalid sample id"),
             level: "species".to_string(),
             name: "Test".to_string(),
             taxid: "1".to_string(),
@@ -324,6 +462,10 @@ async fn test_table_cleanup_between_tests() {
             best_sig_cov: 0.85,
             depth: 10.0,
             rel_abundance: 0.1,
+            raw_line: None,
+            source_line: None,
+            content_hash: None,
+            observed_at: None,
         };
         tx.send(record).await.expect("Failed to send record");
     }
@@ -493,3 +635,692 @@ async fn test_count_records_where() {
 
     assert_eq!(count, 1, "Should find 1 record with specific sample_id");
 }
+
+#[tokio::test]
+async fn test_prune_expired_idempotency_keys() {
+    let db = TestDatabase::new()
+        .await
+        .expect("Failed to create test database");
+
+    for (key, age) in [("old", "2 hours"), ("new", "0 seconds")] {
+        sqlx::query(
+            "INSERT INTO results (run_id, task_id, shard, idempotency_key, schema_version, payload, created_at)
+             VALUES ('run', $1, 0, $1, 1, '{}', NOW() - $2::interval)",
+        )
+        .bind(key)
+        .bind(age)
+        .execute(&db.pool)
+        .await
+        .expect("Failed to seed results");
+    }
+
+    let pruned = prune_expired_idempotency_keys::<DummyRecord>(
+        &db.pool,
+        std::time::Duration::from_secs(3600),
+    )
+    .await
+    .expect("Prune failed");
+    assert_eq!(pruned, 1);
+
+    let remaining: Vec<String> = sqlx::query_scalar("SELECT idempotency_key FROM results")
+        .fetch_all(&db.pool)
+        .await
+        .expect("Failed to read results");
+    assert_eq!(remaining, vec!["new".to_string()]);
+}
+
+#[tokio::test]
+async fn test_ingest_log_keeps_the_first_recorded_count() {
+    let db = TestDatabase::new()
+        .await
+        .expect("Failed to create test database");
+    let endpoint = "ingest-gottcha2";
+    let stale_after = Duration::from_secs(60);
+
+    assert_eq!(
+        find_ingest(&db.pool, "key", endpoint)
+            .await
+            .expect("Lookup failed"),
+        None
+    );
+    assert_eq!(
+        claim_ingest(&db.pool, "key", endpoint, stale_after)
+            .await
+            .expect("Claim failed"),
+        IngestClaim::Claimed
+    );
+    // A pending claim isn't a result, and blocks a second upload
+    assert_eq!(
+        find_ingest(&db.pool, "key", endpoint)
+            .await
+            .expect("Lookup failed"),
+        None
+    );
+    assert_eq!(
+        claim_ingest(&db.pool, "key", endpoint, stale_after)
+            .await
+            .expect("Claim failed"),
+        IngestClaim::InProgress
+    );
+
+    assert!(
+        record_ingest(&db.pool, "key", endpoint, 5)
+            .await
+            .expect("Record failed")
+    );
+    assert!(
+        !record_ingest(&db.pool, "key", endpoint, 7)
+            .await
+            .expect("Record failed")
+    );
+    release_ingest(&db.pool, "key", endpoint)
+        .await
+        .expect("Release failed");
+    assert_eq!(
+        find_ingest(&db.pool, "key", endpoint)
+            .await
+            .expect("Lookup failed"),
+        Some(5),
+        "a recorded count is never released"
+    );
+    assert_eq!(
+        claim_ingest(&db.pool, "key", endpoint, stale_after)
+            .await
+            .expect("Claim failed"),
+        IngestClaim::Done(5)
+    );
+
+    // Keys are scoped to their endpoint
+    assert_eq!(
+        claim_ingest(&db.pool, "key", "ingest-stast", stale_after)
+            .await
+            .expect("Claim failed"),
+        IngestClaim::Claimed
+    );
+}
+
+#[tokio::test]
+async fn test_ingest_log_released_and_stale_claims_can_be_retaken() {
+    let db = TestDatabase::new()
+        .await
+        .expect("Failed to create test database");
+    let endpoint = "ingest-stast";
+    let stale_after = Duration::from_secs(60);
+
+    let claim = |stale_after| claim_ingest(&db.pool, "key", endpoint, stale_after);
+    assert_eq!(
+        claim(stale_after).await.expect("Claim failed"),
+        IngestClaim::Claimed
+    );
+    release_ingest(&db.pool, "key", endpoint)
+        .await
+        .expect("Release failed");
+    assert_eq!(
+        claim(stale_after).await.expect("Claim failed"),
+        IngestClaim::Claimed
+    );
+
+    // A claim older than `stale_after` was abandoned, so it's taken over
+    assert_eq!(
+        claim(Duration::ZERO).await.expect("Claim failed"),
+        IngestClaim::Claimed
+    );
+    assert_eq!(
+        db.count_records("ingest_log").await.expect("count"),
+        1,
+        "a takeover reuses the claim"
+    );
+}
+
+#[tokio::test]
+async fn test_migration_modes() {
+    let db = TestDatabase::new()
+        .await
+        .expect("Failed to create test database");
+
+    // Fully migrated: every mode starts cleanly
+    for mode in [
+        MigrationMode::Auto,
+        MigrationMode::Verify,
+        MigrationMode::Skip,
+    ] {
+        migrations::apply(&db.pool, mode)
+            .await
+            .unwrap_or_else(|e| panic!("{mode:?} failed on a migrated schema: {e}"));
+    }
+
+    // Forget the newest migration, as if this build were ahead of the schema
+    let latest: i64 = sqlx::query_scalar("SELECT MAX(version) FROM _sqlx_migrations")
+        .fetch_one(&db.pool)
+        .await
+        .expect("Failed to read migrations");
+    sqlx::query("DELETE FROM _sqlx_migrations WHERE version = $1")
+        .bind(latest)
+        .execute(&db.pool)
+        .await
+        .expect("Failed to forget migration");
+
+    let err = migrations::apply(&db.pool, MigrationMode::Verify)
+        .await
+        .expect_err("verify should fail with a pending migration");
+    assert!(
+        err.to_string().contains(&latest.to_string()),
+        "error should name the pending version, got: {err}"
+    );
+
+    // Skip neither checks nor repairs
+    migrations::apply(&db.pool, MigrationMode::Skip)
+        .await
+        .expect("skip should not check the schema");
+    let applied: i64 =
+        sqlx::query_scalar("SELECT COUNT(*) FROM _sqlx_migrations WHERE version = $1")
+            .bind(latest)
+            .fetch_one(&db.pool)
+            .await
+            .expect("Failed to read migrations");
+    assert_eq!(applied, 0, "skip must not apply migrations");
+
+    // Auto applies what's pending, after which verify passes again
+    migrations::apply(&db.pool, MigrationMode::Auto)
+        .await
+        .expect("auto should apply the pending migration");
+    migrations::apply(&db.pool, MigrationMode::Verify)
+        .await
+        .expect("verify should pass once migrations are applied");
+}
+
+#[tokio::test]
+async fn test_verify_fails_on_unmigrated_schema() {
+    let db = TestDatabase::new()
+        .await
+        .expect("Failed to create test database");
+    sqlx::query("DROP TABLE _sqlx_migrations")
+        .execute(&db.pool)
+        .await
+        .expect("Failed to drop migrations table");
+
+    let err = migrations::apply(&db.pool, MigrationMode::Verify)
+        .await
+        .expect_err("verify should fail without a migrations table");
+    assert!(
+        err.to_string().contains("has the schema been migrated"),
+        "got: {err}"
+    );
+}
+
+#[tokio::test]
+async fn test_unique_taxon_migration_refuses_existing_duplicates() {
+    let db = TestDatabase::new()
+        .await
+        .expect("Failed to create test database");
+
+    // Roll the schema back to before the unique index and store a duplicate
+    sqlx::query("DROP INDEX idx_gottcha2_sample_taxid_level")
+        .execute(&db.pool)
+        .await
+        .expect("Failed to drop index");
+    sqlx::query("DELETE FROM _sqlx_migrations WHERE version = 10")
+        .execute(&db.pool)
+        .await
+        .expect("Failed to forget migration");
+    for _ in 0..2 {
+        sqlx::query(
+            "INSERT INTO gottcha2_results (sample_id, level, name, taxid, read_count, \
+             total_bp_mapped, ani_ci95, covered_sig_len, best_sig_cov, depth, rel_abundance) \
+             VALUES ('dupes', 'species', 'E. coli', '562', 1, 1, 0.9, 1, 0.9, 1.0, 0.1)",
+        )
+        .execute(&db.pool)
+        .await
+        .expect("Failed to insert duplicate");
+    }
+
+    // A failed run leaves the migrator's session lock held, so it gets a pool
+    // of its own that is closed afterwards
+    let failing = PgPoolOptions::new()
+        .connect(&db.database_url)
+        .await
+        .expect("Failed to connect");
+    let err = migrations::apply(&failing, MigrationMode::Auto)
+        .await
+        .expect_err("the migration should fail while duplicates exist");
+    failing.close().await;
+    assert!(
+        format!("{err:?}").contains("1 (sample_id, taxid, level) groups"),
+        "got: {err:?}"
+    );
+    let rows = db
+        .count_records("gottcha2_results")
+        .await
+        .expect("Failed to count records");
+    assert_eq!(rows, 2, "the migration must not delete stored rows");
+
+    sqlx::query("DELETE FROM gottcha2_results WHERE id = (SELECT MAX(id) FROM gottcha2_results)")
+        .execute(&db.pool)
+        .await
+        .expect("Failed to remove duplicate");
+    migrations::apply(&db.pool, MigrationMode::Auto)
+        .await
+        .expect("the migration should apply once duplicates are removed");
+}
+
+#[tokio::test]
+async fn test_batch_size_over_parameter_limit_is_split() {
+    let db = TestDatabase::new()
+        .await
+        .expect("Failed to create test database");
+
+    // One statement of this many STAST rows would need far more than 65535
+    // bind parameters
+    let oversized = StastRecord::max_batch_size() * 4;
+    let rows = StastRecord::max_batch_size() * 2 + 17;
+
+    let (tx, rx) = mpsc::channel(1000);
+    let pool = db.pool.clone();
+    let insert_handle =
+        tokio::spawn(
+            async move { batch_insert_stast(rx, &pool, InsertOptions::new(oversized)).await },
+        );
+
+    for i in 0..rows {
+        let record = StastRecord {
+            task: "megablast".to_string(),
+            sample_id: "param_limit".parse().expect("Invalid sample id"),
+            qseqid: format!("NODE_{i}_length_1000"),
+            qlen: 1000,
+            sseqid: format!("gi|{i}|ref|NC_000001.1|"),
+            stitle: "Test virus".to_string(),
+            length: 950,
+            pident: 99.5,
+            evalue: 0.0,
+            bitscore: 1800.0,
+            sscinames: "Test virus".to_string(),
+            staxids: "12345".to_string(),
+            rank: "species:Test virus".to_string(),
+            raw_line: None,
+            source_line: None,
+            content_hash: None,
+            observed_at: None,
+        };
+        tx.send(record).await.expect("Failed to send record");
+    }
+    drop(tx);
+
+    let inserted = insert_handle
+        .await
+        .expect("Insert task panicked")
+        .expect("Oversized batch size should be split, not fail");
+    assert_eq!(inserted, rows as u64);
+    assert_eq!(
+        db.count_records("stast_results")
+            .await
+            .expect("Failed to count"),
+        i64::try_from(rows).expect("row count fits in i64")
+    );
+}
+
+#[tokio::test]
+async fn test_concurrent_serializable_ingests_stay_consistent() {
+    let db = TestDatabase::new()
+        .await
+        .expect("Failed to create test database");
+
+    // Every upload carries the same keys, so concurrent batches contend on
+    // both the TTL expiry delete and the ON CONFLICT insert
+    let keys: Vec<String> = (0..60).map(|i| format!("shared-key-{i}")).collect();
+    let uploads = 6;
+    let options = InsertOptions::new(10)
+        .with_isolation_level(IsolationLevel::Serializable)
+        .with_idempotency_ttl(Some(std::time::Duration::from_secs(3600)));
+
+    let mut handles = Vec::new();
+    for upload in 0..uploads {
+        let (tx, rx) = mpsc::channel(100);
+        let pool = db.pool.clone();
+        handles.push(tokio::spawn(async move {
+            batch_insert_dummy(rx, &pool, options).await
+        }));
+        let keys = keys.clone();
+        tokio::spawn(async move {
+            for (i, key) in keys.into_iter().enumerate() {
+                let record = DummyRecord {
+                    run_id: format!("run-{upload}"),
+                    task_id: format!("task-{i}"),
+                    shard: 0,
+                    idempotency_key: key,
+                    schema_version: 1,
+                    payload: serde_json::json!({"upload": upload}),
+                };
+                tx.send(record).await.expect("Failed to send record");
+            }
+        });
+    }
+
+    let mut inserted = 0;
+    for handle in handles {
+        inserted += handle
+            .await
+            .expect("Insert task panicked")
+            .expect("Serialization failures should be retried, not surfaced");
+    }
+
+    // Each key landed exactly once, whichever upload won it
+    assert_eq!(inserted, keys.len() as u64);
+    assert_eq!(
+        db.count_records("results").await.expect("Failed to count"),
+        i64::try_from(keys.len()).expect("key count fits in i64")
+    );
+}
+
+#[tokio::test]
+async fn test_gottcha2_rows_route_to_hash_partitions() {
+    let db = TestDatabase::new()
+        .await
+        .expect("Failed to create test database");
+
+    let records: Vec<Gottcha2FullRecord> = (0..40)
+        .map(|i| Gottcha2FullRecord {
+            sample_id: format!("partition_{i:02}")
+                .parse()
+                .expect("Invalid sample id"),
+            level: "species".to_string(),
+            name: format!("Species_{i}"),
+            taxid: format!("{}", 30000 + i),
+            read_count: 100,
+            total_bp_mapped: 5000,
+            ani_ci95: 0.95,
+            covered_sig_len: 1000,
+            best_sig_cov: 0.85,
+            depth: 10.0,
+            rel_abundance: 0.1,
+            raw_line: None,
+            source_line: None,
+            content_hash: None,
+            observed_at: None,
+        })
+        .collect();
+
+    let (tx, rx) = mpsc::channel(100);
+    let pool = db.pool.clone();
+    let insert_handle = tokio::spawn(async move {
+        batch_insert_gottcha2(
+            rx,
+            &pool,
+            InsertOptions::new(Gottcha2FullRecord::max_batch_size()),
+        )
+        .await
+    });
+    for record in records.clone() {
+        tx.send(record).await.expect("Failed to send record");
+    }
+    drop(tx);
+    insert_handle
+        .await
+        .expect("Insert task panicked")
+        .expect("Batch insert should succeed");
+
+    let mut partitions_used = std::collections::HashSet::new();
+    for record in &records {
+        let key = record.partition_key();
+        // The partition Postgres' own hashing assigns this key to
+        let expected: String = sqlx::query_scalar(
+            "SELECT 'gottcha2_results_p' || remainder
+             FROM generate_series(0, 3) AS remainder
+             WHERE satisfies_hash_partition('gottcha2_results'::regclass, 4, remainder, $1::text)",
+        )
+        .bind(&key)
+        .fetch_one(&db.pool)
+        .await
+        .expect("Failed to hash partition key");
+        let actual: String = sqlx::query_scalar(
+            "SELECT tableoid::regclass::text FROM gottcha2_results WHERE sample_id = $1",
+        )
+        .bind(&key)
+        .fetch_one(&db.pool)
+        .await
+        .expect("Failed to find row");
+        assert_eq!(
+            actual, expected,
+            "row for {key} landed in the wrong partition"
+        );
+        partitions_used.insert(actual);
+    }
+    assert!(
+        partitions_used.len() > 1,
+        "40 samples should spread over several partitions"
+    );
+
+    let total = db
+        .count_records("gottcha2_results")
+        .await
+        .expect("Failed to count records");
+    assert_eq!(total, 40);
+}
+
+#[tokio::test]
+async fn test_reingesting_gottcha2_batch_is_idempotent() {
+    let db = TestDatabase::new()
+        .await
+        .expect("Failed to create test database");
+
+    let records: Vec<Gottcha2FullRecord> = (0..25)
+        .map(|i| Gottcha2FullRecord {
+            sample_id: "retried_sample".parse().expect("Invalid sample id"),
+            level: if i % 2 == 0 { "species" } else { "genus" }.to_string(),
+            name: format!("Taxon_{i}"),
+            taxid: format!("{}", 40000 + i),
+            read_count: 100,
+            total_bp_mapped: 5000,
+            ani_ci95: 0.95,
+            covered_sig_len: 1000,
+            best_sig_cov: 0.85,
+            depth: 10.0,
+            rel_abundance: 0.1,
+            raw_line: None,
+            source_line: None,
+            content_hash: None,
+            observed_at: None,
+        })
+        .collect();
+
+    let mut inserted = Vec::new();
+    for _ in 0..2 {
+        let (tx, rx) = mpsc::channel(100);
+        let pool = db.pool.clone();
+        let insert_handle =
+            tokio::spawn(
+                async move { batch_insert_gottcha2(rx, &pool, InsertOptions::new(10)).await },
+            );
+        for record in records.clone() {
+            tx.send(record).await.expect("Failed to send record");
+        }
+        drop(tx);
+        inserted.push(
+            insert_handle
+                .await
+                .expect("Insert task panicked")
+                .expect("Batch insert should succeed"),
+        );
+
+        let count = db
+            .count_records("gottcha2_results")
+            .await
+            .expect("Failed to count records");
+        assert_eq!(count, 25, "re-ingesting the batch should add no rows");
+    }
+    assert_eq!(inserted, [25, 0], "the retry should insert nothing");
+}
+
+#[tokio::test]
+async fn test_copy_insert_round_trips_stast_rows() {
+    let db = TestDatabase::new()
+        .await
+        .expect("Failed to create test database");
+
+    let records: Vec<StastRecord> = (0..25)
+        .map(|i| StastRecord {
+            task: "megablast".to_string(),
+            sample_id: "copied".parse().expect("Invalid sample id"),
+            qseqid: format!("NODE_{i}"),
+            qlen: 1000,
+            sseqid: format!("gi|{i}|"),
+            // Characters COPY's text format must escape
+            stitle: format!("Tab\there, back\\slash\nline {i}"),
+            length: 950,
+            pident: 99.5,
+            evalue: 1e-180,
+            bitscore: 1800.25,
+            sscinames: "Test virus".to_string(),
+            staxids: "12345".to_string(),
+            rank: "species:Test virus".to_string(),
+            raw_line: None,
+            source_line: Some(i + 1),
+            content_hash: None,
+            observed_at: Some("2024-05-01T12:30:00Z".parse().expect("Invalid timestamp")),
+        })
+        .collect();
+
+    let (tx, rx) = mpsc::channel(100);
+    let pool = db.pool.clone();
+    let insert_handle = tokio::spawn(async move {
+        batch_insert_stast(
+            rx,
+            &pool,
+            InsertOptions::new(10).with_insert_method(InsertMethod::Copy),
+        )
+        .await
+    });
+    for record in records.clone() {
+        tx.send(record).await.expect("Failed to send record");
+    }
+    drop(tx);
+    let inserted = insert_handle
+        .await
+        .expect("Insert task panicked")
+        .expect("COPY insert should succeed");
+    assert_eq!(inserted, 25);
+
+    let stored: (String, f64, f64, i64, Option<i64>, String) = sqlx::query_as(
+        "SELECT stitle, evalue, bitscore, qlen, source_line, observed_at::text
+         FROM stast_results WHERE qseqid = 'NODE_7'",
+    )
+    .fetch_one(&db.pool)
+    .await
+    .expect("Failed to fetch copied row");
+    assert_eq!(stored.0, records[7].stitle);
+    assert_eq!((stored.1, stored.2, stored.3), (1e-180, 1800.25, 1000));
+    assert_eq!(stored.4, Some(8));
+    assert_eq!(stored.5, "2024-05-01 12:30:00+00");
+    let raw_lines: i64 =
+        sqlx::query_scalar("SELECT COUNT(*) FROM stast_results WHERE raw_line IS NULL")
+            .fetch_one(&db.pool)
+            .await
+            .expect("Failed to count NULLs");
+    assert_eq!(raw_lines, 25);
+}
+
+#[tokio::test]
+async fn test_parallel_insert_stores_every_batch() {
+    let db = TestDatabase::new()
+        .await
+        .expect("Failed to create test database");
+
+    let record = |taxid: usize| Gottcha2FullRecord {
+        sample_id: "parallel".parse().expect("Invalid sample id"),
+        level: "species".to_string(),
+        name: format!("Taxon {taxid}"),
+        taxid: taxid.to_string(),
+        read_count: 100,
+        total_bp_mapped: 15_000,
+        ani_ci95: 0.97,
+        covered_sig_len: 1_200,
+        best_sig_cov: 0.8,
+        depth: 12.5,
+        rel_abundance: 0.01,
+        raw_line: None,
+        source_line: None,
+        content_hash: None,
+        observed_at: None,
+    };
+
+    let (tx, rx) = mpsc::channel(100);
+    let pool = db.pool.clone();
+    let insert_handle = tokio::spawn(async move {
+        batch_insert_gottcha2(rx, &pool, InsertOptions::new(10).with_parallelism(4)).await
+    });
+    // The last 20 repeat earlier taxa with other counts. GOTTCHA2 batches
+    // insert in order whatever the parallelism, so the first row is kept.
+    for (i, taxid) in (0..205).chain(0..20).enumerate() {
+        let mut record = record(taxid);
+        if i >= 205 {
+            record.read_count = 999;
+        }
+        tx.send(record).await.expect("Failed to send record");
+    }
+    drop(tx);
+    let inserted = insert_handle
+        .await
+        .expect("Insert task panicked")
+        .expect("Parallel insert should succeed");
+    assert_eq!(inserted, 205);
+    assert_eq!(
+        db.count_records("gottcha2_results")
+            .await
+            .expect("Failed to count"),
+        205
+    );
+    let replaced: i64 =
+        sqlx::query_scalar("SELECT COUNT(*) FROM gottcha2_results WHERE read_count = 999")
+            .fetch_one(&db.pool)
+            .await
+            .expect("Failed to count");
+    assert_eq!(replaced, 0, "a repeated taxon must keep the first row");
+}
+
+#[tokio::test]
+async fn test_connect_with_backoff_waits_for_database() {
+    let db = TestDatabase::new()
+        .await
+        .expect("Failed to create test database");
+
+    // Point at a database that doesn't exist yet and create it shortly after
+    let name = format!("late_{}", rand::random::<u64>());
+    let (base, query) = db
+        .database_url
+        .find('?')
+        .map_or((db.database_url.as_str(), ""), |i| {
+            db.database_url.split_at(i)
+        });
+    let (prefix, _) = base.rsplit_once('/').expect("Database URL has no path");
+    let late_url = format!("{prefix}/{name}{query}");
+
+    let connect = tokio::spawn(async move {
+        connect_with_backoff(PgPoolOptions::new(), &late_url, Duration::from_secs(10)).await
+    });
+    tokio::time::sleep(Duration::from_millis(700)).await;
+    assert!(!connect.is_finished(), "connect should still be retrying");
+    sqlx::query(&format!("CREATE DATABASE {name}"))
+        .execute(&db.pool)
+        .await
+        .expect("Failed to create database");
+
+    let pool = connect
+        .await
+        .expect("Connect task panicked")
+        .expect("Connect should succeed once the database exists");
+    let one: i32 = sqlx::query_scalar("SELECT 1")
+        .fetch_one(&pool)
+        .await
+        .expect("Failed to query late database");
+    assert_eq!(one, 1);
+    pool.close().await;
+    sqlx::query(&format!("DROP DATABASE {name}"))
+        .execute(&db.pool)
+        .await
+        .expect("Failed to drop database");
+
+    // With no time to wait, the first failure is returned
+    let missing = format!("{prefix}/missing_{name}{query}");
+    connect_with_backoff(PgPoolOptions::new(), &missing, Duration::ZERO)
+        .await
+        .expect_err("A missing database should fail without retries");
+}
@@ -25,7 +25,7 @@ async fn test_gottcha2_endpoint_with_valid_data() {
     // Create test GOTTCHA2 records
     let records = vec![
         Gottcha2FullRecord {
-            sample_id: "test_sample_001".to_string(),
+            sample_id: "test_sample_001".parse().expect("Invalid sample id"),
             level: "phylum".to_string(),
             name: "Proteobacteria".to_string(),
             taxid: "1224".to_string(),
@@ -36,9 +36,13 @@ async fn test_gottcha2_endpoint_with_valid_data() {
             best_sig_cov: 0.85,
             depth: 10.5,
             rel_abundance: 0.25,
+            raw_line: None,
+            source_line: None,
+            content_hash: None,
+            observed_at: None,
         },
         Gottcha2FullRecord {
-            sample_id: "test_sample_001".to_string(),
+            sample_id: "test_sample_001".parse().expect("Invalid sample id"),
             level: "genus".to_string(),
             name: "Escherichia".to_string(),
             taxid: "561".to_string(),
@@ -49,6 +53,10 @@ async fn test_gottcha2_endpoint_with_valid_data() {
             best_sig_cov: 0.90,
             depth: 15.2,
             rel_abundance: 0.12,
+            raw_line: None,
+            source_line: None,
+            content_hash: None,
+            observed_at: None,
         },
     ];
 
@@ -84,7 +92,7 @@ async fn test_stast_endpoint_with_valid_data() {
     // Create test STAST records
     let records = vec![StastRecord {
         task: "megablast".to_string(),
-        sample_id: "test_sample_002".to_string(),
+        sample_id: "test_sample_002".parse().expect("Invalid sample id"),
         qseqid: "NODE_1_length_1000".to_string(),
         qlen: 1000,
         sseqid: "gi|123456|ref|NC_000001.1|".to_string(),
@@ -96,6 +104,10 @@ async fn test_stast_endpoint_with_valid_data() {
         sscinames: "Test virus".to_string(),
         staxids: "12345".to_string(),
         rank: "species:Test virus".to_string(),
+        raw_line: None,
+        source_line: None,
+        content_hash: None,
+        observed_at: None,
     }];
 
     // Convert to JSONL
@@ -0,0 +1,125 @@
+//! Throughput comparison of decoding an upload's lines one at a time versus
+//! in parallel batches across the rayon thread pool, on a wide STAST upload.
+//!
+//! Ignored by default so it never slows normal test runs. Run it explicitly,
+//! in release mode, with:
+//!
+//! ```sh
+//! cargo test --release --test parse_bench -- --ignored --nocapture
+//! ```
+#![allow(clippy::print_stdout)] // the report is the point of this harness
+
+use std::{
+    io::Write,
+    time::{Duration, Instant},
+};
+
+use axum::body::Body;
+use flate2::{Compression, write::GzEncoder};
+use nvd_support_car::{
+    config::AppConfig,
+    models::record::StastRecord,
+    services::parsing::{ParseOptions, parse_gzipped_jsonl},
+};
+use tokio::sync::mpsc;
+
+const LINES_PER_RUN: u32 = 100_000;
+const RUNS: usize = 3;
+
+fn stast_line(i: usize) -> String {
+    let record = StastRecord {
+        task: "megablast".to_string(),
+        sample_id: format!("bench_{}", i % 10)
+            .parse()
+            .expect("Invalid sample id"),
+        qseqid: format!("NODE_{i}_length_1000_cov_12.5"),
+        qlen: 1000,
+        sseqid: format!("gi|{i}|ref|NC_{i:06}.1|"),
+        stitle: format!("Test virus {i} isolate BENCH-{i}, complete genome"),
+        length: 950,
+        pident: 99.5,
+        evalue: 1e-10,
+        bitscore: 1750.0,
+        sscinames: format!("Test virus {i}"),
+        staxids: (i % 50_000).to_string(),
+        rank: format!("species:Test virus {i}"),
+        raw_line: None,
+        source_line: None,
+        content_hash: None,
+        observed_at: None,
+    };
+    serde_json::to_string(&record).expect("Failed to serialize")
+}
+
+fn gzipped_upload() -> Vec<u8> {
+    let mut encoder = GzEncoder::new(Vec::new(), Compression::fast());
+    for i in 0..LINES_PER_RUN as usize {
+        writeln!(encoder, "{}", stast_line(i)).expect("Failed to write to encoder");
+    }
+    encoder.finish().expect("Failed to finish compression")
+}
+
+/// Parses `upload` with `parallel_decode` as given, draining the records as
+/// an insert would, and returns the fastest of `RUNS` runs.
+async fn time_parse(upload: &[u8], parallel_decode: bool) -> Duration {
+    let mut fastest = Duration::MAX;
+    for _ in 0..RUNS {
+        let options = ParseOptions {
+            parallel_decode,
+            ..ParseOptions::from_config(&AppConfig::default())
+        };
+        let (tx, mut rx) = mpsc::channel::<StastRecord>(1024);
+        let drain = async {
+            let mut received = 0;
+            while rx.recv().await.is_some() {
+                received += 1;
+            }
+            received
+        };
+
+        let start = Instant::now();
+        let (summary, received) = tokio::join!(
+            parse_gzipped_jsonl(Body::from(upload.to_vec()), tx, options),
+            drain
+        );
+        fastest = fastest.min(start.elapsed());
+
+        let summary = summary.expect("Parse failed");
+        assert_eq!(summary.accepted, LINES_PER_RUN as usize);
+        assert_eq!(received, LINES_PER_RUN as usize);
+    }
+    fastest
+}
+
+#[tokio::test(flavor = "multi_thread")]
+#[ignore = "benchmark; run explicitly with --ignored --nocapture"]
+async fn bench_parallel_decode() {
+    let upload = gzipped_upload();
+    println!(
+        "\n{LINES_PER_RUN} STAST lines, {} bytes gzipped, fastest of {RUNS} runs\n",
+        upload.len()
+    );
+
+    let sequential = time_parse(&upload, false).await;
+    let parallel = time_parse(&upload, true).await;
+
+    let rate = |elapsed: Duration| f64::from(LINES_PER_RUN) / elapsed.as_secs_f64();
+    println!("{:<12} {:>10} {:>14}", "decode", "time", "lines/s");
+    println!(
+        "{:<12} {:>8.0}ms {:>14.0}",
+        "sequential",
+        sequential.as_secs_f64() * 1000.0,
+        rate(sequential)
+    );
+    println!(
+        "{:<12} {:>8.0}ms {:>14.0}",
+        "parallel",
+        parallel.as_secs_f64() * 1000.0,
+        rate(parallel)
+    );
+    println!(
+        "\nspeedup: {:.2}x on {} rayon threads",
+        sequential.as_secs_f64() / parallel.as_secs_f64(),
+        rayon::current_num_threads()
+    );
+}